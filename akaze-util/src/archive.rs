@@ -0,0 +1,448 @@
+//! A single file archive of the features of many images, read through a
+//! memory map.
+//!
+//! Keypoints are stored as fixed size `PackedKeypoint` records and
+//! descriptors as one packed byte array per image, so the features of an
+//! image are zero-copy slices of the mapped file and are only paged in
+//! when used. All integers are little endian:
+//!
+//! | Offset | Size | Content                                              |
+//! |--------|------|------------------------------------------------------|
+//! | 0      | 4    | The magic number `AKZA`                              |
+//! | 4      | 2    | The major format version, currently 1                |
+//! | 6      | 2    | The minor format version, currently 0                |
+//! | 8      | 4    | The descriptor length in bytes                       |
+//! | 12     | 4    | The number of images                                 |
+//! | 16     | 8    | The offset of the index                              |
+//! | 24     |      | Per image, 8 byte aligned: the keypoints, then the   |
+//! |        |      | descriptors                                          |
+//! | index  | rest | The bincode encoded `Vec<ArchiveEntry>`              |
+//!
+//! The index is written last, so archives are written in one pass.
+
+use crate::collection::image_name;
+use crate::deserialize_features_with_header_from_file;
+use crate::feature_file::FeatureFileHeader;
+use crate::Features;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use failure::{format_err, Error};
+use log::*;
+use memmap::Mmap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::slice;
+
+/// The magic number at the start of every feature archive.
+pub const ARCHIVE_MAGIC: [u8; 4] = *b"AKZA";
+
+/// The major format version written and read by this crate.
+pub const ARCHIVE_MAJOR_VERSION: u16 = 1;
+
+/// The minor format version written by this crate.
+pub const ARCHIVE_MINOR_VERSION: u16 = 0;
+
+const PREAMBLE_LENGTH: u64 = 24;
+
+/// A keypoint as stored in a feature archive.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PackedKeypoint {
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+    pub angle: f32,
+    pub response: f32,
+    pub octave: u32,
+    pub class_id: u32,
+}
+
+impl PackedKeypoint {
+    /// Pack a keypoint.
+    ///
+    /// # Arguments
+    /// * `keypoint` - The keypoint.
+    pub fn new(keypoint: &Keypoint) -> PackedKeypoint {
+        PackedKeypoint {
+            x: keypoint.point.0,
+            y: keypoint.point.1,
+            size: keypoint.size,
+            angle: keypoint.angle,
+            response: keypoint.response,
+            octave: keypoint.octave as u32,
+            class_id: keypoint.class_id as u32,
+        }
+    }
+
+    /// Unpack the keypoint.
+    pub fn keypoint(&self) -> Keypoint {
+        Keypoint {
+            point: (self.x, self.y),
+            response: self.response,
+            size: self.size,
+            octave: self.octave as usize,
+            class_id: self.class_id as usize,
+            angle: self.angle,
+        }
+    }
+
+    fn write(&self, mut writer: impl Write) -> Result<(), Error> {
+        for value in &[self.x, self.y, self.size, self.angle, self.response] {
+            writer.write_all(&value.to_bits().to_le_bytes())?;
+        }
+        writer.write_all(&self.octave.to_le_bytes())?;
+        writer.write_all(&self.class_id.to_le_bytes())?;
+        Ok(())
+    }
+}
+
+/// The descriptors of an image, packed one after the other.
+#[derive(Debug, Clone, Copy)]
+pub struct PackedDescriptors<'a> {
+    bytes: &'a [u8],
+    length: usize,
+}
+
+impl<'a> PackedDescriptors<'a> {
+    /// The number of descriptors.
+    pub fn len(&self) -> usize {
+        self.bytes.len().checked_div(self.length).unwrap_or(0)
+    }
+
+    /// Whether there are no descriptors.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The length of each descriptor in bytes.
+    pub fn descriptor_length(&self) -> usize {
+        self.length
+    }
+
+    /// A descriptor.
+    ///
+    /// # Arguments
+    /// * `index` - The index of the descriptor, panics if out of range.
+    pub fn get(&self, index: usize) -> &'a [u8] {
+        &self.bytes[index * self.length..(index + 1) * self.length]
+    }
+
+    /// The descriptors in order.
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        self.bytes.chunks_exact(self.length.max(1))
+    }
+
+    /// All descriptors as one byte array.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+}
+
+/// The index entry of an image in a feature archive.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    /// The image name, unique in the archive
+    pub name: String,
+    /// The header of the features, `num_features` being their number
+    pub header: FeatureFileHeader,
+    /// The offset of the keypoints in the archive
+    pub offset: u64,
+}
+
+/// Writes a feature archive, image by image.
+pub struct FeatureArchiveWriter<W: Write + Seek> {
+    writer: W,
+    position: u64,
+    descriptor_length: Option<u32>,
+    entries: Vec<ArchiveEntry>,
+    ids: HashMap<String, usize>,
+}
+
+impl FeatureArchiveWriter<BufWriter<File>> {
+    /// Create a feature archive file.
+    ///
+    /// # Arguments
+    /// * `path` - The archive, overwritten if it exists.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, Error> {
+        FeatureArchiveWriter::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write + Seek> FeatureArchiveWriter<W> {
+    /// Start a feature archive.
+    ///
+    /// # Arguments
+    /// * `writer` - The destination, at the start of the archive.
+    pub fn new(mut writer: W) -> Result<Self, Error> {
+        // The preamble is rewritten by finish
+        writer.write_all(&[0u8; PREAMBLE_LENGTH as usize])?;
+        Ok(FeatureArchiveWriter {
+            writer,
+            position: PREAMBLE_LENGTH,
+            descriptor_length: None,
+            entries: vec![],
+            ids: HashMap::new(),
+        })
+    }
+
+    /// Add the features of an image.
+    ///
+    /// # Arguments
+    /// * `name` - The image name, unique in the archive.
+    /// * `header` - The header of the features.
+    /// * `features` - The features, with descriptors of the length of all
+    ///   others in the archive.
+    /// # Return value
+    /// The image id, the number of images added before.
+    pub fn add(
+        &mut self,
+        name: &str,
+        header: &FeatureFileHeader,
+        features: &Features,
+    ) -> Result<usize, Error> {
+        if self.ids.contains_key(name) {
+            return Err(format_err!("{} is already in the archive", name));
+        }
+        if header.num_features != features.keypoints.len() as u64
+            || features.keypoints.len() != features.descriptors.len()
+        {
+            return Err(format_err!(
+                "the header describes {} features, got {} keypoints and {} descriptors",
+                header.num_features,
+                features.keypoints.len(),
+                features.descriptors.len()
+            ));
+        }
+        if !features.descriptors.is_empty() {
+            let length = *self
+                .descriptor_length
+                .get_or_insert(header.descriptor_length);
+            if features
+                .descriptors
+                .iter()
+                .any(|descriptor| descriptor.vector.len() != length as usize)
+            {
+                return Err(format_err!(
+                    "the descriptors of {} are not all of length {}",
+                    name,
+                    length
+                ));
+            }
+        }
+        let entry = ArchiveEntry {
+            name: name.to_owned(),
+            header: header.clone(),
+            offset: self.position,
+        };
+        for keypoint in &features.keypoints {
+            PackedKeypoint::new(keypoint).write(&mut self.writer)?;
+        }
+        for descriptor in &features.descriptors {
+            self.writer.write_all(&descriptor.vector)?;
+        }
+        let end = self.position
+            + (features.keypoints.len() * mem::size_of::<PackedKeypoint>()) as u64
+            + features
+                .descriptors
+                .iter()
+                .map(|descriptor| descriptor.vector.len() as u64)
+                .sum::<u64>();
+        let padding = (8 - end % 8) % 8;
+        self.writer.write_all(&vec![0u8; padding as usize])?;
+        self.position = end + padding;
+        self.ids.insert(entry.name.clone(), self.entries.len());
+        self.entries.push(entry);
+        Ok(self.entries.len() - 1)
+    }
+
+    /// Write the index and finish the archive.
+    ///
+    /// # Return value
+    /// The destination.
+    pub fn finish(mut self) -> Result<W, Error> {
+        bincode::serialize_into(&mut self.writer, &self.entries)?;
+        self.writer.seek(SeekFrom::Start(0))?;
+        self.writer.write_all(&ARCHIVE_MAGIC)?;
+        self.writer
+            .write_all(&ARCHIVE_MAJOR_VERSION.to_le_bytes())?;
+        self.writer
+            .write_all(&ARCHIVE_MINOR_VERSION.to_le_bytes())?;
+        self.writer
+            .write_all(&self.descriptor_length.unwrap_or(0).to_le_bytes())?;
+        self.writer
+            .write_all(&(self.entries.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&self.position.to_le_bytes())?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A memory mapped feature archive.
+///
+/// The archive file must not be modified while it is open.
+pub struct FeatureArchive {
+    map: Mmap,
+    descriptor_length: usize,
+    entries: Vec<ArchiveEntry>,
+    ids: HashMap<String, usize>,
+}
+
+impl FeatureArchive {
+    /// Open and map a feature archive.
+    ///
+    /// # Arguments
+    /// * `path` - The archive.
+    pub fn open(path: impl AsRef<Path>) -> Result<FeatureArchive, Error> {
+        if cfg!(target_endian = "big") {
+            return Err(format_err!(
+                "feature archives are only mapped on little endian machines"
+            ));
+        }
+        let path = path.as_ref();
+        debug!("Mapping feature archive {:?}", path);
+        let file = File::open(path)?;
+        // Safe as long as the file is not modified while mapped
+        let map = unsafe { Mmap::map(&file)? };
+        if map.len() < PREAMBLE_LENGTH as usize || map[..4] != ARCHIVE_MAGIC {
+            return Err(format_err!(
+                "not a feature archive, the magic number is missing"
+            ));
+        }
+        let major_version = u16::from_le_bytes([map[4], map[5]]);
+        let minor_version = u16::from_le_bytes([map[6], map[7]]);
+        if major_version != ARCHIVE_MAJOR_VERSION {
+            return Err(format_err!(
+                "unsupported feature archive version {}.{}, expected {}.x",
+                major_version,
+                minor_version,
+                ARCHIVE_MAJOR_VERSION
+            ));
+        }
+        let descriptor_length = u32::from_le_bytes([map[8], map[9], map[10], map[11]]) as usize;
+        let mut index_offset = [0u8; 8];
+        index_offset.copy_from_slice(&map[16..24]);
+        let index_offset = u64::from_le_bytes(index_offset) as usize;
+        let index = map
+            .get(index_offset..)
+            .ok_or_else(|| format_err!("the index of the feature archive is missing"))?;
+        let entries: Vec<ArchiveEntry> = bincode::deserialize(index)?;
+        let mut ids = HashMap::new();
+        for (id, entry) in entries.iter().enumerate() {
+            // A crafted index must not overflow into a valid looking range
+            let end = (mem::size_of::<PackedKeypoint>() + descriptor_length)
+                .checked_mul(entry.header.num_features as usize)
+                .and_then(|size| size.checked_add(entry.offset as usize));
+            if entry.offset as usize % mem::align_of::<PackedKeypoint>() != 0
+                || !matches!(end, Some(end) if end <= index_offset)
+            {
+                return Err(format_err!(
+                    "the features of {} are out of bounds",
+                    entry.name
+                ));
+            }
+            ids.insert(entry.name.clone(), id);
+        }
+        Ok(FeatureArchive {
+            map,
+            descriptor_length,
+            entries,
+            ids,
+        })
+    }
+
+    /// The number of images.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether the archive has no images.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The index entries, by image id.
+    pub fn entries(&self) -> &[ArchiveEntry] {
+        &self.entries
+    }
+
+    /// The id of an image.
+    ///
+    /// # Arguments
+    /// * `name` - The image name.
+    pub fn image_id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).cloned()
+    }
+
+    /// The keypoints of an image, without copying.
+    ///
+    /// # Arguments
+    /// * `id` - The image id, panics if out of range.
+    pub fn keypoints(&self, id: usize) -> &[PackedKeypoint] {
+        let entry = &self.entries[id];
+        let bytes = &self.map[entry.offset as usize..];
+        // The entry was checked to be aligned and in bounds when opening
+        unsafe {
+            slice::from_raw_parts(
+                bytes.as_ptr() as *const PackedKeypoint,
+                entry.header.num_features as usize,
+            )
+        }
+    }
+
+    /// The descriptors of an image, without copying.
+    ///
+    /// # Arguments
+    /// * `id` - The image id, panics if out of range.
+    pub fn descriptors(&self, id: usize) -> PackedDescriptors<'_> {
+        let entry = &self.entries[id];
+        let count = entry.header.num_features as usize;
+        let start = entry.offset as usize + count * mem::size_of::<PackedKeypoint>();
+        PackedDescriptors {
+            bytes: &self.map[start..start + count * self.descriptor_length],
+            length: self.descriptor_length,
+        }
+    }
+
+    /// A copy of the features of an image.
+    ///
+    /// # Arguments
+    /// * `id` - The image id, panics if out of range.
+    pub fn features(&self, id: usize) -> Features {
+        Features {
+            keypoints: self
+                .keypoints(id)
+                .iter()
+                .map(PackedKeypoint::keypoint)
+                .collect(),
+            descriptors: self
+                .descriptors(id)
+                .iter()
+                .map(|vector| Descriptor {
+                    vector: vector.to_vec(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Pack feature files into a feature archive.
+///
+/// Images are named after their feature files and numbered in order.
+///
+/// # Arguments
+/// * `images` - The feature files.
+/// * `path` - The archive, overwritten if it exists.
+pub fn pack_feature_files(images: &[PathBuf], path: impl AsRef<Path>) -> Result<(), Error> {
+    let mut writer = FeatureArchiveWriter::create(path)?;
+    for image in images {
+        let (header, features) = deserialize_features_with_header_from_file(image)?;
+        let header = header.unwrap_or_else(|| FeatureFileHeader::from_features(&features));
+        writer.add(&image_name(image), &header, &features)?;
+    }
+    writer.finish()?;
+    info!("Packed {} feature files.", images.len());
+    Ok(())
+}
@@ -0,0 +1,127 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze::types::evolution::Config;
+use akaze_util::parity::*;
+use akaze_util::*;
+use clap::{App, Arg};
+use std::fs::File;
+use std::path::Path;
+
+fn main() {
+    let matches = App::new("Parity check against reference outputs.")
+        .version("0.1")
+        .about(
+            "Extracts features from an image and compares each stage with a
+            reference dump of the original C++ A-KAZE library: the contrast
+            factor, the FED step schedules, the detector responses, the
+            keypoints and the descriptors. Prints the discrepancies of every
+            stage in the dump and exits with an error if any stage differs by
+            more than its tolerance. With --write, writes the outputs of this
+            crate in the reference dump format instead.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("The input image.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("REFERENCE")
+                .help("The directory of the reference dump.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("options")
+                .short("o")
+                .long("options")
+                .value_name("PATH")
+                .help("A JSON file containing the options the reference was made with.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tolerances")
+                .short("t")
+                .long("tolerances")
+                .value_name("PATH")
+                .help("A JSON file containing the tolerances of the stages.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("report")
+                .short("j")
+                .long("report")
+                .value_name("PATH")
+                .help("Writes the stage reports to a JSON file.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("write")
+                .short("w")
+                .long("write")
+                .help("Writes a reference dump instead of checking one."),
+        )
+        .get_matches();
+
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let options: Config = match matches.value_of("options") {
+        Some(options_path) => {
+            serde_json::from_reader(File::open(options_path).expect("failed to open options"))
+                .expect("failed to parse options")
+        }
+        None => Config::default(),
+    };
+    let config: ParityConfig = match matches.value_of("tolerances") {
+        Some(tolerances_path) => {
+            serde_json::from_reader(File::open(tolerances_path).expect("failed to open tolerances"))
+                .expect("failed to parse tolerances")
+        }
+        None => ParityConfig::default(),
+    };
+    let input_path = matches.value_of("INPUT").unwrap();
+    let reference_path = matches.value_of("REFERENCE").unwrap();
+    let (evolutions, keypoints, descriptors) =
+        akaze::extract_features(Path::new(input_path).to_owned(), options);
+    let features = Features {
+        keypoints,
+        descriptors,
+    };
+    if matches.is_present("write") {
+        info!("Writing reference dump to {}", reference_path);
+        write_reference_dump(reference_path, &evolutions, &features, &options)
+            .expect("failed to write reference dump");
+        return;
+    }
+    let reference = ReferenceDump::load(reference_path).expect("failed to read reference dump");
+    let reports = check_parity(&reference, &evolutions, &features, &options, &config);
+    if reports.is_empty() {
+        warn!("The reference dump {} holds no stages.", reference_path);
+    }
+    for report in &reports {
+        println!(
+            "{:20} {} (max difference {:e})",
+            report.stage,
+            if report.passed { "PASS" } else { "FAIL" },
+            report.max_difference
+        );
+        for line in &report.details {
+            println!("    {}", line);
+        }
+    }
+    if let Some(report_path) = matches.value_of("report") {
+        info!("Writing report to {}", report_path);
+        serde_json::to_writer_pretty(
+            File::create(report_path).expect("failed to create report"),
+            &reports,
+        )
+        .expect("failed to write report");
+    }
+    if reports.iter().any(|report| !report.passed) {
+        std::process::exit(1);
+    }
+}
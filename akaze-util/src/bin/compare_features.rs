@@ -0,0 +1,129 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::golden::*;
+use akaze_util::*;
+use clap::{App, Arg};
+use std::fs::File;
+
+fn main() {
+    let matches = App::new("Comparison of feature files.")
+        .version("0.1")
+        .about(
+            "Compares a candidate feature file with a reference feature file,
+            such as a golden file of test-data/golden, and prints a summary of
+            their differences. Keypoints are paired by position, then compared
+            within the tolerances. Exits with an error if the features differ
+            by more than the tolerances.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("REFERENCE")
+                .help("The reference feature file.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("CANDIDATE")
+                .help("The candidate feature file.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("match_radius")
+                .short("r")
+                .long("match_radius")
+                .value_name("PIXELS")
+                .help("The largest distance at which keypoints are paired.")
+                .default_value("1.5"),
+        )
+        .arg(
+            Arg::with_name("position")
+                .short("p")
+                .long("position")
+                .value_name("PIXELS")
+                .help("The largest accepted position difference.")
+                .default_value("0.001"),
+        )
+        .arg(
+            Arg::with_name("size")
+                .short("s")
+                .long("size")
+                .value_name("PIXELS")
+                .help("The largest accepted size difference.")
+                .default_value("0.001"),
+        )
+        .arg(
+            Arg::with_name("angle")
+                .short("a")
+                .long("angle")
+                .value_name("RADIANS")
+                .help("The largest accepted angle difference.")
+                .default_value("0.001"),
+        )
+        .arg(
+            Arg::with_name("response")
+                .long("response")
+                .value_name("FLOAT")
+                .help("The largest accepted relative response difference.")
+                .default_value("0.001"),
+        )
+        .arg(
+            Arg::with_name("descriptor_bits")
+                .short("b")
+                .long("descriptor_bits")
+                .value_name("INT")
+                .help("The largest accepted number of differing descriptor bits.")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("report")
+                .short("j")
+                .long("report")
+                .value_name("PATH")
+                .help("Writes the comparison to a JSON file.")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let parse = |name: &str| -> f32 {
+        matches
+            .value_of(name)
+            .unwrap()
+            .parse()
+            .unwrap_or_else(|_| panic!("failed to parse {}", name))
+    };
+    let config = GoldenConfig {
+        match_radius: parse("match_radius"),
+        position: parse("position"),
+        size: parse("size"),
+        angle: parse("angle"),
+        response: parse("response"),
+        descriptor_bits: matches
+            .value_of("descriptor_bits")
+            .unwrap()
+            .parse()
+            .expect("failed to parse descriptor_bits"),
+    };
+    let reference = deserialize_features_from_file(matches.value_of("REFERENCE").unwrap())
+        .expect("failed to read reference features");
+    let candidate = deserialize_features_from_file(matches.value_of("CANDIDATE").unwrap())
+        .expect("failed to read candidate features");
+    let comparison = compare_features(&reference, &candidate, &config);
+    println!("{}", comparison);
+    if let Some(report_path) = matches.value_of("report") {
+        info!("Writing report to {}", report_path);
+        serde_json::to_writer_pretty(
+            File::create(report_path).expect("failed to create report"),
+            &comparison,
+        )
+        .expect("failed to write report");
+    }
+    if !comparison.passes() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,75 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::scale_space::*;
+use clap::{App, Arg};
+
+fn main() {
+    let matches = App::new("Comparison of scale spaces.")
+        .version("0.1")
+        .about(
+            "Compares two scale space files written by extract_features with
+            --scale_space, such as those of one image made by two versions of
+            the code, and prints the largest and RMS differences of every
+            image of every evolution. Exits with an error if any difference
+            exceeds the tolerance.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("SCALE_SPACE_0")
+                .help("The first scale space file.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("SCALE_SPACE_1")
+                .help("The second scale space file.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("tolerance")
+                .short("t")
+                .long("tolerance")
+                .value_name("FLOAT")
+                .help("The largest accepted absolute difference.")
+                .default_value("0"),
+        )
+        .get_matches();
+
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let tolerance: f64 = matches
+        .value_of("tolerance")
+        .unwrap()
+        .parse()
+        .expect("failed to parse tolerance");
+    let (header_0, evolutions_0) =
+        deserialize_scale_space_from_file(matches.value_of("SCALE_SPACE_0").unwrap())
+            .expect("failed to read first scale space");
+    let (header_1, evolutions_1) =
+        deserialize_scale_space_from_file(matches.value_of("SCALE_SPACE_1").unwrap())
+            .expect("failed to read second scale space");
+    if let Err(error) = header_0.check_same(&header_1) {
+        warn!("The second scale space differs: {}", error);
+    }
+    let differences =
+        compare_scale_spaces(&evolutions_0, &evolutions_1).expect("failed to compare scale spaces");
+    let mut max_abs = 0f64;
+    for difference in &differences {
+        for (name, image) in &difference.images {
+            println!(
+                "evolution {:2} {:8} max {:e} rms {:e}",
+                difference.level, name, image.max_abs, image.rms
+            );
+        }
+        max_abs = max_abs.max(difference.max_abs());
+    }
+    info!("Largest difference: {:e}", max_abs);
+    if max_abs > tolerance {
+        error!("The scale spaces differ by more than {:e}.", tolerance);
+        std::process::exit(1);
+    }
+}
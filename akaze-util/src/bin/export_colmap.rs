@@ -0,0 +1,75 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::colmap::*;
+use clap::{App, Arg};
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Export of matched collections to COLMAP.")
+        .version("0.1")
+        .about(
+            "Writes the features and verified matches of a collection matched
+            with match_collection into a COLMAP database, or into COLMAP's
+            text formats for feature_importer and matches_importer. Feature
+            files need the image size, as written by extract_features.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("SUMMARY")
+                .help("The summary.json written by match_collection.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The COLMAP database, or a directory for the text format.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("extension")
+                .short("e")
+                .long("extension")
+                .value_name("EXTENSION")
+                .help("The extension of the image files COLMAP reads.")
+                .default_value("jpg")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("single_camera")
+                .short("s")
+                .long("single_camera")
+                .help("Shares one camera between all images."),
+        )
+        .arg(
+            Arg::with_name("text")
+                .short("t")
+                .long("text")
+                .help("Writes the text format instead of a database."),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let summary_path = matches.value_of("SUMMARY").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let extension = matches.value_of("extension").unwrap();
+    info!("Exporting {} to {}.", summary_path, output_path);
+    if matches.is_present("text") {
+        export_collection_to_text(summary_path, output_path, extension)
+            .expect("failed to export collection");
+    } else {
+        export_collection_to_database(
+            summary_path,
+            output_path,
+            extension,
+            matches.is_present("single_camera"),
+        )
+        .expect("failed to export collection");
+    }
+    info!("Done, total duration: {:?}", start.elapsed().unwrap());
+}
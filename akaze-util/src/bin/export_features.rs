@@ -0,0 +1,116 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::collection::list_feature_files;
+use akaze_util::lowe::*;
+use akaze_util::openmvg::*;
+use clap::{App, Arg, SubCommand};
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Export of AKAZE features to other SfM tools.")
+        .version("0.1")
+        .about(
+            "Writes AKAZE feature files in the formats of other structure
+            from motion tools. See export_colmap for COLMAP.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .subcommand(
+            SubCommand::with_name("openmvg")
+                .about(
+                    "Writes .feat and .desc files and a matches.putative.txt
+                    for OpenMVG from a collection matched with match_collection.",
+                )
+                .arg(
+                    Arg::with_name("SUMMARY")
+                        .help("The summary.json written by match_collection.")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .help("The OpenMVG matches directory.")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("descriptor_length")
+                        .short("l")
+                        .long("descriptor_length")
+                        .value_name("BYTES")
+                        .help("The descriptor length of the OpenMVG regions type.")
+                        .default_value("64")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lowe")
+                .about(
+                    "Writes feature files in Lowe's ASCII keypoint format, as
+                    .key files for Bundler or .sift files for VisualSFM.",
+                )
+                .arg(
+                    Arg::with_name("INPUT")
+                        .help("A directory of feature files, or a text file listing them.")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .help("The output directory.")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::with_name("extension")
+                        .short("e")
+                        .long("extension")
+                        .value_name("EXTENSION")
+                        .help("The extension of the written files.")
+                        .possible_values(&["key", "sift"])
+                        .default_value("key")
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    match matches.subcommand() {
+        ("openmvg", Some(matches)) => {
+            let summary_path = matches.value_of("SUMMARY").unwrap();
+            let output_path = matches.value_of("OUTPUT").unwrap();
+            info!("Exporting {} to OpenMVG in {}.", summary_path, output_path);
+            export_collection_to_openmvg(
+                summary_path,
+                output_path,
+                matches
+                    .value_of("descriptor_length")
+                    .unwrap()
+                    .parse()
+                    .unwrap(),
+            )
+            .expect("failed to export collection");
+        }
+        ("lowe", Some(matches)) => {
+            let input_path = matches.value_of("INPUT").unwrap();
+            let output_path = matches.value_of("OUTPUT").unwrap();
+            let images = list_feature_files(input_path).expect("failed to list feature files");
+            info!(
+                "Exporting {} feature files to {}.",
+                images.len(),
+                output_path
+            );
+            export_features_to_lowe(&images, output_path, matches.value_of("extension").unwrap())
+                .expect("failed to export features");
+        }
+        _ => {
+            eprintln!("{}", matches.usage());
+            std::process::exit(1);
+        }
+    }
+    info!("Done, total duration: {:?}", start.elapsed().unwrap());
+}
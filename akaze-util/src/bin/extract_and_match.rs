@@ -4,6 +4,8 @@ extern crate log;
 use akaze::match_features;
 use akaze::types::evolution::Config;
 use akaze::types::feature_match;
+use akaze::types::image::ImageFunctions;
+use akaze_util::feature_file::FeatureFileHeader;
 use akaze_util::*;
 use clap::{App, Arg};
 use std::path::Path;
@@ -72,26 +74,36 @@ fn main() {
     let mut matches_path = prefix_string.clone();
     matches_path.push_str("-matches.cbor");
 
-    let (_, keypoints, descriptors) =
+    let (evolutions, keypoints, descriptors) =
         akaze::extract_features(Path::new(input_path_0).to_owned(), options);
     let features_0 = Features {
         keypoints,
         descriptors,
     };
-    serialize_features_to_file(&features_0, extractions_0_path)
+    let image_size = (
+        evolutions[0].Lt.width() as u32,
+        evolutions[0].Lt.height() as u32,
+    );
+    let header = FeatureFileHeader::extracted(&features_0, image_size, &options);
+    serialize_features_with_header_to_file(&features_0, &header, extractions_0_path)
         .expect("failed to write first image features to file");
     info!(
         "Done, extracted {} features from image 0.",
         features_0.keypoints.len()
     );
 
-    let (_, keypoints, descriptors) =
+    let (evolutions, keypoints, descriptors) =
         akaze::extract_features(Path::new(input_path_1).to_owned(), options);
     let features_1 = Features {
         keypoints,
         descriptors,
     };
-    serialize_features_to_file(&features_1, extractions_1_path)
+    let image_size = (
+        evolutions[0].Lt.width() as u32,
+        evolutions[0].Lt.height() as u32,
+    );
+    let header = FeatureFileHeader::extracted(&features_1, image_size, &options);
+    serialize_features_with_header_to_file(&features_1, &header, extractions_1_path)
         .expect("failed to write second image features to file");
     info!(
         "Done, extracted {} features from image 1, proceeding with matching.",
@@ -7,8 +7,12 @@ extern crate env_logger;
 extern crate image;
 extern crate serde;
 extern crate serde_json;
+use image::GenericImageView;
 use akaze::types::evolution::{write_evolutions, Config};
+use akaze::types::image::ImageFunctions;
 use akaze::types::keypoint::{draw_keypoints_to_image};
+use akaze_util::feature_file::FeatureFileHeader;
+use akaze_util::scale_space::*;
 use clap::{App, Arg};
 use std::fs::File;
 use std::io::{Read, Write};
@@ -52,6 +56,14 @@ fn main() {
                 .help("A JSON file containing options.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("scale_space")
+                .short("s")
+                .long("scale_space")
+                .value_name("PATH")
+                .help("A scale space file, read if it exists instead of creating the scale space, written otherwise. A file made from another image size or other options is refused.")
+                .takes_value(true),
+        )
         .get_matches();
 
     let start = SystemTime::now();
@@ -81,10 +93,46 @@ fn main() {
         }
         None => debug!("Using default options."),
     }
-    let (evolutions, keypoints, descriptors) =
-        akaze::extract_features(Path::new(input_path).to_owned(), options);
+    let (evolutions, keypoints, descriptors) = match matches.value_of("scale_space") {
+        Some(scale_space_path) if Path::new(scale_space_path).exists() => {
+            info!("Reading scale space from {}", scale_space_path);
+            let (header, mut evolutions) = deserialize_scale_space_from_file(scale_space_path)
+                .expect("failed to read scale space");
+            // The cache is only valid for the image and options it was made with
+            let image_size = image::open(input_path)
+                .expect("failed to read the input image")
+                .dimensions();
+            ScaleSpaceHeader::new(image_size, &options)
+                .check_same(&header)
+                .expect("the scale space file does not belong to this image and options, remove it");
+            let (keypoints, descriptors) =
+                akaze::extract_features_from_evolutions(&mut evolutions, options);
+            (evolutions, keypoints, descriptors)
+        }
+        scale_space_path => {
+            let (evolutions, keypoints, descriptors) =
+                akaze::extract_features(Path::new(input_path).to_owned(), options);
+            if let Some(scale_space_path) = scale_space_path {
+                info!("Writing scale space to {}", scale_space_path);
+                let image_size = (
+                    evolutions[0].Lt.width() as u32,
+                    evolutions[0].Lt.height() as u32,
+                );
+                let header = ScaleSpaceHeader::new(image_size, &options);
+                serialize_scale_space_to_file(&evolutions, &header, scale_space_path)
+                    .expect("failed to write scale space");
+            }
+            (evolutions, keypoints, descriptors)
+        }
+    };
     let features = Features { keypoints, descriptors };
-    serialize_features_to_file(&features, output_path).expect("failed to write out features");
+    let image_size = (
+        evolutions[0].Lt.width() as u32,
+        evolutions[0].Lt.height() as u32,
+    );
+    let header = FeatureFileHeader::extracted(&features, image_size, &options);
+    serialize_features_with_header_to_file(&features, &header, output_path)
+        .expect("failed to write out features");
     info!("Done, extracted {} features.", features.keypoints.len());
     match matches.value_of("debug_path") {
         Some(val) => {
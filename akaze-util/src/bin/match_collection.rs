@@ -0,0 +1,173 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::collection::*;
+use clap::{App, Arg};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Feature matching for image collections.")
+        .version("0.1")
+        .about(
+            "Matches pairs of AKAZE feature files from a collection: all pairs,
+            a sliding window of neighbours, or the top candidates from a
+            retrieval pairs file. Writes one match file per pair and a
+            summary.json to the output directory.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("A directory of feature files, or a file listing one feature file per line.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The output directory.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("mode")
+                .short("m")
+                .long("mode")
+                .value_name("MODE")
+                .help("Pair selection: exhaustive, sequential or retrieval.")
+                .possible_values(&["exhaustive", "sequential", "retrieval"])
+                .default_value("exhaustive"),
+        )
+        .arg(
+            Arg::with_name("window")
+                .short("w")
+                .long("window")
+                .value_name("INT")
+                .help("Number of following images to match in sequential mode.")
+                .default_value("5"),
+        )
+        .arg(
+            Arg::with_name("pairs")
+                .short("p")
+                .long("pairs")
+                .value_name("PATH")
+                .help("The pairs file for retrieval mode.")
+                .required_if("mode", "retrieval")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("top_k")
+                .short("k")
+                .long("top_k")
+                .value_name("INT")
+                .help("Number of candidates per query to match in retrieval mode.")
+                .default_value("20"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .short("f")
+                .long("format")
+                .value_name("EXTENSION")
+                .help("The match file format.")
+                .possible_values(&["bin", "json", "npy", "akzc"])
+                .default_value("bin"),
+        )
+        .arg(
+            Arg::with_name("threads")
+                .short("j")
+                .long("threads")
+                .value_name("INT")
+                .help("Number of pairs to match in parallel. Defaults to the CPU count.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lowes_ratio")
+                .short("r")
+                .long("lowes_ratio")
+                .value_name("FLOAT")
+                .help("The largest ratio of the best to the second best descriptor distance.")
+                .default_value("0.86"),
+        )
+        .arg(
+            Arg::with_name("ransac_trials")
+                .short("t")
+                .long("ransac_trials")
+                .value_name("INT")
+                .help("The maximum number of RANSAC trials per pair.")
+                .default_value("1000"),
+        )
+        .arg(
+            Arg::with_name("epsilon")
+                .short("e")
+                .long("epsilon")
+                .value_name("FLOAT")
+                .help("The largest epipolar error of an inlier.")
+                .default_value("3.0"),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let selection = match matches.value_of("mode").unwrap() {
+        "sequential" => PairSelection::Sequential {
+            window: matches.value_of("window").unwrap().parse().unwrap(),
+        },
+        "retrieval" => PairSelection::Retrieval {
+            pairs_path: PathBuf::from(matches.value_of("pairs").unwrap()),
+            top_k: matches.value_of("top_k").unwrap().parse().unwrap(),
+        },
+        _ => PairSelection::Exhaustive,
+    };
+    let num_threads = matches
+        .value_of("threads")
+        .map(|threads| threads.parse().unwrap())
+        .unwrap_or_else(num_cpus::get);
+    let config = MatchingConfig {
+        lowes_ratio: matches
+            .value_of("lowes_ratio")
+            .unwrap()
+            .parse()
+            .expect("failed to parse lowes_ratio"),
+        ransac_trials: matches
+            .value_of("ransac_trials")
+            .unwrap()
+            .parse()
+            .expect("failed to parse ransac_trials"),
+        ransac_epsilon_inliers: matches
+            .value_of("epsilon")
+            .unwrap()
+            .parse()
+            .expect("failed to parse epsilon"),
+    };
+    let images = list_feature_files(input_path).expect("failed to list feature files");
+    info!(
+        "Input: {} ({} feature files), output directory: {}, selection: {:?}.",
+        input_path,
+        images.len(),
+        output_path,
+        selection
+    );
+    let summary = match_collection(
+        &images,
+        &selection,
+        &config,
+        output_path,
+        matches.value_of("format").unwrap(),
+        num_threads,
+    )
+    .expect("failed to match collection");
+    info!(
+        "Done, matched {} pairs with {} verified matches, total duration: {:?}",
+        summary.pairs.len(),
+        summary
+            .pairs
+            .iter()
+            .map(|pair| pair.verified_matches)
+            .sum::<usize>(),
+        start.elapsed().unwrap()
+    );
+}
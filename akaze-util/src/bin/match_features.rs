@@ -6,7 +6,8 @@ extern crate env_logger;
 extern crate image;
 extern crate serde;
 extern crate serde_json;
-use akaze::match_features;
+use akaze::match_features_with_config;
+use akaze::ops::ransac::{RansacConfig, Sampling};
 use akaze_util::*;
 use clap::{App, Arg};
 use std::time::SystemTime;
@@ -43,38 +44,67 @@ fn main() {
                 .short("t")
                 .long("threshold")
                 .value_name("FLOAT")
-                .help("The distance threshold for the matcher.")
+                .help("The Lowe's ratio threshold of the matcher, 0.86 by default.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("prosac")
+                .long("prosac")
+                .help("Samples the matches with the lowest descriptor distance first (PROSAC)."),
+        )
+        .arg(
+            Arg::with_name("local_optimization")
+                .long("local_optimization")
+                .help("Re-estimates each new best model from its inliers (LO-RANSAC)."),
+        )
         .get_matches();
 
     let start = SystemTime::now();
     let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
     env_logger::Builder::from_env(env).init();
-    let input_extractions_0_path = matches.value_of("input_extractions_0").unwrap();
-    let input_extractions_1_path = matches.value_of("input_extractions_1").unwrap();
+    let input_extractions_0_path = matches.value_of("INPUT_EXTRACTIONS_0").unwrap();
+    let input_extractions_1_path = matches.value_of("INPUT_EXTRACTIONS_1").unwrap();
     let output_path = matches.value_of("OUTPUT").unwrap();
     let threshold: f64 = matches
         .value_of("threshold")
-        .unwrap_or("10")
+        .unwrap_or("0.86")
         .parse()
         .unwrap();
     info!(
         "Input extractions: {}/{}, output matches: {}, threshold: {}.",
         input_extractions_0_path, input_extractions_1_path, output_path, threshold
     );
-    let extractions_0 = deserialize_features_from_file(input_extractions_0_path)
-        .expect("failed to read features from first file");
-    let extractions_1 = deserialize_features_from_file(input_extractions_1_path)
-        .expect("failed to read features from second file");
-    let matches = match_features(
+    let (header_0, extractions_0) =
+        deserialize_features_with_header_from_file(input_extractions_0_path)
+            .expect("failed to read features from first file");
+    let (header_1, extractions_1) =
+        deserialize_features_with_header_from_file(input_extractions_1_path)
+            .expect("failed to read features from second file");
+    if let (Some(header_0), Some(header_1)) = (header_0, header_1) {
+        header_0
+            .check_compatible(&header_1)
+            .expect("the feature files cannot be matched");
+    }
+    let ransac_config = RansacConfig {
+        max_trials: 1000,
+        epsilon_inlier: 3.0,
+        sampling: if matches.is_present("prosac") {
+            Sampling::Prosac
+        } else {
+            Sampling::Uniform
+        },
+        local_optimization: matches.is_present("local_optimization"),
+        num_threads: num_cpus::get(),
+        ..RansacConfig::default()
+    };
+    let matches = match_features_with_config(
         &extractions_0.keypoints,
         &extractions_0.descriptors,
         &extractions_1.keypoints,
         &extractions_1.descriptors,
-        0.86,
-        1000,
-        3.0,
+        threshold,
+        &ransac_config,
+        None,
     );
     serialize_matches_to_file(&matches, output_path).expect("unable to write matches to file");
     debug!(
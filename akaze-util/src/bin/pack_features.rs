@@ -0,0 +1,47 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::archive::*;
+use akaze_util::collection::*;
+use clap::{App, Arg};
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Packing of feature files into a feature archive.")
+        .version("0.1")
+        .about(
+            "Packs the feature files of a collection into a single feature
+            archive, which is memory mapped for random access to the features
+            of any image by its id or name.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("A directory of feature files, or a file listing one feature file per line.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The feature archive.")
+                .required(true)
+                .index(2),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let images = list_feature_files(input_path).expect("failed to list feature files");
+    info!(
+        "Packing {} feature files into {}.",
+        images.len(),
+        output_path
+    );
+    pack_feature_files(&images, output_path).expect("failed to pack feature files");
+    info!("Done, total duration: {:?}", start.elapsed().unwrap());
+}
@@ -0,0 +1,87 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze::types::evolution::Config;
+use akaze_util::rectification::*;
+use clap::{App, Arg};
+use std::path::Path;
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Uncalibrated stereo rectification with AKAZE features.")
+        .version("0.1")
+        .about(
+            "Rectifies a stereo pair so that matching points lie on the same
+            row: matches AKAZE features, estimates a fundamental matrix with
+            RANSAC, and resamples both images with Hartley's rectifying
+            homographies. The output can be handed to a dense stereo matcher.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("INPUT_0")
+                .help("The first input image.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT_1")
+                .help("The second input image.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("OUTPUT_0")
+                .help("The first rectified image.")
+                .required(true)
+                .index(3),
+        )
+        .arg(
+            Arg::with_name("OUTPUT_1")
+                .help("The second rectified image.")
+                .required(true)
+                .index(4),
+        )
+        .arg(
+            Arg::with_name("side_by_side")
+                .short("s")
+                .long("side_by_side")
+                .value_name("IMAGE_FILE_PATH")
+                .help("Sets a path to write both rectified images with horizontal lines to.")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let input_path_0 = matches.value_of("INPUT_0").unwrap();
+    let input_path_1 = matches.value_of("INPUT_1").unwrap();
+    info!("Rectifying {} and {}.", input_path_0, input_path_1);
+    let pair = rectify_images(
+        Path::new(input_path_0).to_owned(),
+        Path::new(input_path_1).to_owned(),
+        Config::default(),
+        &RectificationConfig::default(),
+    )
+    .expect("failed to rectify images");
+    info!(
+        "Rectifying homographies: {} {}",
+        pair.homographies.0, pair.homographies.1
+    );
+    pair.images
+        .0
+        .save(matches.value_of("OUTPUT_0").unwrap())
+        .expect("failed to write rectified image");
+    pair.images
+        .1
+        .save(matches.value_of("OUTPUT_1").unwrap())
+        .expect("failed to write rectified image");
+    if let Some(path) = matches.value_of("side_by_side") {
+        side_by_side(&pair, 32)
+            .save(path)
+            .expect("failed to write side by side image");
+    }
+    info!("Done, total duration: {:?}", start.elapsed().unwrap());
+}
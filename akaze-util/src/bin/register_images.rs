@@ -0,0 +1,116 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze::types::evolution::Config;
+use akaze_util::registration::*;
+use clap::{App, Arg};
+use std::path::Path;
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Image registration with AKAZE features.")
+        .version("0.1")
+        .about(
+            "Aligns image B onto image A: matches AKAZE features, verifies the
+            matches with a homography or affine transform, and resamples B in
+            A's frame. Optionally writes a checkerboard or difference overlay
+            of A and the aligned B for visual checking.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("IMAGE_A")
+                .help("The reference image.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("IMAGE_B")
+                .help("The image to align onto the reference.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The aligned image B. The format is chosen by extension, e.g. png or jpg.")
+                .required(true)
+                .index(3),
+        )
+        .arg(
+            Arg::with_name("model")
+                .short("m")
+                .long("model")
+                .value_name("MODEL")
+                .help("The transform relating the images.")
+                .possible_values(&["homography", "affine"])
+                .default_value("homography"),
+        )
+        .arg(
+            Arg::with_name("checkerboard")
+                .short("c")
+                .long("checkerboard")
+                .value_name("IMAGE_FILE_PATH")
+                .help("Sets a path to write the checkerboard overlay to.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("square_size")
+                .short("s")
+                .long("square_size")
+                .value_name("INT")
+                .help("The size of the checkerboard squares in pixels.")
+                .default_value("64"),
+        )
+        .arg(
+            Arg::with_name("difference")
+                .short("d")
+                .long("difference")
+                .value_name("IMAGE_FILE_PATH")
+                .help("Sets a path to write the difference overlay to.")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let path_a = matches.value_of("IMAGE_A").unwrap();
+    let path_b = matches.value_of("IMAGE_B").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let config = RegistrationConfig {
+        model: match matches.value_of("model").unwrap() {
+            "affine" => RegistrationModel::Affine,
+            _ => RegistrationModel::Homography,
+        },
+        ..RegistrationConfig::default()
+    };
+    info!(
+        "Aligning {} onto {}, output is {}, model is {:?}.",
+        path_b, path_a, output_path, config.model
+    );
+    let (registration, reference) = register_images(
+        Path::new(path_a).to_owned(),
+        Path::new(path_b).to_owned(),
+        Config::default(),
+        &config,
+    )
+    .expect("failed to register images");
+    info!("Transform from B to A: {}", registration.transform);
+    registration
+        .warped
+        .save(output_path)
+        .expect("failed to write aligned image");
+    if let Some(path) = matches.value_of("checkerboard") {
+        let square_size = matches.value_of("square_size").unwrap().parse().unwrap();
+        checkerboard_overlay(&reference, &registration, square_size)
+            .save(path)
+            .expect("failed to write checkerboard overlay");
+    }
+    if let Some(path) = matches.value_of("difference") {
+        difference_overlay(&reference, &registration)
+            .save(path)
+            .expect("failed to write difference overlay");
+    }
+    info!("Done, total duration: {:?}", start.elapsed().unwrap());
+}
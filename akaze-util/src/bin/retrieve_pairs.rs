@@ -0,0 +1,73 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze_util::collection::{list_feature_files, retrieve_pairs, write_pairs_file};
+use akaze_util::*;
+use clap::{App, Arg};
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Bag-of-words image retrieval for AKAZE features.")
+        .version("0.1")
+        .about(
+            "Finds the most similar images of each image in a collection with
+            a vocabulary tree, and writes them as a pairs file for
+            match_collection in retrieval mode.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("VOCABULARY")
+                .help("The vocabulary, as written by train_vocabulary.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("A directory of feature files, or a file listing one feature file per line.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The output pairs file.")
+                .required(true)
+                .index(3),
+        )
+        .arg(
+            Arg::with_name("top_k")
+                .short("k")
+                .long("top_k")
+                .value_name("INT")
+                .help("Number of candidates per image.")
+                .default_value("20"),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let vocabulary_path = matches.value_of("VOCABULARY").unwrap();
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let top_k: usize = matches.value_of("top_k").unwrap().parse().unwrap();
+    let vocabulary =
+        deserialize_vocabulary_from_file(vocabulary_path).expect("failed to read vocabulary");
+    let images = list_feature_files(input_path).expect("failed to list feature files");
+    info!(
+        "Vocabulary: {} ({} words), input: {} ({} feature files), output pairs: {}.",
+        vocabulary_path,
+        vocabulary.word_count(),
+        input_path,
+        images.len(),
+        output_path
+    );
+    let pairs = retrieve_pairs(&images, &vocabulary, top_k).expect("failed to retrieve pairs");
+    write_pairs_file(&pairs, &images, output_path).expect("unable to write pairs file");
+    info!(
+        "Done, wrote {} pairs, total duration: {:?}",
+        pairs.len(),
+        start.elapsed().unwrap()
+    );
+}
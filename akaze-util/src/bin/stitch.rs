@@ -0,0 +1,88 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze::types::evolution::Config;
+use akaze_util::stitching::*;
+use clap::{App, Arg};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Panorama stitching with AKAZE features.")
+        .version("0.1")
+        .about(
+            "Stitches a sequence of overlapping images into a panorama. Each
+            image is matched to the next one with a homography, the
+            homographies are chained to a reference image, and the images
+            are warped to a common canvas and blended with feathering.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The output panorama. The format is chosen by extension, e.g. png or jpg.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("The input images, in order, neighbours overlapping.")
+                .required(true)
+                .multiple(true)
+                .min_values(2)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("reference")
+                .short("r")
+                .long("reference")
+                .value_name("INT")
+                .help("The index of the reference image. Defaults to the middle image.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("lowes_ratio")
+                .short("l")
+                .long("lowes_ratio")
+                .value_name("FLOAT")
+                .help("The ratio between the best and second-best match distance.")
+                .default_value("0.8"),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let input_paths: Vec<PathBuf> = matches
+        .values_of("INPUT")
+        .unwrap()
+        .map(PathBuf::from)
+        .collect();
+    let config = StitchingConfig {
+        reference: matches
+            .value_of("reference")
+            .map(|reference| reference.parse().unwrap()),
+        lowes_ratio: matches.value_of("lowes_ratio").unwrap().parse().unwrap(),
+        ..StitchingConfig::default()
+    };
+    info!(
+        "Stitching {} images to {}, config: {:?}.",
+        input_paths.len(),
+        output_path,
+        config
+    );
+    let panorama =
+        stitch(&input_paths, Config::default(), &config).expect("failed to stitch images");
+    panorama
+        .image
+        .save(output_path)
+        .expect("failed to write panorama");
+    info!(
+        "Done, wrote a {} x {} panorama, total duration: {:?}",
+        panorama.image.width(),
+        panorama.image.height(),
+        start.elapsed().unwrap()
+    );
+}
@@ -0,0 +1,175 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+extern crate serde;
+extern crate serde_json;
+use akaze::ops::frame_tracking::{FrameTracker, FrameTrackerConfig};
+use akaze::types::evolution::Config;
+use clap::{App, Arg};
+use failure::{format_err, Error};
+use nalgebra::Matrix3;
+use serde::Serialize;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// A track in one frame of the output.
+#[derive(Serialize)]
+struct TrackPoint {
+    id: usize,
+    point: (f32, f32),
+    age: usize,
+}
+
+/// The tracks of one frame of the output.
+#[derive(Serialize)]
+struct Frame {
+    image: PathBuf,
+    tracks: Vec<TrackPoint>,
+}
+
+/// Read homographies, one per line as 9 numbers in row-major order.
+fn read_priors(path: &str) -> Result<Vec<Matrix3<f32>>, Error> {
+    fs::read_to_string(path)?
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let values = line
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<f32>, _>>()?;
+            if values.len() != 9 {
+                return Err(format_err!(
+                    "expected 9 numbers per homography, got {}",
+                    values.len()
+                ));
+            }
+            Ok(Matrix3::from_row_slice(&values))
+        })
+        .collect()
+}
+
+fn main() {
+    let matches = App::new("Feature tracking across video frames.")
+        .version("0.1")
+        .about(
+            "Extracts AKAZE features from each frame of a video, given as a
+            directory of images in file name order, and tracks them from
+            frame to frame with persistent ids. Writes the tracks of all
+            frames to a JSON file.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("A directory of frames, e.g. frame_0001.png, frame_0002.png, ...")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The output JSON file.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("radius")
+                .short("r")
+                .long("radius")
+                .value_name("FLOAT")
+                .help("The search radius in pixels.")
+                .default_value("20"),
+        )
+        .arg(
+            Arg::with_name("cell_size")
+                .short("c")
+                .long("cell_size")
+                .value_name("FLOAT")
+                .help("The cell size in pixels of the mask for starting new tracks.")
+                .default_value("40"),
+        )
+        .arg(
+            Arg::with_name("prior")
+                .short("p")
+                .long("prior")
+                .value_name("FILE")
+                .help(
+                    "A text file of motion priors: line i holds the homography mapping frame i
+                    to frame i + 1, as 9 numbers in row-major order. Without it, features are
+                    expected near their previous position.",
+                )
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let config = FrameTrackerConfig {
+        search_radius: matches.value_of("radius").unwrap().parse().unwrap(),
+        cell_size: matches.value_of("cell_size").unwrap().parse().unwrap(),
+        ..FrameTrackerConfig::default()
+    };
+    let mut images: Vec<PathBuf> = fs::read_dir(input_path)
+        .expect("failed to read input directory")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            match path
+                .extension()
+                .and_then(OsStr::to_str)
+                .map(str::to_lowercase)
+            {
+                Some(extension) => ["jpg", "jpeg", "png", "webp"].contains(&extension.as_str()),
+                None => false,
+            }
+        })
+        .collect();
+    images.sort();
+    info!(
+        "Input: {} ({} frames), output tracks: {}, options: {:?}.",
+        input_path,
+        images.len(),
+        output_path,
+        config
+    );
+    let priors = matches
+        .value_of("prior")
+        .map(|path| read_priors(path).expect("failed to read motion priors"))
+        .unwrap_or_default();
+    let mut tracker = FrameTracker::new(config);
+    let mut frames = vec![];
+    for (i, image) in images.into_iter().enumerate() {
+        // The prior mapping the previous frame to this one
+        let prior = if i == 0 { None } else { priors.get(i - 1) };
+        // Keypoints are only detected where they continue or start tracks
+        let mask = tracker.detection_mask(prior);
+        let (_evolutions, keypoints, descriptors) =
+            akaze::extract_features_with_mask(image.clone(), Config::default(), &|point| {
+                mask.contains(point)
+            });
+        let tracks = tracker.track(&keypoints, &descriptors, prior);
+        debug!("Frame {:?}: {} tracks.", image, tracks.len());
+        frames.push(Frame {
+            image,
+            tracks: tracks
+                .iter()
+                .map(|track| TrackPoint {
+                    id: track.id,
+                    point: track.keypoint.point,
+                    age: track.age,
+                })
+                .collect(),
+        });
+    }
+    let file = File::create(output_path).expect("unable to create output file");
+    serde_json::to_writer(file, &frames).expect("unable to write tracks");
+    info!(
+        "Done, tracked {} frames, total duration: {:?}",
+        frames.len(),
+        start.elapsed().unwrap()
+    );
+}
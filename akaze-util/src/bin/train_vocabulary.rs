@@ -0,0 +1,89 @@
+extern crate akaze;
+#[macro_use]
+extern crate log;
+extern crate clap;
+extern crate env_logger;
+use akaze::types::vocabulary::{Vocabulary, VocabularyConfig};
+use akaze_util::collection::list_feature_files;
+use akaze_util::*;
+use clap::{App, Arg};
+use random::Xorshift128Plus;
+use std::time::SystemTime;
+
+fn main() {
+    let matches = App::new("Bag-of-words vocabulary training for AKAZE features.")
+        .version("0.1")
+        .about(
+            "Trains a vocabulary tree of binary words from a collection of
+            AKAZE feature files by hierarchical k-majority clustering.
+            Set AKAZE_LOG to debug for more verbose output.",
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("A directory of feature files, or a file listing one feature file per line.")
+                .required(true)
+                .index(1),
+        )
+        .arg(
+            Arg::with_name("OUTPUT")
+                .help("The output vocabulary. Extension can be JSON or bin.")
+                .required(true)
+                .index(2),
+        )
+        .arg(
+            Arg::with_name("branching")
+                .short("b")
+                .long("branching")
+                .value_name("INT")
+                .help("Number of children of each node.")
+                .default_value("10"),
+        )
+        .arg(
+            Arg::with_name("depth")
+                .short("d")
+                .long("depth")
+                .value_name("INT")
+                .help("Number of levels of the tree.")
+                .default_value("5"),
+        )
+        .get_matches();
+
+    let start = SystemTime::now();
+    let env = env_logger::Env::default().filter_or("AKAZE_LOG", "info");
+    env_logger::Builder::from_env(env).init();
+    let input_path = matches.value_of("INPUT").unwrap();
+    let output_path = matches.value_of("OUTPUT").unwrap();
+    let config = VocabularyConfig {
+        branching: matches.value_of("branching").unwrap().parse().unwrap(),
+        depth: matches.value_of("depth").unwrap().parse().unwrap(),
+        ..VocabularyConfig::default()
+    };
+    let images = list_feature_files(input_path).expect("failed to list feature files");
+    info!(
+        "Input: {} ({} feature files), output vocabulary: {}, options: {:?}.",
+        input_path,
+        images.len(),
+        output_path,
+        config
+    );
+    let descriptors: Vec<_> = images
+        .iter()
+        .map(|image| {
+            deserialize_features_from_file(image)
+                .expect("failed to read features")
+                .descriptors
+        })
+        .collect();
+    let vocabulary = Vocabulary::train(
+        &descriptors,
+        &config,
+        &mut Xorshift128Plus::new(config.seed),
+    );
+    serialize_vocabulary_to_file(&vocabulary, output_path)
+        .expect("unable to write vocabulary to file");
+    info!(
+        "Done, trained {} words, total duration: {:?}",
+        vocabulary.word_count(),
+        start.elapsed().unwrap()
+    );
+}
@@ -0,0 +1,420 @@
+//! Matching of image collections: every pair, a sliding window of
+//! neighbours, or candidate pairs from an image retrieval step.
+
+use crate::{
+    deserialize_features_from_file, deserialize_features_with_header_from_file,
+    serialize_matches_to_file, Features,
+};
+use akaze::ops::estimate_fundamental_matrix::{EpipolarError, FundamentalMatrixEstimator};
+use akaze::ops::feature_matching::descriptor_match;
+use akaze::ops::ransac::{ransac, RansacConfig};
+use akaze::types::feature_match::Match;
+use akaze::types::inverted_index::InvertedIndex;
+use akaze::types::vocabulary::Vocabulary;
+use failure::{format_err, Error};
+use log::*;
+use nalgebra::Matrix3;
+use random::Xorshift128Plus;
+use scoped_threadpool::Pool;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Which pairs of a collection to match.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PairSelection {
+    /// All pairs.
+    Exhaustive,
+    /// Each image with the next `window` images, e.g. for video.
+    Sequential { window: usize },
+    /// The first `top_k` candidates of each query in a pairs file.
+    Retrieval { pairs_path: PathBuf, top_k: usize },
+}
+
+/// Options of the pairwise matcher.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MatchingConfig {
+    /// The ratio between the best and second-best match distance
+    pub lowes_ratio: f64,
+    /// The maximum number of RANSAC trials
+    pub ransac_trials: usize,
+    /// The maximum error to accept an inlier
+    pub ransac_epsilon_inliers: f32,
+}
+
+impl Default for MatchingConfig {
+    fn default() -> MatchingConfig {
+        MatchingConfig {
+            lowes_ratio: 0.86,
+            ransac_trials: 1000,
+            ransac_epsilon_inliers: 3.0,
+        }
+    }
+}
+
+/// The result of matching one pair, as recorded in the summary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairSummary {
+    /// Index of the first image in `CollectionSummary::images`.
+    pub image_0: usize,
+    /// Index of the second image in `CollectionSummary::images`.
+    pub image_1: usize,
+    /// The match file, relative to the output directory.
+    pub matches_file: String,
+    /// Number of putative matches before geometric verification.
+    pub putative_matches: usize,
+    /// Number of verified matches written to the match file.
+    pub verified_matches: usize,
+    /// The fundamental matrix of the verified matches, row major, with
+    /// p1^T F p0 = 0. None if the pair had too few matches to verify them.
+    #[serde(default)]
+    pub fundamental_matrix: Option<[[f32; 3]; 3]>,
+}
+
+/// Summary of a collection matching run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollectionSummary {
+    /// The feature files, in image index order.
+    pub images: Vec<PathBuf>,
+    /// How pairs were selected.
+    pub selection: PairSelection,
+    /// The matcher options.
+    pub config: MatchingConfig,
+    /// One entry per matched pair.
+    pub pairs: Vec<PairSummary>,
+}
+
+/// The summary written by `match_collection`, which is not a feature file.
+const SUMMARY_FILE: &str = "summary.json";
+
+fn has_feature_extension(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(OsStr::to_str),
+        Some("bin") | Some("json") | Some("npz") | Some("akzc") | Some("akzq")
+    )
+}
+
+/// Fails if two feature files have the same image name, as their match
+/// files would overwrite each other.
+fn check_unique_names(files: &[PathBuf]) -> Result<(), Error> {
+    let mut names = HashMap::new();
+    for file in files {
+        if let Some(other) = names.insert(image_name(file), file) {
+            return Err(format_err!(
+                "the feature files {:?} and {:?} have the same image name {}",
+                other,
+                file,
+                image_name(file)
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// List the feature files of a collection.
+///
+/// # Arguments
+/// * `path` - Either a directory, in which case all `.bin`, `.json`,
+///   `.npz`, `.akzc` and `.akzq` files in it are used except a
+///   `summary.json`, or a text file listing one feature file per line.
+/// # Return value
+/// The feature files, sorted by path for directories, in file order for
+/// lists. Files with the same stem are rejected.
+pub fn list_feature_files(path: impl AsRef<Path>) -> Result<Vec<PathBuf>, Error> {
+    let path = path.as_ref();
+    if path.is_dir() {
+        let mut files = vec![];
+        for entry in fs::read_dir(path)? {
+            let file = entry?.path();
+            if file.is_file()
+                && has_feature_extension(&file)
+                && file.file_name() != Some(OsStr::new(SUMMARY_FILE))
+            {
+                files.push(file);
+            }
+        }
+        files.sort();
+        check_unique_names(&files)?;
+        Ok(files)
+    } else {
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut files = vec![];
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                files.push(base.join(line));
+            }
+        }
+        check_unique_names(&files)?;
+        Ok(files)
+    }
+}
+
+/// The name used to refer to an image in pairs files and match file names.
+pub fn image_name(path: &Path) -> String {
+    path.file_stem()
+        .and_then(OsStr::to_str)
+        .unwrap_or_default()
+        .to_owned()
+}
+
+/// All pairs (i, j) with i < j.
+pub fn exhaustive_pairs(count: usize) -> Vec<(usize, usize)> {
+    (0..count)
+        .flat_map(|i| (i + 1..count).map(move |j| (i, j)))
+        .collect()
+}
+
+/// Pairs of each image with the next `window` images.
+pub fn sequential_pairs(count: usize, window: usize) -> Vec<(usize, usize)> {
+    (0..count)
+        .flat_map(|i| (i + 1..usize::min(i + 1 + window, count)).map(move |j| (i, j)))
+        .collect()
+}
+
+/// Read candidate pairs from a pairs file.
+///
+/// Each line holds a query image name and a candidate image name, and
+/// optionally a score, separated by whitespace. Images are named by the
+/// file stem of their feature file. The candidates of each query are
+/// expected in rank order, best first.
+///
+/// # Arguments
+/// * `path` - The pairs file.
+/// * `images` - The feature files of the collection.
+/// * `top_k` - The number of candidates to keep per query.
+/// # Return value
+/// The unique pairs (i, j) with i < j, sorted.
+pub fn read_pairs_file(
+    path: impl AsRef<Path>,
+    images: &[PathBuf],
+    top_k: usize,
+) -> Result<Vec<(usize, usize)>, Error> {
+    let names: Vec<String> = images.iter().map(|image| image_name(image)).collect();
+    let index_of = |name: &str| {
+        names
+            .iter()
+            .position(|n| n == name)
+            .ok_or_else(|| format_err!("image {} in pairs file is not in the collection", name))
+    };
+    let mut candidates_per_query = vec![0; images.len()];
+    let mut pairs = BTreeSet::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let mut fields = line.split_whitespace();
+        let (query, candidate) = match (fields.next(), fields.next()) {
+            (Some(query), Some(candidate)) => (index_of(query)?, index_of(candidate)?),
+            (None, _) => continue,
+            _ => return Err(format_err!("malformed pairs file line: {}", line)),
+        };
+        if query == candidate || candidates_per_query[query] >= top_k {
+            continue;
+        }
+        candidates_per_query[query] += 1;
+        pairs.insert((usize::min(query, candidate), usize::max(query, candidate)));
+    }
+    Ok(pairs.into_iter().collect())
+}
+
+/// Find the most similar images of each image of a collection.
+///
+/// # Arguments
+/// * `images` - The feature files of the collection.
+/// * `vocabulary` - The vocabulary used to compute bag-of-words vectors.
+/// * `top_k` - The number of candidates per query.
+/// # Return value
+/// Triplets of query, candidate and score, with the candidates of each
+/// query best first.
+pub fn retrieve_pairs(
+    images: &[PathBuf],
+    vocabulary: &Vocabulary,
+    top_k: usize,
+) -> Result<Vec<(usize, usize, f64)>, Error> {
+    let mut index = InvertedIndex::new(vocabulary.word_count());
+    let mut bows = vec![];
+    for image in images {
+        let features = deserialize_features_from_file(image)?;
+        let bow = vocabulary.transform(&features.descriptors);
+        index.add(&bow);
+        bows.push(bow);
+    }
+    let mut pairs = vec![];
+    for (query, bow) in bows.iter().enumerate() {
+        // The query itself is always the best result
+        for (candidate, score) in index.query(bow, top_k + 1) {
+            if candidate != query {
+                pairs.push((query, candidate, score));
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+/// Write candidate pairs in the format read by `read_pairs_file`.
+pub fn write_pairs_file(
+    pairs: &[(usize, usize, f64)],
+    images: &[PathBuf],
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let mut file = BufWriter::new(File::create(path)?);
+    for &(query, candidate, score) in pairs {
+        writeln!(
+            file,
+            "{} {} {}",
+            image_name(&images[query]),
+            image_name(&images[candidate]),
+            score
+        )?;
+    }
+    Ok(())
+}
+
+/// Match descriptors and verify the matches with a fundamental matrix.
+///
+/// This is `akaze::match_features` with single-threaded RANSAC, since
+/// the collection matcher already runs pairs in parallel.
+///
+/// # Return value
+/// The number of putative matches, the verified matches and their
+/// fundamental matrix. With fewer than 8 putative matches these are
+/// returned unverified, without a fundamental matrix.
+pub fn match_pair(
+    features_0: &Features,
+    features_1: &Features,
+    config: &MatchingConfig,
+) -> (usize, Vec<Match>, Option<Matrix3<f32>>) {
+    let putative = descriptor_match(
+        &features_0.descriptors,
+        &features_1.descriptors,
+        10000,
+        config.lowes_ratio,
+    );
+    if putative.len() < 8 {
+        return (putative.len(), putative, None);
+    }
+    let ransac_config = RansacConfig {
+        max_trials: config.ransac_trials,
+        epsilon_inlier: config.ransac_epsilon_inliers,
+        ..RansacConfig::default()
+    };
+    let mut source = Xorshift128Plus::new(ransac_config.seed);
+    let result = ransac(
+        &FundamentalMatrixEstimator {
+            epsilon_model: 0.05,
+            error: EpipolarError::Algebraic,
+        },
+        &features_0.keypoints,
+        &features_1.keypoints,
+        &putative,
+        &ransac_config,
+        &mut source,
+    );
+    match result {
+        Some(result) => (putative.len(), result.inliers, Some(result.model)),
+        None => (putative.len(), vec![], None),
+    }
+}
+
+/// Match the selected pairs of a collection.
+///
+/// Feature files are loaded once, then pairs are matched in parallel.
+/// Each pair's matches are written to `<output>/<name_0>_<name_1>.<extension>`
+/// and a summary is written to `<output>/summary.json`.
+///
+/// # Arguments
+/// * `images` - The feature files of the collection.
+/// * `selection` - Which pairs to match.
+/// * `config` - The matcher options.
+/// * `output` - The output directory, created if missing.
+/// * `extension` - The match file format, `json`, `npy`, `akzc` or `bin`.
+/// * `num_threads` - The number of pairs matched at once.
+/// # Return value
+/// The summary.
+pub fn match_collection(
+    images: &[PathBuf],
+    selection: &PairSelection,
+    config: &MatchingConfig,
+    output: impl AsRef<Path>,
+    extension: &str,
+    num_threads: usize,
+) -> Result<CollectionSummary, Error> {
+    let output = output.as_ref();
+    fs::create_dir_all(output)?;
+    let pairs = match selection {
+        PairSelection::Exhaustive => exhaustive_pairs(images.len()),
+        PairSelection::Sequential { window } => sequential_pairs(images.len(), *window),
+        PairSelection::Retrieval { pairs_path, top_k } => {
+            read_pairs_file(pairs_path, images, *top_k)?
+        }
+    };
+    info!("Matching {} pairs of {} images.", pairs.len(), images.len());
+    let mut features = vec![];
+    let mut first_header = None;
+    for image in images {
+        let (header, image_features) = deserialize_features_with_header_from_file(image)?;
+        // Features made with different options must not be mixed
+        match (&first_header, header) {
+            (None, Some(header)) => first_header = Some((image, header)),
+            (Some((first_image, first)), Some(header)) => first
+                .check_compatible(&header)
+                .map_err(|error| format_err!("{:?} and {:?}: {}", first_image, image, error))?,
+            _ => {}
+        }
+        features.push(image_features);
+    }
+    let mut results: Vec<Option<Result<PairSummary, Error>>> = pairs.iter().map(|_| None).collect();
+    let mut pool = Pool::new(num_threads.max(1) as u32);
+    pool.scoped(|scoped| {
+        for (&(i, j), result) in pairs.iter().zip(results.iter_mut()) {
+            let features = &features;
+            scoped.execute(move || {
+                let (putative_matches, matches, fundamental_matrix) =
+                    match_pair(&features[i], &features[j], config);
+                let matches_file = format!(
+                    "{}_{}.{}",
+                    image_name(&images[i]),
+                    image_name(&images[j]),
+                    extension
+                );
+                debug!(
+                    "Pair {}: {} putative, {} verified matches.",
+                    matches_file,
+                    putative_matches,
+                    matches.len()
+                );
+                *result = Some(
+                    serialize_matches_to_file(&matches, output.join(&matches_file)).map(|_| {
+                        PairSummary {
+                            image_0: i,
+                            image_1: j,
+                            matches_file,
+                            putative_matches,
+                            verified_matches: matches.len(),
+                            fundamental_matrix: fundamental_matrix.map(|fund_mat| {
+                                let row = |r: usize| {
+                                    [fund_mat[(r, 0)], fund_mat[(r, 1)], fund_mat[(r, 2)]]
+                                };
+                                [row(0), row(1), row(2)]
+                            }),
+                        }
+                    }),
+                );
+            });
+        }
+    });
+    let summary = CollectionSummary {
+        images: images.to_vec(),
+        selection: selection.clone(),
+        config: *config,
+        pairs: results
+            .into_iter()
+            .map(|result| result.expect("every pair is matched"))
+            .collect::<Result<Vec<PairSummary>, Error>>()?,
+    };
+    serde_json::to_writer_pretty(File::create(output.join(SUMMARY_FILE))?, &summary)?;
+    Ok(summary)
+}
@@ -0,0 +1,815 @@
+//! Export to and import from COLMAP, so that AKAZE features can replace
+//! the SIFT front end of a COLMAP reconstruction.
+//!
+//! COLMAP puts the center of the top left pixel at (0.5, 0.5), this crate
+//! at (0, 0), so coordinates and the fundamental matrices relating them
+//! are shifted by half a pixel both ways.
+//! Keypoints are stored with four columns: x, y, scale and orientation in
+//! radians, where the scale is the keypoint radius. COLMAP's own matchers
+//! expect 128 byte SIFT descriptors, so matches must be imported as well,
+//! either into the database or with COLMAP's `matches_importer`. The text
+//! format only takes 128 byte descriptors, so the 61 byte MLDB descriptors
+//! are padded with zeros there.
+
+use crate::collection::{image_name, CollectionSummary};
+use crate::{
+    deserialize_features_from_file, deserialize_features_with_header_from_file,
+    deserialize_matches_from_file, Features,
+};
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use failure::{format_err, Error};
+use log::*;
+use nalgebra::Matrix3;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The descriptor length of COLMAP's text feature format.
+pub const COLMAP_DESCRIPTOR_LENGTH: usize = 128;
+
+/// COLMAP pair ids are `image_id_0 * MAX_IMAGE_ID + image_id_1`.
+const MAX_IMAGE_ID: i64 = 2_147_483_647;
+
+/// The `SIMPLE_RADIAL` camera model of COLMAP.
+const SIMPLE_RADIAL: i64 = 2;
+
+/// The `UNCALIBRATED` two-view geometry configuration of COLMAP, for pairs
+/// verified with a fundamental matrix.
+const UNCALIBRATED: i64 = 3;
+
+const CREATE_TABLES: &str = "
+CREATE TABLE IF NOT EXISTS cameras (
+    camera_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    model INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    params BLOB,
+    prior_focal_length INTEGER NOT NULL);
+CREATE TABLE IF NOT EXISTS images (
+    image_id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    camera_id INTEGER NOT NULL,
+    prior_qw REAL,
+    prior_qx REAL,
+    prior_qy REAL,
+    prior_qz REAL,
+    prior_tx REAL,
+    prior_ty REAL,
+    prior_tz REAL,
+    CONSTRAINT image_id_check CHECK(image_id >= 0 and image_id < 2147483647),
+    FOREIGN KEY(camera_id) REFERENCES cameras(camera_id));
+CREATE TABLE IF NOT EXISTS keypoints (
+    image_id INTEGER PRIMARY KEY NOT NULL,
+    rows INTEGER NOT NULL,
+    cols INTEGER NOT NULL,
+    data BLOB,
+    FOREIGN KEY(image_id) REFERENCES images(image_id) ON DELETE CASCADE);
+CREATE TABLE IF NOT EXISTS descriptors (
+    image_id INTEGER PRIMARY KEY NOT NULL,
+    rows INTEGER NOT NULL,
+    cols INTEGER NOT NULL,
+    data BLOB,
+    FOREIGN KEY(image_id) REFERENCES images(image_id) ON DELETE CASCADE);
+CREATE TABLE IF NOT EXISTS matches (
+    pair_id INTEGER PRIMARY KEY NOT NULL,
+    rows INTEGER NOT NULL,
+    cols INTEGER NOT NULL,
+    data BLOB);
+CREATE TABLE IF NOT EXISTS two_view_geometries (
+    pair_id INTEGER PRIMARY KEY NOT NULL,
+    rows INTEGER NOT NULL,
+    cols INTEGER NOT NULL,
+    data BLOB,
+    config INTEGER NOT NULL,
+    F BLOB,
+    E BLOB,
+    H BLOB,
+    qvec BLOB,
+    tvec BLOB);
+CREATE UNIQUE INDEX IF NOT EXISTS index_name ON images(name);
+";
+
+/// The pair id of two images, and whether the images had to be swapped to
+/// get it.
+fn pair_id(image_id_0: i64, image_id_1: i64) -> (i64, bool) {
+    if image_id_0 > image_id_1 {
+        (image_id_1 * MAX_IMAGE_ID + image_id_0, true)
+    } else {
+        (image_id_0 * MAX_IMAGE_ID + image_id_1, false)
+    }
+}
+
+fn matches_blob(matches: &[Match], swapped: bool) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(matches.len() * 8);
+    for match_i in matches {
+        let (index_0, index_1) = if swapped {
+            (match_i.index_1, match_i.index_0)
+        } else {
+            (match_i.index_0, match_i.index_1)
+        };
+        blob.extend_from_slice(&(index_0 as u32).to_le_bytes());
+        blob.extend_from_slice(&(index_1 as u32).to_le_bytes());
+    }
+    blob
+}
+
+fn matches_from_blob(blob: &[u8], swapped: bool) -> Vec<Match> {
+    blob.chunks_exact(8)
+        .map(|chunk| {
+            let index_0 = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+            let index_1 = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as usize;
+            let (index_0, index_1) = if swapped {
+                (index_1, index_0)
+            } else {
+                (index_0, index_1)
+            };
+            // COLMAP does not keep descriptor distances
+            Match {
+                index_0,
+                index_1,
+                distance: 0f64,
+            }
+        })
+        .collect()
+}
+
+fn matrix_blob(matrix: &Matrix3<f64>) -> Vec<u8> {
+    let mut blob = Vec::with_capacity(9 * 8);
+    for r in 0..3 {
+        for c in 0..3 {
+            blob.extend_from_slice(&matrix[(r, c)].to_bits().to_le_bytes());
+        }
+    }
+    blob
+}
+
+fn matrix_from_blob(blob: &[u8]) -> Matrix3<f64> {
+    if blob.len() != 9 * 8 {
+        return Matrix3::zeros();
+    }
+    let value = |i: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&blob[i * 8..(i + 1) * 8]);
+        f64::from_bits(u64::from_le_bytes(bytes))
+    };
+    Matrix3::from_fn(|r, c| value(r * 3 + c))
+}
+
+fn colmap_keypoint(keypoint: &Keypoint) -> [f32; 4] {
+    [
+        keypoint.point.0 + 0.5f32,
+        keypoint.point.1 + 0.5f32,
+        keypoint.size,
+        keypoint.angle,
+    ]
+}
+
+fn keypoint_from_colmap(x: f32, y: f32, scale: f32, orientation: f32) -> Keypoint {
+    Keypoint {
+        point: (x - 0.5f32, y - 0.5f32),
+        response: 0f32,
+        size: scale,
+        octave: 0,
+        class_id: 0,
+        angle: orientation,
+    }
+}
+
+fn descriptor_length(features: &Features) -> Result<usize, Error> {
+    let length = features
+        .descriptors
+        .first()
+        .map(|descriptor| descriptor.vector.len())
+        .unwrap_or(0);
+    if features
+        .descriptors
+        .iter()
+        .any(|descriptor| descriptor.vector.len() != length)
+    {
+        return Err(format_err!("the descriptors are of different lengths"));
+    }
+    Ok(length)
+}
+
+/// The verified matches of an image pair.
+#[derive(Debug, Clone)]
+pub struct TwoViewGeometry {
+    /// The matches consistent with the geometry, without distances when
+    /// read from a database
+    pub inliers: Vec<Match>,
+    /// The fundamental matrix with p1^T F p0 = 0 for points in the
+    /// coordinates of this crate, zero if unknown
+    pub fund_mat: Matrix3<f64>,
+}
+
+/// Move a fundamental matrix between the coordinates of this crate and
+/// COLMAP's, where points are shifted by `offset` pixels.
+fn shift_fundamental_matrix(fund_mat: &Matrix3<f64>, offset: f64) -> Matrix3<f64> {
+    // p' = p + offset, so p'_1^T F' p'_0 = p_1^T F p_0 with F' = T^T F T
+    let shift = Matrix3::new(1f64, 0f64, -offset, 0f64, 1f64, -offset, 0f64, 0f64, 1f64);
+    shift.transpose() * fund_mat * shift
+}
+
+/// A COLMAP SQLite database.
+pub struct ColmapDatabase {
+    connection: Connection,
+}
+
+impl ColmapDatabase {
+    /// Open a database, creating it and its tables if missing.
+    ///
+    /// # Arguments
+    /// * `path` - The database file, usually `database.db`.
+    /// # Return value
+    /// The database.
+    pub fn open(path: impl AsRef<Path>) -> Result<ColmapDatabase, Error> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(CREATE_TABLES)?;
+        Ok(ColmapDatabase { connection })
+    }
+
+    /// Start a transaction, so that everything written until `commit` is
+    /// written at once, which is much faster for large collections.
+    pub fn begin(&self) -> Result<(), Error> {
+        self.connection.execute_batch("BEGIN TRANSACTION;")?;
+        Ok(())
+    }
+
+    /// End a transaction started with `begin`.
+    pub fn commit(&self) -> Result<(), Error> {
+        self.connection.execute_batch("COMMIT;")?;
+        Ok(())
+    }
+
+    /// Add a `SIMPLE_RADIAL` camera with COLMAP's default intrinsics
+    /// guess, a focal length of 1.2 times the larger image dimension.
+    ///
+    /// # Arguments
+    /// * `width` - The image width.
+    /// * `height` - The image height.
+    /// # Return value
+    /// The camera id.
+    pub fn add_camera(&self, width: u32, height: u32) -> Result<i64, Error> {
+        let focal_length = 1.2f64 * f64::from(width.max(height));
+        let intrinsics = [
+            focal_length,
+            f64::from(width) / 2f64,
+            f64::from(height) / 2f64,
+            0f64,
+        ];
+        let params_blob: Vec<u8> = intrinsics
+            .iter()
+            .flat_map(|value| value.to_bits().to_le_bytes().to_vec())
+            .collect();
+        self.connection.execute(
+            "INSERT INTO cameras (model, width, height, params, prior_focal_length)
+             VALUES (?1, ?2, ?3, ?4, 0)",
+            params![
+                SIMPLE_RADIAL,
+                i64::from(width),
+                i64::from(height),
+                params_blob
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Add an image.
+    ///
+    /// # Arguments
+    /// * `name` - The image file name, relative to COLMAP's image directory.
+    /// * `camera_id` - The camera that took the image.
+    /// # Return value
+    /// The image id.
+    pub fn add_image(&self, name: &str, camera_id: i64) -> Result<i64, Error> {
+        self.connection.execute(
+            "INSERT INTO images (name, camera_id) VALUES (?1, ?2)",
+            params![name, camera_id],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// The id of an image.
+    ///
+    /// # Arguments
+    /// * `name` - The image file name.
+    /// # Return value
+    /// The image id, None if the image is not in the database.
+    pub fn image_id(&self, name: &str) -> Result<Option<i64>, Error> {
+        Ok(self
+            .connection
+            .query_row(
+                "SELECT image_id FROM images WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// All images.
+    ///
+    /// # Return value
+    /// The ids and names of the images, by id.
+    pub fn images(&self) -> Result<Vec<(i64, String)>, Error> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT image_id, name FROM images ORDER BY image_id")?;
+        let rows = statement.query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Write the keypoints and descriptors of an image, replacing any
+    /// existing ones.
+    ///
+    /// # Arguments
+    /// * `image_id` - The image.
+    /// * `features` - The features of the image.
+    pub fn write_features(&self, image_id: i64, features: &Features) -> Result<(), Error> {
+        let mut keypoints_blob = Vec::with_capacity(features.keypoints.len() * 16);
+        for keypoint in &features.keypoints {
+            for value in colmap_keypoint(keypoint).iter() {
+                keypoints_blob.extend_from_slice(&value.to_bits().to_le_bytes());
+            }
+        }
+        self.connection.execute(
+            "INSERT OR REPLACE INTO keypoints (image_id, rows, cols, data)
+             VALUES (?1, ?2, 4, ?3)",
+            params![image_id, features.keypoints.len() as i64, keypoints_blob],
+        )?;
+        let length = descriptor_length(features)?;
+        let descriptors_blob: Vec<u8> = features
+            .descriptors
+            .iter()
+            .flat_map(|descriptor| descriptor.vector.iter().cloned())
+            .collect();
+        self.connection.execute(
+            "INSERT OR REPLACE INTO descriptors (image_id, rows, cols, data)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                image_id,
+                features.descriptors.len() as i64,
+                length as i64,
+                descriptors_blob
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Read the keypoints and descriptors of an image.
+    ///
+    /// # Arguments
+    /// * `image_id` - The image.
+    /// # Return value
+    /// The features. Keypoints have no response or octave.
+    pub fn read_features(&self, image_id: i64) -> Result<Features, Error> {
+        let (rows, cols, blob): (i64, i64, Vec<u8>) = self.connection.query_row(
+            "SELECT rows, cols, data FROM keypoints WHERE image_id = ?1",
+            params![image_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let (rows, cols) = (rows as usize, cols as usize);
+        if cols < 2 || blob.len() != rows * cols * 4 {
+            return Err(format_err!("malformed keypoints of image {}", image_id));
+        }
+        let values: Vec<f32> = blob
+            .chunks_exact(4)
+            .map(|chunk| {
+                f32::from_bits(u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            })
+            .collect();
+        let keypoints = values
+            .chunks_exact(cols)
+            .map(|row| match cols {
+                2 => keypoint_from_colmap(row[0], row[1], 1f32, 0f32),
+                // Affine shapes, with the scale and orientation of the
+                // first axis
+                6 => keypoint_from_colmap(
+                    row[0],
+                    row[1],
+                    f32::sqrt(row[2] * row[2] + row[4] * row[4]),
+                    f32::atan2(row[4], row[2]),
+                ),
+                _ => keypoint_from_colmap(row[0], row[1], row[2], row[3]),
+            })
+            .collect();
+        let (rows, cols, blob): (i64, i64, Vec<u8>) = self.connection.query_row(
+            "SELECT rows, cols, data FROM descriptors WHERE image_id = ?1",
+            params![image_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )?;
+        let (rows, cols) = (rows as usize, cols as usize);
+        if blob.len() != rows * cols {
+            return Err(format_err!("malformed descriptors of image {}", image_id));
+        }
+        let descriptors = (0..rows)
+            .map(|i| Descriptor {
+                vector: blob[i * cols..(i + 1) * cols].to_vec(),
+            })
+            .collect();
+        Ok(Features {
+            keypoints,
+            descriptors,
+        })
+    }
+
+    /// Write the putative matches of an image pair, replacing any existing
+    /// ones.
+    ///
+    /// # Arguments
+    /// * `image_id_0` - The image of `Match::index_0`.
+    /// * `image_id_1` - The image of `Match::index_1`.
+    /// * `matches` - The matches.
+    pub fn write_matches(
+        &self,
+        image_id_0: i64,
+        image_id_1: i64,
+        matches: &[Match],
+    ) -> Result<(), Error> {
+        let (pair_id, swapped) = pair_id(image_id_0, image_id_1);
+        self.connection.execute(
+            "INSERT OR REPLACE INTO matches (pair_id, rows, cols, data)
+             VALUES (?1, ?2, 2, ?3)",
+            params![
+                pair_id,
+                matches.len() as i64,
+                matches_blob(matches, swapped)
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Read the putative matches of an image pair.
+    ///
+    /// # Arguments
+    /// * `image_id_0` - The image of `Match::index_0`.
+    /// * `image_id_1` - The image of `Match::index_1`.
+    /// # Return value
+    /// The matches, without distances. Empty if the pair was not matched.
+    pub fn read_matches(&self, image_id_0: i64, image_id_1: i64) -> Result<Vec<Match>, Error> {
+        let (pair_id, swapped) = pair_id(image_id_0, image_id_1);
+        let blob: Option<Vec<u8>> = self
+            .connection
+            .query_row(
+                "SELECT data FROM matches WHERE pair_id = ?1",
+                params![pair_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(blob
+            .map(|blob| matches_from_blob(&blob, swapped))
+            .unwrap_or_default())
+    }
+
+    /// Write the verified matches of an image pair and their fundamental
+    /// matrix, replacing any existing ones. COLMAP's mapper reconstructs
+    /// from these.
+    ///
+    /// # Arguments
+    /// * `image_id_0` - The image of `Match::index_0`.
+    /// * `image_id_1` - The image of `Match::index_1`.
+    /// * `geometry` - The verified matches.
+    pub fn write_two_view_geometry(
+        &self,
+        image_id_0: i64,
+        image_id_1: i64,
+        geometry: &TwoViewGeometry,
+    ) -> Result<(), Error> {
+        let (pair_id, swapped) = pair_id(image_id_0, image_id_1);
+        // COLMAP's F maps the first image of the pair to the second
+        let fund_mat = shift_fundamental_matrix(&geometry.fund_mat, 0.5f64);
+        let fund_mat = if swapped {
+            fund_mat.transpose()
+        } else {
+            fund_mat
+        };
+        self.connection.execute(
+            "INSERT OR REPLACE INTO two_view_geometries
+             (pair_id, rows, cols, data, config, F, E, H)
+             VALUES (?1, ?2, 2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                pair_id,
+                geometry.inliers.len() as i64,
+                matches_blob(&geometry.inliers, swapped),
+                UNCALIBRATED,
+                matrix_blob(&fund_mat),
+                matrix_blob(&Matrix3::zeros()),
+                matrix_blob(&Matrix3::zeros())
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Read the verified matches of an image pair and their fundamental
+    /// matrix.
+    ///
+    /// # Arguments
+    /// * `image_id_0` - The image of `Match::index_0`.
+    /// * `image_id_1` - The image of `Match::index_1`.
+    /// # Return value
+    /// The verified matches, None if the pair was not verified.
+    pub fn read_two_view_geometry(
+        &self,
+        image_id_0: i64,
+        image_id_1: i64,
+    ) -> Result<Option<TwoViewGeometry>, Error> {
+        let (pair_id, swapped) = pair_id(image_id_0, image_id_1);
+        let row: Option<(Vec<u8>, Option<Vec<u8>>)> = self
+            .connection
+            .query_row(
+                "SELECT data, F FROM two_view_geometries WHERE pair_id = ?1",
+                params![pair_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(row.map(|(blob, fund_mat)| {
+            let fund_mat =
+                shift_fundamental_matrix(&matrix_from_blob(&fund_mat.unwrap_or_default()), -0.5f64);
+            let fund_mat = if swapped {
+                fund_mat.transpose()
+            } else {
+                fund_mat
+            };
+            TwoViewGeometry {
+                inliers: matches_from_blob(&blob, swapped),
+                fund_mat,
+            }
+        }))
+    }
+}
+
+/// Write features in COLMAP's text format, as read by its
+/// `feature_importer`.
+///
+/// The first line holds the number of features and the descriptor length,
+/// each following line the x, y, scale and orientation of a keypoint and
+/// its descriptor bytes, padded with zeros to `COLMAP_DESCRIPTOR_LENGTH`.
+///
+/// # Arguments
+/// * `features` - The features.
+/// * `writer` - The destination, usually `<image name>.txt`.
+pub fn write_colmap_features_text(
+    features: &Features,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    let length = descriptor_length(features)?;
+    if length > COLMAP_DESCRIPTOR_LENGTH {
+        return Err(format_err!(
+            "a descriptor of {} bytes does not fit in {} bytes",
+            length,
+            COLMAP_DESCRIPTOR_LENGTH
+        ));
+    }
+    writeln!(
+        writer,
+        "{} {}",
+        features.keypoints.len(),
+        COLMAP_DESCRIPTOR_LENGTH
+    )?;
+    for (keypoint, descriptor) in features.keypoints.iter().zip(features.descriptors.iter()) {
+        let [x, y, scale, orientation] = colmap_keypoint(keypoint);
+        write!(writer, "{} {} {} {}", x, y, scale, orientation)?;
+        for byte in &descriptor.vector {
+            write!(writer, " {}", byte)?;
+        }
+        for _ in length..COLMAP_DESCRIPTOR_LENGTH {
+            write!(writer, " 0")?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Read features in COLMAP's text format.
+///
+/// # Arguments
+/// * `reader` - The source.
+/// # Return value
+/// The features, with the descriptor length of the file, so descriptors
+/// written by `write_colmap_features_text` keep their padding. Keypoints
+/// have no response or octave.
+pub fn read_colmap_features_text(reader: impl BufRead) -> Result<Features, Error> {
+    let mut lines = reader.lines();
+    let first_line = lines
+        .next()
+        .ok_or_else(|| format_err!("the feature file is empty"))??;
+    let counts = first_line
+        .split_whitespace()
+        .map(str::parse)
+        .collect::<Result<Vec<usize>, _>>()?;
+    if counts.len() != 2 {
+        return Err(format_err!("malformed feature file header: {}", first_line));
+    }
+    let (count, length) = (counts[0], counts[1]);
+    let mut features = Features {
+        keypoints: Vec::with_capacity(count),
+        descriptors: Vec::with_capacity(count),
+    };
+    for line in lines.take(count) {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() != 4 + length {
+            return Err(format_err!("malformed feature line: {}", line));
+        }
+        let geometry = fields[..4]
+            .iter()
+            .map(|field| field.parse())
+            .collect::<Result<Vec<f32>, _>>()?;
+        features.keypoints.push(keypoint_from_colmap(
+            geometry[0],
+            geometry[1],
+            geometry[2],
+            geometry[3],
+        ));
+        features.descriptors.push(Descriptor {
+            vector: fields[4..]
+                .iter()
+                .map(|field| field.parse())
+                .collect::<Result<Vec<u8>, _>>()?,
+        });
+    }
+    if features.keypoints.len() != count {
+        return Err(format_err!(
+            "expected {} features, got {}",
+            count,
+            features.keypoints.len()
+        ));
+    }
+    Ok(features)
+}
+
+/// The image names of a pair and their matches.
+pub type PairMatches = (String, String, Vec<Match>);
+
+/// Write matches in COLMAP's text format, as read by its
+/// `matches_importer`.
+///
+/// Each pair is a line with the two image names, one line per match with
+/// the feature indices, and an empty line.
+///
+/// # Arguments
+/// * `pairs` - The image names of each pair and their matches.
+/// * `writer` - The destination.
+pub fn write_colmap_matches_text(
+    pairs: &[PairMatches],
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    for (name_0, name_1, matches) in pairs {
+        writeln!(writer, "{} {}", name_0, name_1)?;
+        for match_i in matches {
+            writeln!(writer, "{} {}", match_i.index_0, match_i.index_1)?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Read matches in COLMAP's text format.
+///
+/// # Arguments
+/// * `reader` - The source.
+/// # Return value
+/// The image names of each pair and their matches, without distances.
+pub fn read_colmap_matches_text(reader: impl BufRead) -> Result<Vec<PairMatches>, Error> {
+    let mut pairs: Vec<PairMatches> = vec![];
+    let mut in_pair = false;
+    for line in reader.lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.is_empty() {
+            in_pair = false;
+            continue;
+        }
+        if fields.len() != 2 {
+            return Err(format_err!("malformed matches line: {}", line));
+        }
+        if in_pair {
+            let matches = &mut pairs.last_mut().expect("a pair was started").2;
+            matches.push(Match {
+                index_0: fields[0].parse()?,
+                index_1: fields[1].parse()?,
+                distance: 0f64,
+            });
+        } else {
+            pairs.push((fields[0].to_owned(), fields[1].to_owned(), vec![]));
+            in_pair = true;
+        }
+    }
+    Ok(pairs)
+}
+
+fn read_summary(summary_path: &Path) -> Result<CollectionSummary, Error> {
+    Ok(serde_json::from_reader(BufReader::new(File::open(
+        summary_path,
+    )?))?)
+}
+
+/// Export a matched collection to a COLMAP database.
+///
+/// Every image gets its own camera, sized from its feature file header,
+/// unless `single_camera` is set. The verified matches of each pair are
+/// written both as putative matches and as a two-view geometry with the
+/// fundamental matrix they were verified with. Pairs matched without a
+/// fundamental matrix, having too few matches, get no two-view geometry.
+///
+/// # Arguments
+/// * `summary_path` - The `summary.json` written by `match_collection`.
+/// * `database_path` - The COLMAP database, created if missing.
+/// * `image_extension` - The extension of the image files, e.g. `jpg`.
+///   COLMAP refers to images by file name.
+/// * `single_camera` - Whether all images were taken with one camera.
+pub fn export_collection_to_database(
+    summary_path: impl AsRef<Path>,
+    database_path: impl AsRef<Path>,
+    image_extension: &str,
+    single_camera: bool,
+) -> Result<(), Error> {
+    let summary_path = summary_path.as_ref();
+    let summary = read_summary(summary_path)?;
+    let database = ColmapDatabase::open(database_path)?;
+    database.begin()?;
+    let mut camera_id = None;
+    let mut image_ids = vec![];
+    for path in &summary.images {
+        let (header, features) = deserialize_features_with_header_from_file(path)?;
+        let (width, height) = header
+            .and_then(|header| header.image_size)
+            .ok_or_else(|| format_err!("the feature file {:?} has no image size", path))?;
+        let camera = match camera_id {
+            Some(camera) if single_camera => camera,
+            _ => database.add_camera(width, height)?,
+        };
+        camera_id = Some(camera);
+        let name = format!("{}.{}", image_name(path), image_extension);
+        let image_id = database.add_image(&name, camera)?;
+        database.write_features(image_id, &features)?;
+        image_ids.push(image_id);
+    }
+    let base = summary_path.parent().unwrap_or_else(|| Path::new(""));
+    for pair in &summary.pairs {
+        let matches = deserialize_matches_from_file(base.join(&pair.matches_file))?;
+        let (image_id_0, image_id_1) = (image_ids[pair.image_0], image_ids[pair.image_1]);
+        database.write_matches(image_id_0, image_id_1, &matches)?;
+        if let Some(rows) = pair.fundamental_matrix {
+            let geometry = TwoViewGeometry {
+                inliers: matches,
+                fund_mat: Matrix3::from_fn(|r, c| f64::from(rows[r][c])),
+            };
+            database.write_two_view_geometry(image_id_0, image_id_1, &geometry)?;
+        }
+    }
+    database.commit()?;
+    info!(
+        "Exported {} images and {} pairs.",
+        summary.images.len(),
+        summary.pairs.len()
+    );
+    Ok(())
+}
+
+/// Export a matched collection to COLMAP's text formats.
+///
+/// Writes `<image name>.txt` feature files for `feature_importer` and a
+/// `matches.txt` for `matches_importer`.
+///
+/// # Arguments
+/// * `summary_path` - The `summary.json` written by `match_collection`.
+/// * `output` - The output directory, created if missing.
+/// * `image_extension` - The extension of the image files, e.g. `jpg`.
+pub fn export_collection_to_text(
+    summary_path: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    image_extension: &str,
+) -> Result<(), Error> {
+    let summary_path = summary_path.as_ref();
+    let output = output.as_ref();
+    let summary = read_summary(summary_path)?;
+    fs::create_dir_all(output)?;
+    let names: Vec<String> = summary
+        .images
+        .iter()
+        .map(|path| format!("{}.{}", image_name(path), image_extension))
+        .collect();
+    for (path, name) in summary.images.iter().zip(names.iter()) {
+        let features = deserialize_features_from_file(path)?;
+        let file = File::create(output.join(format!("{}.txt", name)))?;
+        write_colmap_features_text(&features, BufWriter::new(file))?;
+    }
+    let base = summary_path.parent().unwrap_or_else(|| Path::new(""));
+    let pairs = summary
+        .pairs
+        .iter()
+        .map(|pair| {
+            let matches = deserialize_matches_from_file(base.join(&pair.matches_file))?;
+            Ok((
+                names[pair.image_0].clone(),
+                names[pair.image_1].clone(),
+                matches,
+            ))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let file = File::create(output.join("matches.txt"))?;
+    write_colmap_matches_text(&pairs, BufWriter::new(file))?;
+    Ok(())
+}
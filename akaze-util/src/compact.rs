@@ -0,0 +1,451 @@
+//! Compact, compressed feature and match files.
+//!
+//! Fields are stored column by column, the bytes of 32 and 64 bit values
+//! split into planes, small integers as variable length integers, and the
+//! whole compressed with deflate. Files start with:
+//!
+//! | Offset | Size | Content                                          |
+//! |--------|------|--------------------------------------------------|
+//! | 0      | 4    | The magic number `AKZC`                          |
+//! | 4      | 2    | The major format version, currently 1            |
+//! | 6      | 2    | The minor format version, currently 0            |
+//! | 8      | 1    | The content, 0 for features and 1 for matches    |
+//! | 9      | 1    | The precision, 0 for lossless and 1 for quantized|
+//!
+//! Feature files then hold the length of the bincode encoded
+//! `FeatureFileHeader` as a 32 bit little endian integer and the header,
+//! like feature file containers. The rest is the compressed zlib stream.
+//!
+//! Quantized features are within these bounds of the originals:
+//! * Coordinates and sizes within `POSITION_STEP / 2` pixels.
+//! * Angles within `PI / ANGLE_LEVELS` radians, modulo `2 PI`.
+//! * Responses within a relative error of `2^-(RESPONSE_MANTISSA_BITS + 1)`.
+//!
+//! Octaves, class ids and descriptors are always exact. Matches are always
+//! lossless, as their distances compress well.
+
+use crate::feature_file::FeatureFileHeader;
+use crate::Features;
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use failure::{format_err, Error};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::f32::consts::PI;
+use std::io::{Read, Write};
+
+/// The magic number at the start of every compact file.
+pub const COMPACT_MAGIC: [u8; 4] = *b"AKZC";
+
+/// The major format version written and read by this crate.
+pub const COMPACT_MAJOR_VERSION: u16 = 1;
+
+/// The minor format version written by this crate.
+pub const COMPACT_MINOR_VERSION: u16 = 0;
+
+/// The quantization step of coordinates and sizes in pixels.
+pub const POSITION_STEP: f32 = 1f32 / 64f32;
+
+/// The number of quantized angles in a full turn.
+pub const ANGLE_LEVELS: u32 = 65536;
+
+/// The number of mantissa bits kept of quantized responses.
+pub const RESPONSE_MANTISSA_BITS: u32 = 10;
+
+const FEATURES: u8 = 0;
+const MATCHES: u8 = 1;
+
+/// How exactly compact files store keypoints.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Precision {
+    /// Keypoints are stored exactly.
+    Lossless,
+    /// Keypoints are quantized within the bounds documented in this module.
+    Quantized,
+}
+
+impl Precision {
+    /// The precision of a compact file extension, `akzc` for lossless and
+    /// `akzq` for quantized files.
+    ///
+    /// # Arguments
+    /// * `extension` - The file extension.
+    /// # Return value
+    /// The precision, None for other extensions.
+    pub fn from_extension(extension: &str) -> Option<Precision> {
+        match extension {
+            "akzc" => Some(Precision::Lossless),
+            "akzq" => Some(Precision::Quantized),
+            _ => None,
+        }
+    }
+
+    fn code(self) -> u8 {
+        match self {
+            Precision::Lossless => 0,
+            Precision::Quantized => 1,
+        }
+    }
+}
+
+fn write_preamble(mut writer: impl Write, content: u8, precision: Precision) -> Result<(), Error> {
+    writer.write_all(&COMPACT_MAGIC)?;
+    writer.write_all(&COMPACT_MAJOR_VERSION.to_le_bytes())?;
+    writer.write_all(&COMPACT_MINOR_VERSION.to_le_bytes())?;
+    writer.write_all(&[content, precision.code()])?;
+    Ok(())
+}
+
+fn read_preamble(mut reader: impl Read, content: u8) -> Result<Precision, Error> {
+    let mut preamble = [0u8; 10];
+    reader.read_exact(&mut preamble)?;
+    if preamble[..4] != COMPACT_MAGIC {
+        return Err(format_err!(
+            "not a compact file, the magic number is missing"
+        ));
+    }
+    let major_version = u16::from_le_bytes([preamble[4], preamble[5]]);
+    let minor_version = u16::from_le_bytes([preamble[6], preamble[7]]);
+    if major_version != COMPACT_MAJOR_VERSION {
+        return Err(format_err!(
+            "unsupported compact file version {}.{}, expected {}.x",
+            major_version,
+            minor_version,
+            COMPACT_MAJOR_VERSION
+        ));
+    }
+    if preamble[8] != content {
+        return Err(format_err!(
+            "expected a compact file of {}",
+            if content == FEATURES {
+                "features"
+            } else {
+                "matches"
+            }
+        ));
+    }
+    match preamble[9] {
+        0 => Ok(Precision::Lossless),
+        1 => Ok(Precision::Quantized),
+        code => Err(format_err!("unknown compact file precision {}", code)),
+    }
+}
+
+fn write_varint(payload: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        payload.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    payload.push(value as u8);
+}
+
+fn read_varint(mut reader: impl Read) -> Result<u64, Error> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0u8];
+        reader.read_exact(&mut byte)?;
+        value |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(format_err!("malformed variable length integer"))
+}
+
+/// Write 32 bit values with their bytes split into planes, which compress
+/// better than interleaved bytes.
+fn write_planes_u32(payload: &mut Vec<u8>, values: &[u32]) {
+    for plane in 0..4 {
+        payload.extend(values.iter().map(|value| (value >> (8 * plane)) as u8));
+    }
+}
+
+fn read_planes_u32(mut reader: impl Read, count: usize) -> Result<Vec<u32>, Error> {
+    let mut bytes = vec![0u8; count * 4];
+    reader.read_exact(&mut bytes)?;
+    Ok((0..count)
+        .map(|i| {
+            (0..4).fold(0u32, |value, plane| {
+                value | u32::from(bytes[plane * count + i]) << (8 * plane)
+            })
+        })
+        .collect())
+}
+
+fn write_planes_u64(payload: &mut Vec<u8>, values: &[u64]) {
+    for plane in 0..8 {
+        payload.extend(values.iter().map(|value| (value >> (8 * plane)) as u8));
+    }
+}
+
+fn read_planes_u64(mut reader: impl Read, count: usize) -> Result<Vec<u64>, Error> {
+    let mut bytes = vec![0u8; count * 8];
+    reader.read_exact(&mut bytes)?;
+    Ok((0..count)
+        .map(|i| {
+            (0..8).fold(0u64, |value, plane| {
+                value | u64::from(bytes[plane * count + i]) << (8 * plane)
+            })
+        })
+        .collect())
+}
+
+fn quantize_position(value: f32) -> Result<u32, Error> {
+    let steps = (value / POSITION_STEP).round();
+    if !steps.is_finite() || steps.abs() > i32::MAX as f32 {
+        return Err(format_err!("{} cannot be quantized", value));
+    }
+    Ok(steps as i32 as u32)
+}
+
+fn dequantize_position(steps: u32) -> f32 {
+    steps as i32 as f32 * POSITION_STEP
+}
+
+fn quantize_angle(angle: f32) -> u32 {
+    let turns = (angle + PI) / (2f32 * PI);
+    (turns.rem_euclid(1f32) * ANGLE_LEVELS as f32).round() as u32 % ANGLE_LEVELS
+}
+
+fn dequantize_angle(level: u32) -> f32 {
+    level as f32 / ANGLE_LEVELS as f32 * 2f32 * PI - PI
+}
+
+/// Round a response to `RESPONSE_MANTISSA_BITS` mantissa bits.
+fn quantize_response(response: f32) -> u32 {
+    let dropped = 23 - RESPONSE_MANTISSA_BITS;
+    if !response.is_finite() {
+        return response.to_bits();
+    }
+    let bits = response.to_bits();
+    // A carry into the exponent still rounds correctly
+    (bits + (1 << (dropped - 1))) & !((1 << dropped) - 1)
+}
+
+fn compress(mut writer: impl Write, payload: &[u8]) -> Result<(), Error> {
+    let mut encoder = ZlibEncoder::new(&mut writer, Compression::best());
+    encoder.write_all(payload)?;
+    encoder.finish()?;
+    Ok(())
+}
+
+fn decompress(reader: impl Read) -> Result<Vec<u8>, Error> {
+    let mut payload = vec![];
+    ZlibDecoder::new(reader).read_to_end(&mut payload)?;
+    Ok(payload)
+}
+
+/// Write features in a compact file.
+///
+/// # Arguments
+/// * `writer` - The destination.
+/// * `header` - The header, with `num_features` matching the features.
+/// * `features` - The features, with descriptors of the header length.
+/// * `precision` - Whether keypoints are stored exactly or quantized.
+pub fn write_compact_features(
+    mut writer: impl Write,
+    header: &FeatureFileHeader,
+    features: &Features,
+    precision: Precision,
+) -> Result<(), Error> {
+    if header.num_features != features.keypoints.len() as u64
+        || features.keypoints.len() != features.descriptors.len()
+    {
+        return Err(format_err!(
+            "the header describes {} features, got {} keypoints and {} descriptors",
+            header.num_features,
+            features.keypoints.len(),
+            features.descriptors.len()
+        ));
+    }
+    if features
+        .descriptors
+        .iter()
+        .any(|descriptor| descriptor.vector.len() != header.descriptor_length as usize)
+    {
+        return Err(format_err!(
+            "the descriptors are not all of length {}",
+            header.descriptor_length
+        ));
+    }
+    write_preamble(&mut writer, FEATURES, precision)?;
+    let encoded_header = bincode::serialize(header)?;
+    writer.write_all(&(encoded_header.len() as u32).to_le_bytes())?;
+    writer.write_all(&encoded_header)?;
+
+    let keypoints = &features.keypoints;
+    let mut payload = vec![];
+    let column = |field: &dyn Fn(&Keypoint) -> f32| -> Result<Vec<u32>, Error> {
+        keypoints
+            .iter()
+            .map(|keypoint| match precision {
+                Precision::Lossless => Ok(field(keypoint).to_bits()),
+                Precision::Quantized => quantize_position(field(keypoint)),
+            })
+            .collect()
+    };
+    write_planes_u32(&mut payload, &column(&|keypoint| keypoint.point.0)?);
+    write_planes_u32(&mut payload, &column(&|keypoint| keypoint.point.1)?);
+    write_planes_u32(&mut payload, &column(&|keypoint| keypoint.size)?);
+    let angles: Vec<u32> = keypoints
+        .iter()
+        .map(|keypoint| match precision {
+            Precision::Lossless => keypoint.angle.to_bits(),
+            Precision::Quantized => quantize_angle(keypoint.angle),
+        })
+        .collect();
+    write_planes_u32(&mut payload, &angles);
+    let responses: Vec<u32> = keypoints
+        .iter()
+        .map(|keypoint| match precision {
+            Precision::Lossless => keypoint.response.to_bits(),
+            Precision::Quantized => quantize_response(keypoint.response),
+        })
+        .collect();
+    write_planes_u32(&mut payload, &responses);
+    for keypoint in keypoints {
+        write_varint(&mut payload, keypoint.octave as u64);
+    }
+    for keypoint in keypoints {
+        write_varint(&mut payload, keypoint.class_id as u64);
+    }
+    for descriptor in &features.descriptors {
+        payload.extend_from_slice(&descriptor.vector);
+    }
+    compress(writer, &payload)
+}
+
+/// Read features from a compact file.
+///
+/// # Arguments
+/// * `reader` - The source, at the start of the file.
+/// # Return value
+/// The header, the features and the precision they were stored with.
+pub fn read_compact_features(
+    mut reader: impl Read,
+) -> Result<(FeatureFileHeader, Features, Precision), Error> {
+    let precision = read_preamble(&mut reader, FEATURES)?;
+    let mut header_length = [0u8; 4];
+    reader.read_exact(&mut header_length)?;
+    let mut encoded_header = vec![0u8; u32::from_le_bytes(header_length) as usize];
+    reader.read_exact(&mut encoded_header)?;
+    let header: FeatureFileHeader = bincode::deserialize(&encoded_header)?;
+
+    let payload = decompress(reader)?;
+    let mut payload = &payload[..];
+    let count = header.num_features as usize;
+    let mut column = |dequantize: &dyn Fn(u32) -> f32| -> Result<Vec<f32>, Error> {
+        Ok(read_planes_u32(&mut payload, count)?
+            .into_iter()
+            .map(|value| match precision {
+                Precision::Lossless => f32::from_bits(value),
+                Precision::Quantized => dequantize(value),
+            })
+            .collect())
+    };
+    let xs = column(&dequantize_position)?;
+    let ys = column(&dequantize_position)?;
+    let sizes = column(&dequantize_position)?;
+    let angles = column(&dequantize_angle)?;
+    let responses = column(&f32::from_bits)?;
+    let octaves = (0..count)
+        .map(|_| read_varint(&mut payload))
+        .collect::<Result<Vec<u64>, Error>>()?;
+    let class_ids = (0..count)
+        .map(|_| read_varint(&mut payload))
+        .collect::<Result<Vec<u64>, Error>>()?;
+    let keypoints = (0..count)
+        .map(|i| Keypoint {
+            point: (xs[i], ys[i]),
+            response: responses[i],
+            size: sizes[i],
+            octave: octaves[i] as usize,
+            class_id: class_ids[i] as usize,
+            angle: angles[i],
+        })
+        .collect();
+    let length = header.descriptor_length as usize;
+    if payload.len() != count * length {
+        return Err(format_err!(
+            "expected {} bytes of descriptors, got {}",
+            count * length,
+            payload.len()
+        ));
+    }
+    let descriptors = (0..count)
+        .map(|i| Descriptor {
+            vector: payload[i * length..(i + 1) * length].to_vec(),
+        })
+        .collect();
+    Ok((
+        header,
+        Features {
+            keypoints,
+            descriptors,
+        },
+        precision,
+    ))
+}
+
+/// Write matches in a compact file, losslessly.
+///
+/// # Arguments
+/// * `writer` - The destination.
+/// * `matches` - The matches.
+pub fn write_compact_matches(mut writer: impl Write, matches: &[Match]) -> Result<(), Error> {
+    write_preamble(&mut writer, MATCHES, Precision::Lossless)?;
+    let mut payload = vec![];
+    write_varint(&mut payload, matches.len() as u64);
+    // Matches are mostly in order of the first index, so store its
+    // differences, zigzag encoded
+    let mut previous = 0i64;
+    for match_i in matches {
+        let difference = match_i.index_0 as i64 - previous;
+        write_varint(
+            &mut payload,
+            ((difference << 1) ^ (difference >> 63)) as u64,
+        );
+        previous = match_i.index_0 as i64;
+    }
+    for match_i in matches {
+        write_varint(&mut payload, match_i.index_1 as u64);
+    }
+    let distances: Vec<u64> = matches
+        .iter()
+        .map(|match_i| match_i.distance.to_bits())
+        .collect();
+    write_planes_u64(&mut payload, &distances);
+    compress(writer, &payload)
+}
+
+/// Read matches from a compact file.
+///
+/// # Arguments
+/// * `reader` - The source, at the start of the file.
+/// # Return value
+/// The matches.
+pub fn read_compact_matches(mut reader: impl Read) -> Result<Vec<Match>, Error> {
+    read_preamble(&mut reader, MATCHES)?;
+    let payload = decompress(reader)?;
+    let mut payload = &payload[..];
+    let count = read_varint(&mut payload)? as usize;
+    let mut previous = 0i64;
+    let mut indices_0 = vec![];
+    for _ in 0..count {
+        let zigzag = read_varint(&mut payload)?;
+        previous += (zigzag >> 1) as i64 ^ -((zigzag & 1) as i64);
+        indices_0.push(previous as usize);
+    }
+    let indices_1 = (0..count)
+        .map(|_| Ok(read_varint(&mut payload)? as usize))
+        .collect::<Result<Vec<usize>, Error>>()?;
+    let distances = read_planes_u64(&mut payload, count)?;
+    Ok((0..count)
+        .map(|i| Match {
+            index_0: indices_0[i],
+            index_1: indices_1[i],
+            distance: f64::from_bits(distances[i]),
+        })
+        .collect())
+}
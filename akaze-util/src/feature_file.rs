@@ -0,0 +1,350 @@
+//! A versioned, self-describing container for feature files.
+//!
+//! All integers of the fixed part are little endian:
+//!
+//! | Offset | Size | Content                                          |
+//! |--------|------|--------------------------------------------------|
+//! | 0      | 4    | The magic number `AKZF`                          |
+//! | 4      | 2    | The major format version, currently 1            |
+//! | 6      | 2    | The minor format version, currently 0            |
+//! | 8      | 4    | The length `n` of the header in bytes            |
+//! | 12     | n    | The bincode encoded `FeatureFileHeader`          |
+//! | 12 + n | rest | The bincode encoded `Features`                   |
+//!
+//! Minor versions only append fields to the header, so readers skip the
+//! header bytes they do not know and read files of newer minor versions.
+//! A new major version is not readable by older readers.
+//!
+//! `FeatureStream` reads the features of a file one at a time, without
+//! loading the file. The keypoints have a fixed encoded size, so the
+//! descriptors are found without reading the keypoints.
+
+use crate::Features;
+use akaze::types::evolution::Config;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use failure::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The magic number at the start of every feature file.
+pub const MAGIC: [u8; 4] = *b"AKZF";
+
+/// The major format version written and read by this crate.
+pub const MAJOR_VERSION: u16 = 1;
+
+/// The minor format version written by this crate.
+pub const MINOR_VERSION: u16 = 0;
+
+/// The kind of descriptor stored in a feature file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum DescriptorType {
+    /// Binary Modified-Local Difference Binary descriptors, compared with
+    /// the Hamming distance.
+    Mldb,
+}
+
+/// The header of a feature file, describing how its features were made.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureFileHeader {
+    /// The width and height of the image, if known
+    pub image_size: Option<(u32, u32)>,
+    /// The kind of descriptor
+    pub descriptor_type: DescriptorType,
+    /// The length of each descriptor in bytes
+    pub descriptor_length: u32,
+    /// The number of features
+    pub num_features: u64,
+    /// The hash of the extraction options, if known, see `config_hash`
+    pub config_hash: Option<u64>,
+}
+
+impl FeatureFileHeader {
+    /// The header of features made with unknown options.
+    ///
+    /// # Arguments
+    /// * `features` - The features.
+    /// # Return value
+    /// The header.
+    pub fn from_features(features: &Features) -> FeatureFileHeader {
+        FeatureFileHeader {
+            image_size: None,
+            descriptor_type: DescriptorType::Mldb,
+            descriptor_length: features
+                .descriptors
+                .first()
+                .map(|descriptor| descriptor.vector.len() as u32)
+                .unwrap_or(0),
+            num_features: features.keypoints.len() as u64,
+            config_hash: None,
+        }
+    }
+
+    /// The header of features extracted from an image.
+    ///
+    /// # Arguments
+    /// * `features` - The features.
+    /// * `image_size` - The width and height of the image.
+    /// * `config` - The extraction options.
+    /// # Return value
+    /// The header.
+    pub fn extracted(
+        features: &Features,
+        image_size: (u32, u32),
+        config: &Config,
+    ) -> FeatureFileHeader {
+        FeatureFileHeader {
+            image_size: Some(image_size),
+            config_hash: Some(config_hash(config)),
+            ..FeatureFileHeader::from_features(features)
+        }
+    }
+
+    /// Check that features of two files can be matched against each other.
+    ///
+    /// Unknown extraction options are assumed to be compatible.
+    ///
+    /// # Arguments
+    /// * `other` - The header of the other file.
+    /// # Return value
+    /// An error describing the mismatch, if any.
+    pub fn check_compatible(&self, other: &FeatureFileHeader) -> Result<(), Error> {
+        if self.descriptor_type != other.descriptor_type {
+            return Err(format_err!(
+                "the descriptor types {:?} and {:?} differ",
+                self.descriptor_type,
+                other.descriptor_type
+            ));
+        }
+        // Empty files have no descriptors to tell the length from
+        if self.num_features > 0
+            && other.num_features > 0
+            && self.descriptor_length != other.descriptor_length
+        {
+            return Err(format_err!(
+                "the descriptor lengths {} and {} differ",
+                self.descriptor_length,
+                other.descriptor_length
+            ));
+        }
+        if let (Some(hash), Some(other_hash)) = (self.config_hash, other.config_hash) {
+            if hash != other_hash {
+                return Err(format_err!(
+                    "the features were extracted with different options ({:016x} and {:016x})",
+                    hash,
+                    other_hash
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A stable hash of extraction options, identical across runs, platforms
+/// and crate versions as long as the options are.
+///
+/// This is the 64 bit FNV-1a hash of the bincode encoding of the options.
+///
+/// # Arguments
+/// * `config` - The extraction options.
+/// # Return value
+/// The hash.
+pub fn config_hash(config: &Config) -> u64 {
+    let bytes = bincode::serialize(config).expect("options are always serializable");
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325u64, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Whether data starts with the magic number of a feature file.
+///
+/// # Arguments
+/// * `bytes` - The first bytes of the data.
+/// # Return value
+/// True if the data is a feature file container.
+pub fn is_feature_file(bytes: &[u8]) -> bool {
+    bytes.starts_with(&MAGIC)
+}
+
+/// Write features in a feature file container.
+///
+/// # Arguments
+/// * `writer` - The destination.
+/// * `header` - The header, with `num_features` matching the features.
+/// * `features` - The features.
+pub fn write_feature_file(
+    mut writer: impl Write,
+    header: &FeatureFileHeader,
+    features: &Features,
+) -> Result<(), Error> {
+    if header.num_features != features.keypoints.len() as u64
+        || features.keypoints.len() != features.descriptors.len()
+    {
+        return Err(format_err!(
+            "the header describes {} features, got {} keypoints and {} descriptors",
+            header.num_features,
+            features.keypoints.len(),
+            features.descriptors.len()
+        ));
+    }
+    let encoded_header = bincode::serialize(header)?;
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&MAJOR_VERSION.to_le_bytes())?;
+    writer.write_all(&MINOR_VERSION.to_le_bytes())?;
+    writer.write_all(&(encoded_header.len() as u32).to_le_bytes())?;
+    writer.write_all(&encoded_header)?;
+    bincode::serialize_into(writer, features)?;
+    Ok(())
+}
+
+/// Read the header of a feature file container, leaving the reader at the
+/// start of the features.
+///
+/// # Arguments
+/// * `reader` - The source, at the start of the container.
+/// # Return value
+/// The header.
+pub fn read_feature_file_header(mut reader: impl Read) -> Result<FeatureFileHeader, Error> {
+    let mut preamble = [0u8; 12];
+    reader.read_exact(&mut preamble)?;
+    if !is_feature_file(&preamble) {
+        return Err(format_err!(
+            "not a feature file, the magic number is missing"
+        ));
+    }
+    let major_version = u16::from_le_bytes([preamble[4], preamble[5]]);
+    let minor_version = u16::from_le_bytes([preamble[6], preamble[7]]);
+    if major_version != MAJOR_VERSION {
+        return Err(format_err!(
+            "unsupported feature file version {}.{}, expected {}.x",
+            major_version,
+            minor_version,
+            MAJOR_VERSION
+        ));
+    }
+    let header_length = u32::from_le_bytes([preamble[8], preamble[9], preamble[10], preamble[11]]);
+    // The length is untrusted, the header is only allocated as it is read
+    let mut encoded_header = vec![];
+    reader
+        .by_ref()
+        .take(u64::from(header_length))
+        .read_to_end(&mut encoded_header)?;
+    if encoded_header.len() != header_length as usize {
+        return Err(format_err!(
+            "the feature file is truncated, its header has {} of {} bytes",
+            encoded_header.len(),
+            header_length
+        ));
+    }
+    // Fields appended by newer minor versions are ignored
+    Ok(bincode::deserialize(&encoded_header)?)
+}
+
+/// Read features from a feature file container.
+///
+/// # Arguments
+/// * `reader` - The source, at the start of the container.
+/// # Return value
+/// The header and the features.
+pub fn read_feature_file(mut reader: impl Read) -> Result<(FeatureFileHeader, Features), Error> {
+    let header = read_feature_file_header(&mut reader)?;
+    let features: Features = bincode::deserialize_from(reader)?;
+    if header.num_features != features.keypoints.len() as u64
+        || features.keypoints.len() != features.descriptors.len()
+    {
+        return Err(format_err!(
+            "the header describes {} features, got {} keypoints and {} descriptors",
+            header.num_features,
+            features.keypoints.len(),
+            features.descriptors.len()
+        ));
+    }
+    Ok((header, features))
+}
+
+/// The features of a feature file container, read one at a time.
+pub struct FeatureStream<R> {
+    keypoints: R,
+    descriptors: R,
+    remaining: u64,
+}
+
+impl FeatureStream<BufReader<File>> {
+    /// Open a feature file container for streaming.
+    ///
+    /// # Arguments
+    /// * `path` - The feature file.
+    /// # Return value
+    /// The header and the stream of keypoints and their descriptors.
+    pub fn open(path: impl AsRef<Path>) -> Result<(FeatureFileHeader, Self), Error> {
+        let path = path.as_ref();
+        FeatureStream::new(
+            BufReader::new(File::open(path)?),
+            BufReader::new(File::open(path)?),
+        )
+    }
+}
+
+impl<R: Read + Seek> FeatureStream<R> {
+    /// Stream a feature file container through two readers of it, one for
+    /// the keypoints and one for the descriptors.
+    ///
+    /// # Arguments
+    /// * `keypoints` - A reader at the start of the container.
+    /// * `descriptors` - Another reader of the same container.
+    /// # Return value
+    /// The header and the stream of keypoints and their descriptors.
+    pub fn new(mut keypoints: R, mut descriptors: R) -> Result<(FeatureFileHeader, Self), Error> {
+        let header = read_feature_file_header(&mut keypoints)?;
+        let num_keypoints: u64 = bincode::deserialize_from(&mut keypoints)?;
+        let keypoint_size = bincode::serialized_size(&Keypoint {
+            point: (0f32, 0f32),
+            response: 0f32,
+            size: 0f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
+        })?;
+        let start = keypoints.stream_position()?;
+        descriptors.seek(SeekFrom::Start(start + num_keypoints * keypoint_size))?;
+        let num_descriptors: u64 = bincode::deserialize_from(&mut descriptors)?;
+        if header.num_features != num_keypoints || num_keypoints != num_descriptors {
+            return Err(format_err!(
+                "the header describes {} features, got {} keypoints and {} descriptors",
+                header.num_features,
+                num_keypoints,
+                num_descriptors
+            ));
+        }
+        Ok((
+            header,
+            FeatureStream {
+                keypoints,
+                descriptors,
+                remaining: num_keypoints,
+            },
+        ))
+    }
+}
+
+impl<R: Read> Iterator for FeatureStream<R> {
+    type Item = Result<(Keypoint, Descriptor), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let feature = bincode::deserialize_from(&mut self.keypoints)
+            .and_then(|keypoint| Ok((keypoint, bincode::deserialize_from(&mut self.descriptors)?)));
+        if feature.is_err() {
+            self.remaining = 0;
+        }
+        Some(feature.map_err(Error::from))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
@@ -0,0 +1,336 @@
+//! Comparison of feature files against golden reference files, for
+//! regression tests of the extractor.
+//!
+//! Keypoints are paired by position: each reference keypoint with its
+//! nearest candidate keypoint of the same evolution level (class id), when
+//! each is the other's nearest within `GoldenConfig::match_radius`. Levels
+//! are compared separately since keypoints of different levels are often
+//! at the same position. The paired keypoints are then compared within
+//! the tolerances, and their descriptors bit by bit.
+
+use crate::Features;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+use std::fmt;
+
+/// Tolerances of the comparison.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GoldenConfig {
+    /// The largest distance in pixels at which keypoints are paired
+    pub match_radius: f32,
+    /// The largest accepted position difference in pixels
+    pub position: f32,
+    /// The largest accepted size difference in pixels
+    pub size: f32,
+    /// The largest accepted angle difference in radians
+    pub angle: f32,
+    /// The largest accepted relative response difference
+    pub response: f32,
+    /// The largest accepted number of differing descriptor bits
+    pub descriptor_bits: u32,
+}
+
+impl Default for GoldenConfig {
+    fn default() -> GoldenConfig {
+        GoldenConfig {
+            match_radius: 1.5,
+            position: 1e-3,
+            size: 1e-3,
+            angle: 1e-3,
+            response: 1e-3,
+            descriptor_bits: 0,
+        }
+    }
+}
+
+/// Statistics of a difference over the paired keypoints.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct DifferenceStatistics {
+    /// The mean difference
+    pub mean: f64,
+    /// The largest difference
+    pub max: f64,
+    /// The number of pairs whose difference exceeds the tolerance
+    pub exceeding: usize,
+}
+
+impl DifferenceStatistics {
+    fn new(differences: impl IntoIterator<Item = f64>, tolerance: f64) -> DifferenceStatistics {
+        let mut statistics = DifferenceStatistics::default();
+        let mut count = 0usize;
+        for difference in differences {
+            statistics.mean += difference;
+            statistics.max = statistics.max.max(difference);
+            if difference > tolerance {
+                statistics.exceeding += 1;
+            }
+            count += 1;
+        }
+        statistics.mean /= count.max(1) as f64;
+        statistics
+    }
+}
+
+/// The differences between reference and candidate features.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureComparison {
+    /// The number of reference features
+    pub reference_count: usize,
+    /// The number of candidate features
+    pub candidate_count: usize,
+    /// Whether the features are identical, in the same order
+    pub identical: bool,
+    /// The number of keypoints paired by position and level
+    pub paired: usize,
+    /// The number of features of each octave, reference then candidate
+    pub octave_counts: Vec<(usize, usize)>,
+    /// Position differences in pixels
+    pub position: DifferenceStatistics,
+    /// Size differences in pixels
+    pub size: DifferenceStatistics,
+    /// Angle differences in radians, modulo 2 pi
+    pub angle: DifferenceStatistics,
+    /// Relative response differences
+    pub response: DifferenceStatistics,
+    /// Numbers of differing descriptor bits
+    pub descriptor_bits: DifferenceStatistics,
+}
+
+impl FeatureComparison {
+    /// Whether the candidate features match the reference features: all
+    /// are paired and within the tolerances.
+    pub fn passes(&self) -> bool {
+        self.identical
+            || (self.paired == self.reference_count
+                && self.paired == self.candidate_count
+                && self.position.exceeding == 0
+                && self.size.exceeding == 0
+                && self.angle.exceeding == 0
+                && self.response.exceeding == 0
+                && self.descriptor_bits.exceeding == 0)
+    }
+}
+
+impl fmt::Display for FeatureComparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "features: {} reference, {} candidate, {}",
+            self.reference_count,
+            self.candidate_count,
+            if self.identical {
+                "identical"
+            } else {
+                "different"
+            }
+        )?;
+        for (octave, (reference, candidate)) in self.octave_counts.iter().enumerate() {
+            writeln!(
+                f,
+                "  octave {}: {} reference, {} candidate",
+                octave, reference, candidate
+            )?;
+        }
+        writeln!(
+            f,
+            "paired by position: {} ({:.2}% of reference, {:.2}% of candidate)",
+            self.paired,
+            100f64 * self.paired as f64 / self.reference_count.max(1) as f64,
+            100f64 * self.paired as f64 / self.candidate_count.max(1) as f64
+        )?;
+        for (name, statistics) in &[
+            ("position", self.position),
+            ("size", self.size),
+            ("angle", self.angle),
+            ("response", self.response),
+            ("descriptor bits", self.descriptor_bits),
+        ] {
+            writeln!(
+                f,
+                "  {:15} mean {:e} max {:e}, {} pairs out of tolerance",
+                name, statistics.mean, statistics.max, statistics.exceeding
+            )?;
+        }
+        write!(f, "{}", if self.passes() { "PASS" } else { "FAIL" })
+    }
+}
+
+fn distance(keypoint_0: &Keypoint, keypoint_1: &Keypoint) -> f32 {
+    let dx = keypoint_0.point.0 - keypoint_1.point.0;
+    let dy = keypoint_0.point.1 - keypoint_1.point.1;
+    (dx * dx + dy * dy).sqrt()
+}
+
+/// The keypoints, bucketed by level in a grid of cells of the match
+/// radius.
+struct Grid<'a> {
+    keypoints: &'a [Keypoint],
+    radius: f32,
+    cells: HashMap<(usize, i64, i64), Vec<usize>>,
+}
+
+impl<'a> Grid<'a> {
+    fn new(keypoints: &'a [Keypoint], radius: f32) -> Grid<'a> {
+        let mut cells: HashMap<(usize, i64, i64), Vec<usize>> = HashMap::new();
+        for (i, keypoint) in keypoints.iter().enumerate() {
+            cells
+                .entry(Grid::cell(keypoint, radius))
+                .or_default()
+                .push(i);
+        }
+        Grid {
+            keypoints,
+            radius,
+            cells,
+        }
+    }
+
+    fn cell(keypoint: &Keypoint, radius: f32) -> (usize, i64, i64) {
+        (
+            keypoint.class_id,
+            (keypoint.point.0 / radius).floor() as i64,
+            (keypoint.point.1 / radius).floor() as i64,
+        )
+    }
+
+    /// The index of the nearest keypoint of the same level within the
+    /// radius.
+    fn nearest(&self, keypoint: &Keypoint) -> Option<usize> {
+        let (level, x, y) = Grid::cell(keypoint, self.radius);
+        let mut best: Option<(usize, f32)> = None;
+        for cell_x in x - 1..=x + 1 {
+            for cell_y in y - 1..=y + 1 {
+                for &i in self
+                    .cells
+                    .get(&(level, cell_x, cell_y))
+                    .into_iter()
+                    .flatten()
+                {
+                    let d = distance(keypoint, &self.keypoints[i]);
+                    if d <= self.radius && best.map_or(true, |(_, best_d)| d < best_d) {
+                        best = Some((i, d));
+                    }
+                }
+            }
+        }
+        best.map(|(i, _)| i)
+    }
+}
+
+fn descriptor_bits(descriptor_0: &Descriptor, descriptor_1: &Descriptor) -> u32 {
+    let common: u32 = descriptor_0
+        .vector
+        .iter()
+        .zip(descriptor_1.vector.iter())
+        .map(|(byte_0, byte_1)| (byte_0 ^ byte_1).count_ones())
+        .sum();
+    let extra = descriptor_0.vector.len().max(descriptor_1.vector.len())
+        - descriptor_0.vector.len().min(descriptor_1.vector.len());
+    common + 8 * extra as u32
+}
+
+fn identical(reference: &Features, candidate: &Features) -> bool {
+    reference.keypoints.len() == candidate.keypoints.len()
+        && reference.descriptors.len() == candidate.descriptors.len()
+        && reference
+            .keypoints
+            .iter()
+            .zip(candidate.keypoints.iter())
+            .all(|(keypoint_0, keypoint_1)| {
+                keypoint_0.point == keypoint_1.point
+                    && keypoint_0.size == keypoint_1.size
+                    && keypoint_0.angle == keypoint_1.angle
+                    && keypoint_0.response == keypoint_1.response
+                    && keypoint_0.octave == keypoint_1.octave
+                    && keypoint_0.class_id == keypoint_1.class_id
+            })
+        && reference
+            .descriptors
+            .iter()
+            .zip(candidate.descriptors.iter())
+            .all(|(descriptor_0, descriptor_1)| descriptor_0.vector == descriptor_1.vector)
+}
+
+/// Compare candidate features with reference features.
+///
+/// # Arguments
+/// * `reference` - The reference features, e.g. of a golden file.
+/// * `candidate` - The features to check.
+/// * `config` - The tolerances.
+/// # Return value
+/// The differences.
+pub fn compare_features(
+    reference: &Features,
+    candidate: &Features,
+    config: &GoldenConfig,
+) -> FeatureComparison {
+    let reference_grid = Grid::new(&reference.keypoints, config.match_radius);
+    let candidate_grid = Grid::new(&candidate.keypoints, config.match_radius);
+    let pairs: Vec<(usize, usize)> = reference
+        .keypoints
+        .iter()
+        .enumerate()
+        .filter_map(|(i, keypoint)| {
+            let j = candidate_grid.nearest(keypoint)?;
+            // Only mutual nearest keypoints are paired
+            if reference_grid.nearest(&candidate.keypoints[j]) == Some(i) {
+                Some((i, j))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let paired = |difference: &dyn Fn(&Keypoint, &Keypoint) -> f32| -> Vec<f64> {
+        pairs
+            .iter()
+            .map(|&(i, j)| f64::from(difference(&reference.keypoints[i], &candidate.keypoints[j])))
+            .collect()
+    };
+    let angle = |keypoint_0: &Keypoint, keypoint_1: &Keypoint| {
+        let difference = (keypoint_0.angle - keypoint_1.angle).rem_euclid(2f32 * PI);
+        difference.min(2f32 * PI - difference)
+    };
+    let response = |keypoint_0: &Keypoint, keypoint_1: &Keypoint| {
+        (keypoint_0.response - keypoint_1.response).abs()
+            / keypoint_0.response.abs().max(f32::MIN_POSITIVE)
+    };
+    let num_octaves = reference
+        .keypoints
+        .iter()
+        .chain(candidate.keypoints.iter())
+        .map(|keypoint| keypoint.octave + 1)
+        .max()
+        .unwrap_or(0);
+    let mut octave_counts = vec![(0, 0); num_octaves];
+    for keypoint in &reference.keypoints {
+        octave_counts[keypoint.octave].0 += 1;
+    }
+    for keypoint in &candidate.keypoints {
+        octave_counts[keypoint.octave].1 += 1;
+    }
+    FeatureComparison {
+        reference_count: reference.keypoints.len(),
+        candidate_count: candidate.keypoints.len(),
+        identical: identical(reference, candidate),
+        paired: pairs.len(),
+        octave_counts,
+        position: DifferenceStatistics::new(paired(&distance), f64::from(config.position)),
+        size: DifferenceStatistics::new(
+            paired(&|keypoint_0, keypoint_1| (keypoint_0.size - keypoint_1.size).abs()),
+            f64::from(config.size),
+        ),
+        angle: DifferenceStatistics::new(paired(&angle), f64::from(config.angle)),
+        response: DifferenceStatistics::new(paired(&response), f64::from(config.response)),
+        descriptor_bits: DifferenceStatistics::new(
+            pairs.iter().filter_map(|&(i, j)| {
+                Some(f64::from(descriptor_bits(
+                    reference.descriptors.get(i)?,
+                    candidate.descriptors.get(j)?,
+                )))
+            }),
+            f64::from(config.descriptor_bits),
+        ),
+    }
+}
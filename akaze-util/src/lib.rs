@@ -1,12 +1,39 @@
+use crate::compact::{
+    read_compact_features, read_compact_matches, write_compact_features, write_compact_matches,
+    Precision,
+};
+use crate::feature_file::{
+    is_feature_file, read_feature_file, write_feature_file, FeatureFileHeader,
+};
+use crate::numpy::{read_features_npz, read_matches_npy, write_features_npz, write_matches_npy};
 use akaze::types::feature_match::Match;
 use akaze::types::keypoint::{Descriptor, Keypoint};
+use akaze::types::vocabulary::Vocabulary;
 use failure::Error;
 use log::*;
 use serde::{Deserialize, Serialize};
 use std::ffi::OsStr;
 use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter};
 use std::path::Path;
 
+pub mod archive;
+pub mod collection;
+pub mod colmap;
+pub mod compact;
+pub mod feature_file;
+pub mod golden;
+pub mod lowe;
+pub mod numpy;
+pub mod opencv;
+pub mod openmvg;
+pub mod parity;
+pub mod rectification;
+pub mod registration;
+pub mod scale_space;
+pub mod stitching;
+pub mod warp;
+
 #[derive(Serialize, Deserialize)]
 pub struct Features {
     pub keypoints: Vec<Keypoint>,
@@ -14,9 +41,31 @@ pub struct Features {
 }
 
 /// Serialize features to a file.
+///
+/// JSON files hold the bare features, `.npz` files NumPy arrays (see
+/// `numpy`), `.akzc` and `.akzq` files compact lossless and quantized
+/// features (see `compact`), other files a feature file container (see
+/// `feature_file`) with the descriptor type and length. Use
+/// `serialize_features_with_header_to_file` to also record the image size
+/// and extraction options.
 pub fn serialize_features_to_file(
     features: &Features,
     path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    serialize_features_with_header_to_file(
+        features,
+        &FeatureFileHeader::from_features(features),
+        path,
+    )
+}
+
+/// Serialize features and their header to a file.
+///
+/// The header is not written to JSON and `.npz` files.
+pub fn serialize_features_with_header_to_file(
+    features: &Features,
+    header: &FeatureFileHeader,
+    path: impl AsRef<Path>,
 ) -> Result<(), Error> {
     let path = path.as_ref();
     debug!("Writing features to {:?}", path);
@@ -24,21 +73,56 @@ pub fn serialize_features_to_file(
     let extension = path.extension().and_then(OsStr::to_str).unwrap_or("bin");
     match extension {
         "json" => serde_json::to_writer(file, features)?,
-        _ => bincode::serialize_into(file, features)?,
+        "npz" => write_features_npz(features, BufWriter::new(file))?,
+        "akzc" | "akzq" => write_compact_features(
+            BufWriter::new(file),
+            header,
+            features,
+            Precision::from_extension(extension).unwrap(),
+        )?,
+        _ => write_feature_file(BufWriter::new(file), header, features)?,
     }
     Ok(())
 }
 
-/// Serialize features to a file.
+/// Deserialize features from a file.
+///
+/// Reads JSON files, `.npz` files, compact files, feature file containers,
+/// and the bare bincode files written by earlier versions of this crate.
 pub fn deserialize_features_from_file(path: impl AsRef<Path>) -> Result<Features, Error> {
+    Ok(deserialize_features_with_header_from_file(path)?.1)
+}
+
+/// Deserialize features and their header from a file.
+///
+/// The header is None for JSON, `.npz` and bare bincode files.
+pub fn deserialize_features_with_header_from_file(
+    path: impl AsRef<Path>,
+) -> Result<(Option<FeatureFileHeader>, Features), Error> {
     let path = path.as_ref();
     debug!("Reading features from {:?}", path);
     let file = File::open(path)?;
     let extension = path.extension().and_then(OsStr::to_str).unwrap_or("bin");
-    Ok(match extension {
-        "json" => serde_json::from_reader(file)?,
-        _ => bincode::deserialize_from(file)?,
-    })
+    match extension {
+        "json" => return Ok((None, serde_json::from_reader(file)?)),
+        "npz" => return Ok((None, read_features_npz(BufReader::new(file))?)),
+        "akzc" | "akzq" => {
+            let (header, features, _) = read_compact_features(BufReader::new(file))?;
+            return Ok((Some(header), features));
+        }
+        _ => {}
+    }
+    let mut reader = BufReader::new(file);
+    if is_feature_file(reader.fill_buf()?) {
+        let (header, features) = read_feature_file(reader)?;
+        Ok((Some(header), features))
+    } else {
+        warn!(
+            "{:?} has no feature file header, reading it as bare bincode.",
+            path
+        );
+        Ok((None, bincode::deserialize_from(reader)?))
+    }
 }
 
 /// Serialize matches to a file.
@@ -49,6 +133,8 @@ pub fn serialize_matches_to_file(matches: &[Match], path: impl AsRef<Path>) -> R
     let extension = path.extension().and_then(OsStr::to_str).unwrap_or("bin");
     match extension {
         "json" => serde_json::to_writer(file, matches)?,
+        "npy" => write_matches_npy(matches, BufWriter::new(file))?,
+        "akzc" | "akzq" => write_compact_matches(BufWriter::new(file), matches)?,
         _ => bincode::serialize_into(file, matches)?,
     }
     Ok(())
@@ -60,6 +146,36 @@ pub fn deserialize_matches_from_file(path: impl AsRef<Path>) -> Result<Vec<Match
     debug!("Reading matches to {:?}", path);
     let file = File::open(path)?;
     let extension = path.extension().and_then(OsStr::to_str).unwrap_or("bin");
+    Ok(match extension {
+        "json" => serde_json::from_reader(file)?,
+        "npy" => read_matches_npy(BufReader::new(file))?,
+        "akzc" | "akzq" => read_compact_matches(BufReader::new(file))?,
+        _ => bincode::deserialize_from(file)?,
+    })
+}
+
+/// Serialize a vocabulary to a file.
+pub fn serialize_vocabulary_to_file(
+    vocabulary: &Vocabulary,
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    debug!("Writing vocabulary to {:?}", path);
+    let file = File::create(path)?;
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or("bin");
+    match extension {
+        "json" => serde_json::to_writer(file, vocabulary)?,
+        _ => bincode::serialize_into(file, vocabulary)?,
+    }
+    Ok(())
+}
+
+/// Deserialize a vocabulary from a file.
+pub fn deserialize_vocabulary_from_file(path: impl AsRef<Path>) -> Result<Vocabulary, Error> {
+    let path = path.as_ref();
+    debug!("Reading vocabulary from {:?}", path);
+    let file = File::open(path)?;
+    let extension = path.extension().and_then(OsStr::to_str).unwrap_or("bin");
     Ok(match extension {
         "json" => serde_json::from_reader(file)?,
         _ => bincode::deserialize_from(file)?,
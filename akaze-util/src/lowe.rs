@@ -0,0 +1,71 @@
+//! Export to David Lowe's ASCII keypoint format, as read by Bundler from
+//! `.key` files and by VisualSFM from `.sift` files.
+//!
+//! The first line holds the number of keypoints and the descriptor
+//! length. Each keypoint is then a line with its row, column, scale and
+//! orientation in radians, followed by its descriptor bytes, 20 per line.
+//! The scale is the keypoint radius. Tools that compare descriptors as
+//! 128 dimensional SIFT vectors cannot match these, so matches should be
+//! imported as well.
+
+use crate::collection::image_name;
+use crate::{deserialize_features_from_file, Features};
+use failure::Error;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Write features in Lowe's ASCII keypoint format.
+///
+/// # Arguments
+/// * `features` - The features.
+/// * `writer` - The destination.
+pub fn write_lowe_keys(features: &Features, mut writer: impl Write) -> Result<(), Error> {
+    let length = features
+        .descriptors
+        .first()
+        .map(|descriptor| descriptor.vector.len())
+        .unwrap_or(0);
+    writeln!(writer, "{} {}", features.keypoints.len(), length)?;
+    for (keypoint, descriptor) in features.keypoints.iter().zip(features.descriptors.iter()) {
+        writeln!(
+            writer,
+            "{} {} {} {}",
+            keypoint.point.1, keypoint.point.0, keypoint.size, keypoint.angle
+        )?;
+        for line in descriptor.vector.chunks(20) {
+            for byte in line {
+                write!(writer, " {}", byte)?;
+            }
+            writeln!(writer)?;
+        }
+    }
+    Ok(())
+}
+
+/// Export feature files to Lowe's ASCII keypoint format.
+///
+/// # Arguments
+/// * `images` - The feature files.
+/// * `output` - The output directory, created if missing.
+/// * `extension` - The extension of the written files, `key` for Bundler
+///   or `sift` for VisualSFM.
+/// # Return value
+/// The written files.
+pub fn export_features_to_lowe(
+    images: &[PathBuf],
+    output: impl AsRef<Path>,
+    extension: &str,
+) -> Result<Vec<PathBuf>, Error> {
+    let output = output.as_ref();
+    fs::create_dir_all(output)?;
+    images
+        .iter()
+        .map(|path| {
+            let features = deserialize_features_from_file(path)?;
+            let key_path = output.join(format!("{}.{}", image_name(path), extension));
+            write_lowe_keys(&features, BufWriter::new(File::create(&key_path)?))?;
+            Ok(key_path)
+        })
+        .collect()
+}
@@ -0,0 +1,465 @@
+//! NumPy `.npy` and `.npz` files, for analysis in Python without parsing
+//! bincode.
+//!
+//! Keypoints are an N x 6 `float32` array with the columns x, y, size,
+//! angle, response and octave. Descriptors are an N x 61 `uint8` array.
+//! Matches are an M x 3 `float64` array with the columns index_0, index_1
+//! and distance. A features `.npz` holds `keypoints.npy` and
+//! `descriptors.npy`, uncompressed as written by `numpy.savez`, so
+//! `numpy.load("features.npz")["keypoints"]` reads the keypoints.
+
+use crate::Features;
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use failure::{format_err, Error};
+use std::io::{Read, Write};
+
+const NPY_MAGIC: &[u8] = b"\x93NUMPY";
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+/// The number of keypoint columns.
+pub const KEYPOINT_COLUMNS: usize = 6;
+
+/// The number of match columns.
+pub const MATCH_COLUMNS: usize = 3;
+
+/// An array read from a `.npy` file.
+struct Array {
+    descr: String,
+    shape: Vec<usize>,
+    data: Vec<u8>,
+}
+
+impl Array {
+    /// The rows and columns of a two dimensional array of a type.
+    fn matrix(&self, descrs: &[&str], columns: usize) -> Result<usize, Error> {
+        if !descrs.contains(&self.descr.as_str()) {
+            return Err(format_err!(
+                "expected an array of type {}, got {}",
+                descrs[0],
+                self.descr
+            ));
+        }
+        match self.shape[..] {
+            [rows, cols] if cols == columns || rows == 0 => Ok(rows),
+            _ => Err(format_err!(
+                "expected an array of shape (N, {}), got {:?}",
+                columns,
+                self.shape
+            )),
+        }
+    }
+}
+
+fn write_npy(
+    mut writer: impl Write,
+    descr: &str,
+    shape: (usize, usize),
+    data: &[u8],
+) -> Result<(), Error> {
+    let mut header = format!(
+        "{{'descr': '{}', 'fortran_order': False, 'shape': ({}, {}), }}",
+        descr, shape.0, shape.1
+    );
+    // The data is aligned to 64 bytes, the header ends with a newline
+    let unpadded = NPY_MAGIC.len() + 4 + header.len() + 1;
+    header.push_str(&" ".repeat((64 - unpadded % 64) % 64));
+    header.push('\n');
+    writer.write_all(NPY_MAGIC)?;
+    writer.write_all(&[1u8, 0u8])?;
+    writer.write_all(&(header.len() as u16).to_le_bytes())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+/// The value of a key in the header dictionary, up to the terminator.
+fn header_value<'a>(header: &'a str, key: &str, terminator: char) -> Result<&'a str, Error> {
+    let key = format!("'{}':", key);
+    let start = header
+        .find(&key)
+        .ok_or_else(|| format_err!("the npy header has no {}", key))?
+        + key.len();
+    let rest = header[start..].trim_start();
+    let end = rest[1..]
+        .find(terminator)
+        .ok_or_else(|| format_err!("malformed npy header: {}", header))?;
+    Ok(&rest[..end + 2])
+}
+
+fn read_npy(mut reader: impl Read) -> Result<Array, Error> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if &preamble[..6] != NPY_MAGIC {
+        return Err(format_err!("not an npy file, the magic number is missing"));
+    }
+    let header_length = match preamble[6] {
+        1 => {
+            let mut length = [0u8; 2];
+            reader.read_exact(&mut length)?;
+            usize::from(u16::from_le_bytes(length))
+        }
+        2 | 3 => {
+            let mut length = [0u8; 4];
+            reader.read_exact(&mut length)?;
+            u32::from_le_bytes(length) as usize
+        }
+        version => return Err(format_err!("unsupported npy version {}", version)),
+    };
+    let mut header = vec![0u8; header_length];
+    reader.read_exact(&mut header)?;
+    let header = String::from_utf8(header)?;
+    let descr = header_value(&header, "descr", '\'')?.trim_matches('\'');
+    if header_value(&header, "fortran_order", 'e')? != "False" {
+        return Err(format_err!("Fortran ordered arrays are not supported"));
+    }
+    let shape = header_value(&header, "shape", ')')?
+        .trim_matches(|c| c == '(' || c == ')')
+        .split(',')
+        .map(str::trim)
+        .filter(|dimension| !dimension.is_empty())
+        .map(str::parse)
+        .collect::<Result<Vec<usize>, _>>()?;
+    let element_size: usize = match descr {
+        "|u1" | "<u1" => 1,
+        "<f4" => 4,
+        "<f8" => 8,
+        _ => return Err(format_err!("unsupported npy type {}", descr)),
+    };
+    let mut data = vec![0u8; shape.iter().product::<usize>() * element_size];
+    reader.read_exact(&mut data)?;
+    Ok(Array {
+        descr: descr.to_owned(),
+        shape,
+        data,
+    })
+}
+
+/// Write keypoints as an N x 6 `float32` `.npy` array.
+///
+/// # Arguments
+/// * `keypoints` - The keypoints.
+/// * `writer` - The destination.
+pub fn write_keypoints_npy(keypoints: &[Keypoint], writer: impl Write) -> Result<(), Error> {
+    let mut data = Vec::with_capacity(keypoints.len() * KEYPOINT_COLUMNS * 4);
+    for keypoint in keypoints {
+        for value in &[
+            keypoint.point.0,
+            keypoint.point.1,
+            keypoint.size,
+            keypoint.angle,
+            keypoint.response,
+            keypoint.octave as f32,
+        ] {
+            data.extend_from_slice(&value.to_bits().to_le_bytes());
+        }
+    }
+    write_npy(writer, "<f4", (keypoints.len(), KEYPOINT_COLUMNS), &data)
+}
+
+/// Read keypoints from an N x 6 `float32` `.npy` array.
+///
+/// # Arguments
+/// * `reader` - The source.
+/// # Return value
+/// The keypoints, with a class id of 0.
+pub fn read_keypoints_npy(reader: impl Read) -> Result<Vec<Keypoint>, Error> {
+    let array = read_npy(reader)?;
+    array.matrix(&["<f4"], KEYPOINT_COLUMNS)?;
+    let values: Vec<f32> = array
+        .data
+        .chunks_exact(4)
+        .map(|bytes| f32::from_bits(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+        .collect();
+    Ok(values
+        .chunks_exact(KEYPOINT_COLUMNS)
+        .map(|row| Keypoint {
+            point: (row[0], row[1]),
+            size: row[2],
+            angle: row[3],
+            response: row[4],
+            octave: row[5] as usize,
+            class_id: 0,
+        })
+        .collect())
+}
+
+/// Write descriptors as an N x 61 `uint8` `.npy` array.
+///
+/// # Arguments
+/// * `descriptors` - The descriptors, all of the same length.
+/// * `writer` - The destination.
+pub fn write_descriptors_npy(descriptors: &[Descriptor], writer: impl Write) -> Result<(), Error> {
+    let length = descriptors
+        .first()
+        .map(|descriptor| descriptor.vector.len())
+        .unwrap_or(0);
+    let mut data = Vec::with_capacity(descriptors.len() * length);
+    for descriptor in descriptors {
+        if descriptor.vector.len() != length {
+            return Err(format_err!("the descriptors are of different lengths"));
+        }
+        data.extend_from_slice(&descriptor.vector);
+    }
+    write_npy(writer, "|u1", (descriptors.len(), length), &data)
+}
+
+/// Read descriptors from an N x L `uint8` `.npy` array.
+///
+/// # Arguments
+/// * `reader` - The source.
+/// # Return value
+/// The descriptors, one per row.
+pub fn read_descriptors_npy(reader: impl Read) -> Result<Vec<Descriptor>, Error> {
+    let array = read_npy(reader)?;
+    if array.shape.len() != 2 {
+        return Err(format_err!(
+            "expected a two dimensional array, got {:?}",
+            array.shape
+        ));
+    }
+    array.matrix(&["|u1", "<u1"], array.shape[1])?;
+    let length = array.shape[1].max(1);
+    Ok(array
+        .data
+        .chunks_exact(length)
+        .map(|vector| Descriptor {
+            vector: vector.to_vec(),
+        })
+        .collect())
+}
+
+/// Write matches as an M x 3 `float64` `.npy` array.
+///
+/// # Arguments
+/// * `matches` - The matches.
+/// * `writer` - The destination.
+pub fn write_matches_npy(matches: &[Match], writer: impl Write) -> Result<(), Error> {
+    let mut data = Vec::with_capacity(matches.len() * MATCH_COLUMNS * 8);
+    for match_i in matches {
+        for value in &[
+            match_i.index_0 as f64,
+            match_i.index_1 as f64,
+            match_i.distance,
+        ] {
+            data.extend_from_slice(&value.to_bits().to_le_bytes());
+        }
+    }
+    write_npy(writer, "<f8", (matches.len(), MATCH_COLUMNS), &data)
+}
+
+/// Read matches from an M x 3 `float64` `.npy` array.
+///
+/// # Arguments
+/// * `reader` - The source.
+/// # Return value
+/// The matches.
+pub fn read_matches_npy(reader: impl Read) -> Result<Vec<Match>, Error> {
+    let array = read_npy(reader)?;
+    array.matrix(&["<f8"], MATCH_COLUMNS)?;
+    let values: Vec<f64> = array
+        .data
+        .chunks_exact(8)
+        .map(|bytes| {
+            let mut value = [0u8; 8];
+            value.copy_from_slice(bytes);
+            f64::from_bits(u64::from_le_bytes(value))
+        })
+        .collect();
+    values
+        .chunks_exact(MATCH_COLUMNS)
+        .map(|row| {
+            if row[0] < 0f64 || row[1] < 0f64 || row[0].fract() != 0f64 || row[1].fract() != 0f64 {
+                return Err(format_err!("invalid match indices {} {}", row[0], row[1]));
+            }
+            Ok(Match {
+                index_0: row[0] as usize,
+                index_1: row[1] as usize,
+                distance: row[2],
+            })
+        })
+        .collect()
+}
+
+/// The CRC-32 checksum of zip files.
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        *entry = (0..8).fold(i as u32, |c, _| {
+            if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            }
+        });
+    }
+    !data.iter().fold(!0u32, |c, &byte| {
+        table[((c ^ u32::from(byte)) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+/// Write files into an uncompressed zip archive, as `numpy.savez` does.
+fn write_zip(mut writer: impl Write, files: &[(&str, Vec<u8>)]) -> Result<(), Error> {
+    let mut offset = 0usize;
+    let mut central_directory = vec![];
+    for (name, data) in files {
+        if data.len() > u32::MAX as usize || offset > u32::MAX as usize {
+            return Err(format_err!(
+                "the archive is too large for zip without zip64"
+            ));
+        }
+        let crc = crc32(data);
+        // Version 2.0, no flags, stored, 1980-01-01 00:00
+        let mut fields = vec![];
+        fields.extend_from_slice(&20u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        fields.extend_from_slice(&0x21u16.to_le_bytes());
+        fields.extend_from_slice(&crc.to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        fields.extend_from_slice(&0u16.to_le_bytes());
+        writer.write_all(&LOCAL_FILE_HEADER.to_le_bytes())?;
+        writer.write_all(&fields)?;
+        writer.write_all(name.as_bytes())?;
+        writer.write_all(data)?;
+        central_directory.extend_from_slice(&CENTRAL_DIRECTORY_HEADER.to_le_bytes());
+        central_directory.extend_from_slice(&20u16.to_le_bytes());
+        central_directory.extend_from_slice(&fields);
+        // No comment, disk 0, no attributes
+        central_directory.extend_from_slice(&[0u8; 10]);
+        central_directory.extend_from_slice(&(offset as u32).to_le_bytes());
+        central_directory.extend_from_slice(name.as_bytes());
+        offset += 4 + fields.len() + name.len() + data.len();
+    }
+    writer.write_all(&central_directory)?;
+    writer.write_all(&END_OF_CENTRAL_DIRECTORY.to_le_bytes())?;
+    writer.write_all(&[0u8; 4])?;
+    writer.write_all(&(files.len() as u16).to_le_bytes())?;
+    writer.write_all(&(files.len() as u16).to_le_bytes())?;
+    writer.write_all(&(central_directory.len() as u32).to_le_bytes())?;
+    writer.write_all(&(offset as u32).to_le_bytes())?;
+    writer.write_all(&0u16.to_le_bytes())?;
+    Ok(())
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<usize, Error> {
+    bytes
+        .get(offset..offset + 2)
+        .map(|field| usize::from(u16::from_le_bytes([field[0], field[1]])))
+        .ok_or_else(|| format_err!("truncated zip archive"))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<usize, Error> {
+    bytes
+        .get(offset..offset + 4)
+        .map(|field| u32::from_le_bytes([field[0], field[1], field[2], field[3]]) as usize)
+        .ok_or_else(|| format_err!("truncated zip archive"))
+}
+
+/// The stored files of a zip archive, by name.
+fn read_zip(archive: &[u8]) -> Result<Vec<(String, &[u8])>, Error> {
+    // The end of central directory record is followed by a comment of at
+    // most 65535 bytes
+    let end = (0..archive.len().saturating_sub(21))
+        .rev()
+        .take(65536)
+        .find(|&offset| u32_at(archive, offset).ok() == Some(END_OF_CENTRAL_DIRECTORY as usize))
+        .ok_or_else(|| format_err!("not a zip archive"))?;
+    let count = u16_at(archive, end + 10)?;
+    let mut offset = u32_at(archive, end + 16)?;
+    let mut files = vec![];
+    for _ in 0..count {
+        if u32_at(archive, offset)? != CENTRAL_DIRECTORY_HEADER as usize {
+            return Err(format_err!("malformed zip central directory"));
+        }
+        let method = u16_at(archive, offset + 10)?;
+        let crc = u32_at(archive, offset + 16)?;
+        let size = u32_at(archive, offset + 20)?;
+        let name_length = u16_at(archive, offset + 28)?;
+        let extra_length = u16_at(archive, offset + 30)?;
+        let comment_length = u16_at(archive, offset + 32)?;
+        let local = u32_at(archive, offset + 42)?;
+        let name = archive
+            .get(offset + 46..offset + 46 + name_length)
+            .ok_or_else(|| format_err!("truncated zip archive"))?;
+        let name = String::from_utf8(name.to_vec())?;
+        offset += 46 + name_length + extra_length + comment_length;
+        if method != 0 {
+            return Err(format_err!(
+                "{} is compressed, only numpy.savez archives are supported",
+                name
+            ));
+        }
+        if u32_at(archive, local)? != LOCAL_FILE_HEADER as usize {
+            return Err(format_err!("malformed zip file header of {}", name));
+        }
+        let start = local + 30 + u16_at(archive, local + 26)? + u16_at(archive, local + 28)?;
+        let data = archive
+            .get(start..start + size)
+            .ok_or_else(|| format_err!("truncated zip archive"))?;
+        if crc32(data) as usize != crc {
+            return Err(format_err!("the checksum of {} does not match", name));
+        }
+        files.push((name, data));
+    }
+    Ok(files)
+}
+
+/// Write features as an `.npz` archive of `keypoints.npy` and
+/// `descriptors.npy`.
+///
+/// # Arguments
+/// * `features` - The features.
+/// * `writer` - The destination.
+pub fn write_features_npz(features: &Features, writer: impl Write) -> Result<(), Error> {
+    let mut keypoints = vec![];
+    write_keypoints_npy(&features.keypoints, &mut keypoints)?;
+    let mut descriptors = vec![];
+    write_descriptors_npy(&features.descriptors, &mut descriptors)?;
+    write_zip(
+        writer,
+        &[
+            ("keypoints.npy", keypoints),
+            ("descriptors.npy", descriptors),
+        ],
+    )
+}
+
+/// Read features from an `.npz` archive with `keypoints` and
+/// `descriptors` arrays, as written by `write_features_npz` or by
+/// `numpy.savez(path, keypoints=..., descriptors=...)`.
+///
+/// # Arguments
+/// * `reader` - The source.
+/// # Return value
+/// The features.
+pub fn read_features_npz(mut reader: impl Read) -> Result<Features, Error> {
+    let mut archive = vec![];
+    reader.read_to_end(&mut archive)?;
+    let files = read_zip(&archive)?;
+    let file = |name: &str| {
+        files
+            .iter()
+            .find(|(file_name, _)| file_name == name)
+            .map(|(_, data)| *data)
+            .ok_or_else(|| format_err!("the archive has no {}", name))
+    };
+    let keypoints = read_keypoints_npy(file("keypoints.npy")?)?;
+    let descriptors = read_descriptors_npy(file("descriptors.npy")?)?;
+    if keypoints.len() != descriptors.len() {
+        return Err(format_err!(
+            "got {} keypoints and {} descriptors",
+            keypoints.len(),
+            descriptors.len()
+        ));
+    }
+    Ok(Features {
+        keypoints,
+        descriptors,
+    })
+}
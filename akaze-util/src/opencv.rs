@@ -0,0 +1,838 @@
+//! Interop with OpenCV `cv::FileStorage` YAML and XML files, for
+//! `std::vector<cv::KeyPoint>`, descriptors in a `CV_8U` `cv::Mat` with one
+//! row per keypoint, and `std::vector<cv::DMatch>`.
+//!
+//! Only what OpenCV writes for these types is read: top level nodes
+//! holding a sequence of numbers or an `opencv-matrix`. Other nodes, such
+//! as strings or nested maps, are skipped.
+//!
+//! OpenCV keypoint sizes are diameters, as are the sizes of this crate,
+//! `esigma * derivative_factor`, so they are kept. OpenCV's detectors
+//! write angles in degrees in [0, 360), this crate uses radians, so they
+//! are converted. The original C++ A-KAZE library writes its own
+//! `cv::KeyPoint` angles in radians, see `AngleUnit`.
+
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use failure::{format_err, Error};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The lines of written sequences are wrapped after this many characters.
+const LINE_WIDTH: usize = 72;
+
+/// The syntax of a file storage.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageFormat {
+    Yaml,
+    Xml,
+}
+
+impl StorageFormat {
+    /// The syntax of a file, from its extension as in OpenCV.
+    ///
+    /// # Arguments
+    /// * `path` - The file, ending in `.yml`, `.yaml` or `.xml`.
+    /// # Return value
+    /// The syntax, None for other extensions.
+    pub fn from_path(path: impl AsRef<Path>) -> Option<StorageFormat> {
+        match path.as_ref().extension().and_then(OsStr::to_str) {
+            Some("yml") | Some("yaml") => Some(StorageFormat::Yaml),
+            Some("xml") => Some(StorageFormat::Xml),
+            _ => None,
+        }
+    }
+}
+
+/// The unit of `cv::KeyPoint::angle` in a file, which depends on the
+/// program that wrote it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AngleUnit {
+    /// Degrees in [0, 360), as written by OpenCV's detectors
+    Degrees,
+    /// Radians, as written by the original C++ A-KAZE library
+    Radians,
+}
+
+/// A number in a file storage. OpenCV tells integers from reals by the
+/// presence of a decimal point or exponent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Int(i64),
+    Real(f64),
+}
+
+impl Value {
+    /// The value as a real number.
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Value::Int(value) => value as f64,
+            Value::Real(value) => value,
+        }
+    }
+
+    /// A real holding the shortest decimal representation of a float, so
+    /// that it is written compactly and reads back to the same float.
+    pub(crate) fn from_f32(value: f32) -> Value {
+        Value::Real(
+            value
+                .to_string()
+                .parse()
+                .unwrap_or_else(|_| f64::from(value)),
+        )
+    }
+
+    fn parse(token: &str) -> Result<Value, Error> {
+        Ok(match token {
+            ".Inf" | ".inf" | "+.Inf" | "+.inf" => Value::Real(f64::INFINITY),
+            "-.Inf" | "-.inf" => Value::Real(f64::NEG_INFINITY),
+            ".Nan" | ".NaN" | ".nan" => Value::Real(f64::NAN),
+            _ if token.contains(&['.', 'e', 'E'][..]) => Value::Real(token.parse()?),
+            _ => Value::Int(token.parse()?),
+        })
+    }
+
+    fn format(self) -> String {
+        match self {
+            Value::Int(value) => value.to_string(),
+            Value::Real(value) if value.is_nan() => ".Nan".to_owned(),
+            Value::Real(value) if value.is_infinite() => {
+                if value > 0f64 {
+                    ".Inf".to_owned()
+                } else {
+                    "-.Inf".to_owned()
+                }
+            }
+            Value::Real(value) => {
+                let magnitude = value.abs();
+                let formatted = if magnitude != 0f64 && !(1e-5..1e16).contains(&magnitude) {
+                    format!("{:e}", value)
+                } else {
+                    value.to_string()
+                };
+                // Integral reals need a decimal point, as in OpenCV
+                if formatted.contains(&['.', 'e'][..]) {
+                    formatted
+                } else {
+                    formatted + "."
+                }
+            }
+        }
+    }
+}
+
+/// A top level node of a file storage.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// A sequence of numbers, as written for vectors of keypoints or
+    /// matches. Nested sequences are flattened.
+    Seq(Vec<Value>),
+    /// A `cv::Mat`, with the OpenCV element type such as `u` for `CV_8U`,
+    /// and the elements row by row.
+    Mat {
+        rows: usize,
+        cols: usize,
+        dt: String,
+        data: Vec<Value>,
+    },
+}
+
+/// The nodes of an OpenCV file storage, in file order.
+#[derive(Debug, Clone, Default)]
+pub struct FileStorage {
+    pub nodes: Vec<(String, Node)>,
+}
+
+impl FileStorage {
+    /// Read a file storage.
+    ///
+    /// # Arguments
+    /// * `path` - The file, with a `.yml`, `.yaml` or `.xml` extension.
+    /// # Return value
+    /// The file storage.
+    pub fn open(path: impl AsRef<Path>) -> Result<FileStorage, Error> {
+        let path = path.as_ref();
+        let format = StorageFormat::from_path(path)
+            .ok_or_else(|| format_err!("unknown file storage extension of {:?}", path))?;
+        FileStorage::read(BufReader::new(File::open(path)?), format)
+    }
+
+    /// Write the file storage.
+    ///
+    /// # Arguments
+    /// * `path` - The file, with a `.yml`, `.yaml` or `.xml` extension.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Error> {
+        let path = path.as_ref();
+        let format = StorageFormat::from_path(path)
+            .ok_or_else(|| format_err!("unknown file storage extension of {:?}", path))?;
+        self.write(BufWriter::new(File::create(path)?), format)
+    }
+
+    /// Read a file storage.
+    ///
+    /// # Arguments
+    /// * `reader` - The source.
+    /// * `format` - The syntax of the source.
+    /// # Return value
+    /// The file storage.
+    pub fn read(mut reader: impl Read, format: StorageFormat) -> Result<FileStorage, Error> {
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        match format {
+            StorageFormat::Yaml => parse_yaml(&text),
+            StorageFormat::Xml => parse_xml(&text),
+        }
+    }
+
+    /// Write the file storage as OpenCV does.
+    ///
+    /// # Arguments
+    /// * `writer` - The destination.
+    /// * `format` - The syntax to write.
+    pub fn write(&self, mut writer: impl Write, format: StorageFormat) -> Result<(), Error> {
+        match format {
+            StorageFormat::Yaml => {
+                writeln!(writer, "%YAML:1.0")?;
+                writeln!(writer, "---")?;
+                for (name, node) in &self.nodes {
+                    write_yaml_node(&mut writer, name, node)?;
+                }
+            }
+            StorageFormat::Xml => {
+                writeln!(writer, "<?xml version=\"1.0\"?>")?;
+                writeln!(writer, "<opencv_storage>")?;
+                for (name, node) in &self.nodes {
+                    write_xml_node(&mut writer, name, node)?;
+                }
+                writeln!(writer, "</opencv_storage>")?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The node with a name.
+    pub fn get(&self, name: &str) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .find(|(node_name, _)| node_name == name)
+            .map(|(_, node)| node)
+    }
+
+    /// Set a node, replacing any node with the same name.
+    ///
+    /// # Arguments
+    /// * `name` - The name, of letters, digits, `_` and `-`.
+    /// * `node` - The node.
+    pub fn set(&mut self, name: &str, node: Node) {
+        match self
+            .nodes
+            .iter_mut()
+            .find(|(node_name, _)| node_name == name)
+        {
+            Some(existing) => existing.1 = node,
+            None => self.nodes.push((name.to_owned(), node)),
+        }
+    }
+
+    /// Set a node to keypoints, as written by OpenCV for a
+    /// `std::vector<cv::KeyPoint>`, with angles in degrees.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// * `keypoints` - The keypoints.
+    pub fn set_keypoints(&mut self, name: &str, keypoints: &[Keypoint]) {
+        self.set_keypoints_with_unit(name, keypoints, AngleUnit::Degrees)
+    }
+
+    /// Set a node to keypoints, as written by OpenCV for a
+    /// `std::vector<cv::KeyPoint>`.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// * `keypoints` - The keypoints.
+    /// * `unit` - The unit of the written angles.
+    pub fn set_keypoints_with_unit(&mut self, name: &str, keypoints: &[Keypoint], unit: AngleUnit) {
+        let mut values = Vec::with_capacity(keypoints.len() * 7);
+        for keypoint in keypoints {
+            values.push(Value::from_f32(keypoint.point.0));
+            values.push(Value::from_f32(keypoint.point.1));
+            values.push(Value::from_f32(keypoint.size));
+            values.push(Value::from_f32(match unit {
+                AngleUnit::Degrees => keypoint.angle.to_degrees().rem_euclid(360f32),
+                AngleUnit::Radians => keypoint.angle,
+            }));
+            values.push(Value::from_f32(keypoint.response));
+            values.push(Value::Int(keypoint.octave as i64));
+            values.push(Value::Int(keypoint.class_id as i64));
+        }
+        self.set(name, Node::Seq(values));
+    }
+
+    /// Read keypoints from a node, with angles in degrees.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// # Return value
+    /// The keypoints. Negative OpenCV angles, octaves and class ids,
+    /// which mean unknown, become zero.
+    pub fn keypoints(&self, name: &str) -> Result<Vec<Keypoint>, Error> {
+        self.keypoints_with_unit(name, AngleUnit::Degrees)
+    }
+
+    /// Read keypoints from a node.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// * `unit` - The unit of the angles in the node.
+    /// # Return value
+    /// The keypoints. Negative angles, octaves and class ids, which mean
+    /// unknown, become zero.
+    pub fn keypoints_with_unit(&self, name: &str, unit: AngleUnit) -> Result<Vec<Keypoint>, Error> {
+        let values = self.sequence(name, 7)?;
+        Ok(values
+            .chunks_exact(7)
+            .map(|keypoint| {
+                let value = |i: usize| keypoint[i].to_f64();
+                Keypoint {
+                    point: (value(0) as f32, value(1) as f32),
+                    size: value(2) as f32,
+                    angle: match unit {
+                        AngleUnit::Degrees => (value(3).max(0f64) as f32).to_radians(),
+                        AngleUnit::Radians => value(3).max(0f64) as f32,
+                    },
+                    response: value(4) as f32,
+                    octave: value(5).max(0f64) as usize,
+                    class_id: value(6).max(0f64) as usize,
+                }
+            })
+            .collect())
+    }
+
+    /// Set a node to descriptors, as a `CV_8U` matrix with one row per
+    /// descriptor.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// * `descriptors` - The descriptors, all of the same length.
+    pub fn set_descriptors(&mut self, name: &str, descriptors: &[Descriptor]) {
+        let cols = descriptors
+            .first()
+            .map(|descriptor| descriptor.vector.len())
+            .unwrap_or(0);
+        let data = descriptors
+            .iter()
+            .flat_map(|descriptor| descriptor.vector.iter())
+            .map(|&byte| Value::Int(i64::from(byte)))
+            .collect();
+        self.set(
+            name,
+            Node::Mat {
+                rows: descriptors.len(),
+                cols,
+                dt: "u".to_owned(),
+                data,
+            },
+        );
+    }
+
+    /// Read descriptors from a `CV_8U` matrix node.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// # Return value
+    /// The descriptors, one per row.
+    pub fn descriptors(&self, name: &str) -> Result<Vec<Descriptor>, Error> {
+        match self.get(name) {
+            Some(Node::Mat {
+                rows,
+                cols,
+                dt,
+                data,
+            }) => {
+                if dt != "u" {
+                    return Err(format_err!(
+                        "the descriptors in {} are of type {}, not u",
+                        name,
+                        dt
+                    ));
+                }
+                if data.len() != rows * cols {
+                    return Err(format_err!(
+                        "the {} x {} matrix {} has {} elements",
+                        rows,
+                        cols,
+                        name,
+                        data.len()
+                    ));
+                }
+                (0..*rows)
+                    .map(|row| {
+                        let vector = data[row * cols..(row + 1) * cols]
+                            .iter()
+                            .map(|&value| match value {
+                                Value::Int(byte) if (0..=255).contains(&byte) => Ok(byte as u8),
+                                _ => Err(format_err!("{:?} in {} is not a byte", value, name)),
+                            })
+                            .collect::<Result<Vec<u8>, Error>>()?;
+                        Ok(Descriptor { vector })
+                    })
+                    .collect()
+            }
+            Some(_) => Err(format_err!("the node {} is not a matrix", name)),
+            None => Err(format_err!("there is no node {}", name)),
+        }
+    }
+
+    /// Set a node to matches, as written by OpenCV for a
+    /// `std::vector<cv::DMatch>` with `index_0` as the query index and
+    /// `index_1` as the train index.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// * `matches` - The matches.
+    pub fn set_matches(&mut self, name: &str, matches: &[Match]) {
+        let mut values = Vec::with_capacity(matches.len() * 4);
+        for match_i in matches {
+            values.push(Value::Int(match_i.index_0 as i64));
+            values.push(Value::Int(match_i.index_1 as i64));
+            values.push(Value::Int(0));
+            values.push(Value::from_f32(match_i.distance as f32));
+        }
+        self.set(name, Node::Seq(values));
+    }
+
+    /// Read matches from a node. The image index of the matches is ignored.
+    ///
+    /// # Arguments
+    /// * `name` - The name of the node.
+    /// # Return value
+    /// The matches.
+    pub fn matches(&self, name: &str) -> Result<Vec<Match>, Error> {
+        let values = self.sequence(name, 4)?;
+        values
+            .chunks_exact(4)
+            .map(|match_i| match (match_i[0], match_i[1]) {
+                (Value::Int(index_0), Value::Int(index_1)) if index_0 >= 0 && index_1 >= 0 => {
+                    Ok(Match {
+                        index_0: index_0 as usize,
+                        index_1: index_1 as usize,
+                        distance: match_i[3].to_f64(),
+                    })
+                }
+                _ => Err(format_err!("invalid match indices in {}", name)),
+            })
+            .collect()
+    }
+
+    /// The values of a sequence node holding records of a fixed length.
+    fn sequence(&self, name: &str, record_length: usize) -> Result<&[Value], Error> {
+        match self.get(name) {
+            Some(Node::Seq(values)) if values.len() % record_length == 0 => Ok(values),
+            Some(Node::Seq(values)) => Err(format_err!(
+                "the {} values of {} are not records of {}",
+                values.len(),
+                name,
+                record_length
+            )),
+            Some(_) => Err(format_err!("the node {} is not a sequence", name)),
+            None => Err(format_err!("there is no node {}", name)),
+        }
+    }
+}
+
+/// Join values into lines of at most `LINE_WIDTH` characters.
+fn wrap(values: &[Value], separator: &str, indent: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for (i, value) in values.iter().enumerate() {
+        let mut formatted = value.format();
+        if i + 1 < values.len() {
+            formatted.push_str(separator);
+        }
+        if !line.is_empty() && line.len() + formatted.len() + 1 > LINE_WIDTH {
+            lines.push(line);
+            line = String::new();
+        }
+        if line.is_empty() {
+            line.push_str(indent);
+        } else {
+            line.push(' ');
+        }
+        line.push_str(&formatted);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn write_yaml_sequence(writer: &mut impl Write, values: &[Value]) -> Result<(), Error> {
+    if values.is_empty() {
+        writeln!(writer, "[]")?;
+        return Ok(());
+    }
+    let lines = wrap(values, ",", "    ");
+    write!(writer, "[ {}", lines[0].trim_start())?;
+    for line in &lines[1..] {
+        write!(writer, "\n{}", line)?;
+    }
+    writeln!(writer, " ]")?;
+    Ok(())
+}
+
+fn write_yaml_node(writer: &mut impl Write, name: &str, node: &Node) -> Result<(), Error> {
+    match node {
+        Node::Seq(values) => {
+            write!(writer, "{}: ", name)?;
+            write_yaml_sequence(writer, values)?;
+        }
+        Node::Mat {
+            rows,
+            cols,
+            dt,
+            data,
+        } => {
+            writeln!(writer, "{}: !!opencv-matrix", name)?;
+            writeln!(writer, "   rows: {}", rows)?;
+            writeln!(writer, "   cols: {}", cols)?;
+            writeln!(writer, "   dt: {}", dt)?;
+            write!(writer, "   data: ")?;
+            write_yaml_sequence(writer, data)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_xml_node(writer: &mut impl Write, name: &str, node: &Node) -> Result<(), Error> {
+    match node {
+        Node::Seq(values) => {
+            write!(writer, "<{}>", name)?;
+            for line in wrap(values, "", "  ") {
+                write!(writer, "\n{}", line)?;
+            }
+            writeln!(writer, "</{}>", name)?;
+        }
+        Node::Mat {
+            rows,
+            cols,
+            dt,
+            data,
+        } => {
+            writeln!(writer, "<{} type_id=\"opencv-matrix\">", name)?;
+            writeln!(writer, "  <rows>{}</rows>", rows)?;
+            writeln!(writer, "  <cols>{}</cols>", cols)?;
+            writeln!(writer, "  <dt>{}</dt>", dt)?;
+            write!(writer, "  <data>")?;
+            for line in wrap(data, "", "    ") {
+                write!(writer, "\n{}", line)?;
+            }
+            writeln!(writer, "</data></{}>", name)?;
+        }
+    }
+    Ok(())
+}
+
+/// Parse whitespace or comma separated numbers, flattening brackets.
+fn parse_values(text: &str) -> Result<Vec<Value>, Error> {
+    text.split(|c: char| c.is_whitespace() || c == ',' || c == '[' || c == ']')
+        .filter(|token| !token.is_empty())
+        .map(Value::parse)
+        .collect()
+}
+
+fn strip_yaml_comment(line: &str) -> &str {
+    match line.find('#') {
+        Some(position) => &line[..position],
+        None => line,
+    }
+}
+
+/// The text of a flow sequence starting with `first`, continued on the
+/// lines from `next` until its brackets are balanced.
+fn collect_flow(lines: &[&str], first: &str, mut next: usize) -> Result<(String, usize), Error> {
+    let depth = |text: &str| {
+        text.chars().fold(0i64, |depth, c| match c {
+            '[' => depth + 1,
+            ']' => depth - 1,
+            _ => depth,
+        })
+    };
+    let mut flow = first.to_owned();
+    // Counted line by line, as matrices may span millions of lines
+    let mut flow_depth = depth(first);
+    while flow_depth > 0 {
+        let line = strip_yaml_comment(
+            lines
+                .get(next)
+                .ok_or_else(|| format_err!("unterminated sequence"))?,
+        );
+        flow.push(' ');
+        flow.push_str(line);
+        flow_depth += depth(line);
+        next += 1;
+    }
+    Ok((flow, next))
+}
+
+fn split_yaml_key(line: &str) -> Option<(&str, &str)> {
+    let position = line.find(':')?;
+    Some((line[..position].trim(), line[position + 1..].trim()))
+}
+
+fn yaml_matrix(fields: &[(&str, String)]) -> Result<Node, Error> {
+    let field = |name: &str| {
+        fields
+            .iter()
+            .find(|(field_name, _)| *field_name == name)
+            .map(|(_, value)| value.as_str())
+            .ok_or_else(|| format_err!("the matrix has no {}", name))
+    };
+    Ok(Node::Mat {
+        rows: field("rows")?.parse()?,
+        cols: field("cols")?.parse()?,
+        dt: field("dt")?.trim_matches('"').to_owned(),
+        data: parse_values(field("data")?)?,
+    })
+}
+
+fn parse_yaml(text: &str) -> Result<FileStorage, Error> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut storage = FileStorage::default();
+    let mut i = 0;
+    while i < lines.len() {
+        let line = strip_yaml_comment(lines[i]).trim_end();
+        i += 1;
+        // Nested lines of skipped nodes are indented
+        if line.is_empty()
+            || line.starts_with('%')
+            || line.starts_with("---")
+            || line.starts_with("...")
+            || line.starts_with(char::is_whitespace)
+        {
+            continue;
+        }
+        let (name, rest) = match split_yaml_key(line) {
+            Some(key_value) => key_value,
+            None => continue,
+        };
+        if rest.starts_with('[') {
+            let (flow, next) = collect_flow(&lines, rest, i)?;
+            i = next;
+            if let Ok(values) = parse_values(&flow) {
+                storage.set(name, Node::Seq(values));
+            }
+        } else if rest.starts_with("!!opencv-matrix") {
+            let mut fields = vec![];
+            while i < lines.len() && lines[i].starts_with(char::is_whitespace) {
+                let field_line = strip_yaml_comment(lines[i]).trim();
+                i += 1;
+                if let Some((field, value)) = split_yaml_key(field_line) {
+                    let value = if value.starts_with('[') {
+                        let (flow, next) = collect_flow(&lines, value, i)?;
+                        i = next;
+                        flow
+                    } else {
+                        value.to_owned()
+                    };
+                    fields.push((field, value));
+                }
+            }
+            storage.set(name, yaml_matrix(&fields)?);
+        }
+    }
+    Ok(storage)
+}
+
+/// An XML element, with its text and child elements.
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    text: String,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(attribute, _)| attribute == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Result<&Element, Error> {
+        self.children
+            .iter()
+            .find(|child| child.name == name)
+            .ok_or_else(|| format_err!("the element {} has no {}", self.name, name))
+    }
+
+    /// The numbers of the element and its `_` children, which OpenCV
+    /// writes for nested sequences.
+    fn values(&self, values: &mut Vec<Value>) -> Result<(), Error> {
+        values.extend(parse_values(&self.text)?);
+        for child in &self.children {
+            if child.name != "_" {
+                return Err(format_err!("unexpected element {}", child.name));
+            }
+            child.values(values)?;
+        }
+        Ok(())
+    }
+}
+
+/// A parser for the subset of XML written by OpenCV, without entities.
+struct XmlParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl<'a> XmlParser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.position..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn skip_past(&mut self, end: &str) -> Result<(), Error> {
+        let offset = self
+            .rest()
+            .find(end)
+            .ok_or_else(|| format_err!("missing {}", end))?;
+        self.position += offset + end.len();
+        Ok(())
+    }
+
+    /// Skip the declaration, comments and whitespace before an element.
+    fn skip_misc(&mut self) -> Result<(), Error> {
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("<?") {
+                self.skip_past("?>")?;
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn name(&mut self) -> String {
+        let rest = self.rest();
+        let length = rest
+            .find(|c: char| c.is_whitespace() || c == '>' || c == '/' || c == '=')
+            .unwrap_or(rest.len());
+        self.position += length;
+        rest[..length].to_owned()
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if !self.rest().starts_with(token) {
+            return Err(format_err!("expected {} at byte {}", token, self.position));
+        }
+        self.position += token.len();
+        Ok(())
+    }
+
+    fn element(&mut self) -> Result<Element, Error> {
+        self.expect("<")?;
+        let mut element = Element {
+            name: self.name(),
+            attributes: vec![],
+            text: String::new(),
+            children: vec![],
+        };
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.position += 2;
+                return Ok(element);
+            }
+            if self.rest().starts_with('>') {
+                self.position += 1;
+                break;
+            }
+            let attribute = self.name();
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let quote = if self.rest().starts_with('\'') {
+                "'"
+            } else {
+                "\""
+            };
+            self.expect(quote)?;
+            let length = self
+                .rest()
+                .find(quote)
+                .ok_or_else(|| format_err!("unterminated attribute {}", attribute))?;
+            let value = self.rest()[..length].to_owned();
+            self.position += length + 1;
+            element.attributes.push((attribute, value));
+        }
+        loop {
+            let length = self
+                .rest()
+                .find('<')
+                .ok_or_else(|| format_err!("unterminated element {}", element.name))?;
+            element.text.push_str(&self.rest()[..length]);
+            element.text.push(' ');
+            self.position += length;
+            if self.rest().starts_with("</") {
+                self.position += 2;
+                let name = self.name();
+                if name != element.name {
+                    return Err(format_err!("element {} closed by {}", element.name, name));
+                }
+                self.skip_whitespace();
+                self.expect(">")?;
+                return Ok(element);
+            } else if self.rest().starts_with("<!--") {
+                self.skip_past("-->")?;
+            } else {
+                element.children.push(self.element()?);
+            }
+        }
+    }
+}
+
+fn xml_node(element: &Element) -> Result<Node, Error> {
+    if element.attribute("type_id") == Some("opencv-matrix") {
+        Ok(Node::Mat {
+            rows: element.child("rows")?.text.trim().parse()?,
+            cols: element.child("cols")?.text.trim().parse()?,
+            dt: element.child("dt")?.text.trim().to_owned(),
+            data: parse_values(&element.child("data")?.text)?,
+        })
+    } else {
+        let mut values = vec![];
+        element.values(&mut values)?;
+        Ok(Node::Seq(values))
+    }
+}
+
+fn parse_xml(text: &str) -> Result<FileStorage, Error> {
+    let mut parser = XmlParser { text, position: 0 };
+    parser.skip_misc()?;
+    let root = parser.element()?;
+    if root.name != "opencv_storage" {
+        return Err(format_err!(
+            "the root element is {}, not opencv_storage",
+            root.name
+        ));
+    }
+    let mut storage = FileStorage::default();
+    for child in &root.children {
+        if child.attribute("type_id") == Some("opencv-matrix") {
+            storage.set(&child.name, xml_node(child)?);
+        } else if let Ok(node) = xml_node(child) {
+            // Strings and maps are not needed
+            storage.set(&child.name, node);
+        }
+    }
+    Ok(storage)
+}
@@ -0,0 +1,139 @@
+//! Export to OpenMVG, as `.feat`/`.desc` region files and a
+//! `matches.putative.txt` match file.
+//!
+//! OpenMVG reads the regions of every image with the regions type of its
+//! `image_describer.json`. `AKAZE_Binary_Regions` holds 64 byte
+//! descriptors, so the 61 byte MLDB descriptors are padded with zeros,
+//! which does not change their Hamming distances.
+
+use crate::collection::{image_name, CollectionSummary};
+use crate::{deserialize_features_from_file, deserialize_matches_from_file, Features};
+use akaze::types::feature_match::Match;
+use failure::{format_err, Error};
+use log::*;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// The descriptor length of OpenMVG's `AKAZE_Binary_Regions`.
+pub const OPENMVG_AKAZE_DESCRIPTOR_LENGTH: usize = 64;
+
+/// Write keypoints as an OpenMVG `.feat` file of `SIOPointFeature`s.
+///
+/// Each line holds the x, y, scale and orientation in radians of a
+/// keypoint, where the scale is the keypoint radius.
+///
+/// # Arguments
+/// * `features` - The features.
+/// * `writer` - The destination.
+pub fn write_openmvg_features(features: &Features, mut writer: impl Write) -> Result<(), Error> {
+    for keypoint in &features.keypoints {
+        writeln!(
+            writer,
+            "{} {} {} {}",
+            keypoint.point.0, keypoint.point.1, keypoint.size, keypoint.angle
+        )?;
+    }
+    Ok(())
+}
+
+/// Write descriptors as an OpenMVG `.desc` file: the number of
+/// descriptors as a 64 bit little endian integer, then the descriptors.
+///
+/// # Arguments
+/// * `features` - The features.
+/// * `descriptor_length` - The descriptor length of the OpenMVG regions
+///   type, at least the length of the descriptors, which are padded with
+///   zeros to it.
+/// * `writer` - The destination.
+pub fn write_openmvg_descriptors(
+    features: &Features,
+    descriptor_length: usize,
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    writer.write_all(&(features.descriptors.len() as u64).to_le_bytes())?;
+    let padding = vec![0u8; descriptor_length];
+    for descriptor in &features.descriptors {
+        if descriptor.vector.len() > descriptor_length {
+            return Err(format_err!(
+                "a descriptor of {} bytes does not fit in {} bytes",
+                descriptor.vector.len(),
+                descriptor_length
+            ));
+        }
+        writer.write_all(&descriptor.vector)?;
+        writer.write_all(&padding[descriptor.vector.len()..])?;
+    }
+    Ok(())
+}
+
+/// Write matches as an OpenMVG `matches.putative.txt` file.
+///
+/// Each pair is a line with the two view ids, a line with the number of
+/// matches, and one line per match with the feature indices.
+///
+/// # Arguments
+/// * `pairs` - The view ids of each pair and their matches.
+/// * `writer` - The destination.
+pub fn write_openmvg_matches(
+    pairs: &[(usize, usize, Vec<Match>)],
+    mut writer: impl Write,
+) -> Result<(), Error> {
+    for (view_0, view_1, matches) in pairs {
+        writeln!(writer, "{} {}", view_0, view_1)?;
+        writeln!(writer, "{}", matches.len())?;
+        for match_i in matches {
+            writeln!(writer, "{} {}", match_i.index_0, match_i.index_1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Export a matched collection to OpenMVG.
+///
+/// Writes `<image name>.feat` and `<image name>.desc` for every image and
+/// a `matches.putative.txt`, with the images numbered in collection order.
+/// OpenMVG numbers views in the order of its sorted image listing, which
+/// is the collection order when feature files are named after the images.
+///
+/// # Arguments
+/// * `summary_path` - The `summary.json` written by `match_collection`.
+/// * `output` - The OpenMVG matches directory, created if missing.
+/// * `descriptor_length` - The descriptor length of the OpenMVG regions
+///   type, usually `OPENMVG_AKAZE_DESCRIPTOR_LENGTH`.
+pub fn export_collection_to_openmvg(
+    summary_path: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    descriptor_length: usize,
+) -> Result<(), Error> {
+    let summary_path = summary_path.as_ref();
+    let output = output.as_ref();
+    let summary: CollectionSummary =
+        serde_json::from_reader(BufReader::new(File::open(summary_path)?))?;
+    fs::create_dir_all(output)?;
+    for path in &summary.images {
+        let features = deserialize_features_from_file(path)?;
+        let name = image_name(path);
+        let file = File::create(output.join(format!("{}.feat", name)))?;
+        write_openmvg_features(&features, BufWriter::new(file))?;
+        let file = File::create(output.join(format!("{}.desc", name)))?;
+        write_openmvg_descriptors(&features, descriptor_length, BufWriter::new(file))?;
+    }
+    let base = summary_path.parent().unwrap_or_else(|| Path::new(""));
+    let pairs = summary
+        .pairs
+        .iter()
+        .map(|pair| {
+            let matches = deserialize_matches_from_file(base.join(&pair.matches_file))?;
+            Ok((pair.image_0, pair.image_1, matches))
+        })
+        .collect::<Result<Vec<_>, Error>>()?;
+    let file = File::create(output.join("matches.putative.txt"))?;
+    write_openmvg_matches(&pairs, BufWriter::new(file))?;
+    info!(
+        "Exported {} images and {} pairs.",
+        summary.images.len(),
+        pairs.len()
+    );
+    Ok(())
+}
@@ -0,0 +1,458 @@
+//! A harness comparing this port stage by stage with reference outputs of
+//! the original C++ A-KAZE library by Pablo F. Alcantarilla.
+//!
+//! A reference dump is a directory of optional files, one per stage, so
+//! a dump may cover only some stages:
+//!
+//! * `contrast_factor.txt` - The contrast factor `k` of the first
+//!   evolution, computed from the blurred input image in `[0, 1]` with a
+//!   gradient histogram scale of 1. The C++ library scales
+//!   `options_.kcontrast` by 0.75 in place at every new octave, so it must
+//!   be written right after the first `compute_k_percentile` in
+//!   `Create_Nonlinear_Scale_Space`, not once the scale space is done.
+//! * `fed_tau.txt` - One line per evolution with its FED step sizes
+//!   separated by whitespace. The line of the first evolution is empty.
+//! * `ldet.yml` or `ldet.xml` - An OpenCV file storage with one `CV_32F`
+//!   matrix `Ldet_<i>` per evolution `i`, the detector responses.
+//! * `features.yml` or `features.xml` - An OpenCV file storage with the
+//!   `std::vector<cv::KeyPoint>` `keypoints`, with sizes as diameters and
+//!   angles in radians, as the C++ library computes them, and the `CV_8U`
+//!   matrix `descriptors`.
+//!
+//! A dump must come from the `AKAZE` class of the original library,
+//! <https://github.com/pablofdezalc/akaze>, with its `AKAZEOptions` set to
+//! the values of `Config::default()`. OpenCV's `cv::AKAZE` is a modified
+//! port, whose keypoint angles are in degrees. From the library, the FED
+//! schedules `tsteps_` are written after construction, the detector
+//! responses `evolution_[i].Ldet` after `Feature_Detection` and the
+//! features after `Compute_Descriptors`, with `cv::FileStorage`.
+//! `write_reference_dump` writes the same files from this port, e.g. to
+//! compare two versions of it.
+
+use crate::golden::{compare_features, FeatureComparison, GoldenConfig};
+use crate::opencv::{AngleUnit, FileStorage, Node, Value};
+use crate::Features;
+use akaze::ops::contrast_factor::compute_contrast_factor;
+use akaze::types::evolution::{Config, EvolutionStep};
+use akaze::types::image::{GrayFloatImage, ImageFunctions};
+use failure::{format_err, Error};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The outputs of the stages of a reference implementation.
+#[derive(Default)]
+pub struct ReferenceDump {
+    /// The contrast factor of the first evolution
+    pub contrast_factor: Option<f64>,
+    /// The FED step sizes of each evolution
+    pub fed_tau_steps: Option<Vec<Vec<f64>>>,
+    /// The detector responses of each evolution
+    pub ldet: Option<Vec<GrayFloatImage>>,
+    /// The keypoints and descriptors
+    pub features: Option<Features>,
+}
+
+/// The path of a file storage in either format, if one exists.
+fn storage_path(directory: &Path, name: &str) -> Option<PathBuf> {
+    ["yml", "yaml", "xml"]
+        .iter()
+        .map(|extension| directory.join(format!("{}.{}", name, extension)))
+        .find(|path| path.is_file())
+}
+
+fn read_ldet(storage: &FileStorage) -> Result<Vec<GrayFloatImage>, Error> {
+    let mut images = vec![];
+    while let Some(node) = storage.get(&format!("Ldet_{}", images.len())) {
+        match node {
+            Node::Mat {
+                rows, cols, data, ..
+            } if data.len() == rows * cols => {
+                let mut image = GrayFloatImage::new(*cols, *rows);
+                for (pixel, value) in image.buffer.iter_mut().zip(data.iter()) {
+                    *pixel = value.to_f64() as f32;
+                }
+                images.push(image);
+            }
+            _ => {
+                return Err(format_err!(
+                    "Ldet_{} is not a complete matrix",
+                    images.len()
+                ))
+            }
+        }
+    }
+    Ok(images)
+}
+
+impl ReferenceDump {
+    /// Load a reference dump, skipping the stages whose files are missing.
+    ///
+    /// # Arguments
+    /// * `directory` - The directory of the dump.
+    pub fn load(directory: impl AsRef<Path>) -> Result<ReferenceDump, Error> {
+        let directory = directory.as_ref();
+        let mut dump = ReferenceDump::default();
+        let path = directory.join("contrast_factor.txt");
+        if path.is_file() {
+            dump.contrast_factor = Some(fs::read_to_string(&path)?.trim().parse()?);
+        }
+        let path = directory.join("fed_tau.txt");
+        if path.is_file() {
+            dump.fed_tau_steps = Some(
+                BufReader::new(File::open(&path)?)
+                    .lines()
+                    .map(|line| {
+                        line?
+                            .split_whitespace()
+                            .map(|step| Ok(step.parse()?))
+                            .collect::<Result<Vec<f64>, Error>>()
+                    })
+                    .collect::<Result<_, Error>>()?,
+            );
+        }
+        if let Some(path) = storage_path(directory, "ldet") {
+            dump.ldet = Some(read_ldet(&FileStorage::open(path)?)?);
+        }
+        if let Some(path) = storage_path(directory, "features") {
+            let storage = FileStorage::open(path)?;
+            dump.features = Some(Features {
+                keypoints: storage.keypoints_with_unit("keypoints", AngleUnit::Radians)?,
+                descriptors: storage.descriptors("descriptors")?,
+            });
+        }
+        Ok(dump)
+    }
+}
+
+/// The contrast factor this port computes for the first evolution.
+///
+/// # Arguments
+/// * `evolutions` - The scale space.
+/// * `options` - The options it was created with.
+pub fn contrast_factor(evolutions: &[EvolutionStep], options: &Config) -> f64 {
+    compute_contrast_factor(
+        &evolutions[0].Lsmooth,
+        options.contrast_percentile,
+        1.0f64,
+        options.contrast_factor_num_bins,
+    )
+}
+
+/// Write the outputs of this port in the reference dump format, as YAML
+/// file storages.
+///
+/// # Arguments
+/// * `directory` - The directory of the dump, created if missing.
+/// * `evolutions` - The scale space.
+/// * `features` - The extracted features.
+/// * `options` - The options of the extraction.
+pub fn write_reference_dump(
+    directory: impl AsRef<Path>,
+    evolutions: &[EvolutionStep],
+    features: &Features,
+    options: &Config,
+) -> Result<(), Error> {
+    let directory = directory.as_ref();
+    fs::create_dir_all(directory)?;
+    fs::write(
+        directory.join("contrast_factor.txt"),
+        format!("{}\n", contrast_factor(evolutions, options)),
+    )?;
+    let mut writer = BufWriter::new(File::create(directory.join("fed_tau.txt"))?);
+    for evolution in evolutions {
+        let steps: Vec<String> = evolution
+            .fed_tau_steps
+            .iter()
+            .map(|step| step.to_string())
+            .collect();
+        writeln!(writer, "{}", steps.join(" "))?;
+    }
+    writer.flush()?;
+    let mut storage = FileStorage::default();
+    for (i, evolution) in evolutions.iter().enumerate() {
+        storage.set(
+            &format!("Ldet_{}", i),
+            Node::Mat {
+                rows: evolution.Ldet.height(),
+                cols: evolution.Ldet.width(),
+                dt: "f".to_owned(),
+                data: evolution
+                    .Ldet
+                    .buffer
+                    .iter()
+                    .cloned()
+                    .map(Value::from_f32)
+                    .collect(),
+            },
+        );
+    }
+    storage.save(directory.join("ldet.yml"))?;
+    let mut storage = FileStorage::default();
+    storage.set_keypoints_with_unit("keypoints", &features.keypoints, AngleUnit::Radians);
+    storage.set_descriptors("descriptors", &features.descriptors);
+    storage.save(directory.join("features.yml"))
+}
+
+/// Tolerances of the parity checks.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ParityConfig {
+    /// The largest accepted relative difference of the contrast factor
+    pub contrast_factor: f64,
+    /// The largest accepted difference of a FED step size
+    pub fed_tau: f64,
+    /// The largest accepted difference of a detector response, relative
+    /// to the largest reference response of the evolution
+    pub ldet: f64,
+    /// The smallest accepted fraction of keypoints paired by position
+    pub min_paired: f64,
+    /// The pairing radius and the tolerances of paired features
+    pub features: GoldenConfig,
+}
+
+impl Default for ParityConfig {
+    fn default() -> ParityConfig {
+        ParityConfig {
+            contrast_factor: 1e-4,
+            fed_tau: 1e-9,
+            ldet: 1e-3,
+            min_paired: 0.95,
+            features: GoldenConfig {
+                match_radius: 1.5,
+                position: 0.1,
+                size: 0.1,
+                angle: 0.05,
+                response: 0.01,
+                descriptor_bits: 8,
+            },
+        }
+    }
+}
+
+/// The agreement of one stage with the reference.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StageReport {
+    /// The name of the stage
+    pub stage: String,
+    /// Whether the stage agrees within the tolerances
+    pub passed: bool,
+    /// The largest difference, in the units of the stage's tolerance
+    pub max_difference: f64,
+    /// The discrepancies, one per line
+    pub details: Vec<String>,
+}
+
+fn compare_contrast_factor(reference: f64, candidate: f64, config: &ParityConfig) -> StageReport {
+    let relative = (candidate - reference).abs() / reference.abs().max(f64::MIN_POSITIVE);
+    StageReport {
+        stage: "contrast factor".to_owned(),
+        passed: relative <= config.contrast_factor,
+        max_difference: relative,
+        details: vec![format!(
+            "reference {} candidate {} relative difference {:e}",
+            reference, candidate, relative
+        )],
+    }
+}
+
+fn compare_fed_tau(
+    reference: &[Vec<f64>],
+    evolutions: &[EvolutionStep],
+    config: &ParityConfig,
+) -> StageReport {
+    let mut details = vec![];
+    let mut max_difference = 0f64;
+    let mut passed = reference.len() == evolutions.len();
+    if !passed {
+        details.push(format!(
+            "{} reference and {} candidate evolutions",
+            reference.len(),
+            evolutions.len()
+        ));
+    }
+    for (i, (steps, evolution)) in reference.iter().zip(evolutions.iter()).enumerate() {
+        if steps.len() != evolution.fed_tau_steps.len() {
+            passed = false;
+            details.push(format!(
+                "evolution {}: {} reference and {} candidate steps",
+                i,
+                steps.len(),
+                evolution.fed_tau_steps.len()
+            ));
+            continue;
+        }
+        let difference = steps
+            .iter()
+            .zip(evolution.fed_tau_steps.iter())
+            .map(|(step_0, step_1)| (step_0 - step_1).abs())
+            .fold(0f64, f64::max);
+        max_difference = max_difference.max(difference);
+        if difference > config.fed_tau {
+            passed = false;
+            details.push(format!("evolution {}: steps differ by {:e}", i, difference));
+        }
+    }
+    StageReport {
+        stage: "FED step schedules".to_owned(),
+        passed,
+        max_difference,
+        details,
+    }
+}
+
+fn compare_ldet(
+    reference: &[GrayFloatImage],
+    evolutions: &[EvolutionStep],
+    config: &ParityConfig,
+) -> StageReport {
+    let mut details = vec![];
+    let mut max_difference = 0f64;
+    let mut passed = reference.len() == evolutions.len();
+    if !passed {
+        details.push(format!(
+            "{} reference and {} candidate evolutions",
+            reference.len(),
+            evolutions.len()
+        ));
+    }
+    for (i, (image, evolution)) in reference.iter().zip(evolutions.iter()).enumerate() {
+        let candidate = &evolution.Ldet;
+        if (image.width(), image.height()) != (candidate.width(), candidate.height()) {
+            passed = false;
+            details.push(format!(
+                "evolution {}: reference {} x {}, candidate {} x {}",
+                i,
+                image.width(),
+                image.height(),
+                candidate.width(),
+                candidate.height()
+            ));
+            continue;
+        }
+        let scale = image
+            .buffer
+            .iter()
+            .fold(0f64, |scale, &pixel| scale.max(f64::from(pixel.abs())))
+            .max(f64::MIN_POSITIVE);
+        let (max_abs, sum_squared) = image.buffer.iter().zip(candidate.buffer.iter()).fold(
+            (0f64, 0f64),
+            |(max_abs, sum_squared), (&pixel_0, &pixel_1)| {
+                let difference = f64::from(pixel_0 - pixel_1).abs();
+                (
+                    max_abs.max(difference),
+                    sum_squared + difference * difference,
+                )
+            },
+        );
+        let relative = max_abs / scale;
+        max_difference = max_difference.max(relative);
+        let rms = (sum_squared / image.buffer.len().max(1) as f64).sqrt();
+        details.push(format!(
+            "evolution {}: max {:e} ({:e} of the largest response) rms {:e}",
+            i, max_abs, relative, rms
+        ));
+        if relative > config.ldet {
+            passed = false;
+        }
+    }
+    StageReport {
+        stage: "detector responses".to_owned(),
+        passed,
+        max_difference,
+        details,
+    }
+}
+
+fn compare_keypoints(comparison: &FeatureComparison, config: &ParityConfig) -> StageReport {
+    let paired_reference = comparison.paired as f64 / comparison.reference_count.max(1) as f64;
+    let paired_candidate = comparison.paired as f64 / comparison.candidate_count.max(1) as f64;
+    let mut details = vec![format!(
+        "{} reference, {} candidate, {} paired ({:.2}% of reference, {:.2}% of candidate)",
+        comparison.reference_count,
+        comparison.candidate_count,
+        comparison.paired,
+        100f64 * paired_reference,
+        100f64 * paired_candidate
+    )];
+    for (octave, (reference, candidate)) in comparison.octave_counts.iter().enumerate() {
+        details.push(format!(
+            "octave {}: {} reference, {} candidate",
+            octave, reference, candidate
+        ));
+    }
+    for (name, statistics) in &[
+        ("position", comparison.position),
+        ("size", comparison.size),
+        ("angle", comparison.angle),
+        ("response", comparison.response),
+    ] {
+        details.push(format!(
+            "{}: mean {:e} max {:e}, {} paired keypoints out of tolerance",
+            name, statistics.mean, statistics.max, statistics.exceeding
+        ));
+    }
+    StageReport {
+        stage: "keypoints".to_owned(),
+        passed: paired_reference >= config.min_paired
+            && paired_candidate >= config.min_paired
+            && comparison.position.exceeding == 0
+            && comparison.size.exceeding == 0
+            && comparison.angle.exceeding == 0
+            && comparison.response.exceeding == 0,
+        max_difference: 1f64 - paired_reference.min(paired_candidate),
+        details,
+    }
+}
+
+fn compare_descriptors(comparison: &FeatureComparison) -> StageReport {
+    let bits = comparison.descriptor_bits;
+    StageReport {
+        stage: "descriptors".to_owned(),
+        passed: bits.exceeding == 0,
+        max_difference: bits.max,
+        details: vec![format!(
+            "differing bits of paired keypoints: mean {} max {}, {} out of tolerance",
+            bits.mean, bits.max, bits.exceeding
+        )],
+    }
+}
+
+/// Compare the outputs of this port with a reference dump, stage by stage.
+///
+/// # Arguments
+/// * `reference` - The reference dump.
+/// * `evolutions` - The scale space of this port.
+/// * `features` - The features of this port.
+/// * `options` - The options of the extraction.
+/// * `config` - The tolerances.
+/// # Return value
+/// The reports of the stages in the dump, in pipeline order.
+pub fn check_parity(
+    reference: &ReferenceDump,
+    evolutions: &[EvolutionStep],
+    features: &Features,
+    options: &Config,
+    config: &ParityConfig,
+) -> Vec<StageReport> {
+    let mut reports = vec![];
+    if let Some(reference_contrast_factor) = reference.contrast_factor {
+        reports.push(compare_contrast_factor(
+            reference_contrast_factor,
+            contrast_factor(evolutions, options),
+            config,
+        ));
+    }
+    if let Some(fed_tau_steps) = &reference.fed_tau_steps {
+        reports.push(compare_fed_tau(fed_tau_steps, evolutions, config));
+    }
+    if let Some(ldet) = &reference.ldet {
+        reports.push(compare_ldet(ldet, evolutions, config));
+    }
+    if let Some(reference_features) = &reference.features {
+        let comparison = compare_features(reference_features, features, &config.features);
+        reports.push(compare_keypoints(&comparison, config));
+        reports.push(compare_descriptors(&comparison));
+    }
+    reports
+}
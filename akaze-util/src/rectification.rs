@@ -0,0 +1,202 @@
+//! Uncalibrated stereo rectification of an image pair, for dense stereo.
+
+use crate::warp::{warp_image, warped_bounds};
+use crate::Features;
+use akaze::ops::estimate_fundamental_matrix::{EpipolarError, FundamentalMatrixEstimator};
+use akaze::ops::feature_matching::descriptor_match;
+use akaze::ops::ransac::{ransac, RansacConfig};
+use akaze::ops::rectification::{rectification_error, rectify_uncalibrated};
+use akaze::types::evolution::Config;
+use akaze::types::feature_match::Match;
+use failure::{format_err, Error};
+use image::{Rgb, RgbImage};
+use log::*;
+use nalgebra::Matrix3;
+use random::Xorshift128Plus;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Options of the stereo rectification.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RectificationConfig {
+    /// The ratio between the best and second-best match distance
+    pub lowes_ratio: f64,
+    /// The maximum number of RANSAC trials
+    pub ransac_trials: usize,
+    /// The maximum Sampson distance in pixels to accept an inlier
+    pub ransac_epsilon_inliers: f32,
+    /// The minimum number of inliers to accept the fundamental matrix
+    pub min_inliers: usize,
+    /// The maximum size of the rectified images relative to the input,
+    /// to catch strongly distorting homographies
+    pub max_scale: f64,
+}
+
+impl Default for RectificationConfig {
+    fn default() -> RectificationConfig {
+        RectificationConfig {
+            lowes_ratio: 0.8,
+            ransac_trials: 2000,
+            ransac_epsilon_inliers: 1.0,
+            min_inliers: 30,
+            max_scale: 9.0,
+        }
+    }
+}
+
+/// A rectified stereo pair.
+pub struct RectifiedPair {
+    /// The fundamental matrix of the input images
+    pub fund_mat: Matrix3<f32>,
+    /// The inlier matches of the fundamental matrix
+    pub inliers: Vec<Match>,
+    /// The homographies mapping the input images to the rectified images
+    pub homographies: (Matrix3<f64>, Matrix3<f64>),
+    /// The rectified images, of equal size
+    pub images: (RgbImage, RgbImage),
+    /// The mean vertical disparity of the inliers after rectification
+    pub error: f64,
+}
+
+/// Rectify a stereo pair so that matching points lie on the same row.
+///
+/// Matches are verified with a fundamental matrix, from which rectifying
+/// homographies are computed with Hartley's method. Both images are then
+/// resampled onto canvases of the same size, with the same offset, so the
+/// horizontal disparity of the rectified images is meaningful.
+///
+/// # Arguments
+/// * `path_0` - The first image.
+/// * `path_1` - The second image, of the same size.
+/// * `options` - The feature extraction options.
+/// * `config` - The rectification options.
+/// # Return value
+/// The rectified pair.
+pub fn rectify_images(
+    path_0: PathBuf,
+    path_1: PathBuf,
+    options: Config,
+    config: &RectificationConfig,
+) -> Result<RectifiedPair, Error> {
+    let image_0 = image::open(&path_0)?.to_rgb();
+    let image_1 = image::open(&path_1)?.to_rgb();
+    if image_0.dimensions() != image_1.dimensions() {
+        return Err(format_err!("the images are of different sizes"));
+    }
+    let (_, keypoints, descriptors) = akaze::extract_features(path_0, options);
+    let features_0 = Features {
+        keypoints,
+        descriptors,
+    };
+    let (_, keypoints, descriptors) = akaze::extract_features(path_1, options);
+    let features_1 = Features {
+        keypoints,
+        descriptors,
+    };
+    let putative = descriptor_match(
+        &features_0.descriptors,
+        &features_1.descriptors,
+        10000,
+        config.lowes_ratio,
+    );
+    let ransac_config = RansacConfig {
+        max_trials: config.ransac_trials,
+        epsilon_inlier: config.ransac_epsilon_inliers,
+        local_optimization: true,
+        ..RansacConfig::default()
+    };
+    let mut source = Xorshift128Plus::new(ransac_config.seed);
+    let result = ransac(
+        &FundamentalMatrixEstimator {
+            epsilon_model: 0.05,
+            error: EpipolarError::Sampson,
+        },
+        &features_0.keypoints,
+        &features_1.keypoints,
+        &putative,
+        &ransac_config,
+        &mut source,
+    )
+    .filter(|result| result.inliers.len() >= config.min_inliers)
+    .ok_or_else(|| format_err!("no fundamental matrix found"))?;
+    info!(
+        "{} putative matches, {} inliers.",
+        putative.len(),
+        result.inliers.len()
+    );
+    let (homography_0, homography_1) = rectify_uncalibrated(
+        &result.model,
+        &features_0.keypoints,
+        &features_1.keypoints,
+        &result.inliers,
+        image_0.dimensions(),
+    )
+    .ok_or_else(|| {
+        format_err!("the pair cannot be rectified, the epipole is in the image or degenerate")
+    })?;
+    let error = rectification_error(
+        &homography_0,
+        &homography_1,
+        &features_0.keypoints,
+        &features_1.keypoints,
+        &result.inliers,
+    );
+    info!("Mean vertical disparity after rectification: {}", error);
+    // A common canvas, so that rows and disparities stay aligned
+    let (width, height) = image_0.dimensions();
+    let bounds = [
+        warped_bounds(width, height, &homography_0),
+        warped_bounds(width, height, &homography_1),
+    ];
+    let (min_0, max_0) =
+        bounds[0].ok_or_else(|| format_err!("an image corner maps to infinity"))?;
+    let (min_1, max_1) =
+        bounds[1].ok_or_else(|| format_err!("an image corner maps to infinity"))?;
+    let min = (min_0.0.min(min_1.0), min_0.1.min(min_1.1));
+    let max = (max_0.0.max(max_1.0), max_0.1.max(max_1.1));
+    let canvas = ((max.0 - min.0).ceil() + 1f64, (max.1 - min.1).ceil() + 1f64);
+    let max_pixels = config.max_scale * f64::from(width) * f64::from(height);
+    if !(canvas.0 * canvas.1).is_finite() || canvas.0 * canvas.1 > max_pixels {
+        return Err(format_err!(
+            "the rectified images of {} x {} pixels are too large",
+            canvas.0,
+            canvas.1
+        ));
+    }
+    let offset = Matrix3::new(1f64, 0f64, -min.0, 0f64, 1f64, -min.1, 0f64, 0f64, 1f64);
+    let homographies = (offset * homography_0, offset * homography_1);
+    let (canvas_width, canvas_height) = (canvas.0 as u32, canvas.1 as u32);
+    let (rectified_0, _) = warp_image(&image_0, &homographies.0, canvas_width, canvas_height)
+        .ok_or_else(|| format_err!("a homography is not invertible"))?;
+    let (rectified_1, _) = warp_image(&image_1, &homographies.1, canvas_width, canvas_height)
+        .ok_or_else(|| format_err!("a homography is not invertible"))?;
+    Ok(RectifiedPair {
+        fund_mat: result.model,
+        inliers: result.inliers,
+        homographies,
+        images: (rectified_0, rectified_1),
+        error,
+    })
+}
+
+/// Place rectified images side by side, with horizontal lines for
+/// checking that features lie on the same rows.
+///
+/// # Arguments
+/// * `pair` - The rectified pair.
+/// * `line_spacing` - The spacing of the lines in pixels.
+/// # Return value
+/// The side by side image.
+pub fn side_by_side(pair: &RectifiedPair, line_spacing: u32) -> RgbImage {
+    let (image_0, image_1) = &pair.images;
+    let line_spacing = u32::max(line_spacing, 1);
+    RgbImage::from_fn(2 * image_0.width(), image_0.height(), |x, y| {
+        if y % line_spacing == 0 {
+            Rgb([0, 255, 0])
+        } else if x < image_0.width() {
+            *image_0.get_pixel(x, y)
+        } else {
+            *image_1.get_pixel(x - image_0.width(), y)
+        }
+    })
+}
@@ -0,0 +1,233 @@
+//! Registration of an image onto another one, e.g. for change detection,
+//! with overlays for visually checking the alignment.
+
+use crate::warp::warp_image;
+use crate::Features;
+use akaze::ops::estimate_affine_transform::AffineEstimator;
+use akaze::ops::estimate_homography::HomographyEstimator;
+use akaze::ops::feature_matching::descriptor_match;
+use akaze::ops::ransac::{ransac, Estimator, RansacConfig};
+use akaze::types::evolution::Config;
+use akaze::types::feature_match::Match;
+use failure::{format_err, Error};
+use image::{Rgb, RgbImage};
+use log::*;
+use nalgebra::Matrix3;
+use random::Xorshift128Plus;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The transform relating the two images.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum RegistrationModel {
+    /// A homography, for planar scenes or a camera that only rotated.
+    Homography,
+    /// An affine transform, for distant scenes such as aerial imagery.
+    Affine,
+}
+
+/// Options of the registration.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RegistrationConfig {
+    /// The transform relating the two images
+    pub model: RegistrationModel,
+    /// The ratio between the best and second-best match distance
+    pub lowes_ratio: f64,
+    /// The maximum number of RANSAC trials
+    pub ransac_trials: usize,
+    /// The maximum transfer error in pixels to accept an inlier
+    pub ransac_epsilon_inliers: f32,
+    /// The minimum number of inliers to accept the transform
+    pub min_inliers: usize,
+}
+
+impl Default for RegistrationConfig {
+    fn default() -> RegistrationConfig {
+        RegistrationConfig {
+            model: RegistrationModel::Homography,
+            lowes_ratio: 0.8,
+            ransac_trials: 2000,
+            ransac_epsilon_inliers: 3.0,
+            min_inliers: 20,
+        }
+    }
+}
+
+/// An image registered onto a reference image.
+pub struct Registration {
+    /// The transform mapping pixels of the registered image to the
+    /// reference image
+    pub transform: Matrix3<f64>,
+    /// The inlier matches, from the registered image to the reference image
+    pub inliers: Vec<Match>,
+    /// The registered image resampled in the reference image's frame
+    pub warped: RgbImage,
+    /// The pixels of `warped` covered by the registered image, row by row
+    pub mask: Vec<bool>,
+}
+
+fn verify<E: Estimator<Model = Matrix3<f32>>>(
+    estimator: &E,
+    features_0: &Features,
+    features_1: &Features,
+    putative: &[Match],
+    config: &RegistrationConfig,
+) -> Option<(Matrix3<f32>, Vec<Match>)> {
+    let ransac_config = RansacConfig {
+        max_trials: config.ransac_trials,
+        epsilon_inlier: config.ransac_epsilon_inliers,
+        local_optimization: true,
+        ..RansacConfig::default()
+    };
+    let mut source = Xorshift128Plus::new(ransac_config.seed);
+    ransac(
+        estimator,
+        &features_0.keypoints,
+        &features_1.keypoints,
+        putative,
+        &ransac_config,
+        &mut source,
+    )
+    .map(|result| (result.model, result.inliers))
+}
+
+/// Estimate the transform between two feature sets.
+///
+/// # Arguments
+/// * `features_0` - The features of the first image.
+/// * `features_1` - The features of the second image.
+/// * `config` - The registration options.
+/// # Return value
+/// The transform mapping the first image to the second one, and its
+/// inliers. None if no transform with enough inliers was found.
+pub fn estimate_transform(
+    features_0: &Features,
+    features_1: &Features,
+    config: &RegistrationConfig,
+) -> Option<(Matrix3<f64>, Vec<Match>)> {
+    let putative = descriptor_match(
+        &features_0.descriptors,
+        &features_1.descriptors,
+        10000,
+        config.lowes_ratio,
+    );
+    let verified = match config.model {
+        RegistrationModel::Homography => verify(
+            &HomographyEstimator,
+            features_0,
+            features_1,
+            &putative,
+            config,
+        ),
+        RegistrationModel::Affine => {
+            verify(&AffineEstimator, features_0, features_1, &putative, config)
+        }
+    };
+    debug!(
+        "{} putative matches, {} inliers.",
+        putative.len(),
+        verified
+            .as_ref()
+            .map(|(_, inliers)| inliers.len())
+            .unwrap_or(0)
+    );
+    verified
+        .filter(|(_, inliers)| inliers.len() >= config.min_inliers)
+        .map(|(transform, inliers)| (transform.map(f64::from), inliers))
+}
+
+/// Register an image onto a reference image.
+///
+/// # Arguments
+/// * `reference_path` - The reference image (A).
+/// * `image_path` - The image to align onto the reference (B).
+/// * `options` - The feature extraction options.
+/// * `config` - The registration options.
+/// # Return value
+/// The registration, with B resampled in A's frame, and A itself.
+pub fn register_images(
+    reference_path: PathBuf,
+    image_path: PathBuf,
+    options: Config,
+    config: &RegistrationConfig,
+) -> Result<(Registration, RgbImage), Error> {
+    let reference = image::open(&reference_path)?.to_rgb();
+    let image = image::open(&image_path)?.to_rgb();
+    let (_, keypoints, descriptors) = akaze::extract_features(reference_path, options);
+    let reference_features = Features {
+        keypoints,
+        descriptors,
+    };
+    let (_, keypoints, descriptors) = akaze::extract_features(image_path, options);
+    let features = Features {
+        keypoints,
+        descriptors,
+    };
+    let (transform, inliers) = estimate_transform(&features, &reference_features, config)
+        .ok_or_else(|| format_err!("no {:?} transform found", config.model))?;
+    info!("Registered with {} inliers.", inliers.len());
+    let (warped, mask) = warp_image(&image, &transform, reference.width(), reference.height())
+        .ok_or_else(|| format_err!("the transform is not invertible"))?;
+    Ok((
+        Registration {
+            transform,
+            inliers,
+            warped,
+            mask,
+        },
+        reference,
+    ))
+}
+
+/// Interleave two aligned images in a checkerboard pattern.
+///
+/// Misalignment shows as broken edges at the square borders.
+///
+/// # Arguments
+/// * `reference` - The reference image.
+/// * `registration` - The registered image.
+/// * `square_size` - The size of the squares in pixels.
+/// # Return value
+/// The overlay. Squares of the registered image it does not cover are black.
+pub fn checkerboard_overlay(
+    reference: &RgbImage,
+    registration: &Registration,
+    square_size: u32,
+) -> RgbImage {
+    let square_size = u32::max(square_size, 1);
+    RgbImage::from_fn(reference.width(), reference.height(), |x, y| {
+        if (x / square_size + y / square_size) & 1 == 0 {
+            *reference.get_pixel(x, y)
+        } else if registration.mask[(y * reference.width() + x) as usize] {
+            *registration.warped.get_pixel(x, y)
+        } else {
+            Rgb([0, 0, 0])
+        }
+    })
+}
+
+/// The absolute difference of two aligned images.
+///
+/// Aligned, unchanged content is dark, changes and misalignment are bright.
+///
+/// # Arguments
+/// * `reference` - The reference image.
+/// * `registration` - The registered image.
+/// # Return value
+/// The difference. Pixels the registered image does not cover are black.
+pub fn difference_overlay(reference: &RgbImage, registration: &Registration) -> RgbImage {
+    RgbImage::from_fn(reference.width(), reference.height(), |x, y| {
+        if !registration.mask[(y * reference.width() + x) as usize] {
+            return Rgb([0, 0, 0]);
+        }
+        let (a, b) = (
+            reference.get_pixel(x, y),
+            registration.warped.get_pixel(x, y),
+        );
+        Rgb([
+            (i16::from(a[0]) - i16::from(b[0])).unsigned_abs() as u8,
+            (i16::from(a[1]) - i16::from(b[1])).unsigned_abs() as u8,
+            (i16::from(a[2]) - i16::from(b[2])).unsigned_abs() as u8,
+        ])
+    })
+}
@@ -0,0 +1,302 @@
+//! Lossless scale space files, for caching nonlinear scale spaces and
+//! comparing them across code versions.
+//!
+//! A scale space file holds the magic number `AKZS`, the major and minor
+//! format versions as 16 bit little endian integers, the bincode encoded
+//! `ScaleSpaceHeader` naming the image size and options it was made with,
+//! then the bincode encoded `Vec<EvolutionStep>` with every image at full
+//! float precision.
+
+use crate::feature_file::config_hash;
+use akaze::types::evolution::{Config, EvolutionStep};
+use akaze::types::image::{GrayFloatImage, ImageFunctions};
+use failure::{format_err, Error};
+use log::*;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+/// The magic number at the start of every scale space file.
+pub const SCALE_SPACE_MAGIC: [u8; 4] = *b"AKZS";
+
+/// The major format version written and read by this crate.
+pub const SCALE_SPACE_MAJOR_VERSION: u16 = 2;
+
+/// The minor format version written by this crate.
+pub const SCALE_SPACE_MINOR_VERSION: u16 = 0;
+
+/// What a scale space was made from, so that a cached one is only reused
+/// for the same image and options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScaleSpaceHeader {
+    /// The width and height of the input image
+    pub image_size: (u32, u32),
+    /// The `config_hash` of the extraction options
+    pub config_hash: u64,
+}
+
+impl ScaleSpaceHeader {
+    /// The header of a scale space made from an image with options.
+    ///
+    /// # Arguments
+    /// * `image_size` - The width and height of the input image.
+    /// * `config` - The extraction options.
+    pub fn new(image_size: (u32, u32), config: &Config) -> ScaleSpaceHeader {
+        ScaleSpaceHeader {
+            image_size,
+            config_hash: config_hash(config),
+        }
+    }
+
+    /// Check that a scale space was made like this one.
+    ///
+    /// # Arguments
+    /// * `other` - The header of the other scale space.
+    /// # Return value
+    /// An error naming the difference if the image sizes or options differ.
+    pub fn check_same(&self, other: &ScaleSpaceHeader) -> Result<(), Error> {
+        if self.image_size != other.image_size {
+            return Err(format_err!(
+                "the scale space was made from a {} x {} image, not {} x {}",
+                other.image_size.0,
+                other.image_size.1,
+                self.image_size.0,
+                self.image_size.1
+            ));
+        }
+        if self.config_hash != other.config_hash {
+            return Err(format_err!(
+                "the scale space was made with other options, hash {:016x} instead of {:016x}",
+                other.config_hash,
+                self.config_hash
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The images of an evolution step, by name.
+fn images(evolution: &EvolutionStep) -> [(&'static str, &GrayFloatImage); 10] {
+    [
+        ("Lt", &evolution.Lt),
+        ("Lsmooth", &evolution.Lsmooth),
+        ("Lx", &evolution.Lx),
+        ("Ly", &evolution.Ly),
+        ("Lxx", &evolution.Lxx),
+        ("Lyy", &evolution.Lyy),
+        ("Lxy", &evolution.Lxy),
+        ("Lflow", &evolution.Lflow),
+        ("Lstep", &evolution.Lstep),
+        ("Ldet", &evolution.Ldet),
+    ]
+}
+
+/// Write a scale space.
+///
+/// # Arguments
+/// * `writer` - The destination.
+/// * `header` - What the scale space was made from.
+/// * `evolutions` - The scale space.
+pub fn write_scale_space(
+    mut writer: impl Write,
+    header: &ScaleSpaceHeader,
+    evolutions: &[EvolutionStep],
+) -> Result<(), Error> {
+    writer.write_all(&SCALE_SPACE_MAGIC)?;
+    writer.write_all(&SCALE_SPACE_MAJOR_VERSION.to_le_bytes())?;
+    writer.write_all(&SCALE_SPACE_MINOR_VERSION.to_le_bytes())?;
+    bincode::serialize_into(&mut writer, header)?;
+    bincode::serialize_into(writer, evolutions)?;
+    Ok(())
+}
+
+/// Read a scale space.
+///
+/// # Arguments
+/// * `reader` - The source, at the start of the scale space.
+/// # Return value
+/// What the scale space was made from and the scale space.
+pub fn read_scale_space(
+    mut reader: impl Read,
+) -> Result<(ScaleSpaceHeader, Vec<EvolutionStep>), Error> {
+    let mut preamble = [0u8; 8];
+    reader.read_exact(&mut preamble)?;
+    if preamble[..4] != SCALE_SPACE_MAGIC {
+        return Err(format_err!(
+            "not a scale space file, the magic number is missing"
+        ));
+    }
+    let major_version = u16::from_le_bytes([preamble[4], preamble[5]]);
+    let minor_version = u16::from_le_bytes([preamble[6], preamble[7]]);
+    if major_version != SCALE_SPACE_MAJOR_VERSION {
+        return Err(format_err!(
+            "unsupported scale space version {}.{}, expected {}.x",
+            major_version,
+            minor_version,
+            SCALE_SPACE_MAJOR_VERSION
+        ));
+    }
+    let header: ScaleSpaceHeader = bincode::deserialize_from(&mut reader)?;
+    let evolutions: Vec<EvolutionStep> = bincode::deserialize_from(reader)?;
+    for (level, evolution) in evolutions.iter().enumerate() {
+        for (name, image) in images(evolution).iter() {
+            if image.buffer.len() != image.width() * image.height() {
+                return Err(format_err!(
+                    "{} of evolution {} has {} pixels, expected {} x {}",
+                    name,
+                    level,
+                    image.buffer.len(),
+                    image.width(),
+                    image.height()
+                ));
+            }
+        }
+    }
+    Ok((header, evolutions))
+}
+
+/// Serialize a scale space to a file.
+pub fn serialize_scale_space_to_file(
+    evolutions: &[EvolutionStep],
+    header: &ScaleSpaceHeader,
+    path: impl AsRef<Path>,
+) -> Result<(), Error> {
+    let path = path.as_ref();
+    debug!("Writing scale space to {:?}", path);
+    write_scale_space(BufWriter::new(File::create(path)?), header, evolutions)
+}
+
+/// Deserialize a scale space from a file.
+pub fn deserialize_scale_space_from_file(
+    path: impl AsRef<Path>,
+) -> Result<(ScaleSpaceHeader, Vec<EvolutionStep>), Error> {
+    let path = path.as_ref();
+    debug!("Reading scale space from {:?}", path);
+    read_scale_space(BufReader::new(File::open(path)?))
+}
+
+/// The difference between two images.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ImageDifference {
+    /// The largest absolute difference of a pixel
+    pub max_abs: f32,
+    /// The root mean square of the pixel differences
+    pub rms: f64,
+}
+
+/// The difference between two evolution steps.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvolutionDifference {
+    /// The index of the evolution step
+    pub level: usize,
+    /// The absolute difference of the evolution times
+    pub etime: f64,
+    /// The absolute difference of the evolution sigmas
+    pub esigma: f64,
+    /// The largest absolute difference of the fed_tau steps
+    pub fed_tau_steps: f64,
+    /// The differences of the images, by name
+    pub images: Vec<(String, ImageDifference)>,
+}
+
+impl EvolutionDifference {
+    /// The largest absolute difference of any value.
+    pub fn max_abs(&self) -> f64 {
+        self.images
+            .iter()
+            .map(|(_, difference)| f64::from(difference.max_abs))
+            .fold(
+                self.etime.max(self.esigma).max(self.fed_tau_steps),
+                f64::max,
+            )
+    }
+}
+
+fn compare_images(image_0: &GrayFloatImage, image_1: &GrayFloatImage) -> ImageDifference {
+    let mut max_abs = 0f32;
+    let mut sum_squared = 0f64;
+    for (&pixel_0, &pixel_1) in image_0.buffer.iter().zip(image_1.buffer.iter()) {
+        let difference = (pixel_0 - pixel_1).abs();
+        // NaN pixels that are NaN in both images are equal
+        if difference.is_nan() && !(pixel_0.is_nan() && pixel_1.is_nan()) {
+            max_abs = f32::INFINITY;
+        } else if !difference.is_nan() {
+            max_abs = max_abs.max(difference);
+            sum_squared += f64::from(difference) * f64::from(difference);
+        }
+    }
+    ImageDifference {
+        max_abs,
+        rms: (sum_squared / image_0.buffer.len().max(1) as f64).sqrt(),
+    }
+}
+
+/// Compare two scale spaces, such as those of one image made by two
+/// versions of the code.
+///
+/// # Arguments
+/// * `evolutions_0` - The first scale space.
+/// * `evolutions_1` - The second scale space.
+/// # Return value
+/// The differences of each evolution step, or an error if the scale spaces
+/// differ in their structure: the number of steps, their octaves and
+/// sublevels, the number of fed_tau steps or the image sizes.
+pub fn compare_scale_spaces(
+    evolutions_0: &[EvolutionStep],
+    evolutions_1: &[EvolutionStep],
+) -> Result<Vec<EvolutionDifference>, Error> {
+    if evolutions_0.len() != evolutions_1.len() {
+        return Err(format_err!(
+            "the scale spaces have {} and {} evolutions",
+            evolutions_0.len(),
+            evolutions_1.len()
+        ));
+    }
+    evolutions_0
+        .iter()
+        .zip(evolutions_1.iter())
+        .enumerate()
+        .map(|(level, (evolution_0, evolution_1))| {
+            if (evolution_0.octave, evolution_0.sublevel)
+                != (evolution_1.octave, evolution_1.sublevel)
+                || evolution_0.fed_tau_steps.len() != evolution_1.fed_tau_steps.len()
+            {
+                return Err(format_err!(
+                    "evolution {} differs in its octave, sublevel or number of steps",
+                    level
+                ));
+            }
+            let images = images(evolution_0)
+                .iter()
+                .zip(images(evolution_1).iter())
+                .map(|((name, image_0), (_, image_1))| {
+                    if (image_0.width(), image_0.height()) != (image_1.width(), image_1.height()) {
+                        return Err(format_err!(
+                            "{} of evolution {} is {} x {} and {} x {}",
+                            name,
+                            level,
+                            image_0.width(),
+                            image_0.height(),
+                            image_1.width(),
+                            image_1.height()
+                        ));
+                    }
+                    Ok((name.to_string(), compare_images(image_0, image_1)))
+                })
+                .collect::<Result<Vec<_>, Error>>()?;
+            Ok(EvolutionDifference {
+                level,
+                etime: (evolution_0.etime - evolution_1.etime).abs(),
+                esigma: (evolution_0.esigma - evolution_1.esigma).abs(),
+                fed_tau_steps: evolution_0
+                    .fed_tau_steps
+                    .iter()
+                    .zip(evolution_1.fed_tau_steps.iter())
+                    .map(|(step_0, step_1)| (step_0 - step_1).abs())
+                    .fold(0f64, f64::max),
+                images,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,288 @@
+//! Panorama stitching: homography matching between neighbouring images,
+//! chaining of the homographies to a reference image, warping to a common
+//! canvas and feathered blending of the seams.
+
+use crate::warp::{sample_bilinear, transform_point, warped_bounds};
+use crate::Features;
+use akaze::ops::estimate_homography::HomographyEstimator;
+use akaze::ops::feature_matching::descriptor_match;
+use akaze::ops::ransac::{ransac, RansacConfig};
+use akaze::types::evolution::Config;
+use akaze::types::feature_match::Match;
+use failure::{format_err, Error};
+use image::{Rgb, RgbImage};
+use log::*;
+use nalgebra::Matrix3;
+use random::Xorshift128Plus;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Options of the panorama builder.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct StitchingConfig {
+    /// The ratio between the best and second-best match distance
+    pub lowes_ratio: f64,
+    /// The maximum number of RANSAC trials
+    pub ransac_trials: usize,
+    /// The maximum transfer error in pixels to accept an inlier
+    pub ransac_epsilon_inliers: f32,
+    /// The minimum number of inliers for a pair of neighbouring images
+    pub min_inliers: usize,
+    /// The image whose frame is the panorama's frame. Defaults to the
+    /// middle image, which keeps the distortion of the outer images low.
+    pub reference: Option<usize>,
+    /// The maximum number of pixels of the canvas, to catch homographies
+    /// which map an image far away. Blending takes 16 bytes per canvas
+    /// pixel, 400 MB at the default of 25 million pixels.
+    pub max_canvas_pixels: u64,
+}
+
+impl Default for StitchingConfig {
+    fn default() -> StitchingConfig {
+        StitchingConfig {
+            lowes_ratio: 0.8,
+            ransac_trials: 2000,
+            ransac_epsilon_inliers: 3.0,
+            min_inliers: 20,
+            reference: None,
+            max_canvas_pixels: 25_000_000,
+        }
+    }
+}
+
+/// A stitched panorama.
+pub struct Panorama {
+    /// The blended image
+    pub image: RgbImage,
+    /// The homographies mapping each input image to the panorama
+    pub transforms: Vec<Matrix3<f64>>,
+    /// The number of inliers between each image and the next one
+    pub inliers: Vec<usize>,
+}
+
+/// Match two feature sets and verify the matches with a homography.
+///
+/// # Arguments
+/// * `features_0` - The features of the first image.
+/// * `features_1` - The features of the second image.
+/// * `config` - The matching options.
+/// # Return value
+/// The homography mapping the first image to the second one, and its
+/// inliers. None if no homography was found.
+pub fn match_homography(
+    features_0: &Features,
+    features_1: &Features,
+    config: &StitchingConfig,
+) -> Option<(Matrix3<f32>, Vec<Match>)> {
+    let putative = descriptor_match(
+        &features_0.descriptors,
+        &features_1.descriptors,
+        10000,
+        config.lowes_ratio,
+    );
+    let ransac_config = RansacConfig {
+        max_trials: config.ransac_trials,
+        epsilon_inlier: config.ransac_epsilon_inliers,
+        local_optimization: true,
+        ..RansacConfig::default()
+    };
+    let mut source = Xorshift128Plus::new(ransac_config.seed);
+    ransac(
+        &HomographyEstimator,
+        &features_0.keypoints,
+        &features_1.keypoints,
+        &putative,
+        &ransac_config,
+        &mut source,
+    )
+    .map(|result| (result.model, result.inliers))
+}
+
+/// Chain the homographies between neighbouring images to a reference.
+///
+/// # Arguments
+/// * `pairwise` - The homography mapping each image to the next one.
+/// * `reference` - The index of the reference image.
+/// # Return value
+/// The homography mapping each image to the reference image. None if a
+/// homography is not invertible.
+pub fn chain_homographies(
+    pairwise: &[Matrix3<f64>],
+    reference: usize,
+) -> Option<Vec<Matrix3<f64>>> {
+    let mut transforms = vec![Matrix3::identity(); pairwise.len() + 1];
+    for i in (0..reference).rev() {
+        transforms[i] = transforms[i + 1] * pairwise[i];
+    }
+    for i in reference + 1..transforms.len() {
+        transforms[i] = transforms[i - 1] * pairwise[i - 1].try_inverse()?;
+    }
+    Some(transforms)
+}
+
+/// The feathering weight of a pixel, its distance to the image border.
+fn feather_weight(point: (f64, f64), width: u32, height: u32) -> f32 {
+    let distance = f64::min(
+        f64::min(point.0 + 1f64, f64::from(width) - point.0),
+        f64::min(point.1 + 1f64, f64::from(height) - point.1),
+    );
+    f64::max(distance, 0f64) as f32
+}
+
+/// Warp images to a common canvas and blend them.
+///
+/// Where images overlap, colors are averaged with weights falling off
+/// linearly towards each image's border (feathering), which hides the
+/// seams between images of slightly different exposure.
+///
+/// # Arguments
+/// * `images` - The images.
+/// * `transforms` - The homography mapping each image to the reference frame.
+/// * `max_canvas_pixels` - The maximum number of pixels of the canvas.
+///   Blending takes 16 bytes per canvas pixel.
+/// # Return value
+/// The blended image, and the homographies mapping each image to it.
+pub fn blend(
+    images: &[RgbImage],
+    transforms: &[Matrix3<f64>],
+    max_canvas_pixels: u64,
+) -> Result<(RgbImage, Vec<Matrix3<f64>>), Error> {
+    // The canvas is the bounding box of the warped images
+    let mut min = (f64::MAX, f64::MAX);
+    let mut max = (f64::MIN, f64::MIN);
+    let mut bounds = vec![];
+    for (image, transform) in images.iter().zip(transforms.iter()) {
+        if image.width() == 0 || image.height() == 0 {
+            return Err(format_err!("an image is empty"));
+        }
+        let (image_min, image_max) = warped_bounds(image.width(), image.height(), transform)
+            .ok_or_else(|| format_err!("an image corner maps to infinity"))?;
+        min = (min.0.min(image_min.0), min.1.min(image_min.1));
+        max = (max.0.max(image_max.0), max.1.max(image_max.1));
+        bounds.push((image_min, image_max));
+    }
+    let width = (max.0 - min.0).ceil() + 1f64;
+    let height = (max.1 - min.1).ceil() + 1f64;
+    if !(width * height).is_finite() || width * height > max_canvas_pixels as f64 {
+        return Err(format_err!(
+            "the canvas of {} x {} pixels is too large",
+            width,
+            height
+        ));
+    }
+    let (width, height) = (width as u32, height as u32);
+    info!(
+        "Blending {} images on a {} x {} canvas.",
+        images.len(),
+        width,
+        height
+    );
+    let offset = Matrix3::new(1f64, 0f64, -min.0, 0f64, 1f64, -min.1, 0f64, 0f64, 1f64);
+    let transforms: Vec<Matrix3<f64>> = transforms.iter().map(|t| offset * t).collect();
+    // Weighted sums of the colors, and the sum of the weights
+    let mut sums = vec![[0f32; 4]; width as usize * height as usize];
+    for (i, (image, transform)) in images.iter().zip(transforms.iter()).enumerate() {
+        let inverse = transform
+            .try_inverse()
+            .ok_or_else(|| format_err!("a homography is not invertible"))?;
+        // Only visit the image's bounding box on the canvas
+        let (image_min, image_max) = bounds[i];
+        let (x_0, y_0) = ((image_min.0 - min.0) as u32, (image_min.1 - min.1) as u32);
+        let x_1 = ((image_max.0 - min.0).ceil() as u32).min(width - 1);
+        let y_1 = ((image_max.1 - min.1).ceil() as u32).min(height - 1);
+        for y in y_0..=y_1 {
+            for x in x_0..=x_1 {
+                let source = match transform_point(&inverse, (f64::from(x), f64::from(y))) {
+                    Some(source) => source,
+                    None => continue,
+                };
+                if let Some(color) = sample_bilinear(image, source.0, source.1) {
+                    let weight = feather_weight(source, image.width(), image.height());
+                    let sum = &mut sums[y as usize * width as usize + x as usize];
+                    for c in 0..3 {
+                        sum[c] += weight * color[c];
+                    }
+                    sum[3] += weight;
+                }
+            }
+        }
+    }
+    let mut canvas = RgbImage::new(width, height);
+    for (i, sum) in sums.iter().enumerate() {
+        if sum[3] > 0f32 {
+            let color = |c: usize| (sum[c] / sum[3]).round().clamp(0f32, 255f32) as u8;
+            canvas.put_pixel(
+                (i % width as usize) as u32,
+                (i / width as usize) as u32,
+                Rgb([color(0), color(1), color(2)]),
+            );
+        }
+    }
+    Ok((canvas, transforms))
+}
+
+/// Build a panorama from a sequence of overlapping images.
+///
+/// Features are extracted from every image and each image is matched to
+/// the next one with a homography, so the images must be ordered such that
+/// neighbours overlap, and taken from roughly the same position (or show a
+/// planar scene).
+///
+/// # Arguments
+/// * `paths` - The images, in order.
+/// * `options` - The feature extraction options.
+/// * `config` - The stitching options.
+/// # Return value
+/// The panorama.
+pub fn stitch(
+    paths: &[PathBuf],
+    options: Config,
+    config: &StitchingConfig,
+) -> Result<Panorama, Error> {
+    if paths.len() < 2 {
+        return Err(format_err!("at least two images are needed"));
+    }
+    let reference = config.reference.unwrap_or(paths.len() / 2);
+    if reference >= paths.len() {
+        return Err(format_err!("reference image {} does not exist", reference));
+    }
+    let mut images = vec![];
+    let mut features = vec![];
+    for path in paths {
+        images.push(image::open(path)?.to_rgb());
+        let (_, keypoints, descriptors) = akaze::extract_features(path.clone(), options);
+        features.push(Features {
+            keypoints,
+            descriptors,
+        });
+    }
+    let mut pairwise = vec![];
+    let mut inliers = vec![];
+    for i in 0..paths.len() - 1 {
+        let (homography, pair_inliers) = match_homography(&features[i], &features[i + 1], config)
+            .filter(|(_, pair_inliers)| pair_inliers.len() >= config.min_inliers)
+            .ok_or_else(|| {
+                format_err!(
+                    "no homography between {:?} and {:?}",
+                    paths[i],
+                    paths[i + 1]
+                )
+            })?;
+        info!(
+            "{:?} -> {:?}: {} inliers.",
+            paths[i],
+            paths[i + 1],
+            pair_inliers.len()
+        );
+        pairwise.push(homography.map(f64::from));
+        inliers.push(pair_inliers.len());
+    }
+    let transforms = chain_homographies(&pairwise, reference)
+        .ok_or_else(|| format_err!("a homography is not invertible"))?;
+    let (image, transforms) = blend(&images, &transforms, config.max_canvas_pixels)?;
+    Ok(Panorama {
+        image,
+        transforms,
+        inliers,
+    })
+}
@@ -0,0 +1,117 @@
+//! Resampling of images under projective transforms.
+
+use image::{Rgb, RgbImage};
+use nalgebra::{Matrix3, Vector3};
+
+/// Apply a homography to a point.
+///
+/// # Return value
+/// The transformed point, or None if it maps to infinity.
+pub fn transform_point(transform: &Matrix3<f64>, point: (f64, f64)) -> Option<(f64, f64)> {
+    let p = transform * Vector3::new(point.0, point.1, 1f64);
+    if p[2].abs() < 1e-12 {
+        None
+    } else {
+        Some((p[0] / p[2], p[1] / p[2]))
+    }
+}
+
+/// The bounding box of an image under a homography.
+///
+/// # Arguments
+/// * `width` - The image width.
+/// * `height` - The image height.
+/// * `transform` - A homography mapping the image's pixel coordinates.
+/// # Return value
+/// The smallest and largest coordinates of the transformed image corners,
+/// or None if the image is empty or a corner maps to infinity.
+pub fn warped_bounds(
+    width: u32,
+    height: u32,
+    transform: &Matrix3<f64>,
+) -> Option<((f64, f64), (f64, f64))> {
+    if width == 0 || height == 0 {
+        return None;
+    }
+    let (x, y) = (f64::from(width - 1), f64::from(height - 1));
+    let mut min = (f64::MAX, f64::MAX);
+    let mut max = (f64::MIN, f64::MIN);
+    for &corner in &[(0f64, 0f64), (x, 0f64), (0f64, y), (x, y)] {
+        let p = transform_point(transform, corner)?;
+        min = (min.0.min(p.0), min.1.min(p.1));
+        max = (max.0.max(p.0), max.1.max(p.1));
+    }
+    Some((min, max))
+}
+
+/// Sample an image at a subpixel position with bilinear interpolation.
+///
+/// Pixel centers are at integer coordinates, as for keypoints.
+///
+/// # Return value
+/// The interpolated color, or None outside of the image.
+pub fn sample_bilinear(image: &RgbImage, x: f64, y: f64) -> Option<[f32; 3]> {
+    let (width, height) = image.dimensions();
+    if width == 0 || height == 0 {
+        return None;
+    }
+    if !(x >= 0f64 && y >= 0f64 && x <= f64::from(width - 1) && y <= f64::from(height - 1)) {
+        return None;
+    }
+    let (x_0, y_0) = (x.floor() as u32, y.floor() as u32);
+    let (x_1, y_1) = (u32::min(x_0 + 1, width - 1), u32::min(y_0 + 1, height - 1));
+    let (f_x, f_y) = ((x - x.floor()) as f32, (y - y.floor()) as f32);
+    let mut color = [0f32; 3];
+    for (c, value) in color.iter_mut().enumerate() {
+        let top = f32::from(image.get_pixel(x_0, y_0)[c]) * (1f32 - f_x)
+            + f32::from(image.get_pixel(x_1, y_0)[c]) * f_x;
+        let bottom = f32::from(image.get_pixel(x_0, y_1)[c]) * (1f32 - f_x)
+            + f32::from(image.get_pixel(x_1, y_1)[c]) * f_x;
+        *value = top * (1f32 - f_y) + bottom * f_y;
+    }
+    Some(color)
+}
+
+/// Resample an image into another frame.
+///
+/// Each output pixel is mapped back into the input with the inverse of
+/// `transform` and sampled bilinearly.
+///
+/// # Arguments
+/// * `image` - The input image.
+/// * `transform` - A homography mapping input pixel coordinates to output
+///   pixel coordinates.
+/// * `width` - The output width.
+/// * `height` - The output height.
+/// # Return value
+/// The warped image and a mask of the output pixels covered by the input,
+/// row by row. None if the transform is not invertible.
+pub fn warp_image(
+    image: &RgbImage,
+    transform: &Matrix3<f64>,
+    width: u32,
+    height: u32,
+) -> Option<(RgbImage, Vec<bool>)> {
+    let inverse = transform.try_inverse()?;
+    let mut output = RgbImage::new(width, height);
+    let mut mask = vec![false; width as usize * height as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let color = transform_point(&inverse, (f64::from(x), f64::from(y)))
+                .and_then(|(u, v)| sample_bilinear(image, u, v));
+            if let Some(color) = color {
+                output.put_pixel(
+                    x,
+                    y,
+                    Rgb([
+                        color[0].round() as u8,
+                        color[1].round() as u8,
+                        color[2].round() as u8,
+                    ]),
+                );
+                mask[y as usize * width as usize + x as usize] = true;
+            }
+        }
+    }
+    Some((output, mask))
+}
@@ -0,0 +1,125 @@
+mod common;
+
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use akaze_util::archive::*;
+use akaze_util::feature_file::{FeatureFileHeader, FeatureStream};
+use akaze_util::{serialize_features_to_file, Features};
+use common::{assert_features_eq, features, temporary_path};
+
+#[test]
+fn archive_round_trip() {
+    let path = temporary_path("archive.akza");
+    // Odd sizes, so the images are padded for alignment
+    let images: Vec<Features> = vec![features(13, 0), features(0, 1), features(7, 2)];
+    let mut writer = FeatureArchiveWriter::create(&path).unwrap();
+    for (i, image) in images.iter().enumerate() {
+        let id = writer
+            .add(
+                &format!("image_{}", i),
+                &FeatureFileHeader::from_features(image),
+                image,
+            )
+            .unwrap();
+        assert_eq!(id, i);
+    }
+    let duplicate = writer.add(
+        "image_0",
+        &FeatureFileHeader::from_features(&images[0]),
+        &images[0],
+    );
+    assert!(duplicate.is_err());
+    writer.finish().unwrap();
+
+    let archive = FeatureArchive::open(&path).unwrap();
+    assert_eq!(archive.len(), 3);
+    assert_eq!(archive.image_id("image_2"), Some(2));
+    assert_eq!(archive.image_id("image_3"), None);
+    for (id, image) in images.iter().enumerate() {
+        assert_eq!(
+            archive.entries()[id].header.num_features,
+            image.keypoints.len() as u64
+        );
+        let keypoints = archive.keypoints(id);
+        let descriptors = archive.descriptors(id);
+        assert_eq!(keypoints.len(), image.keypoints.len());
+        assert_eq!(descriptors.len(), image.descriptors.len());
+        for (i, descriptor) in image.descriptors.iter().enumerate() {
+            assert_eq!(keypoints[i], PackedKeypoint::new(&image.keypoints[i]));
+            assert_eq!(descriptors.get(i), &descriptor.vector[..]);
+        }
+        assert_features_eq(&archive.features(id), image);
+    }
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn mixed_descriptor_lengths() {
+    let mut short = features(3, 0);
+    for descriptor in &mut short.descriptors {
+        descriptor.vector.truncate(32);
+    }
+    let path = temporary_path("mixed.akza");
+    let mut writer = FeatureArchiveWriter::create(&path).unwrap();
+    let image = features(3, 0);
+    writer
+        .add("long", &FeatureFileHeader::from_features(&image), &image)
+        .unwrap();
+    assert!(writer
+        .add("short", &FeatureFileHeader::from_features(&short), &short)
+        .is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn archive_rejects_entries_out_of_bounds() {
+    let path = temporary_path("crafted.akza");
+    let image = features(5, 0);
+    let mut writer = FeatureArchiveWriter::create(&path).unwrap();
+    writer
+        .add("image", &FeatureFileHeader::from_features(&image), &image)
+        .unwrap();
+    writer.finish().unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    let mut index_offset = [0u8; 8];
+    index_offset.copy_from_slice(&bytes[16..24]);
+    let index_offset = u64::from_le_bytes(index_offset) as usize;
+    let entries: Vec<ArchiveEntry> = bincode::deserialize(&bytes[index_offset..]).unwrap();
+    let check = |entry: ArchiveEntry| {
+        let mut crafted = bytes[..index_offset].to_vec();
+        bincode::serialize_into(&mut crafted, &vec![entry]).unwrap();
+        std::fs::write(&path, &crafted).unwrap();
+        let error = FeatureArchive::open(&path).err().unwrap();
+        assert!(error.to_string().contains("out of bounds"), "{}", error);
+    };
+    // Sizes that overflow and offsets past the index
+    let mut entry = entries[0].clone();
+    entry.header.num_features = u64::MAX / 4;
+    check(entry);
+    let mut entry = entries[0].clone();
+    entry.offset = u64::MAX - 3;
+    check(entry);
+    let mut entry = entries[0].clone();
+    entry.header.num_features += 1;
+    check(entry);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn stream_feature_file() {
+    let path = temporary_path("stream.bin");
+    let image = features(21, 3);
+    serialize_features_to_file(&image, &path).unwrap();
+    let (header, stream) = FeatureStream::open(&path).unwrap();
+    assert_eq!(header.num_features, 21);
+    assert_eq!(stream.size_hint(), (21, Some(21)));
+    let (keypoints, descriptors): (Vec<Keypoint>, Vec<Descriptor>) =
+        stream.map(Result::unwrap).unzip();
+    assert_features_eq(
+        &Features {
+            keypoints,
+            descriptors,
+        },
+        &image,
+    );
+    std::fs::remove_file(&path).unwrap();
+}
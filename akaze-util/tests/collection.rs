@@ -0,0 +1,75 @@
+mod common;
+
+use akaze_util::collection::*;
+use akaze_util::{deserialize_matches_from_file, serialize_features_to_file};
+use common::{temporary_path, two_views};
+use std::fs;
+
+#[test]
+fn pair_selection() {
+    assert_eq!(exhaustive_pairs(3), vec![(0, 1), (0, 2), (1, 2)]);
+    assert_eq!(
+        sequential_pairs(4, 2),
+        vec![(0, 1), (0, 2), (1, 2), (1, 3), (2, 3)]
+    );
+}
+
+#[test]
+fn match_two_image_collection() {
+    let input = temporary_path("collection-input");
+    let output = temporary_path("collection-output");
+    fs::create_dir_all(&input).unwrap();
+    let (features_0, features_1) = two_views(100);
+    serialize_features_to_file(&features_0, input.join("a.bin")).unwrap();
+    serialize_features_to_file(&features_1, input.join("b.bin")).unwrap();
+    // Other files and summaries are ignored
+    fs::write(input.join("notes.txt"), "not features").unwrap();
+    fs::write(input.join("summary.json"), "{}").unwrap();
+    let images = list_feature_files(&input).unwrap();
+    assert_eq!(images, vec![input.join("a.bin"), input.join("b.bin")]);
+    // Image names must be unique, as they name the match files
+    serialize_features_to_file(&features_1, input.join("b.json")).unwrap();
+    let error = list_feature_files(&input).unwrap_err();
+    assert!(error.to_string().contains("same image name b"), "{}", error);
+    fs::remove_file(input.join("b.json")).unwrap();
+    let list = input.join("list.txt");
+    fs::write(&list, "a.bin\nb.bin\nsub/a.bin\n").unwrap();
+    assert!(list_feature_files(&list).is_err());
+    fs::remove_file(&list).unwrap();
+
+    // The epipolar threshold is in algebraic units, which depend on the
+    // image coordinates, and is tightened for these views
+    let config = MatchingConfig {
+        ransac_epsilon_inliers: 1e-3,
+        ..MatchingConfig::default()
+    };
+    let summary = match_collection(
+        &images,
+        &PairSelection::Exhaustive,
+        &config,
+        &output,
+        "bin",
+        2,
+    )
+    .unwrap();
+    assert_eq!(summary.pairs.len(), 1);
+    let pair = &summary.pairs[0];
+    assert_eq!((pair.image_0, pair.image_1), (0, 1));
+    assert_eq!(pair.matches_file, "a_b.bin");
+    // Identical descriptors pass the ratio test, the moved keypoints fail
+    // the epipolar constraint
+    assert_eq!(pair.putative_matches, 100);
+    assert_eq!(pair.verified_matches, 80);
+    let matches = deserialize_matches_from_file(output.join("a_b.bin")).unwrap();
+    assert_eq!(matches.len(), 80);
+    assert!(matches
+        .iter()
+        .all(|m| m.index_0 == m.index_1 && m.index_0 % 5 != 0));
+    let summary_file: CollectionSummary =
+        serde_json::from_reader(fs::File::open(output.join("summary.json")).unwrap()).unwrap();
+    assert_eq!(summary_file.pairs[0].verified_matches, 80);
+    assert_eq!(summary_file.config.ransac_epsilon_inliers, 1e-3);
+
+    fs::remove_dir_all(&input).unwrap();
+    fs::remove_dir_all(&output).unwrap();
+}
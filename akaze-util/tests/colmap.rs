@@ -0,0 +1,317 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::Keypoint;
+use akaze_util::collection::{match_collection, MatchingConfig, PairSelection};
+use akaze_util::colmap::*;
+use akaze_util::feature_file::FeatureFileHeader;
+use akaze_util::serialize_features_with_header_to_file;
+use common::{assert_matches_eq, features, matches, temporary_path, two_views};
+use nalgebra::{Matrix3, Vector3};
+use rusqlite::{params, Connection};
+use std::fs;
+
+/// COLMAP's pair id of two image ids, the smaller one first.
+fn colmap_pair_id(image_id_0: i64, image_id_1: i64) -> i64 {
+    image_id_0.min(image_id_1) * 2_147_483_647 + image_id_0.max(image_id_1)
+}
+
+/// Matches without distances, as COLMAP stores them.
+fn without_distances(matches: &[Match]) -> Vec<Match> {
+    matches
+        .iter()
+        .map(|match_i| Match {
+            distance: 0f64,
+            ..*match_i
+        })
+        .collect()
+}
+
+/// Matches with the images swapped.
+fn swapped(matches: &[Match]) -> Vec<Match> {
+    matches
+        .iter()
+        .map(|match_i| Match {
+            index_0: match_i.index_1,
+            index_1: match_i.index_0,
+            distance: match_i.distance,
+        })
+        .collect()
+}
+
+#[test]
+fn database_round_trip() {
+    let path = temporary_path("colmap.db");
+    let _ = fs::remove_file(&path);
+    let database = ColmapDatabase::open(&path).unwrap();
+    let camera_id = database.add_camera(640, 480).unwrap();
+    let image_ids: Vec<i64> = ["a.jpg", "b.jpg", "c.jpg"]
+        .iter()
+        .map(|name| database.add_image(name, camera_id).unwrap())
+        .collect();
+    assert_eq!(database.image_id("b.jpg").unwrap(), Some(image_ids[1]));
+    assert_eq!(database.image_id("d.jpg").unwrap(), None);
+    assert_eq!(
+        database.images().unwrap(),
+        vec![
+            (image_ids[0], "a.jpg".to_owned()),
+            (image_ids[1], "b.jpg".to_owned()),
+            (image_ids[2], "c.jpg".to_owned())
+        ]
+    );
+
+    // Keypoints and descriptors
+    let original = features(30, 0);
+    database.write_features(image_ids[0], &original).unwrap();
+    let read = database.read_features(image_ids[0]).unwrap();
+    assert_eq!(read.keypoints.len(), original.keypoints.len());
+    for (read, original) in read.keypoints.iter().zip(original.keypoints.iter()) {
+        // Shifted by half a pixel and back
+        assert!((read.point.0 - original.point.0).abs() < 1e-3);
+        assert!((read.point.1 - original.point.1).abs() < 1e-3);
+        assert_eq!(read.size, original.size);
+        assert_eq!(read.angle, original.angle);
+    }
+    for (read, original) in read.descriptors.iter().zip(original.descriptors.iter()) {
+        assert_eq!(read.vector, original.vector);
+    }
+
+    // Matches of a pair in COLMAP's order and swapped
+    let pair_matches = matches(30);
+    database
+        .write_matches(image_ids[0], image_ids[1], &pair_matches)
+        .unwrap();
+    database
+        .write_matches(image_ids[2], image_ids[1], &pair_matches)
+        .unwrap();
+    let expected = without_distances(&pair_matches);
+    assert_matches_eq(
+        &database.read_matches(image_ids[0], image_ids[1]).unwrap(),
+        &expected,
+    );
+    assert_matches_eq(
+        &database.read_matches(image_ids[1], image_ids[0]).unwrap(),
+        &swapped(&expected),
+    );
+    assert_matches_eq(
+        &database.read_matches(image_ids[2], image_ids[1]).unwrap(),
+        &expected,
+    );
+    assert!(database
+        .read_matches(image_ids[0], image_ids[2])
+        .unwrap()
+        .is_empty());
+
+    // Verified matches, whose fundamental matrix is stored for COLMAP's order
+    let fund_mat = Matrix3::new(1f64, 2f64, 3f64, 4f64, 5f64, 6f64, 7f64, 8f64, 9f64);
+    let geometry = TwoViewGeometry {
+        inliers: pair_matches[..10].to_vec(),
+        fund_mat,
+    };
+    database
+        .write_two_view_geometry(image_ids[2], image_ids[1], &geometry)
+        .unwrap();
+    let read = database
+        .read_two_view_geometry(image_ids[2], image_ids[1])
+        .unwrap()
+        .unwrap();
+    assert_matches_eq(&read.inliers, &without_distances(&geometry.inliers));
+    assert_eq!(read.fund_mat, fund_mat);
+    let read = database
+        .read_two_view_geometry(image_ids[1], image_ids[2])
+        .unwrap()
+        .unwrap();
+    assert_matches_eq(
+        &read.inliers,
+        &swapped(&without_distances(&geometry.inliers)),
+    );
+    assert_eq!(read.fund_mat, fund_mat.transpose());
+    assert!(database
+        .read_two_view_geometry(image_ids[0], image_ids[1])
+        .unwrap()
+        .is_none());
+    drop(database);
+
+    // The rows as COLMAP reads them
+    let connection = Connection::open(&path).unwrap();
+    let (rows, cols, blob): (i64, i64, Vec<u8>) = connection
+        .query_row(
+            "SELECT rows, cols, data FROM keypoints WHERE image_id = ?1",
+            params![image_ids[0]],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .unwrap();
+    assert_eq!((rows, cols, blob.len()), (30, 4, 30 * 4 * 4));
+    assert_eq!(
+        &blob[..4],
+        &(original.keypoints[0].point.0 + 0.5f32).to_le_bytes()
+    );
+    let (rows, cols): (i64, i64) = connection
+        .query_row(
+            "SELECT rows, cols FROM descriptors WHERE image_id = ?1",
+            params![image_ids[0]],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!((rows, cols), (30, 61));
+    let mut statement = connection
+        .prepare("SELECT pair_id, data FROM matches ORDER BY pair_id")
+        .unwrap();
+    let rows: Vec<(i64, Vec<u8>)> = statement
+        .query_map(params![], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].0, colmap_pair_id(image_ids[0], image_ids[1]));
+    assert_eq!(rows[1].0, colmap_pair_id(image_ids[1], image_ids[2]));
+    // The pair (2, 1) is stored as (1, 2), with swapped indices
+    let first = |blob: &[u8]| {
+        (
+            u32::from_le_bytes([blob[0], blob[1], blob[2], blob[3]]) as usize,
+            u32::from_le_bytes([blob[4], blob[5], blob[6], blob[7]]) as usize,
+        )
+    };
+    assert_eq!(
+        first(&rows[0].1),
+        (pair_matches[0].index_0, pair_matches[0].index_1)
+    );
+    assert_eq!(
+        first(&rows[1].1),
+        (pair_matches[0].index_1, pair_matches[0].index_0)
+    );
+    drop(statement);
+    drop(connection);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn text_round_trip() {
+    let original = features(30, 0);
+    let mut text = vec![];
+    write_colmap_features_text(&original, &mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    let mut lines = text.lines();
+    // feature_importer takes 128 byte descriptors only
+    assert_eq!(lines.next(), Some("30 128"));
+    for (line, original) in lines.zip(original.descriptors.iter()) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        assert_eq!(fields.len(), 4 + COLMAP_DESCRIPTOR_LENGTH);
+        assert!(fields[4 + original.vector.len()..]
+            .iter()
+            .all(|&field| field == "0"));
+    }
+    let read = read_colmap_features_text(text.as_bytes()).unwrap();
+    assert_eq!(read.keypoints.len(), original.keypoints.len());
+    for (read, original) in read.keypoints.iter().zip(original.keypoints.iter()) {
+        assert!((read.point.0 - original.point.0).abs() < 1e-3);
+        assert!((read.point.1 - original.point.1).abs() < 1e-3);
+        assert_eq!(read.size, original.size);
+        assert_eq!(read.angle, original.angle);
+    }
+    for (read, original) in read.descriptors.iter().zip(original.descriptors.iter()) {
+        assert_eq!(read.vector.len(), COLMAP_DESCRIPTOR_LENGTH);
+        assert_eq!(&read.vector[..original.vector.len()], &original.vector[..]);
+    }
+    // Truncated files are rejected
+    let truncated: String = text.lines().take(10).collect::<Vec<_>>().join("\n");
+    assert!(read_colmap_features_text(truncated.as_bytes()).is_err());
+
+    let pairs = vec![
+        ("a.jpg".to_owned(), "b.jpg".to_owned(), matches(20)),
+        ("a.jpg".to_owned(), "c.jpg".to_owned(), vec![]),
+        ("b.jpg".to_owned(), "c.jpg".to_owned(), matches(7)),
+    ];
+    let mut text = vec![];
+    write_colmap_matches_text(&pairs, &mut text).unwrap();
+    let read = read_colmap_matches_text(&text[..]).unwrap();
+    assert_eq!(read.len(), pairs.len());
+    for (read, original) in read.iter().zip(pairs.iter()) {
+        assert_eq!((&read.0, &read.1), (&original.0, &original.1));
+        assert_matches_eq(&read.2, &without_distances(&original.2));
+    }
+}
+
+/// The epipolar constraint of a match, relative to the norm of F.
+fn epipolar_residual(fund_mat: &Matrix3<f64>, point_0: (f32, f32), point_1: (f32, f32)) -> f64 {
+    let homogeneous = |(x, y): (f32, f32)| Vector3::new(f64::from(x), f64::from(y), 1f64);
+    (homogeneous(point_1).transpose() * fund_mat * homogeneous(point_0))[0].abs() / fund_mat.norm()
+}
+
+#[test]
+fn export_matched_collection() {
+    let input = temporary_path("colmap-export-input");
+    let output = temporary_path("colmap-export-output");
+    fs::create_dir_all(&input).unwrap();
+    let (features_0, features_1) = two_views(100);
+    let images = vec![input.join("a.bin"), input.join("b.bin")];
+    for (features, path) in [&features_0, &features_1].iter().zip(images.iter()) {
+        let header = FeatureFileHeader::extracted(features, (640, 480), &Config::default());
+        serialize_features_with_header_to_file(features, &header, path).unwrap();
+    }
+    let config = MatchingConfig {
+        ransac_epsilon_inliers: 1e-3,
+        ..MatchingConfig::default()
+    };
+    let summary = match_collection(
+        &images,
+        &PairSelection::Exhaustive,
+        &config,
+        &output,
+        "bin",
+        1,
+    )
+    .unwrap();
+    assert!(summary.pairs[0].fundamental_matrix.is_some());
+    let database_path = output.join("database.db");
+    export_collection_to_database(output.join("summary.json"), &database_path, "jpg", true)
+        .unwrap();
+
+    let database = ColmapDatabase::open(&database_path).unwrap();
+    let image_ids: Vec<i64> = ["a.jpg", "b.jpg"]
+        .iter()
+        .map(|name| database.image_id(name).unwrap().unwrap())
+        .collect();
+    let geometry = database
+        .read_two_view_geometry(image_ids[0], image_ids[1])
+        .unwrap()
+        .unwrap();
+    assert_eq!(geometry.inliers.len(), 80);
+    // The verifying F, in the coordinates of this crate when read back
+    let keypoint = |keypoints: &[Keypoint], i: usize| keypoints[i].point;
+    for inlier in &geometry.inliers {
+        let residual = epipolar_residual(
+            &geometry.fund_mat,
+            keypoint(&features_0.keypoints, inlier.index_0),
+            keypoint(&features_1.keypoints, inlier.index_1),
+        );
+        assert!(residual < 1e-3, "{}", residual);
+    }
+    // And in COLMAP's, half a pixel off, as stored
+    drop(database);
+    let connection = Connection::open(&database_path).unwrap();
+    let blob: Vec<u8> = connection
+        .query_row("SELECT F FROM two_view_geometries", params![], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    let value = |i: usize| {
+        let mut bytes = [0u8; 8];
+        bytes.copy_from_slice(&blob[i * 8..(i + 1) * 8]);
+        f64::from_le_bytes(bytes)
+    };
+    let stored = Matrix3::from_fn(|r, c| value(r * 3 + c));
+    let shifted = |(x, y): (f32, f32)| (x + 0.5f32, y + 0.5f32);
+    for inlier in &geometry.inliers {
+        let residual = epipolar_residual(
+            &stored,
+            shifted(keypoint(&features_0.keypoints, inlier.index_0)),
+            shifted(keypoint(&features_1.keypoints, inlier.index_1)),
+        );
+        assert!(residual < 1e-3, "{}", residual);
+    }
+    drop(connection);
+    fs::remove_dir_all(&input).unwrap();
+    fs::remove_dir_all(&output).unwrap();
+}
@@ -0,0 +1,148 @@
+//! Fixtures shared by the integration tests.
+#![allow(dead_code)]
+
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use akaze_util::Features;
+use std::f32::consts::PI;
+use std::path::PathBuf;
+
+/// The test data of this repository.
+pub fn locate_test_data() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .join("test-data")
+}
+
+/// A path in the temporary directory, unique to the test process.
+pub fn temporary_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("akaze-util-{}-{}", std::process::id(), name))
+}
+
+/// Synthetic features with distinct, non-integral values in every field.
+/// Features of different seeds differ in position and descriptors.
+pub fn features(count: usize, seed: usize) -> Features {
+    Features {
+        keypoints: (0..count)
+            .map(|i| Keypoint {
+                point: ((i + seed) as f32 * 5.123f32, 2000f32 - i as f32 / 7f32),
+                response: 1.37e-3f32 * (i + 1) as f32,
+                size: 2.4f32 + i as f32 * 0.731f32,
+                octave: i % 4,
+                class_id: i,
+                angle: -PI + 2f32 * PI * i as f32 / count as f32,
+            })
+            .collect(),
+        descriptors: (0..count)
+            .map(|i| Descriptor {
+                vector: (0..61)
+                    .map(|j| ((i * 61 + j + seed) * 37 % 256) as u8)
+                    .collect(),
+            })
+            .collect(),
+    }
+}
+
+/// A pseudo-random descriptor; descriptors of different seeds differ in
+/// about half of their bits.
+pub fn random_descriptor(seed: usize) -> Descriptor {
+    let mut state = (seed as u64 + 1).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    Descriptor {
+        vector: (0..61)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect(),
+    }
+}
+
+/// The features of a point cloud seen by two cameras, with identical
+/// descriptors for the two projections of each point. Keypoint `i` of the
+/// first view corresponds to keypoint `i` of the second, except that every
+/// fifth keypoint of the second view is moved off its epipolar line.
+pub fn two_views(count: usize) -> (Features, Features) {
+    let focal = 500f64;
+    let project = |x: f64, y: f64, z: f64| {
+        (
+            (focal * x / z + 320f64) as f32,
+            (focal * y / z + 240f64) as f32,
+        )
+    };
+    let (sin, cos) = 0.1f64.sin_cos();
+    let mut features_0 = Features {
+        keypoints: vec![],
+        descriptors: vec![],
+    };
+    let mut features_1 = Features {
+        keypoints: vec![],
+        descriptors: vec![],
+    };
+    for i in 0..count {
+        let x = (i % 10) as f64 * 0.4f64 - 2f64;
+        let y = (i / 10) as f64 * 0.3f64 - 1.5f64;
+        let z = 4f64 + ((i * i) % 13) as f64 * 0.25f64;
+        // Rotated about the y axis and translated
+        let (x_1, y_1, z_1) = (
+            cos * x + sin * z + 1f64,
+            y + 0.1f64,
+            cos * z - sin * x + 0.05f64,
+        );
+        let mut point_1 = project(x_1, y_1, z_1);
+        if i % 5 == 0 {
+            point_1.1 += 40f32;
+        }
+        let keypoint = |point| Keypoint {
+            point,
+            response: 1f32,
+            size: 4f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
+        };
+        features_0.keypoints.push(keypoint(project(x, y, z)));
+        features_1.keypoints.push(keypoint(point_1));
+        features_0.descriptors.push(random_descriptor(i));
+        features_1.descriptors.push(random_descriptor(i));
+    }
+    (features_0, features_1)
+}
+
+/// Synthetic matches, unordered in both indices.
+pub fn matches(count: usize) -> Vec<Match> {
+    (0..count)
+        .map(|i| Match {
+            index_0: (i * 7) % count,
+            index_1: count - 1 - i,
+            distance: f64::from(i as u32 % 61),
+        })
+        .collect()
+}
+
+pub fn assert_features_eq(read: &Features, original: &Features) {
+    assert_eq!(read.keypoints.len(), original.keypoints.len());
+    for (read, original) in read.keypoints.iter().zip(original.keypoints.iter()) {
+        assert_eq!(read.point, original.point);
+        assert_eq!(read.size, original.size);
+        assert_eq!(read.angle, original.angle);
+        assert_eq!(read.response, original.response);
+        assert_eq!(read.octave, original.octave);
+        assert_eq!(read.class_id, original.class_id);
+    }
+    assert_eq!(read.descriptors.len(), original.descriptors.len());
+    for (read, original) in read.descriptors.iter().zip(original.descriptors.iter()) {
+        assert_eq!(read.vector, original.vector);
+    }
+}
+
+pub fn assert_matches_eq(read: &[Match], original: &[Match]) {
+    assert_eq!(read.len(), original.len());
+    for (read, original) in read.iter().zip(original.iter()) {
+        assert_eq!(read.index_0, original.index_0);
+        assert_eq!(read.index_1, original.index_1);
+        assert_eq!(read.distance, original.distance);
+    }
+}
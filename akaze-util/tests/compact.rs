@@ -0,0 +1,64 @@
+mod common;
+
+use akaze_util::compact::*;
+use akaze_util::feature_file::FeatureFileHeader;
+use akaze_util::Features;
+use common::{assert_features_eq, assert_matches_eq, features, matches};
+use std::f32::consts::PI;
+
+fn write(features: &Features, precision: Precision) -> Vec<u8> {
+    let mut buffer = vec![];
+    write_compact_features(
+        &mut buffer,
+        &FeatureFileHeader::from_features(features),
+        features,
+        precision,
+    )
+    .unwrap();
+    buffer
+}
+
+#[test]
+fn lossless_round_trip() {
+    let features = features(300, 0);
+    let buffer = write(&features, Precision::Lossless);
+    let (header, read, precision) = read_compact_features(&buffer[..]).unwrap();
+    assert_eq!(header, FeatureFileHeader::from_features(&features));
+    assert_eq!(precision, Precision::Lossless);
+    assert_features_eq(&read, &features);
+}
+
+#[test]
+fn quantized_error_bounds() {
+    let features = features(300, 0);
+    let buffer = write(&features, Precision::Quantized);
+    assert!(buffer.len() < write(&features, Precision::Lossless).len());
+    let (_, read, precision) = read_compact_features(&buffer[..]).unwrap();
+    assert_eq!(precision, Precision::Quantized);
+    assert_eq!(read.keypoints.len(), features.keypoints.len());
+    for (read, original) in read.keypoints.iter().zip(features.keypoints.iter()) {
+        assert!((read.point.0 - original.point.0).abs() <= POSITION_STEP / 2f32);
+        assert!((read.point.1 - original.point.1).abs() <= POSITION_STEP / 2f32);
+        assert!((read.size - original.size).abs() <= POSITION_STEP / 2f32);
+        let difference = (read.angle - original.angle).rem_euclid(2f32 * PI);
+        let difference = difference.min(2f32 * PI - difference);
+        assert!(difference <= PI / ANGLE_LEVELS as f32 + 1e-6);
+        let relative = ((read.response - original.response) / original.response).abs();
+        assert!(relative <= 0.5f32.powi(RESPONSE_MANTISSA_BITS as i32 + 1));
+        assert_eq!(read.octave, original.octave);
+        assert_eq!(read.class_id, original.class_id);
+    }
+    for (read, original) in read.descriptors.iter().zip(features.descriptors.iter()) {
+        assert_eq!(read.vector, original.vector);
+    }
+}
+
+#[test]
+fn matches_round_trip() {
+    let matches = matches(200);
+    let mut buffer = vec![];
+    write_compact_matches(&mut buffer, &matches).unwrap();
+    assert_matches_eq(&read_compact_matches(&buffer[..]).unwrap(), &matches);
+    // Matches are not features
+    assert!(read_compact_features(&buffer[..]).is_err());
+}
@@ -0,0 +1,116 @@
+use akaze::types::feature_match::Match;
+use akaze::types::keypoint::{Descriptor, Keypoint};
+use akaze_util::lowe::write_lowe_keys;
+use akaze_util::openmvg::*;
+use akaze_util::Features;
+
+/// Two features with exactly printable values and 25 byte descriptors.
+fn features() -> Features {
+    let keypoint = |point, size, angle| Keypoint {
+        point,
+        response: 0.5f32,
+        size,
+        octave: 1,
+        class_id: 2,
+        angle,
+    };
+    Features {
+        keypoints: vec![
+            keypoint((12.5f32, 3.25f32), 2.75f32, -1.5f32),
+            keypoint((0f32, 480.125f32), 10f32, 3f32),
+        ],
+        descriptors: vec![
+            Descriptor {
+                vector: (0..25).collect(),
+            },
+            Descriptor {
+                vector: (0..25).map(|i| 255 - i).collect(),
+            },
+        ],
+    }
+}
+
+#[test]
+fn openmvg_features_text() {
+    let mut text = vec![];
+    write_openmvg_features(&features(), &mut text).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "12.5 3.25 2.75 -1.5\n0 480.125 10 3\n"
+    );
+}
+
+#[test]
+fn openmvg_descriptors_bytes() {
+    let mut bytes = vec![];
+    write_openmvg_descriptors(&features(), 32, &mut bytes).unwrap();
+    let mut expected = vec![2, 0, 0, 0, 0, 0, 0, 0];
+    expected.extend(0..25);
+    expected.extend(&[0; 7]);
+    expected.extend((0..25).map(|i| 255 - i));
+    expected.extend(&[0; 7]);
+    assert_eq!(bytes, expected);
+    // Without padding
+    let mut bytes = vec![];
+    write_openmvg_descriptors(&features(), 25, &mut bytes).unwrap();
+    assert_eq!(bytes.len(), 8 + 2 * 25);
+    // Descriptors longer than the regions type are rejected
+    let error = write_openmvg_descriptors(&features(), 24, vec![]).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "a descriptor of 25 bytes does not fit in 24 bytes"
+    );
+}
+
+#[test]
+fn openmvg_matches_text() {
+    let pairs = vec![
+        (
+            0,
+            1,
+            vec![
+                Match {
+                    index_0: 3,
+                    index_1: 4,
+                    distance: 10f64,
+                },
+                Match {
+                    index_0: 5,
+                    index_1: 0,
+                    distance: 20f64,
+                },
+            ],
+        ),
+        (1, 2, vec![]),
+    ];
+    let mut text = vec![];
+    write_openmvg_matches(&pairs, &mut text).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "0 1\n2\n3 4\n5 0\n1 2\n0\n"
+    );
+}
+
+#[test]
+fn lowe_keys_text() {
+    let mut text = vec![];
+    write_lowe_keys(&features(), &mut text).unwrap();
+    assert_eq!(
+        String::from_utf8(text).unwrap(),
+        "2 25\n\
+         3.25 12.5 2.75 -1.5\n \
+         0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19\n \
+         20 21 22 23 24\n\
+         480.125 0 10 3\n \
+         255 254 253 252 251 250 249 248 247 246 245 244 243 242 241 240 239 238 237 236\n \
+         235 234 233 232 231\n"
+    );
+    // No features
+    let mut text = vec![];
+    let empty = Features {
+        keypoints: vec![],
+        descriptors: vec![],
+    };
+    write_lowe_keys(&empty, &mut text).unwrap();
+    assert_eq!(String::from_utf8(text).unwrap(), "0 0\n");
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze_util::feature_file::*;
+use common::{assert_features_eq, features};
+
+fn container(header: &FeatureFileHeader) -> Vec<u8> {
+    let features = features(20, 0);
+    let mut bytes = vec![];
+    write_feature_file(&mut bytes, header, &features).unwrap();
+    bytes
+}
+
+#[test]
+fn container_layout() {
+    let features = features(20, 0);
+    let header = FeatureFileHeader::extracted(&features, (640, 480), &Config::default());
+    let bytes = container(&header);
+    assert_eq!(&bytes[..4], b"AKZF");
+    assert!(is_feature_file(&bytes));
+    assert_eq!(&bytes[4..6], &MAJOR_VERSION.to_le_bytes());
+    assert_eq!(&bytes[6..8], &MINOR_VERSION.to_le_bytes());
+    let header_length = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+    assert_eq!(
+        bincode::deserialize::<FeatureFileHeader>(&bytes[12..12 + header_length]).unwrap(),
+        header
+    );
+    let (read_header, read_features) = read_feature_file(&bytes[..]).unwrap();
+    assert_eq!(read_header, header);
+    assert_features_eq(&read_features, &features);
+}
+
+#[test]
+fn container_rejects_other_data() {
+    let header = FeatureFileHeader::from_features(&features(20, 0));
+    let mut bytes = container(&header);
+    assert!(!is_feature_file(b"AKZ"));
+    bytes[0] = b'X';
+    assert!(read_feature_file_header(&bytes[..]).is_err());
+    // Other major versions are not readable
+    let mut bytes = container(&header);
+    bytes[4..6].copy_from_slice(&(MAJOR_VERSION + 1).to_le_bytes());
+    let error = read_feature_file_header(&bytes[..]).unwrap_err();
+    assert!(error.to_string().contains("unsupported"), "{}", error);
+    // A header length beyond the end of the data is not allocated
+    let mut bytes = container(&header);
+    bytes[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+    let error = read_feature_file_header(&bytes[..]).unwrap_err();
+    assert!(error.to_string().contains("truncated"), "{}", error);
+    // Truncated preamble
+    assert!(read_feature_file_header(&bytes[..10]).is_err());
+}
+
+#[test]
+fn container_reads_newer_minor_versions() {
+    let features = features(20, 0);
+    let header = FeatureFileHeader::from_features(&features);
+    let bytes = container(&header);
+    let header_length = u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize;
+    // A newer minor version with a field appended to the header
+    let appended = [7u8, 0, 0, 0, 0, 0, 0, 0, 42];
+    let mut newer = bytes[..4].to_vec();
+    newer.extend_from_slice(&MAJOR_VERSION.to_le_bytes());
+    newer.extend_from_slice(&(MINOR_VERSION + 1).to_le_bytes());
+    newer.extend_from_slice(&((header_length + appended.len()) as u32).to_le_bytes());
+    newer.extend_from_slice(&bytes[12..12 + header_length]);
+    newer.extend_from_slice(&appended);
+    newer.extend_from_slice(&bytes[12 + header_length..]);
+    let (read_header, read_features) = read_feature_file(&newer[..]).unwrap();
+    assert_eq!(read_header, header);
+    assert_features_eq(&read_features, &features);
+}
+
+#[test]
+fn compatibility_of_headers() {
+    let features_0 = features(20, 0);
+    let options = Config::default();
+    let header = FeatureFileHeader::extracted(&features_0, (640, 480), &options);
+    // Other image sizes and feature counts match
+    let other = FeatureFileHeader::extracted(&features(5, 1), (320, 240), &options);
+    header.check_compatible(&other).unwrap();
+    // Unknown options are assumed to be compatible
+    let unknown = FeatureFileHeader::from_features(&features_0);
+    header.check_compatible(&unknown).unwrap();
+    unknown.check_compatible(&header).unwrap();
+    // Different options are not
+    let different = FeatureFileHeader::extracted(
+        &features_0,
+        (640, 480),
+        &Config {
+            detector_threshold: options.detector_threshold * 2f64,
+            ..options
+        },
+    );
+    assert!(header.check_compatible(&different).is_err());
+    // Nor are different descriptor lengths, unless a file is empty
+    let longer = FeatureFileHeader {
+        descriptor_length: 64,
+        ..header.clone()
+    };
+    assert!(header.check_compatible(&longer).is_err());
+    let empty = FeatureFileHeader {
+        num_features: 0,
+        ..longer
+    };
+    header.check_compatible(&empty).unwrap();
+}
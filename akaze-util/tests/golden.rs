@@ -0,0 +1,88 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze::types::image::ImageFunctions;
+use akaze_util::feature_file::{config_hash, FeatureFileHeader};
+use akaze_util::golden::{compare_features, GoldenConfig};
+use akaze_util::{
+    deserialize_features_with_header_from_file, serialize_features_with_header_to_file, Features,
+};
+use common::locate_test_data;
+
+/// Extract the features of a test image and compare them with its golden
+/// file. Set AKAZE_UPDATE_GOLDEN to rewrite the golden file instead, after
+/// an intended change of the extractor.
+fn check_golden(image: &str) {
+    let options = Config::default();
+    let test_data = locate_test_data();
+    let (evolutions, keypoints, descriptors) =
+        akaze::extract_features(test_data.join(format!("{}.jpg", image)), options);
+    let features = Features {
+        keypoints,
+        descriptors,
+    };
+    let golden_path = test_data.join("golden").join(format!("{}.akzc", image));
+    if std::env::var("AKAZE_UPDATE_GOLDEN").is_ok() {
+        let image_size = (
+            evolutions[0].Lt.width() as u32,
+            evolutions[0].Lt.height() as u32,
+        );
+        let header = FeatureFileHeader::extracted(&features, image_size, &options);
+        serialize_features_with_header_to_file(&features, &header, &golden_path).unwrap();
+        return;
+    }
+    let (header, golden) = deserialize_features_with_header_from_file(&golden_path).unwrap();
+    assert_eq!(
+        header.unwrap().config_hash,
+        Some(config_hash(&options)),
+        "the default options changed, update the golden files"
+    );
+    let comparison = compare_features(&golden, &features, &GoldenConfig::default());
+    assert!(
+        comparison.passes(),
+        "{} differs from {:?}:\n{}",
+        image,
+        golden_path,
+        comparison
+    );
+}
+
+#[test]
+fn golden_1() {
+    check_golden("1");
+}
+
+#[test]
+fn golden_2() {
+    check_golden("2");
+}
+
+#[test]
+fn perturbed_features_fail() {
+    let (_, golden) = deserialize_features_with_header_from_file(
+        locate_test_data().join("golden").join("2.akzc"),
+    )
+    .unwrap();
+    let mut candidate = Features {
+        keypoints: golden.keypoints.clone(),
+        descriptors: golden.descriptors.clone(),
+    };
+    candidate.keypoints[10].point.0 += 0.01f32;
+    candidate.descriptors[20].vector[3] ^= 0b101;
+    candidate.keypoints.pop();
+    candidate.descriptors.pop();
+    let comparison = compare_features(&golden, &candidate, &GoldenConfig::default());
+    assert!(!comparison.identical);
+    assert_eq!(comparison.paired, golden.keypoints.len() - 1);
+    assert_eq!(comparison.position.exceeding, 1);
+    assert_eq!(comparison.descriptor_bits.exceeding, 1);
+    assert_eq!(comparison.descriptor_bits.max, 2f64);
+    assert!(!comparison.passes());
+    let tolerant = GoldenConfig {
+        position: 0.02,
+        descriptor_bits: 2,
+        ..GoldenConfig::default()
+    };
+    // The missing keypoint still fails the comparison
+    assert!(!compare_features(&golden, &candidate, &tolerant).passes());
+}
@@ -0,0 +1,54 @@
+mod common;
+
+use akaze_util::numpy::*;
+use common::{assert_features_eq, assert_matches_eq, features, matches};
+
+#[test]
+fn npy_header() {
+    let mut buffer = vec![];
+    write_keypoints_npy(&features(40, 0).keypoints, &mut buffer).unwrap();
+    assert_eq!(&buffer[..8], b"\x93NUMPY\x01\x00");
+    let header_length = usize::from(u16::from_le_bytes([buffer[8], buffer[9]]));
+    // The data is 64 byte aligned
+    assert_eq!((10 + header_length) % 64, 0);
+    let header = std::str::from_utf8(&buffer[10..10 + header_length]).unwrap();
+    assert!(header.starts_with("{'descr': '<f4', 'fortran_order': False, 'shape': (40, 6), }"));
+    assert!(header.ends_with('\n'));
+    assert_eq!(buffer.len(), 10 + header_length + 40 * 6 * 4);
+}
+
+#[test]
+fn features_round_trip() {
+    let mut features = features(40, 0);
+    let mut buffer = vec![];
+    write_features_npz(&features, &mut buffer).unwrap();
+    let read = read_features_npz(&buffer[..]).unwrap();
+    // The keypoint array has no class id column
+    for keypoint in &mut features.keypoints {
+        keypoint.class_id = 0;
+    }
+    assert_features_eq(&read, &features);
+}
+
+#[test]
+fn matches_round_trip() {
+    let matches = matches(40);
+    let mut buffer = vec![];
+    write_matches_npy(&matches, &mut buffer).unwrap();
+    assert_matches_eq(&read_matches_npy(&buffer[..]).unwrap(), &matches);
+}
+
+#[test]
+fn corrupted_archive() {
+    let mut buffer = vec![];
+    write_features_npz(&features(40, 0), &mut buffer).unwrap();
+    buffer[200] ^= 0xff;
+    assert!(read_features_npz(&buffer[..]).is_err());
+}
+
+#[test]
+fn wrong_shape() {
+    let mut buffer = vec![];
+    write_descriptors_npy(&features(40, 0).descriptors, &mut buffer).unwrap();
+    assert!(read_keypoints_npy(&buffer[..]).is_err());
+}
@@ -0,0 +1,120 @@
+mod common;
+
+use akaze_util::opencv::{AngleUnit, FileStorage, Node, StorageFormat};
+use akaze_util::Features;
+use common::{assert_matches_eq, features, locate_test_data, matches};
+use std::path::PathBuf;
+
+/// The fixtures are in the test data of this repository.
+fn locate_fixture(name: &str) -> PathBuf {
+    locate_test_data().join("opencv").join(name)
+}
+
+fn check_fixture(storage: &FileStorage) {
+    let keypoints = storage.keypoints("keypoints").unwrap();
+    assert_eq!(keypoints.len(), 3);
+    assert_eq!(keypoints[0].point, (133.4f32, 53.2f32));
+    assert_eq!(keypoints[0].size, 12f32);
+    assert!((keypoints[0].angle - 32.2f32.to_radians()).abs() < 1e-6);
+    assert_eq!(keypoints[0].response, 0.01f32);
+    assert_eq!(keypoints[1].angle, 270f32.to_radians());
+    assert_eq!(keypoints[1].octave, 1);
+    assert_eq!(keypoints[2].point, (0f32, 7.75f32));
+    assert_eq!(keypoints[2].octave, 3);
+    // OpenCV's default class id of -1
+    assert_eq!(keypoints[2].class_id, 0);
+    let descriptors = storage.descriptors("descriptors").unwrap();
+    assert_eq!(descriptors.len(), 3);
+    assert_eq!(descriptors[0].vector, vec![255, 0, 17, 128, 64, 3, 200, 91]);
+    assert_eq!(descriptors[2].vector, vec![0, 0, 0, 0, 255, 255, 255, 254]);
+    let matches = storage.matches("matches").unwrap();
+    assert_eq!(matches.len(), 3);
+    assert_eq!((matches[1].index_0, matches[1].index_1), (2, 0));
+    assert_eq!(matches[2].distance, 7.5f64);
+    // Strings are skipped
+    assert!(storage.get("image").is_none());
+}
+
+#[test]
+fn read_yaml_fixture() {
+    check_fixture(&FileStorage::open(locate_fixture("features.yml")).unwrap());
+}
+
+#[test]
+fn read_xml_fixture() {
+    check_fixture(&FileStorage::open(locate_fixture("features.xml")).unwrap());
+}
+
+#[test]
+fn fixtures_round_trip() {
+    for fixture in &["features.yml", "features.xml"] {
+        let storage = FileStorage::open(locate_fixture(fixture)).unwrap();
+        for &format in &[StorageFormat::Yaml, StorageFormat::Xml] {
+            let mut buffer = vec![];
+            storage.write(&mut buffer, format).unwrap();
+            let written = FileStorage::read(&buffer[..], format).unwrap();
+            assert_eq!(written.nodes, storage.nodes);
+        }
+    }
+}
+
+#[test]
+fn features_round_trip() {
+    let Features {
+        keypoints,
+        descriptors,
+    } = features(40, 0);
+    let matches = matches(40);
+    let mut storage = FileStorage::default();
+    storage.set_keypoints("keypoints", &keypoints);
+    storage.set_descriptors("descriptors", &descriptors);
+    storage.set_matches("matches", &matches);
+    let two_pi = std::f32::consts::PI * 2f32;
+    for &format in &[StorageFormat::Yaml, StorageFormat::Xml] {
+        let mut buffer = vec![];
+        storage.write(&mut buffer, format).unwrap();
+        let written = FileStorage::read(&buffer[..], format).unwrap();
+        let read_keypoints = written.keypoints("keypoints").unwrap();
+        assert_eq!(read_keypoints.len(), keypoints.len());
+        for (read, original) in read_keypoints.iter().zip(keypoints.iter()) {
+            assert_eq!(read.point, original.point);
+            assert_eq!(read.size, original.size);
+            assert_eq!(read.response, original.response);
+            assert_eq!(read.octave, original.octave);
+            assert_eq!(read.class_id, original.class_id);
+            // Angles come back in [0, 2 pi)
+            let difference = (read.angle - original.angle).rem_euclid(two_pi);
+            assert!(difference < 1e-5 || difference > two_pi - 1e-5);
+        }
+        let read_descriptors = written.descriptors("descriptors").unwrap();
+        assert_eq!(read_descriptors.len(), descriptors.len());
+        for (read, original) in read_descriptors.iter().zip(descriptors.iter()) {
+            assert_eq!(read.vector, original.vector);
+        }
+        assert_matches_eq(&written.matches("matches").unwrap(), &matches);
+    }
+}
+
+#[test]
+fn keypoint_angle_units() {
+    let Features { keypoints, .. } = features(3, 0);
+    let mut storage = FileStorage::default();
+    storage.set_keypoints("degrees", &keypoints);
+    storage.set_keypoints_with_unit("radians", &keypoints, AngleUnit::Radians);
+    let angle = |name: &str, i: usize| match storage.get(name) {
+        Some(Node::Seq(values)) => values[i * 7 + 3].to_f64() as f32,
+        node => panic!("not a sequence: {:?}", node),
+    };
+    for (i, keypoint) in keypoints.iter().enumerate() {
+        let degrees = keypoint.angle.to_degrees().rem_euclid(360f32);
+        assert_eq!(angle("degrees", i), degrees);
+        assert_eq!(angle("radians", i), keypoint.angle);
+    }
+    // Radians read back as written
+    let read = storage
+        .keypoints_with_unit("radians", AngleUnit::Radians)
+        .unwrap();
+    for (read, original) in read.iter().zip(keypoints.iter()) {
+        assert_eq!(read.angle, original.angle.max(0f32));
+    }
+}
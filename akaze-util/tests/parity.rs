@@ -0,0 +1,88 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze_util::parity::*;
+use akaze_util::Features;
+use common::{locate_test_data, temporary_path};
+use std::fs;
+use std::path::PathBuf;
+
+/// A small image of blobs, quick to extract in debug builds.
+fn synthetic_image(path: &PathBuf) {
+    image::GrayImage::from_fn(160, 120, |x, y| {
+        let (x, y) = (x as f32, y as f32);
+        let blobs = (x / 7f32).sin() * (y / 5f32).cos() + (x * y / 900f32).sin();
+        image::Luma([(127f32 + 60f32 * blobs) as u8])
+    })
+    .save(path)
+    .unwrap();
+}
+
+#[test]
+fn reference_dump_round_trip() {
+    let image_path = temporary_path("parity.png");
+    let dump_path = temporary_path("parity-dump");
+    synthetic_image(&image_path);
+    let options = Config::default();
+    let (evolutions, keypoints, descriptors) = akaze::extract_features(image_path.clone(), options);
+    assert!(!keypoints.is_empty());
+    let features = Features {
+        keypoints,
+        descriptors,
+    };
+    write_reference_dump(&dump_path, &evolutions, &features, &options).unwrap();
+    let config = ParityConfig::default();
+
+    let reference = ReferenceDump::load(&dump_path).unwrap();
+    let reports = check_parity(&reference, &evolutions, &features, &options, &config);
+    let stages: Vec<&str> = reports.iter().map(|report| report.stage.as_str()).collect();
+    assert_eq!(
+        stages,
+        vec![
+            "contrast factor",
+            "FED step schedules",
+            "detector responses",
+            "keypoints",
+            "descriptors"
+        ]
+    );
+    for report in &reports {
+        assert!(report.passed, "{:?}", report);
+        assert_eq!(report.max_difference, 0f64, "{:?}", report);
+    }
+
+    // A differing stage fails, the others still pass
+    let contrast_factor: f64 = fs::read_to_string(dump_path.join("contrast_factor.txt"))
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    fs::write(
+        dump_path.join("contrast_factor.txt"),
+        format!("{}\n", contrast_factor * 1.01),
+    )
+    .unwrap();
+    // Missing stages are skipped
+    fs::remove_file(dump_path.join("ldet.yml")).unwrap();
+    let reference = ReferenceDump::load(&dump_path).unwrap();
+    let reports = check_parity(&reference, &evolutions, &features, &options, &config);
+    assert_eq!(reports.len(), 4);
+    assert!(!reports[0].passed);
+    assert!(reports[1..].iter().all(|report| report.passed));
+
+    fs::remove_dir_all(&dump_path).unwrap();
+    fs::remove_file(&image_path).unwrap();
+}
+
+#[test]
+fn reference_dump_in_opencv_format() {
+    // The features of a dump are a cv::FileStorage, as in the OpenCV fixture
+    let reference = ReferenceDump::load(locate_test_data().join("opencv")).unwrap();
+    assert!(reference.contrast_factor.is_none());
+    assert!(reference.fed_tau_steps.is_none());
+    assert!(reference.ldet.is_none());
+    let features = reference.features.unwrap();
+    assert_eq!(features.keypoints.len(), 3);
+    assert_eq!(features.keypoints[0].point, (133.4f32, 53.2f32));
+    assert_eq!(features.descriptors.len(), 3);
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze_util::rectification::{rectify_images, side_by_side, RectificationConfig};
+use akaze_util::warp::{transform_point, warp_image};
+use common::{locate_test_data, temporary_path};
+use image::{GenericImageView, RgbImage};
+use nalgebra::Matrix3;
+use std::fs;
+
+/// A synthetic stereo pair of two planes at different depths: the left half
+/// of the second view is shifted by 20 pixels, the right half by 45 pixels.
+/// The second view is then rotated about its center, so the pair is not
+/// rectified yet.
+fn stereo_pair() -> (RgbImage, RgbImage) {
+    let full = image::open(locate_test_data().join("1.jpg"))
+        .unwrap()
+        .to_rgb();
+    let image_0 = full.view(300, 300, 480, 360).to_image();
+    let shifted = RgbImage::from_fn(480, 360, |x, y| {
+        let disparity = if x < 240 { 20 } else { 45 };
+        *full.get_pixel(300 + x + disparity, 300 + y)
+    });
+    let (sin, cos) = 0.03f64.sin_cos();
+    let center = (239.5f64, 179.5f64);
+    let rotation = Matrix3::new(
+        cos,
+        -sin,
+        center.0 - cos * center.0 + sin * center.1,
+        sin,
+        cos,
+        center.1 - sin * center.0 - cos * center.1,
+        0f64,
+        0f64,
+        1f64,
+    );
+    let (image_1, _) = warp_image(&shifted, &rotation, 480, 360).unwrap();
+    (image_0, image_1)
+}
+
+#[test]
+fn rectify_stereo_pair() {
+    let (image_0, image_1) = stereo_pair();
+    let paths = (
+        temporary_path("rectification-0.png"),
+        temporary_path("rectification-1.png"),
+    );
+    image_0.save(&paths.0).unwrap();
+    image_1.save(&paths.1).unwrap();
+    let config = RectificationConfig::default();
+    let pair =
+        rectify_images(paths.0.clone(), paths.1.clone(), Config::default(), &config).unwrap();
+    assert!(pair.inliers.len() >= config.min_inliers);
+    assert!(pair.error < 0.5, "vertical disparity {}", pair.error);
+    // The rectified images share a canvas, which holds both images
+    let (width, height) = pair.images.0.dimensions();
+    assert_eq!(pair.images.1.dimensions(), (width, height));
+    for homography in &[pair.homographies.0, pair.homographies.1] {
+        for &corner in &[
+            (0f64, 0f64),
+            (479f64, 0f64),
+            (0f64, 359f64),
+            (479f64, 359f64),
+        ] {
+            let (x, y) = transform_point(homography, corner).unwrap();
+            assert!(x > -1f64 && x < f64::from(width) && y > -1f64 && y < f64::from(height));
+        }
+    }
+    assert!(u64::from(width) * u64::from(height) <= 9 * 480 * 360);
+    assert_eq!(side_by_side(&pair, 16).dimensions(), (2 * width, height));
+
+    // Images of different sizes are rejected
+    image_1
+        .view(0, 0, 400, 360)
+        .to_image()
+        .save(&paths.1)
+        .unwrap();
+    assert!(rectify_images(paths.0.clone(), paths.1.clone(), Config::default(), &config).is_err());
+    fs::remove_file(&paths.0).unwrap();
+    fs::remove_file(&paths.1).unwrap();
+}
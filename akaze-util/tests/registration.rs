@@ -0,0 +1,89 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze_util::registration::{
+    difference_overlay, register_images, RegistrationConfig, RegistrationModel,
+};
+use akaze_util::warp::{transform_point, warp_image};
+use common::{locate_test_data, temporary_path};
+use image::GenericImageView;
+use nalgebra::Matrix3;
+use std::fs;
+
+/// Register a rotated, scaled and shifted copy of a region of the first
+/// test image onto the region, and compare the recovered transform with the
+/// inverse of the applied one.
+fn check_registration(model: RegistrationModel, transform: Matrix3<f64>) {
+    let reference = image::open(locate_test_data().join("1.jpg"))
+        .unwrap()
+        .to_rgb()
+        .view(300, 300, 480, 360)
+        .to_image();
+    let (image, _) = warp_image(&reference, &transform, 480, 360).unwrap();
+    let name = format!("{:?}", model).to_lowercase();
+    let reference_path = temporary_path(&format!("registration-{}-a.png", name));
+    let image_path = temporary_path(&format!("registration-{}-b.png", name));
+    reference.save(&reference_path).unwrap();
+    image.save(&image_path).unwrap();
+    // The copy differs only by resampling, so tight inliers suffice
+    let config = RegistrationConfig {
+        model,
+        ransac_epsilon_inliers: 1.0,
+        ..RegistrationConfig::default()
+    };
+    let (registration, read_reference) = register_images(
+        reference_path.clone(),
+        image_path.clone(),
+        Config::default(),
+        &config,
+    )
+    .unwrap();
+    assert_eq!(read_reference.dimensions(), (480, 360));
+    assert!(registration.inliers.len() >= config.min_inliers);
+    // The registered image maps back onto the reference
+    let inverse = transform.try_inverse().unwrap();
+    for &point in &[
+        (100f64, 80f64),
+        (380f64, 80f64),
+        (100f64, 280f64),
+        (380f64, 280f64),
+    ] {
+        let expected = transform_point(&inverse, point).unwrap();
+        let found = transform_point(&registration.transform, point).unwrap();
+        let error = ((found.0 - expected.0).powi(2) + (found.1 - expected.1).powi(2)).sqrt();
+        assert!(
+            error < 1f64,
+            "{:?} -> {:?}, expected {:?}",
+            point,
+            found,
+            expected
+        );
+    }
+    // The aligned images differ little where both are defined
+    let difference = difference_overlay(&read_reference, &registration);
+    let covered = registration.mask.iter().filter(|&&covered| covered).count();
+    assert!(covered > 480 * 360 / 2);
+    let total: u64 = difference.pixels().map(|pixel| u64::from(pixel[1])).sum();
+    assert!(total / (covered as u64) < 20, "{}", total / covered as u64);
+    fs::remove_file(&reference_path).unwrap();
+    fs::remove_file(&image_path).unwrap();
+}
+
+#[test]
+fn register_similarity_transformed_image() {
+    let (sin, cos) = 0.08f64.sin_cos();
+    let scale = 0.95f64;
+    let transform = Matrix3::new(
+        scale * cos,
+        -scale * sin,
+        40f64,
+        scale * sin,
+        scale * cos,
+        -15f64,
+        0f64,
+        0f64,
+        1f64,
+    );
+    check_registration(RegistrationModel::Homography, transform);
+    check_registration(RegistrationModel::Affine, transform);
+}
@@ -0,0 +1,91 @@
+use akaze::types::evolution::{allocate_evolutions, Config, EvolutionStep};
+use akaze::types::image::{GrayFloatImage, ImageFunctions};
+use akaze_util::scale_space::*;
+
+fn scale_space() -> Vec<EvolutionStep> {
+    let mut evolutions = allocate_evolutions(160, 80, Config::default());
+    for (level, evolution) in evolutions.iter_mut().enumerate() {
+        let width = 160 >> evolution.octave;
+        let height = 80 >> evolution.octave;
+        let mut image = GrayFloatImage::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.put(x, y, (x * y + level) as f32 / 1000f32);
+            }
+        }
+        evolution.Lt = image.clone();
+        evolution.Ldet = image;
+    }
+    evolutions
+}
+
+#[test]
+fn round_trip() {
+    let evolutions = scale_space();
+    let header = ScaleSpaceHeader::new((160, 80), &Config::default());
+    let mut buffer = vec![];
+    write_scale_space(&mut buffer, &header, &evolutions).unwrap();
+    let (read_header, read) = read_scale_space(&buffer[..]).unwrap();
+    assert_eq!(read_header, header);
+    assert_eq!(read.len(), evolutions.len());
+    for (read, original) in read.iter().zip(evolutions.iter()) {
+        assert_eq!(read.etime, original.etime);
+        assert_eq!(read.esigma, original.esigma);
+        assert_eq!(read.octave, original.octave);
+        assert_eq!(read.sublevel, original.sublevel);
+        assert_eq!(read.fed_tau_steps, original.fed_tau_steps);
+        assert_eq!(read.Lt.width(), original.Lt.width());
+        assert_eq!(read.Lt.buffer, original.Lt.buffer);
+        assert_eq!(read.Ldet.buffer, original.Ldet.buffer);
+    }
+    let differences = compare_scale_spaces(&read, &evolutions).unwrap();
+    assert!(differences
+        .iter()
+        .all(|difference| difference.max_abs() == 0f64));
+}
+
+#[test]
+fn compare_differences() {
+    let evolutions = scale_space();
+    let mut changed = evolutions.clone();
+    let value = changed[2].Ldet.get(3, 4) + 0.5f32;
+    changed[2].Ldet.put(3, 4, value);
+    let differences = compare_scale_spaces(&evolutions, &changed).unwrap();
+    for difference in &differences {
+        if difference.level == 2 {
+            assert_eq!(difference.max_abs(), 0.5f64);
+            let (name, ldet) = difference.images.last().unwrap();
+            assert_eq!(name, "Ldet");
+            assert_eq!(ldet.max_abs, 0.5f32);
+        } else {
+            assert_eq!(difference.max_abs(), 0f64);
+        }
+    }
+    changed.pop();
+    assert!(compare_scale_spaces(&evolutions, &changed).is_err());
+}
+
+#[test]
+fn header_identifies_image_and_options() {
+    let options = Config::default();
+    let header = ScaleSpaceHeader::new((160, 80), &options);
+    header
+        .check_same(&ScaleSpaceHeader::new((160, 80), &options))
+        .unwrap();
+    let error = header
+        .check_same(&ScaleSpaceHeader::new((80, 160), &options))
+        .unwrap_err();
+    assert!(error.to_string().contains("80 x 160"), "{}", error);
+    let other_options = Config {
+        detector_threshold: options.detector_threshold * 2f64,
+        ..options
+    };
+    assert!(header
+        .check_same(&ScaleSpaceHeader::new((160, 80), &other_options))
+        .is_err());
+    // Files of the previous format, without a header, are not read
+    let mut buffer = vec![];
+    write_scale_space(&mut buffer, &header, &scale_space()).unwrap();
+    buffer[4..6].copy_from_slice(&1u16.to_le_bytes());
+    assert!(read_scale_space(&buffer[..]).is_err());
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use akaze::types::evolution::Config;
+use akaze_util::stitching::{blend, stitch, StitchingConfig};
+use akaze_util::warp::warped_bounds;
+use common::{locate_test_data, temporary_path};
+use image::{GenericImageView, RgbImage};
+use nalgebra::Matrix3;
+use std::fs;
+
+fn translation(x: f64, y: f64) -> Matrix3<f64> {
+    Matrix3::new(1f64, 0f64, x, 0f64, 1f64, y, 0f64, 0f64, 1f64)
+}
+
+/// A region of the first test image.
+fn crop(x: u32, y: u32, width: u32, height: u32) -> RgbImage {
+    image::open(locate_test_data().join("1.jpg"))
+        .unwrap()
+        .to_rgb()
+        .view(x, y, width, height)
+        .to_image()
+}
+
+#[test]
+fn bounds_of_warped_images() {
+    let (min, max) = warped_bounds(100, 50, &translation(-10f64, 20f64)).unwrap();
+    assert_eq!(min, (-10f64, 20f64));
+    assert_eq!(max, (89f64, 69f64));
+    // Scaled about the origin and mirrored
+    let transform = Matrix3::new(-2f64, 0f64, 0f64, 0f64, 0.5f64, 0f64, 0f64, 0f64, 1f64);
+    let (min, max) = warped_bounds(101, 21, &transform).unwrap();
+    assert_eq!(min, (-200f64, 0f64));
+    assert_eq!(max, (0f64, 10f64));
+    // A corner on the line at infinity
+    let transform = Matrix3::new(1f64, 0f64, 0f64, 0f64, 1f64, 0f64, -0.01f64, 0f64, 1f64);
+    assert!(warped_bounds(101, 21, &transform).is_none());
+    // Empty images have no bounds
+    assert!(warped_bounds(0, 21, &Matrix3::identity()).is_none());
+    assert!(warped_bounds(101, 0, &Matrix3::identity()).is_none());
+}
+
+#[test]
+fn blend_translated_images() {
+    let images = vec![crop(100, 100, 120, 80), crop(160, 130, 120, 80)];
+    let transforms = vec![Matrix3::identity(), translation(60f64, 30f64)];
+    let (canvas, transforms) = blend(&images, &transforms, 1_000_000).unwrap();
+    assert_eq!(canvas.dimensions(), (180, 110));
+    assert_eq!(transforms[1], translation(60f64, 30f64));
+    // The overlap of two views of the same pixels blends to those pixels
+    let expected = crop(100, 100, 180, 110);
+    for (x, y) in [(0, 0), (70, 40), (119, 79), (179, 109), (100, 50)].iter() {
+        assert_eq!(canvas.get_pixel(*x, *y), expected.get_pixel(*x, *y));
+    }
+    // Outside of both images
+    assert_eq!(canvas.get_pixel(179, 0).data, [0, 0, 0]);
+    // Too large canvases and empty images are rejected
+    assert!(blend(&images, &transforms, 1000).is_err());
+    let empty = vec![images[0].clone(), RgbImage::new(0, 0)];
+    assert!(blend(&empty, &transforms, 1_000_000).is_err());
+}
+
+#[test]
+fn stitch_translated_images() {
+    let paths = vec![
+        temporary_path("stitch-0.png"),
+        temporary_path("stitch-1.png"),
+    ];
+    crop(300, 300, 480, 360).save(&paths[0]).unwrap();
+    crop(380, 320, 480, 360).save(&paths[1]).unwrap();
+    let panorama = stitch(&paths, Config::default(), &StitchingConfig::default()).unwrap();
+    // The second image is the reference, the first one is translated onto it
+    let relative = panorama.transforms[1].try_inverse().unwrap() * panorama.transforms[0];
+    let expected = translation(-80f64, -20f64);
+    assert!(
+        (relative / relative[(2, 2)] - expected).norm() < 0.5,
+        "{}",
+        relative
+    );
+    assert!(panorama.inliers[0] >= StitchingConfig::default().min_inliers);
+    let (width, height) = panorama.image.dimensions();
+    assert!((559..=561).contains(&width) && (379..=381).contains(&height));
+    for path in &paths {
+        fs::remove_file(path).unwrap();
+    }
+}
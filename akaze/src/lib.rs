@@ -7,7 +7,9 @@ use time::PreciseTime;
 
 pub mod ops;
 pub mod types;
-use ops::estimate_fundamental_matrix::remove_outliers;
+use ops::estimate_fundamental_matrix::remove_outliers_with_config;
+use ops::ransac::RansacConfig;
+use scoped_threadpool::Pool;
 use types::evolution::{Config, EvolutionStep};
 use types::feature_match::Match;
 use types::image::{gaussian_blur, GrayFloatImage, ImageFunctions};
@@ -124,16 +126,24 @@ fn create_nonlinear_scale_space(
 /// # Arguments
 /// * `input_image` - An image from which to extract features.
 /// * `options` the options for the algorithm.
+/// * `mask` - Optionally, where to detect keypoints.
 /// # Return Value
 /// The resulting keypoints.
 ///
-fn find_image_keypoints(evolutions: &mut Vec<EvolutionStep>, options: Config) -> Vec<Keypoint> {
+fn find_image_keypoints(
+    evolutions: &mut Vec<EvolutionStep>,
+    options: Config,
+    mask: Option<&dyn Fn((f32, f32)) -> bool>,
+) -> Vec<Keypoint> {
     let start = PreciseTime::now();
     ops::detector_response::detector_response(evolutions, options);
     debug!(
         "Computing detector response took {}.",
         start.to(PreciseTime::now())
     );
+    if let Some(mask) = mask {
+        ops::detector_response::mask_detector_response(evolutions, mask);
+    }
     ops::scale_space_extrema::detect_keypoints(evolutions, options)
 }
 
@@ -168,6 +178,38 @@ pub fn extract_features(
     input_image_path: PathBuf,
     options: Config,
 ) -> (Vec<EvolutionStep>, Vec<Keypoint>, Vec<Descriptor>) {
+    let mut evolutions = load_scale_space(input_image_path, options);
+    let (keypoints, descriptors) = extract_features_from_evolutions(&mut evolutions, options);
+    (evolutions, keypoints, descriptors)
+}
+
+/// Extract features only where a mask allows them, e.g. in the image regions
+/// which need new features. The scale space is created for the whole image,
+/// but the detector responses outside of the mask are zeroed, so neither
+/// keypoints nor descriptors are computed there.
+///
+/// # Arguments
+/// * `input_image_path` - The input image for which to extract features.
+/// * `options` The options for the algorithm.
+/// * `mask` - Whether to detect keypoints at a point of the input image.
+///
+/// # Return value
+/// * The evolutions of the process.
+/// * The keypoints at which features occur, all inside the mask.
+/// * The descriptors that were computed.
+pub fn extract_features_with_mask(
+    input_image_path: PathBuf,
+    options: Config,
+    mask: &dyn Fn((f32, f32)) -> bool,
+) -> (Vec<EvolutionStep>, Vec<Keypoint>, Vec<Descriptor>) {
+    let mut evolutions = load_scale_space(input_image_path, options);
+    let keypoints = find_image_keypoints(&mut evolutions, options, Some(mask));
+    let descriptors = ops::descriptors::extract_descriptors(&evolutions, &keypoints, options);
+    (evolutions, keypoints, descriptors)
+}
+
+/// Load an image and create its nonlinear scale space.
+fn load_scale_space(input_image_path: PathBuf, options: Config) -> Vec<EvolutionStep> {
     let input_image = image::open(input_image_path).unwrap();
     let float_image = types::image::create_unit_float_image(&input_image);
     info!(
@@ -183,14 +225,34 @@ pub fn extract_features(
         "Creating scale space took {}.",
         start.to(PreciseTime::now())
     );
-    let keypoints = find_image_keypoints(&mut evolutions, options);
+    evolutions
+}
+
+/// Extract features from a nonlinear scale space, such as one returned by
+/// `extract_features` and cached.
+///
+/// The detector responses of the evolutions are recomputed.
+///
+/// # Arguments
+/// * `evolutions` - The scale space.
+/// * `options` The options for the algorithm, those the scale space was
+///   created with.
+///
+/// # Return value
+/// * The keypoints at which features occur.
+/// * The descriptors that were computed.
+pub fn extract_features_from_evolutions(
+    evolutions: &mut Vec<EvolutionStep>,
+    options: Config,
+) -> (Vec<Keypoint>, Vec<Descriptor>) {
+    let keypoints = find_image_keypoints(evolutions, options, None);
     let start = PreciseTime::now();
-    let descriptors = ops::descriptors::extract_descriptors(&evolutions, &keypoints, options);
+    let descriptors = ops::descriptors::extract_descriptors(evolutions, &keypoints, options);
     debug!(
         "Computing descriptors took {}.",
         start.to(PreciseTime::now())
     );
-    (evolutions, keypoints, descriptors)
+    (keypoints, descriptors)
 }
 
 /// Match two sets of keypoints and descriptors. The
@@ -228,6 +290,10 @@ pub fn extract_features(
 /// in your own benchmarks with the data you have because every dataset is
 /// different.
 ///
+/// If tuning `ransac_epsilon_inliers` is impractical, run the matching and
+/// then `ops::magsac::remove_outliers_magsac`, which only needs a loose
+/// upper bound on the noise.
+///
 /// # Return value
 /// A vector of matches.
 ///
@@ -258,18 +324,50 @@ pub fn match_features(
     ransac_trials: usize,
     ransac_epsilon_inliers: f32,
 ) -> Vec<Match> {
-    let output = ops::feature_matching::descriptor_match(
+    let config = RansacConfig {
+        max_trials: ransac_trials,
+        epsilon_inlier: ransac_epsilon_inliers,
+        num_threads: num_cpus::get(),
+        ..RansacConfig::default()
+    };
+    match_features_with_config(
+        keypoints_0,
         descriptors_0,
+        keypoints_1,
         descriptors_1,
-        10000,
         lowes_ratio,
-    );
-    remove_outliers(
-        &keypoints_0,
-        &keypoints_1,
-        &output,
-        ransac_trials,
-        0.05,
-        ransac_epsilon_inliers,
+        &config,
+        None,
     )
 }
+
+/// Match two sets of keypoints and descriptors as `match_features` does,
+/// with full control over the geometric verification, e.g. PROSAC sampling,
+/// local optimization or the seed.
+///
+/// # Arguments
+/// * `keypoints_0` - The first set of keypoints
+/// * `descriptors_0` - The first set of descriptors
+/// * `keypoints_1` - The second set of keypoints
+/// * `descriptors_1` - The second set of descriptors
+/// * `lowes_ratio` - The ratio for the Lowe's ratio test
+/// * `config` - The RANSAC options
+/// * `pool` - Threads to run RANSAC in, shared by the calls for many image
+///   pairs. If None, a pool of `config.num_threads` is created when that is
+///   above one.
+///
+/// # Return value
+/// A vector of matches.
+pub fn match_features_with_config(
+    keypoints_0: &[Keypoint],
+    descriptors_0: &[Descriptor],
+    keypoints_1: &[Keypoint],
+    descriptors_1: &[Descriptor],
+    lowes_ratio: f64,
+    config: &RansacConfig,
+    pool: Option<&mut Pool>,
+) -> Vec<Match> {
+    let output =
+        ops::feature_matching::descriptor_match(descriptors_0, descriptors_1, 10000, lowes_ratio);
+    remove_outliers_with_config(keypoints_0, keypoints_1, &output, 0.05, config, pool)
+}
@@ -0,0 +1,529 @@
+use crate::ops::triangulation::camera_matrix;
+use crate::types::keypoint::Keypoint;
+use crate::types::tracks::Track;
+use nalgebra::{
+    Cholesky, DMatrix, DVector, Matrix2x3, Matrix2x6, Matrix3, Matrix3x4, Matrix6, Matrix6x3,
+    Rotation3, Vector2, Vector3, Vector6, LU, U3, U6,
+};
+use serde::{Deserialize, Serialize};
+
+/// A pinhole camera with fixed intrinsics, as refined by bundle adjustment.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleCamera {
+    /// The rotation from world to camera coordinates, as an axis scaled by
+    /// the angle in radians
+    pub rotation: (f64, f64, f64),
+    /// The translation from world to camera coordinates
+    pub translation: (f64, f64, f64),
+    /// The focal lengths in pixels along x and y
+    pub focal_length: (f64, f64),
+    /// The principal point in pixels
+    pub principal_point: (f64, f64),
+}
+
+impl BundleCamera {
+    /// Create a camera from its intrinsics and pose.
+    ///
+    /// # Arguments
+    /// * `k` - The intrinsic calibration matrix. Skew is ignored.
+    /// * `r` - The rotation from world to camera coordinates.
+    /// * `t` - The translation from world to camera coordinates.
+    pub fn new(k: &Matrix3<f64>, r: &Matrix3<f64>, t: &Vector3<f64>) -> BundleCamera {
+        let axis = Rotation3::from_matrix_unchecked(*r).scaled_axis();
+        BundleCamera {
+            rotation: (axis[0], axis[1], axis[2]),
+            translation: (t[0], t[1], t[2]),
+            focal_length: (k[(0, 0)], k[(1, 1)]),
+            principal_point: (k[(0, 2)], k[(1, 2)]),
+        }
+    }
+
+    fn rotation_matrix(&self) -> Matrix3<f64> {
+        let axis = Vector3::new(self.rotation.0, self.rotation.1, self.rotation.2);
+        *Rotation3::new(axis).matrix()
+    }
+
+    /// The camera matrix K [R | t], e.g. for `triangulation::triangulate`.
+    pub fn matrix(&self) -> Matrix3x4<f64> {
+        let k = Matrix3::new(
+            self.focal_length.0,
+            0f64,
+            self.principal_point.0,
+            0f64,
+            self.focal_length.1,
+            self.principal_point.1,
+            0f64,
+            0f64,
+            1f64,
+        );
+        let t = Vector3::new(self.translation.0, self.translation.1, self.translation.2);
+        camera_matrix(&k, &self.rotation_matrix(), &t)
+    }
+
+    /// Project a point into the image.
+    ///
+    /// # Return value
+    /// The pixel position, or None if the point is not in front of the camera.
+    pub fn project(&self, point: (f64, f64, f64)) -> Option<(f64, f64)> {
+        let p = self.rotation_matrix() * Vector3::new(point.0, point.1, point.2)
+            + Vector3::new(self.translation.0, self.translation.1, self.translation.2);
+        if p[2] <= f64::EPSILON {
+            return None;
+        }
+        Some((
+            self.focal_length.0 * p[0] / p[2] + self.principal_point.0,
+            self.focal_length.1 * p[1] / p[2] + self.principal_point.1,
+        ))
+    }
+}
+
+/// An observation of a point by a camera.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleObservation {
+    /// The index of the camera
+    pub camera: usize,
+    /// The index of the point
+    pub point: usize,
+    /// The observed pixel position
+    pub pixel: (f64, f64),
+}
+
+/// The loss applied to the squared reprojection error of each observation.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RobustLoss {
+    /// Plain least squares.
+    Squared,
+    /// Quadratic up to the given error in pixels, linear beyond.
+    Huber(f64),
+    /// Logarithmic beyond the given error in pixels, which suppresses
+    /// gross outliers more strongly than Huber.
+    Cauchy(f64),
+}
+
+impl RobustLoss {
+    /// The loss of a squared error.
+    fn loss(self, squared_error: f64) -> f64 {
+        match self {
+            RobustLoss::Squared => squared_error,
+            RobustLoss::Huber(delta) => {
+                if squared_error <= delta * delta {
+                    squared_error
+                } else {
+                    2f64 * delta * squared_error.sqrt() - delta * delta
+                }
+            }
+            RobustLoss::Cauchy(scale) => {
+                scale * scale * f64::ln(1f64 + squared_error / (scale * scale))
+            }
+        }
+    }
+
+    /// The derivative of the loss, used to reweight the residuals.
+    fn weight(self, squared_error: f64) -> f64 {
+        match self {
+            RobustLoss::Squared => 1f64,
+            RobustLoss::Huber(delta) => {
+                if squared_error <= delta * delta {
+                    1f64
+                } else {
+                    delta / squared_error.sqrt()
+                }
+            }
+            RobustLoss::Cauchy(scale) => 1f64 / (1f64 + squared_error / (scale * scale)),
+        }
+    }
+}
+
+/// Options of the bundle adjustment.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct BundleAdjustmentConfig {
+    /// Maximum number of Levenberg-Marquardt iterations
+    pub max_iterations: usize,
+    /// The robust loss
+    pub loss: RobustLoss,
+    /// The initial damping of Levenberg-Marquardt
+    pub initial_lambda: f64,
+    /// Stop once an iteration decreases the cost by less than this fraction
+    pub function_tolerance: f64,
+    /// The number of leading cameras whose pose is held fixed. Fixing the
+    /// first camera removes the rotation and translation ambiguity of the
+    /// reconstruction; the scale is left to the damping.
+    pub fixed_cameras: usize,
+}
+
+impl Default for BundleAdjustmentConfig {
+    fn default() -> BundleAdjustmentConfig {
+        BundleAdjustmentConfig {
+            max_iterations: 100,
+            loss: RobustLoss::Huber(2f64),
+            initial_lambda: 1e-3,
+            function_tolerance: 1e-9,
+            fixed_cameras: 1,
+        }
+    }
+}
+
+/// The outcome of a bundle adjustment.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BundleAdjustmentSummary {
+    /// The robust cost before refinement
+    pub initial_cost: f64,
+    /// The robust cost after refinement
+    pub final_cost: f64,
+    /// The root mean square reprojection error in pixels before refinement
+    pub initial_rms_error: f64,
+    /// The root mean square reprojection error in pixels after refinement
+    pub final_rms_error: f64,
+    /// The number of iterations run
+    pub iterations: usize,
+}
+
+/// The robust cost and the root mean square error of all observations.
+/// Observations behind their camera are ignored.
+fn evaluate(
+    cameras: &[BundleCamera],
+    points: &[(f64, f64, f64)],
+    observations: &[BundleObservation],
+    loss: RobustLoss,
+) -> (f64, f64) {
+    let mut cost = 0f64;
+    let mut squared_sum = 0f64;
+    let mut count = 0;
+    for observation in observations {
+        if let Some(p) = cameras[observation.camera].project(points[observation.point]) {
+            let squared_error =
+                (p.0 - observation.pixel.0).powi(2) + (p.1 - observation.pixel.1).powi(2);
+            cost += loss.loss(squared_error);
+            squared_sum += squared_error;
+            count += 1;
+        }
+    }
+    (cost, f64::sqrt(squared_sum / count.max(1) as f64))
+}
+
+/// A residual and its Jacobians with respect to the rotation, the
+/// translation and the point.
+type Linearization = (Vector2<f64>, Matrix2x3<f64>, Matrix2x3<f64>, Matrix2x3<f64>);
+
+/// The residual of an observation and its Jacobians with respect to a
+/// left rotation increment and the translation of the camera, and the point.
+fn linearize(
+    camera: &BundleCamera,
+    point: (f64, f64, f64),
+    pixel: (f64, f64),
+) -> Option<Linearization> {
+    let rotation = camera.rotation_matrix();
+    let rotated = rotation * Vector3::new(point.0, point.1, point.2);
+    let p = rotated
+        + Vector3::new(
+            camera.translation.0,
+            camera.translation.1,
+            camera.translation.2,
+        );
+    if p[2] <= f64::EPSILON {
+        return None;
+    }
+    let (f_x, f_y) = camera.focal_length;
+    let residual = Vector2::new(
+        f_x * p[0] / p[2] + camera.principal_point.0 - pixel.0,
+        f_y * p[1] / p[2] + camera.principal_point.1 - pixel.1,
+    );
+    let d_projection = Matrix2x3::new(
+        f_x / p[2],
+        0f64,
+        -f_x * p[0] / (p[2] * p[2]),
+        0f64,
+        f_y / p[2],
+        -f_y * p[1] / (p[2] * p[2]),
+    );
+    // d(exp(w) R X) / dw = -[R X]x at w = 0
+    let d_rotation = d_projection * (-rotated.cross_matrix());
+    let d_translation = d_projection;
+    let d_point = d_projection * rotation;
+    Some((residual, d_rotation, d_translation, d_point))
+}
+
+/// Refine camera poses and points by minimizing the robust reprojection
+/// error with Levenberg-Marquardt.
+///
+/// The normal equations are solved with the Schur complement: points are
+/// eliminated first, leaving a dense system of 6 parameters per camera,
+/// which is fast for tens of cameras and any number of points. Robust
+/// losses are handled by reweighting the residuals in each iteration.
+///
+/// # Arguments
+/// * `cameras` - The cameras, refined in place. Intrinsics are fixed.
+/// * `points` - The points in world coordinates, refined in place.
+/// * `observations` - The observations of the points.
+/// * `config` - The solver options.
+/// # Return value
+/// A summary of the refinement.
+pub fn bundle_adjust(
+    cameras: &mut [BundleCamera],
+    points: &mut [(f64, f64, f64)],
+    observations: &[BundleObservation],
+    config: &BundleAdjustmentConfig,
+) -> BundleAdjustmentSummary {
+    let (initial_cost, initial_rms_error) = evaluate(cameras, points, observations, config.loss);
+    let mut cost = initial_cost;
+    let mut lambda = config.initial_lambda;
+    let free_cameras = cameras.len().saturating_sub(config.fixed_cameras);
+    let mut iterations = 0;
+    while iterations < config.max_iterations {
+        iterations += 1;
+        // Accumulate the blocks of the normal equations
+        let mut u = vec![Matrix6::<f64>::zeros(); free_cameras];
+        let mut v = vec![Matrix3::<f64>::zeros(); points.len()];
+        let mut w: Vec<Option<Matrix6x3<f64>>> = vec![None; observations.len()];
+        let mut g_camera = vec![Vector6::<f64>::zeros(); free_cameras];
+        let mut g_point = vec![Vector3::<f64>::zeros(); points.len()];
+        for (i, observation) in observations.iter().enumerate() {
+            let camera = &cameras[observation.camera];
+            let (residual, d_rotation, d_translation, d_point) =
+                match linearize(camera, points[observation.point], observation.pixel) {
+                    Some(linearization) => linearization,
+                    None => continue,
+                };
+            let weight = config.loss.weight(residual.norm_squared());
+            v[observation.point] += weight * d_point.transpose() * d_point;
+            g_point[observation.point] += weight * d_point.transpose() * residual;
+            if observation.camera >= config.fixed_cameras {
+                let c = observation.camera - config.fixed_cameras;
+                let mut d_camera = Matrix2x6::zeros();
+                d_camera.fixed_columns_mut::<U3>(0).copy_from(&d_rotation);
+                d_camera
+                    .fixed_columns_mut::<U3>(3)
+                    .copy_from(&d_translation);
+                u[c] += weight * d_camera.transpose() * d_camera;
+                g_camera[c] += weight * d_camera.transpose() * residual;
+                w[i] = Some(weight * d_camera.transpose() * d_point);
+            }
+        }
+        // Damp the diagonals and eliminate the points
+        let damp3 = |m: &Matrix3<f64>| {
+            let mut damped = *m;
+            for k in 0..3 {
+                damped[(k, k)] += lambda * f64::max(m[(k, k)], 1e-9);
+            }
+            damped
+        };
+        let v_inverse: Vec<Matrix3<f64>> = v
+            .iter()
+            .map(|v_p| damp3(v_p).try_inverse().unwrap_or_else(Matrix3::zeros))
+            .collect();
+        let mut reduced = DMatrix::<f64>::zeros(6 * free_cameras, 6 * free_cameras);
+        let mut rhs = DVector::<f64>::zeros(6 * free_cameras);
+        for c in 0..free_cameras {
+            let mut damped = u[c];
+            for k in 0..6 {
+                damped[(k, k)] += lambda * f64::max(u[c][(k, k)], 1e-9);
+            }
+            let mut block = reduced.fixed_slice_mut::<U6, U6>(6 * c, 6 * c);
+            block += damped;
+            let mut block = rhs.fixed_rows_mut::<U6>(6 * c);
+            block -= g_camera[c];
+        }
+        // Observations of a point by two free cameras couple the cameras
+        let mut by_point: Vec<Vec<usize>> = vec![vec![]; points.len()];
+        for (i, observation) in observations.iter().enumerate() {
+            if w[i].is_some() {
+                by_point[observation.point].push(i);
+            }
+        }
+        for (p, point_observations) in by_point.iter().enumerate() {
+            for &i in point_observations {
+                let c_i = observations[i].camera - config.fixed_cameras;
+                let w_v = w[i].unwrap() * v_inverse[p];
+                let mut block = rhs.fixed_rows_mut::<U6>(6 * c_i);
+                block += w_v * g_point[p];
+                for &j in point_observations {
+                    let c_j = observations[j].camera - config.fixed_cameras;
+                    let mut block = reduced.fixed_slice_mut::<U6, U6>(6 * c_i, 6 * c_j);
+                    block -= w_v * w[j].unwrap().transpose();
+                }
+            }
+        }
+        let camera_step = match Cholesky::new(reduced.clone()) {
+            Some(cholesky) => cholesky.solve(&rhs),
+            None => match LU::new(reduced).solve(&rhs) {
+                Some(step) => step,
+                None => {
+                    lambda *= 10f64;
+                    continue;
+                }
+            },
+        };
+        // Back substitute the point steps
+        let mut point_steps: Vec<Vector3<f64>> = g_point.iter().map(|g| -g).collect();
+        for (i, observation) in observations.iter().enumerate() {
+            if let Some(w_i) = w[i] {
+                let c = observation.camera - config.fixed_cameras;
+                let step = camera_step.fixed_rows::<U6>(6 * c).into_owned();
+                point_steps[observation.point] -= w_i.transpose() * step;
+            }
+        }
+        // Apply the step to copies, and keep it if the cost decreases
+        let mut new_cameras = cameras.to_vec();
+        for (c, camera) in new_cameras
+            .iter_mut()
+            .skip(config.fixed_cameras)
+            .enumerate()
+        {
+            let step = camera_step.fixed_rows::<U6>(6 * c).into_owned();
+            let rotation = Rotation3::new(Vector3::new(step[0], step[1], step[2]))
+                * Rotation3::from_matrix_unchecked(camera.rotation_matrix());
+            let axis = rotation.scaled_axis();
+            camera.rotation = (axis[0], axis[1], axis[2]);
+            camera.translation = (
+                camera.translation.0 + step[3],
+                camera.translation.1 + step[4],
+                camera.translation.2 + step[5],
+            );
+        }
+        let new_points: Vec<(f64, f64, f64)> = points
+            .iter()
+            .zip(point_steps.iter())
+            .zip(v_inverse.iter())
+            .map(|((point, step), v_inverse_p)| {
+                let step = v_inverse_p * step;
+                (point.0 + step[0], point.1 + step[1], point.2 + step[2])
+            })
+            .collect();
+        let (new_cost, _) = evaluate(&new_cameras, &new_points, observations, config.loss);
+        if new_cost < cost {
+            let decrease = (cost - new_cost) / cost.max(f64::MIN_POSITIVE);
+            cameras.copy_from_slice(&new_cameras);
+            points.copy_from_slice(&new_points);
+            cost = new_cost;
+            lambda = f64::max(lambda / 10f64, 1e-12);
+            if decrease < config.function_tolerance {
+                break;
+            }
+        } else {
+            lambda *= 10f64;
+            if lambda > 1e12 {
+                break;
+            }
+        }
+    }
+    let (final_cost, final_rms_error) = evaluate(cameras, points, observations, config.loss);
+    debug!(
+        "Bundle adjustment took {} iterations, RMS error {} -> {}.",
+        iterations, initial_rms_error, final_rms_error
+    );
+    BundleAdjustmentSummary {
+        initial_cost,
+        final_cost,
+        initial_rms_error,
+        final_rms_error,
+        iterations,
+    }
+}
+
+/// Build the observations of tracks, with one point per track.
+///
+/// # Arguments
+/// * `tracks` - The tracks, e.g. from `types::tracks::Tracks::build`.
+///   Image ids are used as camera indices.
+/// * `keypoints` - The keypoints of each image.
+/// # Return value
+/// The observations. The point index of an observation is its track index.
+pub fn observations_from_tracks(
+    tracks: &[Track],
+    keypoints: &[Vec<Keypoint>],
+) -> Vec<BundleObservation> {
+    let mut observations = vec![];
+    for (point, track) in tracks.iter().enumerate() {
+        for observation in &track.observations {
+            let keypoint = keypoints[observation.image_id][observation.keypoint_index];
+            observations.push(BundleObservation {
+                camera: observation.image_id,
+                point,
+                pixel: (f64::from(keypoint.point.0), f64::from(keypoint.point.1)),
+            });
+        }
+    }
+    observations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        bundle_adjust, BundleAdjustmentConfig, BundleCamera, BundleObservation, RobustLoss,
+    };
+    use nalgebra::{Matrix3, Rotation3, Vector3};
+
+    #[test]
+    fn bundle_adjustment_recovers_perturbed_scene() {
+        let k = Matrix3::new(800f64, 0f64, 320f64, 0f64, 800f64, 240f64, 0f64, 0f64, 1f64);
+        let truth: Vec<BundleCamera> = (0..4)
+            .map(|i| {
+                let angle = i as f64 * 0.05f64;
+                let r = *Rotation3::from_euler_angles(0.01f64 * i as f64, -angle, 0f64).matrix();
+                BundleCamera::new(&k, &r, &Vector3::new(-0.5f64 * i as f64, 0.05f64, 0f64))
+            })
+            .collect();
+        let true_points: Vec<(f64, f64, f64)> = (0..60)
+            .map(|i| {
+                (
+                    (i % 10) as f64 * 0.4f64 - 1.8f64,
+                    (i / 10) as f64 * 0.4f64 - 1f64,
+                    6f64 + ((i * 7) % 5) as f64 * 0.5f64,
+                )
+            })
+            .collect();
+        let mut observations = vec![];
+        for (c, camera) in truth.iter().enumerate() {
+            for (p, &point) in true_points.iter().enumerate() {
+                let mut pixel = camera.project(point).unwrap();
+                // A few gross outliers, off the epipolar lines
+                if (c * 60 + p) % 37 == 0 {
+                    pixel.1 += 40f64;
+                }
+                observations.push(BundleObservation {
+                    camera: c,
+                    point: p,
+                    pixel,
+                });
+            }
+        }
+        // Perturb all but the first two cameras, and all points
+        let mut cameras = truth.clone();
+        for (i, camera) in cameras.iter_mut().enumerate().skip(2) {
+            camera.rotation.1 += 0.01f64 * i as f64;
+            camera.translation.0 += 0.02f64;
+            camera.translation.2 -= 0.03f64;
+        }
+        let mut points: Vec<(f64, f64, f64)> = true_points
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (p.0 + 0.03f64 * (i % 3) as f64, p.1 - 0.02f64, p.2 + 0.1f64))
+            .collect();
+        // Fixing two cameras also fixes the scale. The outliers bias the
+        // result slightly, even with the Cauchy loss.
+        let config = BundleAdjustmentConfig {
+            loss: RobustLoss::Cauchy(1f64),
+            fixed_cameras: 2,
+            ..BundleAdjustmentConfig::default()
+        };
+        let summary = bundle_adjust(&mut cameras, &mut points, &observations, &config);
+        assert!(summary.final_cost < summary.initial_cost);
+        for (camera, true_camera) in cameras.iter().zip(truth.iter()) {
+            assert!((camera.translation.0 - true_camera.translation.0).abs() < 1e-3);
+            assert!((camera.rotation.1 - true_camera.rotation.1).abs() < 1e-3);
+        }
+        for (point, true_point) in points.iter().zip(true_points.iter()) {
+            assert!((point.2 - true_point.2).abs() < 1e-2);
+        }
+        // The inliers are reprojected closely
+        let inlier_errors = observations
+            .iter()
+            .filter(|o| (o.camera * 60 + o.point) % 37 != 0)
+            .map(|o| {
+                let p = cameras[o.camera].project(points[o.point]).unwrap();
+                f64::hypot(p.0 - o.pixel.0, p.1 - o.pixel.1)
+            })
+            .fold(0f64, f64::max);
+        assert!(inlier_errors < 0.1, "max inlier error {}", inlier_errors);
+    }
+}
@@ -53,3 +53,23 @@ pub fn detector_response(evolutions: &mut Vec<EvolutionStep>, options: Config) {
         }
     }
 }
+
+/// Zero the detector responses outside of a mask, so that no keypoints are
+/// detected there. The responses must have been computed first.
+///
+/// # Arguments
+/// * `evolutions` - The evolutions with their detector responses.
+/// * `mask` - Whether to detect keypoints at a point, in the coordinates of
+///   the input image.
+pub fn mask_detector_response(evolutions: &mut [EvolutionStep], mask: &dyn Fn((f32, f32)) -> bool) {
+    for evolution in evolutions.iter_mut() {
+        let ratio = f32::powf(2.0f32, evolution.octave as f32);
+        let width = evolution.Ldet.width();
+        for (i, response) in evolution.Ldet.buffer.iter_mut().enumerate() {
+            let point = ((i % width) as f32 * ratio, (i / width) as f32 * ratio);
+            if !mask(point) {
+                *response = 0f32;
+            }
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use crate::ops::estimate_similarity_transform::transfer_error;
+use crate::ops::ransac::{ransac, ransac_with_pool, Estimator, RansacConfig};
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use nalgebra::Matrix3;
+use random::Xorshift128Plus;
+use scoped_threadpool::Pool;
+
+/// Estimate a 2D affine transform from three or more correspondences.
+///
+/// With exactly three matches this is the minimal solution. With more
+/// matches the transform is the least squares fit, which is useful for
+/// refining a model from a full inlier set. Points are centered before
+/// solving the normal equations to keep them well conditioned.
+///
+/// # Arguments
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// # Return value
+/// Optionally, the transform as a 3x3 matrix mapping homogeneous points in
+/// the first image to the second image. None if there are fewer than three
+/// matches or the points in the first image are collinear.
+pub fn estimate_affine_transform(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+) -> Option<Matrix3<f32>> {
+    estimate_affine_transform_weighted(
+        keypoints_0,
+        keypoints_1,
+        matches,
+        &vec![1f64; matches.len()],
+    )
+}
+
+/// Estimate a 2D affine transform by weighted least squares.
+///
+/// # Arguments
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// * `weights` - A non-negative weight for each match.
+/// # Return value
+/// Optionally, the transform. None if there are fewer than three matches,
+/// all weights are zero or the weighted points are collinear.
+pub fn estimate_affine_transform_weighted(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    weights: &[f64],
+) -> Option<Matrix3<f32>> {
+    debug_assert!(matches.len() == weights.len());
+    let total_weight: f64 = weights.iter().sum();
+    if matches.len() < 3 || total_weight <= 0f64 {
+        return None;
+    }
+    let mut mean_0 = (0f64, 0f64);
+    let mut mean_1 = (0f64, 0f64);
+    for (match_i, weight) in matches.iter().zip(weights.iter()) {
+        let p_0 = keypoints_0[match_i.index_0].point;
+        let p_1 = keypoints_1[match_i.index_1].point;
+        mean_0.0 += weight * f64::from(p_0.0) / total_weight;
+        mean_0.1 += weight * f64::from(p_0.1) / total_weight;
+        mean_1.0 += weight * f64::from(p_1.0) / total_weight;
+        mean_1.1 += weight * f64::from(p_1.1) / total_weight;
+    }
+    // Accumulate the normal equations of the centered problem
+    let mut s_xx = 0f64;
+    let mut s_xy = 0f64;
+    let mut s_yy = 0f64;
+    let mut s_ux = 0f64;
+    let mut s_uy = 0f64;
+    let mut s_vx = 0f64;
+    let mut s_vy = 0f64;
+    for (match_i, weight) in matches.iter().zip(weights.iter()) {
+        let p_0 = keypoints_0[match_i.index_0].point;
+        let p_1 = keypoints_1[match_i.index_1].point;
+        let x = f64::from(p_0.0) - mean_0.0;
+        let y = f64::from(p_0.1) - mean_0.1;
+        let u = f64::from(p_1.0) - mean_1.0;
+        let v = f64::from(p_1.1) - mean_1.1;
+        s_xx += weight * x * x;
+        s_xy += weight * x * y;
+        s_yy += weight * y * y;
+        s_ux += weight * u * x;
+        s_uy += weight * u * y;
+        s_vx += weight * v * x;
+        s_vy += weight * v * y;
+    }
+    let det = s_xx * s_yy - s_xy * s_xy;
+    let scale = s_xx + s_yy;
+    if scale <= 0f64 || det.abs() <= 1e-9 * scale * scale {
+        return None;
+    }
+    let a11 = (s_ux * s_yy - s_uy * s_xy) / det;
+    let a12 = (s_uy * s_xx - s_ux * s_xy) / det;
+    let a21 = (s_vx * s_yy - s_vy * s_xy) / det;
+    let a22 = (s_vy * s_xx - s_vx * s_xy) / det;
+    let t_x = mean_1.0 - (a11 * mean_0.0 + a12 * mean_0.1);
+    let t_y = mean_1.1 - (a21 * mean_0.0 + a22 * mean_0.1);
+    Some(Matrix3::new(
+        a11 as f32, a12 as f32, t_x as f32, a21 as f32, a22 as f32, t_y as f32, 0f32, 0f32, 1f32,
+    ))
+}
+
+/// The 3-point affine model for RANSAC.
+#[derive(Debug, Copy, Clone)]
+pub struct AffineEstimator;
+
+impl Estimator for AffineEstimator {
+    type Model = Matrix3<f32>;
+
+    fn min_sample_size(&self) -> usize {
+        3
+    }
+
+    fn estimate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_affine_transform(keypoints_0, keypoints_1, sample)
+    }
+
+    fn estimate_from_inliers(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        inliers: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_affine_transform(keypoints_0, keypoints_1, inliers)
+    }
+
+    fn estimate_weighted(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        matches: &[Match],
+        weights: &[f64],
+    ) -> Option<Matrix3<f32>> {
+        estimate_affine_transform_weighted(keypoints_0, keypoints_1, matches, weights)
+    }
+
+    fn residual(&self, model: &Matrix3<f32>, keypoint_0: &Keypoint, keypoint_1: &Keypoint) -> f32 {
+        transfer_error(*model, keypoint_0, keypoint_1)
+    }
+
+    /// Reject samples whose points are collinear in either image.
+    fn is_degenerate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> bool {
+        let twice_area = |p: [(f32, f32); 3]| {
+            ((p[1].0 - p[0].0) * (p[2].1 - p[0].1) - (p[2].0 - p[0].0) * (p[1].1 - p[0].1)).abs()
+        };
+        let points_0 = [
+            keypoints_0[sample[0].index_0].point,
+            keypoints_0[sample[1].index_0].point,
+            keypoints_0[sample[2].index_0].point,
+        ];
+        let points_1 = [
+            keypoints_1[sample[0].index_1].point,
+            keypoints_1[sample[1].index_1].point,
+            keypoints_1[sample[2].index_1].point,
+        ];
+        twice_area(points_0) < 1f32 || twice_area(points_1) < 1f32
+    }
+}
+
+/// Remove outliers using RANSAC with a 3-point affine model.
+///
+/// Each new best model is refined from its inliers by least squares
+/// (local optimization). Use `remove_outliers_affine_with_config` to
+/// choose the RANSAC options.
+///
+/// # Arguments
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `num_trials` - Maximum number of RANSAC iterations
+/// * `epsilon_inlier` - Maximum transfer error in pixels to accept an inlier.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_affine(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    num_trials: usize,
+    epsilon_inlier: f32,
+) -> Vec<Match> {
+    let config = RansacConfig {
+        max_trials: num_trials,
+        epsilon_inlier,
+        local_optimization: true,
+        ..RansacConfig::default()
+    };
+    remove_outliers_affine_with_config(keypoints_0, keypoints_1, matches, &config, None)
+}
+
+/// Remove outliers using RANSAC with a 3-point affine model.
+///
+/// # Arguments
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `config` - The RANSAC options, e.g. PROSAC sampling, local
+///   optimization or the seed. `epsilon_inlier` is a transfer error in
+///   pixels.
+/// * `pool` - Threads to evaluate hypotheses in, shared by the calls for
+///   many image pairs. If None, a pool of `config.num_threads` is created
+///   when that is above one.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_affine_with_config(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    config: &RansacConfig,
+    pool: Option<&mut Pool>,
+) -> Vec<Match> {
+    if matches.len() < 3 {
+        warn!("Not enough points to do RANSAC.");
+        return matches.to_vec();
+    } else {
+        debug!("Removing outliers with RANSAC using affine model.");
+    }
+    let mut source = Xorshift128Plus::new(config.seed);
+    let result = match pool {
+        Some(pool) => ransac_with_pool(
+            &AffineEstimator,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config,
+            &mut source,
+            Some(pool),
+        ),
+        None => ransac(
+            &AffineEstimator,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config,
+            &mut source,
+        ),
+    };
+    match result {
+        Some(result) => result.inliers,
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_affine_transform, remove_outliers_affine};
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use nalgebra::{Matrix3, Vector3};
+
+    fn keypoint(x: f32, y: f32, size: f32, angle: f32) -> Keypoint {
+        Keypoint {
+            point: (x, y),
+            response: 1f32,
+            size,
+            octave: 0,
+            class_id: 0,
+            angle,
+        }
+    }
+
+    fn apply(transform: &Matrix3<f32>, keypoint_0: &Keypoint) -> (f32, f32) {
+        let p = transform * Vector3::new(keypoint_0.point.0, keypoint_0.point.1, 1f32);
+        (p[0] / p[2], p[1] / p[2])
+    }
+
+    /// A grid of points, mapped by `transform`, with every fifth match corrupted.
+    fn synthetic(transform: &Matrix3<f32>) -> (Vec<Keypoint>, Vec<Keypoint>, Vec<Match>) {
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..50 {
+            let k_0 = keypoint(
+                (i % 10) as f32 * 37f32 + 5f32,
+                (i / 10) as f32 * 53f32 + 11f32,
+                4f32,
+                0.1f32,
+            );
+            let mut point_1 = apply(transform, &k_0);
+            if i % 5 == 0 {
+                point_1.0 += 100f32;
+            }
+            keypoints_0.push(k_0);
+            keypoints_1.push(keypoint(point_1.0, point_1.1, 8f32, 0.6f32));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            });
+        }
+        (keypoints_0, keypoints_1, matches)
+    }
+
+    #[test]
+    fn affine_recovers_transform() {
+        let transform = Matrix3::new(
+            1.1f32, 0.2f32, 15f32, -0.1f32, 0.9f32, -7f32, 0f32, 0f32, 1f32,
+        );
+        let (keypoints_0, keypoints_1, matches) = synthetic(&transform);
+        let inliers: Vec<Match> = matches
+            .iter()
+            .filter(|m| m.index_0 % 5 != 0)
+            .cloned()
+            .collect();
+        let estimate = estimate_affine_transform(&keypoints_0, &keypoints_1, &inliers).unwrap();
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((estimate[(i, j)] - transform[(i, j)]).abs() < 1e-3);
+            }
+        }
+        let verified = remove_outliers_affine(&keypoints_0, &keypoints_1, &matches, 100, 1f32);
+        assert_eq!(verified.len(), 40);
+    }
+
+    #[test]
+    fn too_few_matches_are_returned_unchanged() {
+        let transform = Matrix3::new(1f32, 0f32, 3f32, 0f32, 1f32, 4f32, 0f32, 0f32, 1f32);
+        let (keypoints_0, keypoints_1, matches) = synthetic(&transform);
+        let verified = remove_outliers_affine(&keypoints_0, &keypoints_1, &matches[..2], 20, 1f32);
+        assert_eq!(verified.len(), 2);
+    }
+}
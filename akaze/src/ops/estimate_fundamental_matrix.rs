@@ -1,13 +1,18 @@
+use crate::ops::ransac::{ransac, ransac_with_pool, Estimator, RansacConfig};
 use crate::types::feature_match::Match;
 use crate::types::keypoint::Keypoint;
 use nalgebra::{DMatrix, Matrix3, Vector3, SVD};
-use random;
-use random::Source;
-use std::collections::HashSet;
+use random::Xorshift128Plus;
+use scoped_threadpool::Pool;
 use std::ops::{Index, IndexMut};
 
-/// Do singular value decomposition to estimate the fundamental matrix
-/// given a set of 8 prospective inliers.
+/// Estimate the fundamental matrix given a set of 8 prospective inliers.
+/// The sample is rejected if its linear system is rank deficient,
+/// otherwise the matrix is solved with `normalized_eight_point`.
+///
+/// Up to version 0.1.3 the matrix was read from a thin SVD of the
+/// unnormalized system, which does not contain its null space, so the
+/// returned matrix did not satisfy the epipolar constraint of the sample.
 /// * `keypoints_0 ` - Keypoints in set 0
 /// * `keypoints_1 ` - Keypoints in set 1
 /// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
@@ -21,51 +26,173 @@ pub fn estimate_fundamental_matrix(
     epsilon: f32,
 ) -> Option<Matrix3<f32>> {
     debug_assert!(matches.len() == 8);
-    let mut a: DMatrix<f32> = DMatrix::zeros(8, 9);
-    let mut b: DMatrix<f32> = DMatrix::zeros(8, 1);
+    if sample_has_full_rank(keypoints_0, keypoints_1, matches, epsilon) {
+        normalized_eight_point(keypoints_0, keypoints_1, matches)
+    } else {
+        None
+    }
+}
+
+/// Whether the linear system of an 8-point sample has full rank, i.e.
+/// the sample determines a unique fundamental matrix.
+fn sample_has_full_rank(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    epsilon: f32,
+) -> bool {
+    let mut a: DMatrix<f32> = DMatrix::zeros(matches.len(), 9);
     for (i, match_i) in matches.iter().enumerate() {
-        *a.index_mut((i, 0)) =
-            keypoints_0[match_i.index_0].point.0 * keypoints_1[match_i.index_1].point.0;
-        *a.index_mut((i, 1)) =
-            keypoints_0[match_i.index_0].point.0 * keypoints_1[match_i.index_1].point.1;
-        *a.index_mut((i, 2)) = keypoints_0[match_i.index_0].point.0;
-        *a.index_mut((i, 3)) =
-            keypoints_0[match_i.index_0].point.1 * keypoints_1[match_i.index_1].point.0;
-        *a.index_mut((i, 4)) =
-            keypoints_0[match_i.index_0].point.1 * keypoints_1[match_i.index_1].point.1;
-        *a.index_mut((i, 5)) = keypoints_0[match_i.index_0].point.1;
-        *a.index_mut((i, 6)) = keypoints_1[match_i.index_1].point.0;
-        *a.index_mut((i, 7)) = keypoints_1[match_i.index_1].point.1;
+        let (x_0, y_0) = keypoints_0[match_i.index_0].point;
+        let (x_1, y_1) = keypoints_1[match_i.index_1].point;
+        *a.index_mut((i, 0)) = x_0 * x_1;
+        *a.index_mut((i, 1)) = x_0 * y_1;
+        *a.index_mut((i, 2)) = x_0;
+        *a.index_mut((i, 3)) = y_0 * x_1;
+        *a.index_mut((i, 4)) = y_0 * y_1;
+        *a.index_mut((i, 5)) = y_0;
+        *a.index_mut((i, 6)) = x_1;
+        *a.index_mut((i, 7)) = y_1;
         *a.index_mut((i, 8)) = 1f32;
-        *b.index_mut((i, 0)) = 0f32;
     }
-    let svd = SVD::new(a, true, true);
-    if svd.rank(epsilon) != 8 {
-        None
+    SVD::new(a, false, false).rank(epsilon) == 8
+}
+
+/// Similarity transform which moves the centroid of the points to the
+/// origin and scales them to an average distance of sqrt(2) from it.
+pub(crate) fn normalizing_transform(points: &[(f32, f32)]) -> Matrix3<f64> {
+    let count = points.len() as f64;
+    let mean_x = points.iter().map(|p| f64::from(p.0)).sum::<f64>() / count;
+    let mean_y = points.iter().map(|p| f64::from(p.1)).sum::<f64>() / count;
+    let mean_distance = points
+        .iter()
+        .map(|p| f64::hypot(f64::from(p.0) - mean_x, f64::from(p.1) - mean_y))
+        .sum::<f64>()
+        / count;
+    let scale = if mean_distance > 0f64 {
+        f64::sqrt(2f64) / mean_distance
     } else {
-        let mut min_eigen_i = 0;
-        let mut min_eigen = std::f32::MAX;
-        for i in 0..8 {
-            if svd.singular_values[i] < min_eigen {
-                min_eigen_i = i;
-                min_eigen = svd.singular_values[i];
-            }
+        1f64
+    };
+    Matrix3::new(
+        scale,
+        0f64,
+        -scale * mean_x,
+        0f64,
+        scale,
+        -scale * mean_y,
+        0f64,
+        0f64,
+        1f64,
+    )
+}
+
+/// Estimate the fundamental matrix from 8 or more matches with the
+/// normalized 8-point algorithm. With more than 8 matches this is the
+/// least squares solution. The result has rank 2 and unit Frobenius norm.
+///
+/// # Arguments
+/// * `keypoints_0 ` - Keypoints in set 0
+/// * `keypoints_1 ` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// # Return value
+/// Optionally, the fundamental matrix, a 3x3 matrix
+pub fn normalized_eight_point(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+) -> Option<Matrix3<f32>> {
+    normalized_eight_point_weighted(
+        keypoints_0,
+        keypoints_1,
+        matches,
+        &vec![1f64; matches.len()],
+    )
+}
+
+/// The normalized 8-point algorithm with each epipolar constraint
+/// scaled by a weight.
+///
+/// # Arguments
+/// * `keypoints_0 ` - Keypoints in set 0
+/// * `keypoints_1 ` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// * `weights` - A non-negative weight for each match.
+/// # Return value
+/// Optionally, the fundamental matrix, a 3x3 matrix
+pub fn normalized_eight_point_weighted(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    weights: &[f64],
+) -> Option<Matrix3<f32>> {
+    debug_assert!(matches.len() == weights.len());
+    if weights.iter().filter(|&&w| w > 0f64).count() < 8 {
+        return None;
+    }
+    let points_0: Vec<(f32, f32)> = matches
+        .iter()
+        .map(|m| keypoints_0[m.index_0].point)
+        .collect();
+    let points_1: Vec<(f32, f32)> = matches
+        .iter()
+        .map(|m| keypoints_1[m.index_1].point)
+        .collect();
+    let t_0 = normalizing_transform(&points_0);
+    let t_1 = normalizing_transform(&points_1);
+    // Pad to at least 9 rows so that the SVD contains the full null space
+    let mut a: DMatrix<f64> = DMatrix::zeros(usize::max(matches.len(), 9), 9);
+    for (i, (p_0, p_1)) in points_0.iter().zip(points_1.iter()).enumerate() {
+        let p_0 = t_0 * Vector3::new(f64::from(p_0.0), f64::from(p_0.1), 1f64);
+        let p_1 = t_1 * Vector3::new(f64::from(p_1.0), f64::from(p_1.1), 1f64);
+        let w = weights[i];
+        *a.index_mut((i, 0)) = w * p_0[0] * p_1[0];
+        *a.index_mut((i, 1)) = w * p_0[0] * p_1[1];
+        *a.index_mut((i, 2)) = w * p_0[0];
+        *a.index_mut((i, 3)) = w * p_0[1] * p_1[0];
+        *a.index_mut((i, 4)) = w * p_0[1] * p_1[1];
+        *a.index_mut((i, 5)) = w * p_0[1];
+        *a.index_mut((i, 6)) = w * p_1[0];
+        *a.index_mut((i, 7)) = w * p_1[1];
+        *a.index_mut((i, 8)) = w;
+    }
+    let svd = SVD::new(a, false, true);
+    let v_t = svd.v_t?;
+    let mut min_i = 0;
+    for i in 1..svd.singular_values.len() {
+        if svd.singular_values[i] < svd.singular_values[min_i] {
+            min_i = i;
         }
-        match svd.v_t {
-            Some(v_t) => Some(Matrix3::new(
-                *v_t.index((min_eigen_i, 0)),
-                *v_t.index((min_eigen_i, 3)),
-                *v_t.index((min_eigen_i, 6)),
-                *v_t.index((min_eigen_i, 1)),
-                *v_t.index((min_eigen_i, 4)),
-                *v_t.index((min_eigen_i, 7)),
-                *v_t.index((min_eigen_i, 2)),
-                *v_t.index((min_eigen_i, 5)),
-                *v_t.index((min_eigen_i, 8)),
-            )),
-            None => None,
+    }
+    let f_normalized = Matrix3::new(
+        *v_t.index((min_i, 0)),
+        *v_t.index((min_i, 3)),
+        *v_t.index((min_i, 6)),
+        *v_t.index((min_i, 1)),
+        *v_t.index((min_i, 4)),
+        *v_t.index((min_i, 7)),
+        *v_t.index((min_i, 2)),
+        *v_t.index((min_i, 5)),
+        *v_t.index((min_i, 8)),
+    );
+    // Enforce rank 2 by zeroing the smallest singular value
+    let svd = SVD::new(f_normalized, true, true);
+    let (u, v_t) = (svd.u?, svd.v_t?);
+    let mut singular_values = svd.singular_values;
+    let mut min_i = 0;
+    for i in 1..3 {
+        if singular_values[i] < singular_values[min_i] {
+            min_i = i;
         }
     }
+    singular_values[min_i] = 0f64;
+    let f_normalized = u * Matrix3::from_diagonal(&singular_values) * v_t;
+    let f = t_1.transpose() * f_normalized * t_0;
+    let norm = f.norm();
+    if norm <= 0f64 || !norm.is_finite() {
+        return None;
+    }
+    Some((f / norm).map(|x| x as f32))
 }
 
 /// Apply the fundamental matrix and return the error. to a pair of keypoints.
@@ -82,8 +209,112 @@ fn evaluate_model(fund_mat: Matrix3<f32>, keypoint_0: &Keypoint, keypoint_1: &Ke
     (p_r.transpose() * fund_mat * p_l).norm()
 }
 
+/// The Sampson distance of a pair of keypoints to a fundamental matrix.
+///
+/// This is a first order approximation of the geometric reprojection error,
+/// so unlike `evaluate_model` it is measured in pixels.
+///
+/// # Arguments
+/// * `fund_mat` - the Fundamental Matrix
+/// * `keypoint_0` - the keypoint in image plane l
+/// * `keypoint_1` - the keypoint in image plane r
+/// # Return value
+/// The Sampson distance.
+pub fn sampson_distance(
+    fund_mat: Matrix3<f32>,
+    keypoint_0: &Keypoint,
+    keypoint_1: &Keypoint,
+) -> f32 {
+    let p_r = Vector3::new(keypoint_1.point.0, keypoint_1.point.1, 1f32);
+    let p_l = Vector3::new(keypoint_0.point.0, keypoint_0.point.1, 1f32);
+    let f_p_l = fund_mat * p_l;
+    let f_t_p_r = fund_mat.transpose() * p_r;
+    let error = p_r.dot(&f_p_l);
+    let denominator = f_p_l[0] * f_p_l[0]
+        + f_p_l[1] * f_p_l[1]
+        + f_t_p_r[0] * f_t_p_r[0]
+        + f_t_p_r[1] * f_t_p_r[1];
+    if denominator <= 0f32 {
+        f32::MAX
+    } else {
+        error.abs() / f32::sqrt(denominator)
+    }
+}
+
+/// How the error of a match under a fundamental matrix is measured.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum EpipolarError {
+    /// The algebraic error, see `evaluate_model`.
+    Algebraic,
+    /// The Sampson distance in pixels, see `sampson_distance`.
+    Sampson,
+}
+
+/// The fundamental matrix model for RANSAC, fit with the 8-point algorithm.
+#[derive(Debug, Copy, Clone)]
+pub struct FundamentalMatrixEstimator {
+    /// epsilon used when solving SVD
+    pub epsilon_model: f32,
+    /// The residual to compare against the inlier threshold
+    pub error: EpipolarError,
+}
+
+impl Estimator for FundamentalMatrixEstimator {
+    type Model = Matrix3<f32>;
+
+    fn min_sample_size(&self) -> usize {
+        8
+    }
+
+    fn estimate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_fundamental_matrix(
+            keypoints_0,
+            keypoints_1,
+            &mut sample.to_vec(),
+            self.epsilon_model,
+        )
+    }
+
+    fn estimate_from_inliers(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        inliers: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        normalized_eight_point(keypoints_0, keypoints_1, inliers)
+    }
+
+    fn estimate_weighted(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        matches: &[Match],
+        weights: &[f64],
+    ) -> Option<Matrix3<f32>> {
+        normalized_eight_point_weighted(keypoints_0, keypoints_1, matches, weights)
+    }
+
+    fn residual(&self, model: &Matrix3<f32>, keypoint_0: &Keypoint, keypoint_1: &Keypoint) -> f32 {
+        match self.error {
+            EpipolarError::Algebraic => evaluate_model(*model, keypoint_0, keypoint_1),
+            EpipolarError::Sampson => sampson_distance(*model, keypoint_0, keypoint_1),
+        }
+    }
+}
+
 /// Remove outliers using RANSAC.
-/// Contains RANSAC implementation.
+///
+/// Trials stop early once enough have been run to find an outlier-free
+/// sample with the default confidence of `RansacConfig`, and sampling uses
+/// the default seed, so the result is reproducible. Hypotheses are
+/// evaluated on all CPUs, which does not change the result. Use
+/// `remove_outliers_with_config` to choose these options, the sampling and
+/// local optimization.
 ///
 /// # Arguments
 /// * `keypoints_0` - Tirst set of keypoints
@@ -103,6 +334,46 @@ pub fn remove_outliers(
     num_trials: usize,
     epsilon_model: f32,
     epsilon_inlier: f32,
+) -> Vec<Match> {
+    let config = RansacConfig {
+        max_trials: num_trials,
+        epsilon_inlier,
+        num_threads: num_cpus::get(),
+        ..RansacConfig::default()
+    };
+    remove_outliers_with_config(
+        keypoints_0,
+        keypoints_1,
+        matches,
+        epsilon_model,
+        &config,
+        None,
+    )
+}
+
+/// Remove outliers using RANSAC with the fundamental matrix model.
+///
+/// # Arguments
+/// * `keypoints_0` - Tirst set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `epsilon_model` - epsilon used when solving SVD
+/// * `config` - The RANSAC options, e.g. PROSAC sampling, local
+///   optimization or the seed.
+/// * `pool` - Threads to evaluate hypotheses in, shared by the calls for
+///   many image pairs. If None, a pool of `config.num_threads` is created
+///   when that is above one.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_with_config(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    epsilon_model: f32,
+    config: &RansacConfig,
+    pool: Option<&mut Pool>,
 ) -> Vec<Match> {
     if matches.len() < 8 {
         warn!("Not enough points to do RANSAC.");
@@ -110,56 +381,230 @@ pub fn remove_outliers(
     } else {
         debug!("Removing outliers with RANSAC using fundamental matrix model.");
     }
-    let mut max_inlier_count = 0;
-    let mut final_model: Matrix3<f32> = Matrix3::zeros();
-    for _ in 0..num_trials {
-        // Pick the points for the model
-        let mut set = HashSet::new();
-        let mut source = random::default();
-        while set.len() < 8 {
-            set.insert(source.read::<usize>() % matches.len());
-        }
-        let mut model_matches: Vec<Match> = vec![];
-        for j in set {
-            model_matches.push(matches[j]);
+    let estimator = FundamentalMatrixEstimator {
+        epsilon_model,
+        error: EpipolarError::Algebraic,
+    };
+    let mut source = Xorshift128Plus::new(config.seed);
+    let result = match pool {
+        Some(pool) => ransac_with_pool(
+            &estimator,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config,
+            &mut source,
+            Some(pool),
+        ),
+        None => ransac(
+            &estimator,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config,
+            &mut source,
+        ),
+    };
+    match result {
+        Some(result) => result.inliers,
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_fundamental_matrix, evaluate_model, normalized_eight_point};
+    use super::{remove_outliers_with_config, EpipolarError, FundamentalMatrixEstimator};
+    use crate::ops::ransac::{ransac, Estimator, RansacConfig, Sampling};
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use nalgebra::{DMatrix, Matrix3, Rotation3, Vector3, SVD};
+    use random::Xorshift128Plus;
+
+    fn keypoint(point: (f32, f32)) -> Keypoint {
+        Keypoint {
+            point,
+            response: 1f32,
+            size: 1f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
         }
+    }
 
-        // Get a model
-        if let Some(model) = estimate_fundamental_matrix(
-            &keypoints_0,
-            &keypoints_1,
-            &mut model_matches,
-            epsilon_model,
-        ) {
-            let mut inlier_count = 0;
-            for match_i in matches {
-                let error_i = evaluate_model(
-                    model,
-                    &keypoints_0[match_i.index_0],
-                    &keypoints_1[match_i.index_1],
-                );
-                if error_i < epsilon_inlier {
-                    inlier_count += 1;
-                }
+    /// Project a cloud of points into two views. Every fifth match is an
+    /// outlier with a large descriptor distance.
+    fn two_views() -> (Vec<Keypoint>, Vec<Keypoint>, Vec<Match>) {
+        let k = Matrix3::new(500f64, 0f64, 320f64, 0f64, 500f64, 240f64, 0f64, 0f64, 1f64);
+        let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), 0.1f64);
+        let translation = Vector3::new(1f64, 0.1f64, 0.05f64);
+        let project = |point: Vector3<f64>| {
+            let p = k * point;
+            ((p[0] / p[2]) as f32, (p[1] / p[2]) as f32)
+        };
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..100 {
+            let point = Vector3::new(
+                (i % 10) as f64 * 0.4f64 - 2f64,
+                (i / 10) as f64 * 0.3f64 - 1.5f64,
+                4f64 + ((i * i) % 13) as f64 * 0.25f64,
+            );
+            let mut point_1 = project(rotation * point + translation);
+            let outlier = i % 5 == 0;
+            if outlier {
+                point_1.1 += 40f32;
             }
-            if inlier_count > max_inlier_count {
-                max_inlier_count = inlier_count;
-                final_model = model;
+            keypoints_0.push(keypoint(project(point)));
+            keypoints_1.push(keypoint(point_1));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: if outlier { 100f64 } else { i as f64 },
+            });
+        }
+        (keypoints_0, keypoints_1, matches)
+    }
+
+    #[test]
+    fn eight_point_satisfies_epipolar_constraint() {
+        let (keypoints_0, keypoints_1, matches) = two_views();
+        let inliers: Vec<Match> = matches
+            .iter()
+            .filter(|m| m.index_0 % 5 != 0)
+            .cloned()
+            .collect();
+        // One point from each row of the grid, at varying depths
+        let sample: Vec<Match> = [1, 13, 22, 34, 46, 57, 68, 79]
+            .iter()
+            .map(|&i| matches[i])
+            .collect();
+        let estimator = FundamentalMatrixEstimator {
+            epsilon_model: 1e-6,
+            error: EpipolarError::Algebraic,
+        };
+        let minimal = estimator
+            .estimate(&keypoints_0, &keypoints_1, &sample)
+            .unwrap();
+        let least_squares = normalized_eight_point(&keypoints_0, &keypoints_1, &inliers).unwrap();
+        for model in &[minimal, least_squares] {
+            for m in &inliers {
+                let error =
+                    evaluate_model(*model, &keypoints_0[m.index_0], &keypoints_1[m.index_1]);
+                assert!(error < 1e-3, "epipolar error {}", error);
             }
         }
     }
 
-    // Calculate final inlier set
-    let mut inliers: Vec<Match> = vec![];
-    for match_i in matches {
-        let error_i = evaluate_model(
-            final_model,
-            &keypoints_0[match_i.index_0],
-            &keypoints_1[match_i.index_1],
-        );
-        if error_i < epsilon_inlier {
-            inliers.push(*match_i);
+    /// The solver of version 0.1.3, reading the matrix from a thin SVD.
+    fn thin_svd_solver(
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        matches: &[Match],
+    ) -> Matrix3<f32> {
+        let mut a: DMatrix<f32> = DMatrix::zeros(8, 9);
+        for (i, m) in matches.iter().enumerate() {
+            let (x_0, y_0) = keypoints_0[m.index_0].point;
+            let (x_1, y_1) = keypoints_1[m.index_1].point;
+            let row = [
+                x_0 * x_1,
+                x_0 * y_1,
+                x_0,
+                y_0 * x_1,
+                y_0 * y_1,
+                y_0,
+                x_1,
+                y_1,
+                1f32,
+            ];
+            for (j, value) in row.iter().enumerate() {
+                a[(i, j)] = *value;
+            }
         }
+        let svd = SVD::new(a, true, true);
+        let v_t = svd.v_t.unwrap();
+        let i = svd.singular_values.imin();
+        Matrix3::new(
+            v_t[(i, 0)],
+            v_t[(i, 3)],
+            v_t[(i, 6)],
+            v_t[(i, 1)],
+            v_t[(i, 4)],
+            v_t[(i, 7)],
+            v_t[(i, 2)],
+            v_t[(i, 5)],
+            v_t[(i, 8)],
+        )
+    }
+
+    /// The cosine of the angle between two matrices as vectors, which is
+    /// 1 in magnitude when they are equal up to scale.
+    fn cosine(a: &Matrix3<f64>, b: &Matrix3<f64>) -> f64 {
+        a.dot(b) / (a.norm() * b.norm())
+    }
+
+    #[test]
+    fn minimal_solver_recovers_known_fundamental_matrix() {
+        let (keypoints_0, keypoints_1, matches) = two_views();
+        // The views of two_views: x_1^T F x_0 = 0 with F = K^-T [t]x R K^-1
+        let k = Matrix3::new(500f64, 0f64, 320f64, 0f64, 500f64, 240f64, 0f64, 0f64, 1f64);
+        let rotation = Rotation3::from_axis_angle(&Vector3::y_axis(), 0.1f64);
+        let translation = Vector3::new(1f64, 0.1f64, 0.05f64);
+        let k_inverse = k.try_inverse().unwrap();
+        let known =
+            k_inverse.transpose() * translation.cross_matrix() * rotation.matrix() * k_inverse;
+        let mut sample: Vec<Match> = [1, 13, 22, 34, 46, 57, 68, 79]
+            .iter()
+            .map(|&i| matches[i])
+            .collect();
+        let estimate =
+            estimate_fundamental_matrix(&keypoints_0, &keypoints_1, &mut sample, 1e-6).unwrap();
+        assert!(cosine(&estimate.map(f64::from), &known).abs() > 1f64 - 1e-6);
+        // The previous solver returned a matrix orthogonal to the solution
+        let previous = thin_svd_solver(&keypoints_0, &keypoints_1, &sample);
+        assert!(cosine(&previous.map(f64::from), &known).abs() < 1e-2);
+    }
+
+    #[test]
+    fn prosac_with_local_optimization_finds_inliers() {
+        let (keypoints_0, keypoints_1, matches) = two_views();
+        let config = RansacConfig {
+            epsilon_inlier: 1e-3,
+            sampling: Sampling::Prosac,
+            local_optimization: true,
+            ..RansacConfig::default()
+        };
+        let mut source = Xorshift128Plus::new(config.seed);
+        let result = ransac(
+            &FundamentalMatrixEstimator {
+                epsilon_model: 1e-6,
+                error: EpipolarError::Algebraic,
+            },
+            &keypoints_0,
+            &keypoints_1,
+            &matches,
+            &config,
+            &mut source,
+        )
+        .unwrap();
+        assert_eq!(result.inliers.len(), 80);
+        assert!(result.inliers.iter().all(|m| m.index_0 % 5 != 0));
+        assert!(result.trials < 100);
+    }
+
+    #[test]
+    fn remove_outliers_with_prosac_and_local_optimization() {
+        let (keypoints_0, keypoints_1, matches) = two_views();
+        let config = RansacConfig {
+            epsilon_inlier: 1e-3,
+            sampling: Sampling::Prosac,
+            local_optimization: true,
+            ..RansacConfig::default()
+        };
+        let inliers =
+            remove_outliers_with_config(&keypoints_0, &keypoints_1, &matches, 1e-6, &config, None);
+        assert_eq!(inliers.len(), 80);
+        assert!(inliers.iter().all(|m| m.index_0 % 5 != 0));
     }
-    inliers
 }
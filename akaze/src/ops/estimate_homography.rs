@@ -0,0 +1,282 @@
+use crate::ops::estimate_fundamental_matrix::normalizing_transform;
+use crate::ops::estimate_similarity_transform::transfer_error;
+use crate::ops::ransac::{ransac, Estimator, RansacConfig};
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use nalgebra::{DMatrix, Matrix3, Vector3, SVD};
+use random::Xorshift128Plus;
+
+/// Estimate a homography from four or more correspondences with the
+/// normalized direct linear transform. With more than four matches this
+/// is the algebraic least squares solution.
+///
+/// # Arguments
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// # Return value
+/// Optionally, the homography as a 3x3 matrix mapping homogeneous points
+/// in the first image to the second image, scaled so that the bottom right
+/// entry is 1. None if there are fewer than four matches or the problem
+/// is degenerate.
+pub fn estimate_homography(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+) -> Option<Matrix3<f32>> {
+    estimate_homography_weighted(
+        keypoints_0,
+        keypoints_1,
+        matches,
+        &vec![1f64; matches.len()],
+    )
+}
+
+/// The normalized direct linear transform with each correspondence
+/// scaled by a weight.
+///
+/// # Arguments
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// * `weights` - A non-negative weight for each match.
+/// # Return value
+/// Optionally, the homography. None if fewer than four matches have a
+/// positive weight or the problem is degenerate.
+pub fn estimate_homography_weighted(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    weights: &[f64],
+) -> Option<Matrix3<f32>> {
+    debug_assert!(matches.len() == weights.len());
+    if weights.iter().filter(|&&w| w > 0f64).count() < 4 {
+        return None;
+    }
+    let points_0: Vec<(f32, f32)> = matches
+        .iter()
+        .map(|m| keypoints_0[m.index_0].point)
+        .collect();
+    let points_1: Vec<(f32, f32)> = matches
+        .iter()
+        .map(|m| keypoints_1[m.index_1].point)
+        .collect();
+    let t_0 = normalizing_transform(&points_0);
+    let t_1 = normalizing_transform(&points_1);
+    // Two rows per match, padded to at least 9 rows so that the SVD
+    // contains the full null space
+    let mut a: DMatrix<f64> = DMatrix::zeros(usize::max(2 * matches.len(), 9), 9);
+    for (i, (p_0, p_1)) in points_0.iter().zip(points_1.iter()).enumerate() {
+        let p_0 = t_0 * Vector3::new(f64::from(p_0.0), f64::from(p_0.1), 1f64);
+        let p_1 = t_1 * Vector3::new(f64::from(p_1.0), f64::from(p_1.1), 1f64);
+        let w = weights[i];
+        let (x, y, u, v) = (p_0[0], p_0[1], p_1[0], p_1[1]);
+        let rows = [
+            [-x, -y, -1f64, 0f64, 0f64, 0f64, u * x, u * y, u],
+            [0f64, 0f64, 0f64, -x, -y, -1f64, v * x, v * y, v],
+        ];
+        for (j, row) in rows.iter().enumerate() {
+            for (k, value) in row.iter().enumerate() {
+                a[(2 * i + j, k)] = w * value;
+            }
+        }
+    }
+    let svd = SVD::new(a, false, true);
+    let v_t = svd.v_t?;
+    let mut min_i = 0;
+    for i in 1..svd.singular_values.len() {
+        if svd.singular_values[i] < svd.singular_values[min_i] {
+            min_i = i;
+        }
+    }
+    let h_normalized = Matrix3::from_fn(|r, c| v_t[(min_i, 3 * r + c)]);
+    let h = t_1.try_inverse()? * h_normalized * t_0;
+    if h[(2, 2)].abs() <= 1e-12 * h.norm() || !h.norm().is_finite() {
+        return None;
+    }
+    Some((h / h[(2, 2)]).map(|x| x as f32))
+}
+
+/// The 4-point homography model for RANSAC.
+#[derive(Debug, Copy, Clone)]
+pub struct HomographyEstimator;
+
+impl Estimator for HomographyEstimator {
+    type Model = Matrix3<f32>;
+
+    fn min_sample_size(&self) -> usize {
+        4
+    }
+
+    fn estimate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_homography(keypoints_0, keypoints_1, sample)
+    }
+
+    fn estimate_from_inliers(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        inliers: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_homography(keypoints_0, keypoints_1, inliers)
+    }
+
+    fn estimate_weighted(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        matches: &[Match],
+        weights: &[f64],
+    ) -> Option<Matrix3<f32>> {
+        estimate_homography_weighted(keypoints_0, keypoints_1, matches, weights)
+    }
+
+    fn residual(&self, model: &Matrix3<f32>, keypoint_0: &Keypoint, keypoint_1: &Keypoint) -> f32 {
+        transfer_error(*model, keypoint_0, keypoint_1)
+    }
+
+    /// Reject samples with three collinear points in either image.
+    fn is_degenerate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> bool {
+        let twice_area = |a: (f32, f32), b: (f32, f32), c: (f32, f32)| {
+            ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs()
+        };
+        let points_0: Vec<(f32, f32)> = sample
+            .iter()
+            .map(|m| keypoints_0[m.index_0].point)
+            .collect();
+        let points_1: Vec<(f32, f32)> = sample
+            .iter()
+            .map(|m| keypoints_1[m.index_1].point)
+            .collect();
+        for points in &[points_0, points_1] {
+            for i in 0..points.len() {
+                for j in i + 1..points.len() {
+                    for k in j + 1..points.len() {
+                        if twice_area(points[i], points[j], points[k]) < 1f32 {
+                            return true;
+                        }
+                    }
+                }
+            }
+        }
+        false
+    }
+}
+
+/// Remove outliers using RANSAC with a 4-point homography model.
+///
+/// A homography is the right model for planar scenes and for images taken
+/// from the same position, e.g. for panoramas.
+///
+/// # Arguments
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `num_trials` - Maximum number of RANSAC iterations
+/// * `epsilon_inlier` - Maximum transfer error in pixels to accept an inlier.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_homography(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    num_trials: usize,
+    epsilon_inlier: f32,
+) -> Vec<Match> {
+    if matches.len() < 4 {
+        warn!("Not enough points to do RANSAC.");
+        return matches.to_vec();
+    } else {
+        debug!("Removing outliers with RANSAC using homography model.");
+    }
+    let config = RansacConfig {
+        max_trials: num_trials,
+        epsilon_inlier,
+        ..RansacConfig::default()
+    };
+    let mut source = Xorshift128Plus::new(config.seed);
+    match ransac(
+        &HomographyEstimator,
+        keypoints_0,
+        keypoints_1,
+        matches,
+        &config,
+        &mut source,
+    ) {
+        Some(result) => result.inliers,
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{estimate_homography, remove_outliers_homography};
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use nalgebra::{Matrix3, Vector3};
+
+    fn keypoint(x: f32, y: f32) -> Keypoint {
+        Keypoint {
+            point: (x, y),
+            response: 1f32,
+            size: 4f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
+        }
+    }
+
+    #[test]
+    fn homography_recovers_perspective_transform() {
+        let homography = Matrix3::new(
+            0.9f32, 0.1f32, 20f32, -0.05f32, 1.1f32, -10f32, 2e-4f32, -1e-4f32, 1f32,
+        );
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..60 {
+            let k_0 = keypoint(
+                (i % 10) as f32 * 41f32 + 3f32,
+                (i / 10) as f32 * 47f32 + 8f32,
+            );
+            let p = homography * Vector3::new(k_0.point.0, k_0.point.1, 1f32);
+            let mut point_1 = (p[0] / p[2], p[1] / p[2]);
+            if i % 4 == 0 {
+                point_1.1 -= 60f32;
+            }
+            keypoints_0.push(k_0);
+            keypoints_1.push(keypoint(point_1.0, point_1.1));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            });
+        }
+        let inliers: Vec<Match> = matches
+            .iter()
+            .filter(|m| m.index_0 % 4 != 0)
+            .cloned()
+            .collect();
+        let estimate = estimate_homography(&keypoints_0, &keypoints_1, &inliers).unwrap();
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!((estimate[(i, j)] - homography[(i, j)]).abs() < 1e-3);
+            }
+        }
+        let verified = remove_outliers_homography(&keypoints_0, &keypoints_1, &matches, 200, 1f32);
+        assert_eq!(verified.len(), 45);
+        assert!(verified.iter().all(|m| m.index_0 % 4 != 0));
+    }
+}
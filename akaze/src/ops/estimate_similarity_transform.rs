@@ -0,0 +1,408 @@
+use crate::ops::ransac::{ransac, ransac_with_pool, Estimator, RansacConfig};
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use nalgebra::{Matrix3, Vector3};
+use random::Xorshift128Plus;
+use scoped_threadpool::Pool;
+
+/// Estimate a similarity transform (rotation, uniform scale and translation)
+/// from a single correspondence.
+///
+/// A-KAZE keypoints carry a size and an orientation, so one match already
+/// determines the relative scale (ratio of the sizes) and the relative
+/// rotation (difference of the angles). The translation then follows from
+/// the keypoint positions.
+///
+/// # Arguments
+/// * `keypoint_0` - The keypoint in the first image.
+/// * `keypoint_1` - The corresponding keypoint in the second image.
+/// # Return value
+/// Optionally, the transform as a 3x3 matrix mapping homogeneous points in
+/// the first image to the second image. None if the keypoint size is zero.
+pub fn estimate_similarity_transform(
+    keypoint_0: &Keypoint,
+    keypoint_1: &Keypoint,
+) -> Option<Matrix3<f32>> {
+    if keypoint_0.size <= 0f32 || keypoint_1.size <= 0f32 {
+        return None;
+    }
+    let scale = keypoint_1.size / keypoint_0.size;
+    let theta = keypoint_1.angle - keypoint_0.angle;
+    let a = scale * f32::cos(theta);
+    let b = scale * f32::sin(theta);
+    let (x_0, y_0) = keypoint_0.point;
+    let (x_1, y_1) = keypoint_1.point;
+    Some(Matrix3::new(
+        a,
+        -b,
+        x_1 - (a * x_0 - b * y_0),
+        b,
+        a,
+        y_1 - (b * x_0 + a * y_0),
+        0f32,
+        0f32,
+        1f32,
+    ))
+}
+
+/// Estimate a similarity transform from two or more correspondences by
+/// least squares, using only the keypoint positions.
+///
+/// # Arguments
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// # Return value
+/// Optionally, the transform as a 3x3 matrix mapping homogeneous points in
+/// the first image to the second image. None if there are fewer than two
+/// matches or all points in the first image coincide.
+pub fn estimate_similarity_transform_least_squares(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+) -> Option<Matrix3<f32>> {
+    estimate_similarity_transform_weighted(
+        keypoints_0,
+        keypoints_1,
+        matches,
+        &vec![1f64; matches.len()],
+    )
+}
+
+/// Estimate a similarity transform by weighted least squares.
+///
+/// # Arguments
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// * `weights` - A non-negative weight for each match.
+/// # Return value
+/// Optionally, the transform. None if there are fewer than two matches,
+/// all weights are zero or all weighted points coincide.
+pub fn estimate_similarity_transform_weighted(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    weights: &[f64],
+) -> Option<Matrix3<f32>> {
+    debug_assert!(matches.len() == weights.len());
+    let total_weight: f64 = weights.iter().sum();
+    if matches.len() < 2 || total_weight <= 0f64 {
+        return None;
+    }
+    let mut mean_0 = (0f64, 0f64);
+    let mut mean_1 = (0f64, 0f64);
+    for (match_i, weight) in matches.iter().zip(weights.iter()) {
+        let p_0 = keypoints_0[match_i.index_0].point;
+        let p_1 = keypoints_1[match_i.index_1].point;
+        mean_0.0 += weight * f64::from(p_0.0) / total_weight;
+        mean_0.1 += weight * f64::from(p_0.1) / total_weight;
+        mean_1.0 += weight * f64::from(p_1.0) / total_weight;
+        mean_1.1 += weight * f64::from(p_1.1) / total_weight;
+    }
+    let mut norm = 0f64;
+    let mut a = 0f64;
+    let mut b = 0f64;
+    for (match_i, weight) in matches.iter().zip(weights.iter()) {
+        let p_0 = keypoints_0[match_i.index_0].point;
+        let p_1 = keypoints_1[match_i.index_1].point;
+        let x = f64::from(p_0.0) - mean_0.0;
+        let y = f64::from(p_0.1) - mean_0.1;
+        let u = f64::from(p_1.0) - mean_1.0;
+        let v = f64::from(p_1.1) - mean_1.1;
+        norm += weight * (x * x + y * y);
+        a += weight * (x * u + y * v);
+        b += weight * (x * v - y * u);
+    }
+    if norm <= 0f64 {
+        return None;
+    }
+    let a = a / norm;
+    let b = b / norm;
+    let t_x = mean_1.0 - (a * mean_0.0 - b * mean_0.1);
+    let t_y = mean_1.1 - (b * mean_0.0 + a * mean_0.1);
+    Some(Matrix3::new(
+        a as f32, -b as f32, t_x as f32, b as f32, a as f32, t_y as f32, 0f32, 0f32, 1f32,
+    ))
+}
+
+/// Apply a 2D transform to a keypoint and return the transfer error.
+///
+/// # Arguments
+/// * `transform` - A 3x3 transform mapping image 0 to image 1.
+/// * `keypoint_0` - The keypoint in image 0.
+/// * `keypoint_1` - The keypoint in image 1.
+/// # Return value
+/// The distance in pixels between the transformed keypoint 0 and keypoint 1.
+pub fn transfer_error(
+    transform: Matrix3<f32>,
+    keypoint_0: &Keypoint,
+    keypoint_1: &Keypoint,
+) -> f32 {
+    let p_0 = transform * Vector3::new(keypoint_0.point.0, keypoint_0.point.1, 1f32);
+    if p_0[2].abs() < f32::EPSILON {
+        return f32::MAX;
+    }
+    let delta_x = p_0[0] / p_0[2] - keypoint_1.point.0;
+    let delta_y = p_0[1] / p_0[2] - keypoint_1.point.1;
+    f32::sqrt(delta_x * delta_x + delta_y * delta_y)
+}
+
+/// The 1-point similarity model for RANSAC.
+#[derive(Debug, Copy, Clone)]
+pub struct SimilarityEstimator;
+
+impl Estimator for SimilarityEstimator {
+    type Model = Matrix3<f32>;
+
+    fn min_sample_size(&self) -> usize {
+        1
+    }
+
+    fn estimate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_similarity_transform(
+            &keypoints_0[sample[0].index_0],
+            &keypoints_1[sample[0].index_1],
+        )
+    }
+
+    fn estimate_from_inliers(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        inliers: &[Match],
+    ) -> Option<Matrix3<f32>> {
+        estimate_similarity_transform_least_squares(keypoints_0, keypoints_1, inliers)
+    }
+
+    fn estimate_weighted(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        matches: &[Match],
+        weights: &[f64],
+    ) -> Option<Matrix3<f32>> {
+        estimate_similarity_transform_weighted(keypoints_0, keypoints_1, matches, weights)
+    }
+
+    fn residual(&self, model: &Matrix3<f32>, keypoint_0: &Keypoint, keypoint_1: &Keypoint) -> f32 {
+        transfer_error(*model, keypoint_0, keypoint_1)
+    }
+
+    fn is_degenerate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> bool {
+        keypoints_0[sample[0].index_0].size <= 0f32 || keypoints_1[sample[0].index_1].size <= 0f32
+    }
+}
+
+/// Remove outliers using RANSAC with a 1-point similarity model.
+///
+/// Each trial hypothesizes a transform from the scale and orientation
+/// of a single match, so far fewer trials are needed than with the
+/// 8-point fundamental matrix model. This is suitable for near-planar
+/// scenes or nadir aerial imagery.
+///
+/// Each new best model is refined from its inliers by least squares
+/// (local optimization). Use `remove_outliers_similarity_with_config` to
+/// choose the RANSAC options.
+///
+/// # Arguments
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `num_trials` - Maximum number of RANSAC iterations
+/// * `epsilon_inlier` - Maximum transfer error in pixels to accept an inlier.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_similarity(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    num_trials: usize,
+    epsilon_inlier: f32,
+) -> Vec<Match> {
+    let config = RansacConfig {
+        max_trials: num_trials,
+        epsilon_inlier,
+        local_optimization: true,
+        ..RansacConfig::default()
+    };
+    remove_outliers_similarity_with_config(keypoints_0, keypoints_1, matches, &config, None)
+}
+
+/// Remove outliers using RANSAC with a 1-point similarity model.
+///
+/// # Arguments
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `config` - The RANSAC options, e.g. PROSAC sampling, local
+///   optimization or the seed. `epsilon_inlier` is a transfer error in
+///   pixels.
+/// * `pool` - Threads to evaluate hypotheses in, shared by the calls for
+///   many image pairs. If None, a pool of `config.num_threads` is created
+///   when that is above one.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_similarity_with_config(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    config: &RansacConfig,
+    pool: Option<&mut Pool>,
+) -> Vec<Match> {
+    if matches.is_empty() {
+        warn!("Not enough points to do RANSAC.");
+        return matches.to_vec();
+    } else {
+        debug!("Removing outliers with RANSAC using similarity model.");
+    }
+    let mut source = Xorshift128Plus::new(config.seed);
+    let result = match pool {
+        Some(pool) => ransac_with_pool(
+            &SimilarityEstimator,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config,
+            &mut source,
+            Some(pool),
+        ),
+        None => ransac(
+            &SimilarityEstimator,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config,
+            &mut source,
+        ),
+    };
+    match result {
+        Some(result) => result.inliers,
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        estimate_similarity_transform, remove_outliers_similarity,
+        remove_outliers_similarity_with_config, transfer_error,
+    };
+    use crate::ops::ransac::{RansacConfig, Sampling};
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use nalgebra::{Matrix3, Vector3};
+
+    fn keypoint(x: f32, y: f32, size: f32, angle: f32) -> Keypoint {
+        Keypoint {
+            point: (x, y),
+            response: 1f32,
+            size,
+            octave: 0,
+            class_id: 0,
+            angle,
+        }
+    }
+
+    fn apply(transform: &Matrix3<f32>, keypoint_0: &Keypoint) -> (f32, f32) {
+        let p = transform * Vector3::new(keypoint_0.point.0, keypoint_0.point.1, 1f32);
+        (p[0] / p[2], p[1] / p[2])
+    }
+
+    /// A grid of points, mapped by `transform`, with every fifth match corrupted.
+    fn synthetic(transform: &Matrix3<f32>) -> (Vec<Keypoint>, Vec<Keypoint>, Vec<Match>) {
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..50 {
+            let k_0 = keypoint(
+                (i % 10) as f32 * 37f32 + 5f32,
+                (i / 10) as f32 * 53f32 + 11f32,
+                4f32,
+                0.1f32,
+            );
+            let mut point_1 = apply(transform, &k_0);
+            if i % 5 == 0 {
+                point_1.0 += 100f32;
+            }
+            keypoints_0.push(k_0);
+            keypoints_1.push(keypoint(point_1.0, point_1.1, 8f32, 0.6f32));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            });
+        }
+        (keypoints_0, keypoints_1, matches)
+    }
+
+    #[test]
+    fn similarity_from_one_correspondence() {
+        let k_0 = keypoint(10f32, 20f32, 4f32, 0.25f32);
+        let k_1 = keypoint(50f32, -3f32, 8f32, 1.25f32);
+        let transform = estimate_similarity_transform(&k_0, &k_1).unwrap();
+        assert!(transfer_error(transform, &k_0, &k_1) < 1e-4);
+        // scale 2, rotation 1 radian
+        assert!((transform[(0, 0)] - 2f32 * f32::cos(1f32)).abs() < 1e-5);
+        assert!((transform[(1, 0)] - 2f32 * f32::sin(1f32)).abs() < 1e-5);
+    }
+    #[test]
+    fn similarity_ransac_rejects_outliers() {
+        let (cos, sin) = (2f32 * f32::cos(0.5f32), 2f32 * f32::sin(0.5f32));
+        let transform = Matrix3::new(cos, -sin, 3f32, sin, cos, 4f32, 0f32, 0f32, 1f32);
+        let (keypoints_0, keypoints_1, matches) = synthetic(&transform);
+        let verified = remove_outliers_similarity(&keypoints_0, &keypoints_1, &matches, 20, 1f32);
+        assert_eq!(verified.len(), 40);
+        assert!(verified.iter().all(|m| m.index_0 % 5 != 0));
+    }
+    #[test]
+    fn similarity_ransac_with_config() {
+        let (cos, sin) = (2f32 * f32::cos(0.5f32), 2f32 * f32::sin(0.5f32));
+        let transform = Matrix3::new(cos, -sin, 3f32, sin, cos, 4f32, 0f32, 0f32, 1f32);
+        let (keypoints_0, keypoints_1, matches) = synthetic(&transform);
+        let config = RansacConfig {
+            max_trials: 20,
+            epsilon_inlier: 1f32,
+            sampling: Sampling::Prosac,
+            num_threads: 1,
+            ..RansacConfig::default()
+        };
+        let verified = remove_outliers_similarity_with_config(
+            &keypoints_0,
+            &keypoints_1,
+            &matches,
+            &config,
+            None,
+        );
+        assert_eq!(verified.len(), 40);
+        assert!(remove_outliers_similarity_with_config(
+            &keypoints_0,
+            &keypoints_1,
+            &[],
+            &config,
+            None
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn no_matches_are_returned_unchanged() {
+        let transform = Matrix3::new(1f32, 0f32, 3f32, 0f32, 1f32, 4f32, 0f32, 0f32, 1f32);
+        let (keypoints_0, keypoints_1, _) = synthetic(&transform);
+        assert!(remove_outliers_similarity(&keypoints_0, &keypoints_1, &[], 20, 1f32).is_empty());
+    }
+}
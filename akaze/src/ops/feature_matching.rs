@@ -110,7 +110,7 @@ pub fn descriptor_match(
 ///    consider anyway.
 /// # Return value
 /// The Hamming distance
-fn hamming_distance(d0: &Descriptor, d1: &Descriptor, bailout_distance: usize) -> usize {
+pub(crate) fn hamming_distance(d0: &Descriptor, d1: &Descriptor, bailout_distance: usize) -> usize {
     let mut distance = 0usize;
     for it in d0.vector.iter().zip(d1.vector.iter()) {
         let (x0, x1) = it;
@@ -0,0 +1,369 @@
+use crate::ops::feature_matching::hamming_distance;
+use crate::types::keypoint::{Descriptor, Keypoint};
+use crate::types::spatial_index::GridIndex;
+use nalgebra::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+
+/// Options of the frame-to-frame tracker.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct FrameTrackerConfig {
+    /// Radius in pixels around the predicted position in which a track
+    /// is searched for in the next frame.
+    pub search_radius: f32,
+    /// Maximum Hamming distance of a track to its match.
+    pub max_distance: usize,
+    /// Maximum ratio of the best to the second-best distance within the
+    /// search radius.
+    pub lowes_ratio: f64,
+    /// Size in pixels of the cells of the occupancy mask.
+    pub cell_size: f32,
+    /// Number of tracks per cell above which no new tracks are started.
+    pub max_tracks_per_cell: usize,
+}
+
+impl Default for FrameTrackerConfig {
+    fn default() -> FrameTrackerConfig {
+        FrameTrackerConfig {
+            search_radius: 20f32,
+            max_distance: 100,
+            lowes_ratio: 0.9,
+            cell_size: 40f32,
+            max_tracks_per_cell: 2,
+        }
+    }
+}
+
+/// A feature tracked over consecutive frames.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TrackedFeature {
+    /// The track id, unique over the lifetime of the tracker.
+    pub id: usize,
+    /// The keypoint in the current frame.
+    pub keypoint: Keypoint,
+    /// The descriptor in the current frame.
+    pub descriptor: Descriptor,
+    /// The index of the keypoint in the current frame's keypoints.
+    pub index: usize,
+    /// The number of frames the feature has been tracked for.
+    pub age: usize,
+}
+
+/// Where keypoints are needed in the next frame of a `FrameTracker`.
+#[derive(Debug, Clone)]
+pub struct DetectionMask {
+    /// The predicted positions of the tracks
+    predicted: GridIndex,
+    search_radius: f32,
+    cell_size: f32,
+    /// The cells of the occupancy mask which start no new tracks
+    full_cells: HashSet<(i32, i32)>,
+}
+
+impl DetectionMask {
+    /// Whether keypoints are needed at a point.
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        let cell = (
+            f32::floor(point.0 / self.cell_size) as i32,
+            f32::floor(point.1 / self.cell_size) as i32,
+        );
+        !self.full_cells.contains(&cell)
+            || !self.predicted.within(point, self.search_radius).is_empty()
+    }
+}
+
+/// The position of a track in the next frame.
+///
+/// # Return value
+/// The predicted position, or None if the prior maps it to infinity.
+fn predict(track: &TrackedFeature, prior: Option<&Matrix3<f32>>) -> Option<(f32, f32)> {
+    match prior {
+        Some(homography) => {
+            let p = homography * Vector3::new(track.keypoint.point.0, track.keypoint.point.1, 1f32);
+            if p[2].abs() < f32::EPSILON {
+                None
+            } else {
+                Some((p[0] / p[2], p[1] / p[2]))
+            }
+        }
+        None => Some(track.keypoint.point),
+    }
+}
+
+/// Tracks features over video frames with persistent ids.
+///
+/// Each frame, existing tracks are predicted into the new frame, and
+/// matched to the new keypoints within a search radius of the prediction.
+/// New keypoints start new tracks only in cells of an occupancy mask that
+/// hold few tracks, which keeps the track density roughly constant.
+#[derive(Debug, Clone)]
+pub struct FrameTracker {
+    /// The tracker options
+    pub config: FrameTrackerConfig,
+    tracks: Vec<TrackedFeature>,
+    next_id: usize,
+}
+
+impl FrameTracker {
+    /// Create a tracker with no tracks.
+    pub fn new(config: FrameTrackerConfig) -> FrameTracker {
+        FrameTracker {
+            config,
+            tracks: vec![],
+            next_id: 0,
+        }
+    }
+
+    /// The tracks in the most recent frame.
+    pub fn tracks(&self) -> &[TrackedFeature] {
+        &self.tracks
+    }
+
+    /// The occupancy of the mask by the current tracks.
+    ///
+    /// # Return value
+    /// The number of tracks in each non-empty cell.
+    pub fn occupancy(&self) -> HashMap<(i32, i32), usize> {
+        let mut occupancy = HashMap::new();
+        for track in &self.tracks {
+            *occupancy
+                .entry(self.cell(track.keypoint.point))
+                .or_insert(0) += 1;
+        }
+        occupancy
+    }
+
+    /// The mask of where the next frame needs keypoints: around the
+    /// predicted positions of the tracks, to continue them, and in the cells
+    /// which can still start new tracks once the tracks moved there.
+    ///
+    /// # Arguments
+    /// * `prior` - Optionally, a homography mapping the current frame to the
+    ///   next one, as passed to `track`.
+    /// # Return value
+    /// The mask, to be passed to `extract_features_with_mask`.
+    pub fn detection_mask(&self, prior: Option<&Matrix3<f32>>) -> DetectionMask {
+        let predicted: Vec<(f32, f32)> = self
+            .tracks
+            .iter()
+            .filter_map(|track| predict(track, prior))
+            .collect();
+        // The occupancy of the next frame, as the tracks are predicted
+        let mut occupancy = HashMap::new();
+        for &point in &predicted {
+            *occupancy.entry(self.cell(point)).or_insert(0) += 1;
+        }
+        let full_cells = occupancy
+            .into_iter()
+            .filter(|&(_, count)| count >= self.config.max_tracks_per_cell)
+            .map(|(cell, _)| cell)
+            .collect();
+        DetectionMask {
+            predicted: GridIndex::new(&predicted, self.config.search_radius),
+            search_radius: self.config.search_radius,
+            cell_size: self.config.cell_size,
+            full_cells,
+        }
+    }
+
+    fn cell(&self, point: (f32, f32)) -> (i32, i32) {
+        (
+            f32::floor(point.0 / self.config.cell_size) as i32,
+            f32::floor(point.1 / self.config.cell_size) as i32,
+        )
+    }
+
+    /// Track the features into a new frame.
+    ///
+    /// # Arguments
+    /// * `keypoints` - The keypoints of the new frame.
+    /// * `descriptors` - The descriptors of the new frame.
+    /// * `prior` - Optionally, a homography mapping the previous frame to
+    ///   the new one, e.g. from the camera motion. Without it, features
+    ///   are expected near their previous position.
+    /// # Return value
+    /// The tracks in the new frame. Tracks which were not found are ended.
+    pub fn track(
+        &mut self,
+        keypoints: &[Keypoint],
+        descriptors: &[Descriptor],
+        prior: Option<&Matrix3<f32>>,
+    ) -> &[TrackedFeature] {
+        let points: Vec<(f32, f32)> = keypoints.iter().map(|k| k.point).collect();
+        let index = GridIndex::new(&points, self.config.search_radius);
+        // Best candidate for each track, as (track, keypoint, distance)
+        let mut candidates: Vec<(usize, usize, usize)> = vec![];
+        for (t, track) in self.tracks.iter().enumerate() {
+            let predicted = match predict(track, prior) {
+                Some(predicted) => predicted,
+                None => continue,
+            };
+            let mut best = (usize::MAX, usize::MAX);
+            let mut second_distance = usize::MAX;
+            for i in index.within(predicted, self.config.search_radius) {
+                let distance =
+                    hamming_distance(&track.descriptor, &descriptors[i], second_distance);
+                if distance < best.1 {
+                    second_distance = best.1;
+                    best = (i, distance);
+                } else if distance < second_distance {
+                    second_distance = distance;
+                }
+            }
+            if best.1 <= self.config.max_distance
+                && (second_distance == usize::MAX
+                    || (best.1 as f64) < self.config.lowes_ratio * second_distance as f64)
+            {
+                candidates.push((t, best.0, best.1));
+            }
+        }
+        // Each keypoint continues at most one track, the closest one
+        candidates.sort_by_key(|&(t, _, distance)| (distance, t));
+        let mut used = vec![false; keypoints.len()];
+        let mut continued: Vec<TrackedFeature> = vec![];
+        for (t, i, _) in candidates {
+            if used[i] {
+                continue;
+            }
+            used[i] = true;
+            let track = &self.tracks[t];
+            continued.push(TrackedFeature {
+                id: track.id,
+                keypoint: keypoints[i],
+                descriptor: descriptors[i].clone(),
+                index: i,
+                age: track.age + 1,
+            });
+        }
+        continued.sort_by_key(|track| track.id);
+        let ended = self.tracks.len() - continued.len();
+        self.tracks = continued;
+        // Start new tracks from the strongest keypoints in sparse cells
+        let mut occupancy = self.occupancy();
+        let mut unused: Vec<usize> = (0..keypoints.len()).filter(|&i| !used[i]).collect();
+        unused.sort_by(|&a, &b| {
+            keypoints[b]
+                .response
+                .partial_cmp(&keypoints[a].response)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        let mut started = 0;
+        for i in unused {
+            let count = occupancy.entry(self.cell(keypoints[i].point)).or_insert(0);
+            if *count >= self.config.max_tracks_per_cell {
+                continue;
+            }
+            *count += 1;
+            self.tracks.push(TrackedFeature {
+                id: self.next_id,
+                keypoint: keypoints[i],
+                descriptor: descriptors[i].clone(),
+                index: i,
+                age: 1,
+            });
+            self.next_id += 1;
+            started += 1;
+        }
+        debug!(
+            "Tracked {} features, ended {} and started {} tracks.",
+            self.tracks.len() - started,
+            ended,
+            started
+        );
+        &self.tracks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FrameTracker, FrameTrackerConfig};
+    use crate::types::keypoint::{Descriptor, Keypoint};
+    use nalgebra::Matrix3;
+
+    fn frame(offset: (f32, f32), count: usize) -> (Vec<Keypoint>, Vec<Descriptor>) {
+        (0..count)
+            .map(|i| {
+                let keypoint = Keypoint {
+                    point: (
+                        (i % 5) as f32 * 50f32 + 20f32 + offset.0,
+                        (i / 5) as f32 * 50f32 + 20f32 + offset.1,
+                    ),
+                    response: 100f32 - i as f32,
+                    size: 4f32,
+                    octave: 0,
+                    class_id: 0,
+                    angle: 0f32,
+                };
+                // Descriptors far apart from each other
+                let descriptor = Descriptor {
+                    vector: (0..8)
+                        .map(|b| if b == i % 8 { 0xff } else { (i / 8) as u8 })
+                        .collect(),
+                };
+                (keypoint, descriptor)
+            })
+            .unzip()
+    }
+
+    #[test]
+    fn tracker_keeps_ids_and_fills_empty_cells() {
+        let config = FrameTrackerConfig {
+            search_radius: 10f32,
+            max_distance: 4,
+            ..FrameTrackerConfig::default()
+        };
+        let mut tracker = FrameTracker::new(config);
+        let (keypoints, descriptors) = frame((0f32, 0f32), 10);
+        assert_eq!(tracker.track(&keypoints, &descriptors, None).len(), 10);
+        // Small motion, no prior needed
+        let (keypoints, descriptors) = frame((3f32, -2f32), 10);
+        let tracks = tracker.track(&keypoints, &descriptors, None);
+        assert_eq!(tracks.len(), 10);
+        assert!(tracks
+            .iter()
+            .all(|track| track.id == track.index && track.age == 2));
+        // Large motion is only tracked with the prior
+        let (keypoints, descriptors) = frame((33f32, -2f32), 15);
+        let shift = Matrix3::new(1f32, 0f32, 30f32, 0f32, 1f32, 0f32, 0f32, 0f32, 1f32);
+        let tracks = tracker.track(&keypoints, &descriptors, Some(&shift));
+        let continued: Vec<_> = tracks.iter().filter(|track| track.age == 3).collect();
+        assert_eq!(continued.len(), 10);
+        assert!(continued.iter().all(|track| track.id == track.index));
+        // The new row lies in empty cells and starts new tracks
+        let started: Vec<_> = tracks.iter().filter(|track| track.age == 1).collect();
+        assert_eq!(started.len(), 5);
+        assert!(started
+            .iter()
+            .all(|track| track.id >= 10 && track.index >= 10));
+    }
+
+    #[test]
+    fn detection_mask_skips_full_cells() {
+        let config = FrameTrackerConfig {
+            search_radius: 10f32,
+            max_distance: 4,
+            max_tracks_per_cell: 1,
+            ..FrameTrackerConfig::default()
+        };
+        let mut tracker = FrameTracker::new(config);
+        let (keypoints, descriptors) = frame((0f32, 0f32), 10);
+        tracker.track(&keypoints, &descriptors, None);
+        let mask = tracker.detection_mask(None);
+        // Around the tracks, to continue them
+        assert!(mask.contains((20f32, 20f32)));
+        assert!(mask.contains((27f32, 14f32)));
+        // Not elsewhere in their full cells
+        assert!(!mask.contains((35f32, 35f32)));
+        // Anywhere in empty cells
+        assert!(mask.contains((-100f32, -100f32)));
+        assert!(mask.contains((35f32, 115f32)));
+        // Around the predicted positions with a prior, whose cells fill up
+        // while the cells the tracks leave open up
+        let shift = Matrix3::new(1f32, 0f32, 30f32, 0f32, 1f32, 0f32, 0f32, 0f32, 1f32);
+        let mask = tracker.detection_mask(Some(&shift));
+        assert!(mask.contains((50f32, 20f32)));
+        assert!(!mask.contains((75f32, 5f32)));
+        assert!(mask.contains((20f32, 20f32)));
+        assert!(mask.contains((35f32, 35f32)));
+    }
+}
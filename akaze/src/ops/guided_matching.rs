@@ -0,0 +1,218 @@
+use crate::ops::feature_matching::hamming_distance;
+use crate::types::feature_match::Match;
+use crate::types::keypoint::{Descriptor, Keypoint};
+use crate::types::spatial_index::GridIndex;
+use nalgebra::{Matrix3, Vector3};
+use serde::{Deserialize, Serialize};
+
+/// The geometry that restricts the candidates of guided matching.
+#[derive(Debug, Copy, Clone)]
+pub enum Guidance {
+    /// A fundamental matrix F with p1^T F p0 = 0, e.g. the model found by
+    /// `ransac` with a `FundamentalMatrixEstimator`. Candidates must lie
+    /// near the epipolar line in the second image, and the keypoint near
+    /// the epipolar line of the candidate in the first image.
+    Epipolar(Matrix3<f32>),
+    /// A homography H mapping the first image to the second. Candidates
+    /// must lie near the predicted location.
+    Homography(Matrix3<f32>),
+}
+
+/// Options of guided matching.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct GuidedMatchingConfig {
+    /// Maximum distance in pixels from the epipolar line or the
+    /// predicted location.
+    pub max_pixel_distance: f32,
+    /// Maximum Hamming distance of a match.
+    pub max_descriptor_distance: usize,
+    /// Maximum ratio of the best to the second-best distance, among the
+    /// candidates that satisfy the geometry only.
+    pub lowes_ratio: f64,
+}
+
+impl Default for GuidedMatchingConfig {
+    fn default() -> GuidedMatchingConfig {
+        GuidedMatchingConfig {
+            max_pixel_distance: 2f32,
+            max_descriptor_distance: 100,
+            lowes_ratio: 0.9,
+        }
+    }
+}
+
+/// Distance of a point to a line, in pixels.
+fn line_distance(line: &Vector3<f32>, point: (f32, f32)) -> f32 {
+    (line[0] * point.0 + line[1] * point.1 + line[2]).abs()
+        / f32::sqrt(line[0] * line[0] + line[1] * line[1])
+}
+
+/// Match descriptors among the candidates allowed by a geometric model.
+///
+/// This is meant to run after a first RANSAC pass: with the geometry known,
+/// the ratio test only has to discriminate between the few keypoints that
+/// are geometrically plausible, which recovers many correct matches that
+/// failed the global ratio test of `descriptor_match`, e.g. on repetitive
+/// structure.
+///
+/// # Arguments
+/// * `keypoints_0` - The first set of keypoints
+/// * `descriptors_0` - The first set of descriptors
+/// * `keypoints_1` - The second set of keypoints
+/// * `descriptors_1` - The second set of descriptors
+/// * `guidance` - The geometric model relating the two images.
+/// * `config` - The matching options.
+/// # Return value
+/// The matches. Each keypoint of either image is used at most once.
+pub fn guided_match(
+    keypoints_0: &[Keypoint],
+    descriptors_0: &[Descriptor],
+    keypoints_1: &[Keypoint],
+    descriptors_1: &[Descriptor],
+    guidance: &Guidance,
+    config: &GuidedMatchingConfig,
+) -> Vec<Match> {
+    let points: Vec<(f32, f32)> = keypoints_1.iter().map(|k| k.point).collect();
+    let index = GridIndex::new(&points, f32::max(8f32 * config.max_pixel_distance, 16f32));
+    let mut candidates: Vec<Match> = vec![];
+    for (i, keypoint_0) in keypoints_0.iter().enumerate() {
+        let p_0 = Vector3::new(keypoint_0.point.0, keypoint_0.point.1, 1f32);
+        let nearby = match guidance {
+            Guidance::Epipolar(fund_mat) => {
+                let line_1 = fund_mat * p_0;
+                index
+                    .near_line((line_1[0], line_1[1], line_1[2]), config.max_pixel_distance)
+                    .into_iter()
+                    .filter(|&j| {
+                        let p_1 = Vector3::new(points[j].0, points[j].1, 1f32);
+                        let line_0 = fund_mat.transpose() * p_1;
+                        line_distance(&line_0, keypoint_0.point) <= config.max_pixel_distance
+                    })
+                    .collect()
+            }
+            Guidance::Homography(homography) => {
+                let p = homography * p_0;
+                if p[2].abs() < f32::EPSILON {
+                    continue;
+                }
+                index.within((p[0] / p[2], p[1] / p[2]), config.max_pixel_distance)
+            }
+        };
+        let mut best = (0, usize::MAX);
+        let mut second_distance = usize::MAX;
+        for j in nearby {
+            let distance = hamming_distance(&descriptors_0[i], &descriptors_1[j], second_distance);
+            if distance < best.1 {
+                second_distance = best.1;
+                best = (j, distance);
+            } else if distance < second_distance {
+                second_distance = distance;
+            }
+        }
+        if best.1 <= config.max_descriptor_distance
+            && (second_distance == usize::MAX
+                || (best.1 as f64) < config.lowes_ratio * second_distance as f64)
+        {
+            candidates.push(Match {
+                index_0: i,
+                index_1: best.0,
+                distance: best.1 as f64,
+            });
+        }
+    }
+    // Keep the closest match of each keypoint in the second image
+    candidates.sort_by(|a, b| {
+        a.distance
+            .partial_cmp(&b.distance)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then(a.index_0.cmp(&b.index_0))
+    });
+    let mut used = vec![false; keypoints_1.len()];
+    let mut matches: Vec<Match> = candidates
+        .into_iter()
+        .filter(|match_i| !std::mem::replace(&mut used[match_i.index_1], true))
+        .collect();
+    matches.sort_by_key(|match_i| match_i.index_0);
+    debug!("Guided matching found {} matches.", matches.len());
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{guided_match, Guidance, GuidedMatchingConfig};
+    use crate::ops::feature_matching::descriptor_match;
+    use crate::types::keypoint::{Descriptor, Keypoint};
+    use nalgebra::Matrix3;
+
+    fn keypoint(x: f32, y: f32) -> Keypoint {
+        Keypoint {
+            point: (x, y),
+            response: 1f32,
+            size: 4f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
+        }
+    }
+
+    #[test]
+    fn geometry_disambiguates_repeated_descriptors() {
+        // Two copies of each descriptor, as on a repetitive facade
+        let descriptors: Vec<Descriptor> = (0..20)
+            .map(|i| Descriptor {
+                vector: (0..8)
+                    .map(|b| {
+                        if b == (i / 2) % 8 {
+                            0xff
+                        } else {
+                            (i / 16) as u8
+                        }
+                    })
+                    .collect(),
+            })
+            .collect();
+        let keypoints_0: Vec<Keypoint> = (0..20)
+            .map(|i| {
+                keypoint(
+                    (i % 5) as f32 * 40f32 + 10f32,
+                    (i / 5) as f32 * 40f32 + 10f32,
+                )
+            })
+            .collect();
+        let keypoints_1: Vec<Keypoint> = keypoints_0
+            .iter()
+            .map(|k| keypoint(k.point.0 + 12f32, k.point.1 + 3f32))
+            .collect();
+        // The global ratio test rejects every match
+        assert!(descriptor_match(&descriptors, &descriptors, 10000, 0.8).is_empty());
+        let homography = Matrix3::new(1f32, 0f32, 12f32, 0f32, 1f32, 3f32, 0f32, 0f32, 1f32);
+        let config = GuidedMatchingConfig::default();
+        let matches = guided_match(
+            &keypoints_0,
+            &descriptors,
+            &keypoints_1,
+            &descriptors,
+            &Guidance::Homography(homography),
+            &config,
+        );
+        assert_eq!(matches.len(), 20);
+        assert!(matches.iter().all(|m| m.index_0 == m.index_1));
+        // A pure horizontal translation has horizontal epipolar lines
+        let keypoints_1: Vec<Keypoint> = keypoints_0
+            .iter()
+            .map(|k| keypoint(k.point.0 + 7f32, k.point.1))
+            .collect();
+        let fund_mat = Matrix3::new(0f32, 0f32, 0f32, 0f32, 0f32, -1f32, 0f32, 1f32, 0f32);
+        let matches = guided_match(
+            &keypoints_0,
+            &descriptors,
+            &keypoints_1,
+            &descriptors,
+            &Guidance::Epipolar(fund_mat),
+            &config,
+        );
+        // Along each row, the copies are still ambiguous, but others are not
+        assert!(!matches.is_empty());
+        assert!(matches.iter().all(|m| m.index_0 / 5 == m.index_1 / 5));
+    }
+}
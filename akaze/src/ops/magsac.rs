@@ -0,0 +1,428 @@
+use crate::ops::estimate_fundamental_matrix::{EpipolarError, FundamentalMatrixEstimator};
+use crate::ops::ransac::{
+    find_inliers, required_trials, Estimator, RansacResult, Sampler, Sampling, MAX_SAMPLE_ATTEMPTS,
+};
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use nalgebra::Matrix3;
+use random::{Source, Xorshift128Plus};
+use serde::{Deserialize, Serialize};
+
+/// The 0.99 quantile of the chi distribution used to truncate the
+/// residuals, following the MAGSAC++ paper.
+const SIGMA_QUANTILE: f64 = 3.64;
+
+/// The normalizing constant of the chi-squared density with four degrees
+/// of freedom, 1 / (2^(4/2) * Gamma(4/2)).
+const CHI_SQUARED_NORMALIZER: f64 = 0.25;
+
+/// Configuration of MAGSAC++.
+///
+/// Unlike `RansacConfig` there is no inlier threshold. Instead the noise
+/// level is marginalized over [0, `max_sigma`], so `max_sigma` only needs
+/// to be a loose upper bound on the noise.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct MagsacConfig {
+    /// Maximum number of trials.
+    pub max_trials: usize,
+    /// Minimum number of trials, even if the confidence is already reached.
+    pub min_trials: usize,
+    /// Desired probability of having drawn at least one outlier free sample.
+    pub confidence: f64,
+    /// Upper bound of the noise standard deviation, in units of the residual.
+    pub max_sigma: f32,
+    /// Seed for the random number generator.
+    pub seed: [u64; 2],
+    /// How minimal samples are drawn.
+    pub sampling: Sampling,
+    /// Maximum number of iteratively reweighted least squares steps
+    /// used to refine each new best model.
+    pub refinement_iterations: usize,
+}
+
+impl Default for MagsacConfig {
+    fn default() -> MagsacConfig {
+        MagsacConfig {
+            max_trials: 1000,
+            min_trials: 10,
+            confidence: 0.999,
+            max_sigma: 10f32,
+            seed: [42, 69],
+            sampling: Sampling::Uniform,
+            refinement_iterations: 10,
+        }
+    }
+}
+
+/// The complementary error function.
+///
+/// Chebyshev approximation from Numerical Recipes, with a fractional
+/// error below 1.2e-7 everywhere.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1f64 / (1f64 + 0.5f64 * z);
+    let r = t * f64::exp(
+        -z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))),
+    );
+    if x >= 0f64 {
+        r
+    } else {
+        2f64 - r
+    }
+}
+
+/// The upper incomplete gamma function Gamma(3/2, x).
+fn upper_incomplete_gamma_3_2(x: f64) -> f64 {
+    let sqrt_pi = std::f64::consts::PI.sqrt();
+    x.sqrt() * f64::exp(-x) + 0.5f64 * sqrt_pi * erfc(x.sqrt())
+}
+
+/// The lower incomplete gamma function gamma(5/2, x).
+fn lower_incomplete_gamma_5_2(x: f64) -> f64 {
+    let sqrt_pi = std::f64::consts::PI.sqrt();
+    let upper = 1.5f64 * upper_incomplete_gamma_3_2(x) + x.powf(1.5f64) * f64::exp(-x);
+    0.75f64 * sqrt_pi - upper
+}
+
+/// The MAGSAC++ weight and loss of residuals, marginalized over
+/// sigma in [0, max_sigma].
+struct MarginalizedLoss {
+    max_sigma: f64,
+    factor: f64,
+    gamma_k: f64,
+    max_residual: f64,
+    max_loss: f64,
+}
+
+impl MarginalizedLoss {
+    fn new(max_sigma: f32) -> MarginalizedLoss {
+        let max_sigma = f64::from(max_sigma);
+        let k_squared_half = SIGMA_QUANTILE * SIGMA_QUANTILE / 2f64;
+        let mut loss = MarginalizedLoss {
+            max_sigma,
+            factor: CHI_SQUARED_NORMALIZER * 2f64.powf(1.5f64) / max_sigma,
+            gamma_k: upper_incomplete_gamma_3_2(k_squared_half),
+            max_residual: SIGMA_QUANTILE * max_sigma,
+            max_loss: 0f64,
+        };
+        loss.max_loss = loss.loss(loss.max_residual);
+        loss
+    }
+
+    /// The IRLS weight of a residual, zero beyond the truncation.
+    fn weight(&self, residual: f64) -> f64 {
+        if residual >= self.max_residual {
+            return 0f64;
+        }
+        let x = residual * residual / (2f64 * self.max_sigma * self.max_sigma);
+        self.factor * (upper_incomplete_gamma_3_2(x) - self.gamma_k)
+    }
+
+    /// The loss of a residual, the integral of weight(r) * r. Constant
+    /// beyond the truncation, so outliers all cost the same.
+    fn loss(&self, residual: f64) -> f64 {
+        let residual = residual.min(self.max_residual);
+        let sigma_squared = self.max_sigma * self.max_sigma;
+        let x = residual * residual / (2f64 * sigma_squared);
+        self.factor
+            * sigma_squared
+            * (x * upper_incomplete_gamma_3_2(x) + lower_incomplete_gamma_5_2(x) - x * self.gamma_k)
+    }
+}
+
+/// The total marginalized loss of a model and its number of matches
+/// below the truncation threshold.
+fn score<E: Estimator>(
+    estimator: &E,
+    model: &E::Model,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    loss: &MarginalizedLoss,
+) -> (f64, usize) {
+    let mut total = 0f64;
+    let mut support = 0;
+    for match_i in matches {
+        let residual = f64::from(estimator.residual(
+            model,
+            &keypoints_0[match_i.index_0],
+            &keypoints_1[match_i.index_1],
+        ));
+        if residual < loss.max_residual {
+            total += loss.loss(residual);
+            support += 1;
+        } else {
+            total += loss.max_loss;
+        }
+    }
+    (total, support)
+}
+
+/// Refine a model with sigma-consensus++, iteratively reweighted least
+/// squares using the marginalized weights. Stops as soon as the loss
+/// no longer decreases.
+fn sigma_consensus<E: Estimator>(
+    estimator: &E,
+    model: (E::Model, (f64, usize)),
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    loss: &MarginalizedLoss,
+    iterations: usize,
+) -> (E::Model, (f64, usize)) {
+    let mut best = model;
+    for _ in 0..iterations {
+        let weights: Vec<f64> = matches
+            .iter()
+            .map(|match_i| {
+                loss.weight(f64::from(estimator.residual(
+                    &best.0,
+                    &keypoints_0[match_i.index_0],
+                    &keypoints_1[match_i.index_1],
+                )))
+            })
+            .collect();
+        let refit = match estimator.estimate_weighted(keypoints_0, keypoints_1, matches, &weights) {
+            Some(refit) => refit,
+            None => break,
+        };
+        let refit_score = score(estimator, &refit, keypoints_0, keypoints_1, matches, loss);
+        if refit_score.0 < (best.1).0 {
+            best = (refit, refit_score);
+        } else {
+            break;
+        }
+    }
+    best
+}
+
+/// Robustly estimate a model with MAGSAC++.
+///
+/// Hypotheses are generated from minimal samples as in `ransac`, but they
+/// are scored by a loss marginalized over the noise level instead of by
+/// counting inliers below a fixed threshold. Every new best model is
+/// refined by sigma-consensus++.
+///
+/// # Arguments
+/// * `estimator` - The model to fit.
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `config` - MAGSAC++ parameters
+/// * `source` - Random number source used to draw samples.
+/// # Return value
+/// Optionally, the best model and the matches with a residual below the
+/// truncation threshold of 3.64 * `max_sigma`. None if no model was found.
+pub fn magsac<E: Estimator, S: Source>(
+    estimator: &E,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    config: &MagsacConfig,
+    source: &mut S,
+) -> Option<RansacResult<E::Model>> {
+    let sample_size = estimator.min_sample_size();
+    if matches.len() < sample_size || sample_size == 0 {
+        warn!("Not enough points to do MAGSAC++.");
+        return None;
+    }
+    let loss = MarginalizedLoss::new(config.max_sigma);
+    let mut best: Option<(E::Model, (f64, usize))> = None;
+    let mut trial_limit = config.max_trials;
+    let mut trials = 0;
+    let mut sampler = Sampler::new(config.sampling, matches, sample_size, config.max_trials);
+    while trials < trial_limit {
+        trials += 1;
+        sampler.next_trial();
+        let sample = match (0..MAX_SAMPLE_ATTEMPTS)
+            .map(|_| sampler.draw(source, matches))
+            .find(|sample| !estimator.is_degenerate(keypoints_0, keypoints_1, sample))
+        {
+            Some(sample) => sample,
+            None => continue,
+        };
+        let model = match estimator.estimate(keypoints_0, keypoints_1, &sample) {
+            Some(model) => model,
+            None => continue,
+        };
+        let model_score = score(estimator, &model, keypoints_0, keypoints_1, matches, &loss);
+        let improved = match best {
+            Some((_, best_score)) => model_score.0 < best_score.0,
+            None => true,
+        };
+        if improved {
+            let refined = sigma_consensus(
+                estimator,
+                (model, model_score),
+                keypoints_0,
+                keypoints_1,
+                matches,
+                &loss,
+                config.refinement_iterations,
+            );
+            let inlier_ratio = (refined.1).1 as f64 / matches.len() as f64;
+            trial_limit = required_trials(inlier_ratio, sample_size, config.confidence)
+                .max(config.min_trials)
+                .min(config.max_trials);
+            best = Some(refined);
+        }
+    }
+    best.map(|(model, (total_loss, support))| {
+        debug!(
+            "MAGSAC++ ran {} trials, best model has loss {} and {}/{} inliers.",
+            trials,
+            total_loss,
+            support,
+            matches.len()
+        );
+        RansacResult {
+            model,
+            inliers: find_inliers(
+                estimator,
+                &model,
+                keypoints_0,
+                keypoints_1,
+                matches,
+                loss.max_residual as f32,
+            ),
+            trials,
+        }
+    })
+}
+
+/// Remove outliers with MAGSAC++ and a fundamental matrix model.
+///
+/// This is a drop-in alternative to `remove_outliers` that needs no
+/// inlier threshold. Residuals are Sampson distances in pixels, and
+/// `max_sigma` is a loose upper bound on the keypoint noise.
+///
+/// # Arguments
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `num_trials` - Maximum number of MAGSAC++ iterations
+/// * `epsilon_model` - The epsilon used when solving SVD
+/// * `max_sigma` - Upper bound of the noise standard deviation in pixels.
+///
+/// # Return value
+/// The inlier matches. If no model was found, the size
+/// of the vector will be 0.
+pub fn remove_outliers_magsac(
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    num_trials: usize,
+    epsilon_model: f32,
+    max_sigma: f32,
+) -> Vec<Match> {
+    if matches.len() < 8 {
+        warn!("Not enough points to do MAGSAC++.");
+        return matches.to_vec();
+    } else {
+        debug!("Removing outliers with MAGSAC++.");
+    }
+    let config = MagsacConfig {
+        max_trials: num_trials,
+        max_sigma,
+        ..MagsacConfig::default()
+    };
+    let mut source = Xorshift128Plus::new(config.seed);
+    let result: Option<RansacResult<Matrix3<f32>>> = magsac(
+        &FundamentalMatrixEstimator {
+            epsilon_model,
+            error: EpipolarError::Sampson,
+        },
+        keypoints_0,
+        keypoints_1,
+        matches,
+        &config,
+        &mut source,
+    );
+    match result {
+        Some(result) => result.inliers,
+        None => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{erfc, magsac, MagsacConfig, MarginalizedLoss};
+    use crate::ops::estimate_affine_transform::AffineEstimator;
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use random::Xorshift128Plus;
+
+    #[test]
+    fn marginalized_loss_is_monotonic_and_truncated() {
+        assert!((erfc(0f64) - 1f64).abs() < 1e-6);
+        assert!((erfc(1f64) - 0.157_299_207).abs() < 1e-6);
+        let loss = MarginalizedLoss::new(2f32);
+        assert!(loss.loss(0f64).abs() < 1e-6);
+        let mut previous = loss.loss(0f64);
+        for i in 1..=20 {
+            let current = loss.loss(f64::from(i) * 0.5f64);
+            assert!(current >= previous);
+            previous = current;
+        }
+        assert!((loss.loss(100f64) - loss.max_loss).abs() < 1e-12);
+        assert!(loss.weight(0f64) > loss.weight(3f64));
+        assert_eq!(loss.weight(loss.max_residual), 0f64);
+    }
+
+    #[test]
+    fn magsac_finds_noisy_affine_inliers() {
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..80 {
+            let (x, y) = ((i % 10) as f32 * 30f32, (i / 10) as f32 * 40f32);
+            // Deterministic noise of up to half a pixel
+            let noise = ((i * 7) % 11) as f32 / 10f32 - 0.5f32;
+            let offset = if i % 4 == 0 { 60f32 } else { 0f32 };
+            let keypoint = |point| Keypoint {
+                point,
+                response: 1f32,
+                size: 4f32,
+                octave: 0,
+                class_id: 0,
+                angle: 0f32,
+            };
+            keypoints_0.push(keypoint((x, y)));
+            keypoints_1.push(keypoint((
+                0.9f32 * x + 0.1f32 * y + 5f32 + noise,
+                -0.2f32 * x + y - 3f32 - noise + offset,
+            )));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            });
+        }
+        let config = MagsacConfig {
+            max_sigma: 3f32,
+            ..MagsacConfig::default()
+        };
+        let mut source = Xorshift128Plus::new(config.seed);
+        let result = magsac(
+            &AffineEstimator,
+            &keypoints_0,
+            &keypoints_1,
+            &matches,
+            &config,
+            &mut source,
+        )
+        .unwrap();
+        assert_eq!(result.inliers.len(), 60);
+        assert!(result.inliers.iter().all(|m| m.index_0 % 4 != 0));
+        assert!((result.model[(0, 0)] - 0.9f32).abs() < 1e-2);
+        assert!(result.trials < config.max_trials);
+    }
+}
@@ -1,9 +1,19 @@
+pub mod bundle_adjustment;
 pub mod contrast_factor;
 pub mod derivatives;
 pub mod descriptors;
 pub mod detector_response;
+pub mod estimate_affine_transform;
 pub mod estimate_fundamental_matrix;
+pub mod estimate_homography;
+pub mod estimate_similarity_transform;
 pub mod feature_matching;
 pub mod fed_tau;
+pub mod frame_tracking;
+pub mod guided_matching;
+pub mod magsac;
 pub mod nonlinear_diffusion;
+pub mod ransac;
+pub mod rectification;
 pub mod scale_space_extrema;
+pub mod triangulation;
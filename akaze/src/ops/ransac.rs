@@ -0,0 +1,832 @@
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use random::Source;
+use scoped_threadpool::Pool;
+use serde::{Deserialize, Serialize};
+
+/// The number of times a degenerate minimal sample is redrawn before
+/// the trial is abandoned.
+pub(crate) const MAX_SAMPLE_ATTEMPTS: usize = 100;
+
+/// The number of hypotheses each thread evaluates per batch when
+/// RANSAC runs in parallel.
+const TRIALS_PER_THREAD: usize = 8;
+
+/// How minimal samples are drawn.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Sampling {
+    /// Draw uniformly from all matches.
+    Uniform,
+    /// PROSAC: draw from a progressively growing set of the best matches,
+    /// ranked by `Match::distance`. Converges much faster than uniform
+    /// sampling when descriptor distance predicts inliers.
+    Prosac,
+}
+
+/// Options controlling RANSAC.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct RansacConfig {
+    /// Maximum number of RANSAC iterations
+    pub max_trials: usize,
+
+    /// Minimum number of iterations to run before adaptive termination
+    /// is allowed to stop early
+    pub min_trials: usize,
+
+    /// Desired probability (0-1) that at least one sample was free of
+    /// outliers. Set to 1 to disable adaptive termination.
+    pub confidence: f64,
+
+    /// Maximum error to accept an inlier
+    pub epsilon_inlier: f32,
+
+    /// Seed for the random number generator. At least one bit must be set.
+    pub seed: [u64; 2],
+
+    /// How minimal samples are drawn
+    pub sampling: Sampling,
+
+    /// Whether to re-estimate each new best model from its inliers
+    /// (LO-RANSAC). Requires `Estimator::estimate_from_inliers`.
+    pub local_optimization: bool,
+
+    /// Maximum number of re-estimation rounds per local optimization
+    pub local_optimization_iterations: usize,
+
+    /// Number of threads used to estimate and score hypotheses. The
+    /// result is the same for any number of threads.
+    pub num_threads: usize,
+}
+
+impl Default for RansacConfig {
+    fn default() -> RansacConfig {
+        RansacConfig {
+            max_trials: 1000,
+            min_trials: 10,
+            confidence: 0.999f64,
+            epsilon_inlier: 3.0f32,
+            seed: [42, 69],
+            sampling: Sampling::Uniform,
+            local_optimization: false,
+            local_optimization_iterations: 4,
+            num_threads: 1,
+        }
+    }
+}
+
+/// A model which can be fit to matches with RANSAC.
+pub trait Estimator: Sync {
+    /// The estimated model, e.g. a 3x3 matrix.
+    type Model: Copy + Send;
+
+    /// The number of matches in a minimal sample.
+    fn min_sample_size(&self) -> usize;
+
+    /// Estimate a model from a minimal sample.
+    ///
+    /// # Arguments
+    /// * `keypoints_0` - Keypoints in set 0
+    /// * `keypoints_1` - Keypoints in set 1
+    /// * `sample` - `min_sample_size()` matches.
+    /// # Return value
+    /// Optionally, the model. None if no model could be computed.
+    fn estimate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> Option<Self::Model>;
+
+    /// Estimate a model from more than a minimal sample, e.g. by least
+    /// squares. Used for local optimization. The default returns None,
+    /// which disables local optimization for the model.
+    ///
+    /// # Arguments
+    /// * `keypoints_0` - Keypoints in set 0
+    /// * `keypoints_1` - Keypoints in set 1
+    /// * `inliers` - At least `min_sample_size()` matches.
+    /// # Return value
+    /// Optionally, the model. None if no model could be computed.
+    fn estimate_from_inliers(
+        &self,
+        _keypoints_0: &[Keypoint],
+        _keypoints_1: &[Keypoint],
+        _inliers: &[Match],
+    ) -> Option<Self::Model> {
+        None
+    }
+
+    /// Estimate a model by weighted least squares, used by MAGSAC++
+    /// refinement. The default fits the matches with a positive weight
+    /// using `estimate_from_inliers`.
+    ///
+    /// # Arguments
+    /// * `keypoints_0` - Keypoints in set 0
+    /// * `keypoints_1` - Keypoints in set 1
+    /// * `matches` - Candidate matches.
+    /// * `weights` - A non-negative weight for each match.
+    /// # Return value
+    /// Optionally, the model. None if no model could be computed.
+    fn estimate_weighted(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        matches: &[Match],
+        weights: &[f64],
+    ) -> Option<Self::Model> {
+        let weighted: Vec<Match> = matches
+            .iter()
+            .zip(weights.iter())
+            .filter(|(_, &weight)| weight > 0f64)
+            .map(|(match_i, _)| *match_i)
+            .collect();
+        self.estimate_from_inliers(keypoints_0, keypoints_1, &weighted)
+    }
+
+    /// The error of a correspondence under a model.
+    ///
+    /// # Arguments
+    /// * `model` - The model.
+    /// * `keypoint_0` - The keypoint in image 0.
+    /// * `keypoint_1` - The keypoint in image 1.
+    /// # Return value
+    /// The error, compared against `RansacConfig::epsilon_inlier`.
+    fn residual(&self, model: &Self::Model, keypoint_0: &Keypoint, keypoint_1: &Keypoint) -> f32;
+
+    /// Whether a minimal sample is degenerate and should be redrawn.
+    ///
+    /// The default rejects samples which use the same keypoint twice
+    /// or contain coincident points in either image.
+    fn is_degenerate(
+        &self,
+        keypoints_0: &[Keypoint],
+        keypoints_1: &[Keypoint],
+        sample: &[Match],
+    ) -> bool {
+        for (i, match_i) in sample.iter().enumerate() {
+            for match_j in &sample[i + 1..] {
+                if match_i.index_0 == match_j.index_0
+                    || match_i.index_1 == match_j.index_1
+                    || keypoints_0[match_i.index_0].point == keypoints_0[match_j.index_0].point
+                    || keypoints_1[match_i.index_1].point == keypoints_1[match_j.index_1].point
+                {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+/// The result of a successful RANSAC run.
+#[derive(Debug, Clone)]
+pub struct RansacResult<M> {
+    /// The best model found.
+    pub model: M,
+    /// The matches consistent with the model.
+    pub inliers: Vec<Match>,
+    /// The number of trials that were run.
+    pub trials: usize,
+}
+
+/// The number of trials needed to draw an outlier-free sample with the
+/// desired confidence, given the current inlier ratio.
+///
+/// # Arguments
+/// * `inlier_ratio` - The fraction of matches which are inliers.
+/// * `sample_size` - The size of a minimal sample.
+/// * `confidence` - The desired probability of success.
+/// # Return value
+/// The required number of trials, saturating at `usize::MAX`.
+pub fn required_trials(inlier_ratio: f64, sample_size: usize, confidence: f64) -> usize {
+    if confidence >= 1f64 {
+        return usize::MAX;
+    }
+    let outlier_free = inlier_ratio.powi(sample_size as i32);
+    if outlier_free >= 1f64 {
+        return 0;
+    }
+    if outlier_free <= 0f64 {
+        return usize::MAX;
+    }
+    let trials = f64::ln(1f64 - confidence) / f64::ln(1f64 - outlier_free);
+    if trials >= usize::MAX as f64 {
+        usize::MAX
+    } else {
+        f64::ceil(trials) as usize
+    }
+}
+
+/// Draw a minimal sample of distinct matches.
+///
+/// Indices are drawn without replacement and kept in draw order, so
+/// the sample depends only on the state of `source`.
+pub(crate) fn draw_sample<S: Source, T: Copy>(
+    source: &mut S,
+    matches: &[T],
+    sample_size: usize,
+) -> Vec<T> {
+    let mut indices: Vec<usize> = Vec::with_capacity(sample_size);
+    while indices.len() < sample_size {
+        let index = source.read::<usize>() % matches.len();
+        if !indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+    indices.into_iter().map(|i| matches[i]).collect()
+}
+
+/// Draws minimal samples according to `Sampling`.
+pub(crate) struct Sampler {
+    sampling: Sampling,
+    sample_size: usize,
+    /// Match indices sorted from best to worst descriptor distance
+    order: Vec<usize>,
+    /// PROSAC: the size of the current sampling pool
+    pool_size: usize,
+    /// PROSAC: the expected number of samples drawn from the pool
+    /// before it grows, in the continuous approximation
+    pool_trials: f64,
+    /// PROSAC: the trial at which the pool grows next
+    pool_growth_trial: usize,
+    /// The number of trials so far
+    trial: usize,
+}
+
+impl Sampler {
+    pub(crate) fn new(
+        sampling: Sampling,
+        matches: &[Match],
+        sample_size: usize,
+        max_trials: usize,
+    ) -> Sampler {
+        let mut order: Vec<usize> = (0..matches.len()).collect();
+        if sampling == Sampling::Prosac {
+            order.sort_by(|&a, &b| {
+                matches[a]
+                    .distance
+                    .partial_cmp(&matches[b].distance)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        let count = matches.len();
+        // Expected number of samples drawn only from the first
+        // `sample_size` matches, out of `max_trials` uniform samples
+        let mut pool_trials = max_trials as f64;
+        for i in 0..sample_size {
+            pool_trials *= (sample_size - i) as f64 / (count - i) as f64;
+        }
+        Sampler {
+            sampling,
+            sample_size,
+            order,
+            pool_size: sample_size,
+            pool_trials,
+            pool_growth_trial: 1,
+            trial: 0,
+        }
+    }
+
+    /// Begin a new trial. Grows the PROSAC sampling pool when due.
+    pub(crate) fn next_trial(&mut self) {
+        self.trial += 1;
+        if self.sampling == Sampling::Prosac
+            && self.trial >= self.pool_growth_trial
+            && self.pool_size < self.order.len()
+        {
+            let next_pool_trials = self.pool_trials * (self.pool_size + 1) as f64
+                / (self.pool_size + 1 - self.sample_size) as f64;
+            self.pool_size += 1;
+            self.pool_growth_trial += f64::ceil(next_pool_trials - self.pool_trials) as usize;
+            self.pool_trials = next_pool_trials;
+        }
+    }
+
+    /// Draw a sample for the current trial.
+    pub(crate) fn draw<S: Source>(&self, source: &mut S, matches: &[Match]) -> Vec<Match> {
+        match self.sampling {
+            Sampling::Uniform => draw_sample(source, matches, self.sample_size),
+            Sampling::Prosac => {
+                let pool = &self.order[..self.pool_size];
+                if self.pool_growth_trial < self.trial || self.pool_size == self.sample_size {
+                    // Draw everything from the pool
+                    draw_sample(source, pool, self.sample_size)
+                        .into_iter()
+                        .map(|i| matches[i])
+                        .collect()
+                } else {
+                    // Always include the newest match in the pool
+                    let mut sample: Vec<Match> =
+                        draw_sample(source, &pool[..self.pool_size - 1], self.sample_size - 1)
+                            .into_iter()
+                            .map(|i| matches[i])
+                            .collect();
+                    sample.push(matches[pool[self.pool_size - 1]]);
+                    sample
+                }
+            }
+        }
+    }
+}
+
+/// Iteratively re-estimate a model from its inliers while the inlier
+/// count improves.
+///
+/// # Return value
+/// The improved model and its inlier count, or None if no improvement
+/// was found.
+pub(crate) fn local_optimization<E: Estimator>(
+    estimator: &E,
+    model: &E::Model,
+    inlier_count: usize,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    config: &RansacConfig,
+) -> Option<(E::Model, usize)> {
+    let mut best: Option<(E::Model, usize)> = None;
+    let mut current = (*model, inlier_count);
+    for _ in 0..config.local_optimization_iterations {
+        let inliers = find_inliers(
+            estimator,
+            &current.0,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config.epsilon_inlier,
+        );
+        if inliers.len() <= estimator.min_sample_size() {
+            break;
+        }
+        let refit = match estimator.estimate_from_inliers(keypoints_0, keypoints_1, &inliers) {
+            Some(refit) => refit,
+            None => break,
+        };
+        let refit_count = count_inliers(
+            estimator,
+            &refit,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config.epsilon_inlier,
+        );
+        if refit_count <= current.1 {
+            break;
+        }
+        current = (refit, refit_count);
+        best = Some(current);
+    }
+    best
+}
+
+/// Count the matches whose residual under `model` is below `epsilon_inlier`.
+pub(crate) fn count_inliers<E: Estimator>(
+    estimator: &E,
+    model: &E::Model,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    epsilon_inlier: f32,
+) -> usize {
+    matches
+        .iter()
+        .filter(|match_i| {
+            estimator.residual(
+                model,
+                &keypoints_0[match_i.index_0],
+                &keypoints_1[match_i.index_1],
+            ) < epsilon_inlier
+        })
+        .count()
+}
+
+/// Count the inliers of `model` like `count_inliers`, but stop as soon as
+/// the count can no longer exceed `bound`.
+///
+/// # Return value
+/// The inlier count if it is greater than `bound`, otherwise None.
+pub(crate) fn count_inliers_above<E: Estimator>(
+    estimator: &E,
+    model: &E::Model,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    epsilon_inlier: f32,
+    bound: usize,
+) -> Option<usize> {
+    let mut count = 0;
+    for (i, match_i) in matches.iter().enumerate() {
+        if estimator.residual(
+            model,
+            &keypoints_0[match_i.index_0],
+            &keypoints_1[match_i.index_1],
+        ) < epsilon_inlier
+        {
+            count += 1;
+        } else if count + (matches.len() - i - 1) <= bound {
+            return None;
+        }
+    }
+    if count > bound {
+        Some(count)
+    } else {
+        None
+    }
+}
+
+/// A model and its inlier count, or None if the trial gave no model
+/// which could beat the best so far.
+type Hypothesis<M> = Option<(M, usize)>;
+
+/// Estimate a model from each sample and count its inliers. Hypotheses
+/// that cannot have more than `bound` inliers are dropped early.
+fn evaluate_hypotheses<E: Estimator>(
+    estimator: &E,
+    samples: &[Option<Vec<Match>>],
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    epsilon_inlier: f32,
+    bound: usize,
+) -> Vec<Hypothesis<E::Model>> {
+    samples
+        .iter()
+        .map(|sample| {
+            sample
+                .as_ref()
+                .and_then(|sample| estimator.estimate(keypoints_0, keypoints_1, sample))
+                .and_then(|model| {
+                    count_inliers_above(
+                        estimator,
+                        &model,
+                        keypoints_0,
+                        keypoints_1,
+                        matches,
+                        epsilon_inlier,
+                        bound,
+                    )
+                    .map(|count| (model, count))
+                })
+        })
+        .collect()
+}
+
+/// Collect the matches whose residual under `model` is below `epsilon_inlier`.
+pub fn find_inliers<E: Estimator>(
+    estimator: &E,
+    model: &E::Model,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    epsilon_inlier: f32,
+) -> Vec<Match> {
+    matches
+        .iter()
+        .filter(|match_i| {
+            estimator.residual(
+                model,
+                &keypoints_0[match_i.index_0],
+                &keypoints_1[match_i.index_1],
+            ) < epsilon_inlier
+        })
+        .cloned()
+        .collect()
+}
+
+/// Fit a model to matches with RANSAC.
+///
+/// Sampling is driven entirely by `source`, so two runs with sources in the
+/// same state give the same result. Trials stop early once the number of
+/// trials required to reach `config.confidence` at the current inlier ratio
+/// has been run, but never before `config.min_trials` or after
+/// `config.max_trials`. Degenerate samples are redrawn.
+///
+/// With `Sampling::Prosac`, samples are drawn from the matches with the
+/// lowest `Match::distance` first. With `config.local_optimization`, each
+/// new best model is re-estimated from its inliers.
+///
+/// With `config.num_threads` above one, hypotheses are estimated and scored
+/// in parallel batches. Samples are still drawn serially and the batch is
+/// reduced in trial order, so the result is identical to a serial run.
+///
+/// # Arguments
+/// * `estimator` - The model to fit.
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `config` - The RANSAC options.
+/// * `source` - The random number source used for sampling.
+///
+/// # Return value
+/// The best model and its inliers, or None if there were not enough
+/// matches or no model could be estimated.
+pub fn ransac<E: Estimator, S: Source>(
+    estimator: &E,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    config: &RansacConfig,
+    source: &mut S,
+) -> Option<RansacResult<E::Model>> {
+    let mut pool = if config.num_threads > 1 {
+        Some(Pool::new(config.num_threads as u32))
+    } else {
+        None
+    };
+    ransac_with_pool(
+        estimator,
+        keypoints_0,
+        keypoints_1,
+        matches,
+        config,
+        source,
+        pool.as_mut(),
+    )
+}
+
+/// Fit a model with RANSAC as `ransac` does, evaluating hypotheses in a
+/// thread pool of the caller. Reusing one pool avoids spawning threads for
+/// each of many image pairs.
+///
+/// # Arguments
+/// * `estimator` - The model to fit.
+/// * `keypoints_0` - First set of keypoints
+/// * `keypoints_1` - Second set of keypoints
+/// * `matches` - Candidate matches
+/// * `config` - The RANSAC options. `num_threads` is ignored.
+/// * `source` - The random number source used for sampling.
+/// * `pool` - The threads evaluating hypotheses, or None to evaluate them
+///   on the calling thread.
+///
+/// # Return value
+/// The best model and its inliers, or None if there were not enough
+/// matches or no model could be estimated.
+pub fn ransac_with_pool<E: Estimator, S: Source>(
+    estimator: &E,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    config: &RansacConfig,
+    source: &mut S,
+    mut pool: Option<&mut Pool>,
+) -> Option<RansacResult<E::Model>> {
+    let sample_size = estimator.min_sample_size();
+    if matches.len() < sample_size || sample_size == 0 {
+        warn!("Not enough points to do RANSAC.");
+        return None;
+    }
+    let mut max_inlier_count = 0;
+    let mut best_model: Option<E::Model> = None;
+    let mut trial_limit = config.max_trials;
+    let mut trials = 0;
+    let mut sampler = Sampler::new(config.sampling, matches, sample_size, config.max_trials);
+    let num_threads = match pool {
+        Some(ref pool) => pool.thread_count().max(1) as usize,
+        None => 1,
+    };
+    let batch_size = if num_threads > 1 {
+        num_threads * TRIALS_PER_THREAD
+    } else {
+        1
+    };
+    while trials < trial_limit {
+        // Samples are always drawn serially and in trial order, so the
+        // random number source is consumed exactly as in a serial run.
+        let samples: Vec<Option<Vec<Match>>> = (0..batch_size.min(trial_limit - trials))
+            .map(|_| {
+                sampler.next_trial();
+                (0..MAX_SAMPLE_ATTEMPTS)
+                    .map(|_| sampler.draw(source, matches))
+                    .find(|sample| !estimator.is_degenerate(keypoints_0, keypoints_1, sample))
+            })
+            .collect();
+        // The best count before the batch is a lower bound of the best
+        // count at any trial in the batch, so early exit never drops a
+        // hypothesis that the serial run would have kept.
+        let bound = max_inlier_count;
+        let hypotheses: Vec<Hypothesis<E::Model>> = match pool {
+            Some(ref mut pool) if num_threads > 1 => {
+                let chunk_size = (samples.len() + num_threads - 1) / num_threads;
+                let mut chunks: Vec<Vec<Hypothesis<E::Model>>> =
+                    vec![vec![]; (samples.len() + chunk_size - 1) / chunk_size];
+                pool.scoped(|scoped| {
+                    for (samples, chunk) in samples.chunks(chunk_size).zip(chunks.iter_mut()) {
+                        scoped.execute(move || {
+                            *chunk = evaluate_hypotheses(
+                                estimator,
+                                samples,
+                                keypoints_0,
+                                keypoints_1,
+                                matches,
+                                config.epsilon_inlier,
+                                bound,
+                            );
+                        });
+                    }
+                });
+                chunks.into_iter().flatten().collect()
+            }
+            _ => evaluate_hypotheses(
+                estimator,
+                &samples,
+                keypoints_0,
+                keypoints_1,
+                matches,
+                config.epsilon_inlier,
+                bound,
+            ),
+        };
+        // Reduce in trial order, exactly as the serial loop would
+        for hypothesis in hypotheses {
+            if trials >= trial_limit {
+                break;
+            }
+            trials += 1;
+            let (model, inlier_count) = match hypothesis {
+                Some(hypothesis) => hypothesis,
+                None => continue,
+            };
+            if inlier_count > max_inlier_count {
+                max_inlier_count = inlier_count;
+                best_model = Some(model);
+                if config.local_optimization {
+                    if let Some((refit, refit_count)) = local_optimization(
+                        estimator,
+                        &model,
+                        inlier_count,
+                        keypoints_0,
+                        keypoints_1,
+                        matches,
+                        config,
+                    ) {
+                        max_inlier_count = refit_count;
+                        best_model = Some(refit);
+                    }
+                }
+                let inlier_ratio = max_inlier_count as f64 / matches.len() as f64;
+                trial_limit = required_trials(inlier_ratio, sample_size, config.confidence)
+                    .max(config.min_trials)
+                    .min(config.max_trials);
+            }
+        }
+    }
+    debug!(
+        "RANSAC ran {} trials, best model has {}/{} inliers.",
+        trials,
+        max_inlier_count,
+        matches.len()
+    );
+    best_model.map(|model| RansacResult {
+        model,
+        inliers: find_inliers(
+            estimator,
+            &model,
+            keypoints_0,
+            keypoints_1,
+            matches,
+            config.epsilon_inlier,
+        ),
+        trials,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ransac, ransac_with_pool, required_trials, RansacConfig};
+    use crate::ops::estimate_affine_transform::AffineEstimator;
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use random::Xorshift128Plus;
+    use scoped_threadpool::Pool;
+
+    fn line_keypoint(x: f32, y: f32) -> Keypoint {
+        Keypoint {
+            point: (x, y),
+            response: 1f32,
+            size: 1f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
+        }
+    }
+
+    /// Translated grid points, with every fourth match corrupted.
+    fn translated_grid() -> (Vec<Keypoint>, Vec<Keypoint>, Vec<Match>) {
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..100 {
+            let (x, y) = ((i % 10) as f32 * 20f32, (i / 10) as f32 * 30f32);
+            let offset = if i % 4 == 0 { 50f32 } else { 0f32 };
+            keypoints_0.push(line_keypoint(x, y));
+            keypoints_1.push(line_keypoint(x + 5f32 + offset, y - 2f32));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            });
+        }
+        (keypoints_0, keypoints_1, matches)
+    }
+
+    #[test]
+    fn required_trials_known_values() {
+        // 50% inliers, 8 point samples, 99% confidence
+        assert_eq!(required_trials(0.5, 8, 0.99), 1177);
+        assert_eq!(required_trials(1.0, 3, 0.99), 0);
+        assert_eq!(required_trials(0.0, 3, 0.99), usize::MAX);
+    }
+
+    #[test]
+    fn seeded_ransac_is_reproducible_and_stops_early() {
+        let (keypoints_0, keypoints_1, matches) = translated_grid();
+        let config = RansacConfig {
+            epsilon_inlier: 1f32,
+            ..RansacConfig::default()
+        };
+        let run = || {
+            let mut source = Xorshift128Plus::new(config.seed);
+            ransac(
+                &AffineEstimator,
+                &keypoints_0,
+                &keypoints_1,
+                &matches,
+                &config,
+                &mut source,
+            )
+            .unwrap()
+        };
+        let first = run();
+        let second = run();
+        assert_eq!(first.inliers.len(), 75);
+        assert_eq!(first.trials, second.trials);
+        assert_eq!(first.model, second.model);
+        assert!(first.trials < config.max_trials);
+    }
+
+    #[test]
+    fn parallel_ransac_matches_serial() {
+        let (keypoints_0, keypoints_1, matches) = translated_grid();
+        let run = |num_threads| {
+            let config = RansacConfig {
+                epsilon_inlier: 1f32,
+                local_optimization: true,
+                num_threads,
+                ..RansacConfig::default()
+            };
+            let mut source = Xorshift128Plus::new(config.seed);
+            ransac(
+                &AffineEstimator,
+                &keypoints_0,
+                &keypoints_1,
+                &matches,
+                &config,
+                &mut source,
+            )
+            .unwrap()
+        };
+        let serial = run(1);
+        for &num_threads in &[2, 3, 8] {
+            let parallel = run(num_threads);
+            assert_eq!(parallel.trials, serial.trials);
+            assert_eq!(parallel.model, serial.model);
+            assert_eq!(parallel.inliers.len(), serial.inliers.len());
+        }
+    }
+
+    #[test]
+    fn shared_pool_matches_serial() {
+        let (keypoints_0, keypoints_1, matches) = translated_grid();
+        let config = RansacConfig {
+            epsilon_inlier: 1f32,
+            ..RansacConfig::default()
+        };
+        let mut source = Xorshift128Plus::new(config.seed);
+        let serial = ransac(
+            &AffineEstimator,
+            &keypoints_0,
+            &keypoints_1,
+            &matches,
+            &config,
+            &mut source,
+        )
+        .unwrap();
+        let mut pool = Pool::new(4);
+        // One pool serves many calls
+        for _ in 0..3 {
+            let mut source = Xorshift128Plus::new(config.seed);
+            let pooled = ransac_with_pool(
+                &AffineEstimator,
+                &keypoints_0,
+                &keypoints_1,
+                &matches,
+                &config,
+                &mut source,
+                Some(&mut pool),
+            )
+            .unwrap();
+            assert_eq!(pooled.trials, serial.trials);
+            assert_eq!(pooled.model, serial.model);
+        }
+    }
+}
@@ -0,0 +1,245 @@
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use nalgebra::{Matrix3, Vector3, SVD};
+
+/// The null vector of a rank 2 matrix.
+fn null_vector(matrix: Matrix3<f64>) -> Option<Vector3<f64>> {
+    let svd = SVD::new(matrix, false, true);
+    let v_t = svd.v_t?;
+    let mut min_i = 0;
+    for i in 1..3 {
+        if svd.singular_values[i] < svd.singular_values[min_i] {
+            min_i = i;
+        }
+    }
+    Some(Vector3::new(
+        v_t[(min_i, 0)],
+        v_t[(min_i, 1)],
+        v_t[(min_i, 2)],
+    ))
+}
+
+fn apply(homography: &Matrix3<f64>, point: (f32, f32)) -> Vector3<f64> {
+    let p = homography * Vector3::new(f64::from(point.0), f64::from(point.1), 1f64);
+    p / p[2]
+}
+
+/// Compute rectifying homographies for an uncalibrated stereo pair with
+/// Hartley's method.
+///
+/// The second image is transformed so that its epipole moves to infinity
+/// along the x axis, with little distortion around the image center. The
+/// homography of the first image is the one that maps the epipolar lines
+/// onto the same rows, and minimizes the horizontal disparity of the
+/// matches. After rectification, matching points lie on the same row,
+/// which is what dense stereo matchers expect.
+///
+/// # Arguments
+/// * `fund_mat` - The fundamental matrix with p1^T F p0 = 0, e.g. from
+///   `ops::ransac::ransac` with a `FundamentalMatrixEstimator`.
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Inlier matches of the fundamental matrix, at least three.
+/// * `image_size` - The width and height of the images.
+/// # Return value
+/// Optionally, the homographies of the first and second image, mapping
+/// pixel coordinates to rectified coordinates. None if the epipole of the
+/// second image lies inside the image, e.g. for a camera moving forward, as
+/// the image would be torn apart, or if the problem is degenerate.
+pub fn rectify_uncalibrated(
+    fund_mat: &Matrix3<f32>,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    image_size: (u32, u32),
+) -> Option<(Matrix3<f64>, Matrix3<f64>)> {
+    if matches.len() < 3 {
+        return None;
+    }
+    let fund_mat = fund_mat.map(f64::from);
+    let epipole_1 = null_vector(fund_mat.transpose())?;
+    // Move the image center to the origin
+    let center = (
+        f64::from(image_size.0.max(1) - 1) / 2f64,
+        f64::from(image_size.1.max(1) - 1) / 2f64,
+    );
+    let translation = Matrix3::new(
+        1f64, 0f64, -center.0, 0f64, 1f64, -center.1, 0f64, 0f64, 1f64,
+    );
+    let mut epipole = translation * epipole_1;
+    if epipole[2] < 0f64 {
+        epipole = -epipole;
+    }
+    if epipole[2] > 1e-12 * epipole.norm()
+        && (epipole[0] / epipole[2]).abs() <= center.0 + 0.5f64
+        && (epipole[1] / epipole[2]).abs() <= center.1 + 0.5f64
+    {
+        return None;
+    }
+    // Rotate the epipole onto the x axis, on the side it is closest to,
+    // so that the image is not turned upside down
+    let mut angle = f64::atan2(epipole[1], epipole[0]);
+    if angle > std::f64::consts::FRAC_PI_2 {
+        angle -= std::f64::consts::PI;
+    } else if angle < -std::f64::consts::FRAC_PI_2 {
+        angle += std::f64::consts::PI;
+    }
+    let (sin, cos) = angle.sin_cos();
+    let rotation = Matrix3::new(cos, sin, 0f64, -sin, cos, 0f64, 0f64, 0f64, 1f64);
+    let epipole = rotation * epipole;
+    if epipole[0].abs() <= 1e-9 * epipole.norm() {
+        return None;
+    }
+    // Send the epipole (f, 0, 1) to infinity
+    let projection = Matrix3::new(
+        1f64,
+        0f64,
+        0f64,
+        0f64,
+        1f64,
+        0f64,
+        -epipole[2] / epipole[0],
+        0f64,
+        1f64,
+    );
+    let homography_1 = projection * rotation * translation;
+    // A matching homography of the first image, which maps epipolar lines
+    // onto the same rows as the second one
+    let m = epipole_1.cross_matrix() * fund_mat
+        + epipole_1 * Vector3::new(1f64, 1f64, 1f64).transpose();
+    let base_0 = homography_1 * m;
+    // Least squares fit of the horizontal shear, scale and offset
+    let mut normal = Matrix3::zeros();
+    let mut rhs = Vector3::zeros();
+    for match_i in matches {
+        let p_0 = apply(&base_0, keypoints_0[match_i.index_0].point);
+        let p_1 = apply(&homography_1, keypoints_1[match_i.index_1].point);
+        let row = Vector3::new(p_0[0], p_0[1], 1f64);
+        normal += row * row.transpose();
+        rhs += row * p_1[0];
+    }
+    let shear = normal.try_inverse()? * rhs;
+    let affine = Matrix3::new(
+        shear[0], shear[1], shear[2], 0f64, 1f64, 0f64, 0f64, 0f64, 1f64,
+    );
+    let homography_0 = affine * base_0;
+    if !homography_0.norm().is_finite() || !homography_1.norm().is_finite() {
+        return None;
+    }
+    Some((homography_0, homography_1))
+}
+
+/// The mean vertical disparity of matches after rectification.
+///
+/// # Arguments
+/// * `homography_0` - The rectifying homography of the first image.
+/// * `homography_1` - The rectifying homography of the second image.
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// # Return value
+/// The mean absolute difference of the rectified y coordinates in pixels.
+pub fn rectification_error(
+    homography_0: &Matrix3<f64>,
+    homography_1: &Matrix3<f64>,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+) -> f64 {
+    let total: f64 = matches
+        .iter()
+        .map(|match_i| {
+            let p_0 = apply(homography_0, keypoints_0[match_i.index_0].point);
+            let p_1 = apply(homography_1, keypoints_1[match_i.index_1].point);
+            (p_0[1] - p_1[1]).abs()
+        })
+        .sum();
+    total / matches.len().max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rectification_error, rectify_uncalibrated};
+    use crate::ops::triangulation::{camera_matrix, fundamental_from_cameras};
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use nalgebra::{Matrix3, Rotation3, Vector3, Vector4, U2};
+
+    fn keypoint(x: f64, y: f64) -> Keypoint {
+        Keypoint {
+            point: (x as f32, y as f32),
+            response: 1f32,
+            size: 4f32,
+            octave: 0,
+            class_id: 0,
+            angle: 0f32,
+        }
+    }
+
+    /// Projections of a point cloud into two cameras, with their matches
+    /// and fundamental matrix.
+    fn views(
+        rotation: &Matrix3<f64>,
+        translation: &Vector3<f64>,
+    ) -> (Vec<Keypoint>, Vec<Keypoint>, Vec<Match>, Matrix3<f32>) {
+        let k = Matrix3::new(500f64, 0f64, 320f64, 0f64, 500f64, 240f64, 0f64, 0f64, 1f64);
+        let camera_0 = camera_matrix(&k, &Matrix3::identity(), &Vector3::zeros());
+        let camera_1 = camera_matrix(&k, rotation, translation);
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut matches = vec![];
+        for i in 0..40 {
+            let point = Vector4::new(
+                (i % 8) as f64 * 0.5f64 - 2f64,
+                (i / 8) as f64 * 0.6f64 - 1.2f64,
+                5f64 + (i % 3) as f64,
+                1f64,
+            );
+            let p_0 = camera_0 * point;
+            let p_1 = camera_1 * point;
+            keypoints_0.push(keypoint(p_0[0] / p_0[2], p_0[1] / p_0[2]));
+            keypoints_1.push(keypoint(p_1[0] / p_1[2], p_1[1] / p_1[2]));
+            matches.push(Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            });
+        }
+        let fund_mat = fundamental_from_cameras(&camera_0, &camera_1)
+            .unwrap()
+            .map(|x| x as f32);
+        (keypoints_0, keypoints_1, matches, fund_mat)
+    }
+
+    #[test]
+    fn rectified_matches_share_rows() {
+        let rotation = *Rotation3::from_euler_angles(0.02f64, -0.05f64, 0.03f64).matrix();
+        let (keypoints_0, keypoints_1, matches, fund_mat) =
+            views(&rotation, &Vector3::new(-1f64, 0.1f64, 0.05f64));
+        let (homography_0, homography_1) =
+            rectify_uncalibrated(&fund_mat, &keypoints_0, &keypoints_1, &matches, (640, 480))
+                .unwrap();
+        let error = rectification_error(
+            &homography_0,
+            &homography_1,
+            &keypoints_0,
+            &keypoints_1,
+            &matches,
+        );
+        assert!(error < 0.05, "vertical disparity {}", error);
+        // Neither image is mirrored
+        assert!(homography_0.fixed_slice::<U2, U2>(0, 0).determinant() > 0f64);
+        assert!(homography_1.fixed_slice::<U2, U2>(0, 0).determinant() > 0f64);
+    }
+
+    #[test]
+    fn forward_motion_is_not_rectified() {
+        // The epipole is near the image center
+        let (keypoints_0, keypoints_1, matches, fund_mat) =
+            views(&Matrix3::identity(), &Vector3::new(0.1f64, 0.05f64, -1f64));
+        assert!(
+            rectify_uncalibrated(&fund_mat, &keypoints_0, &keypoints_1, &matches, (640, 480))
+                .is_none()
+        );
+    }
+}
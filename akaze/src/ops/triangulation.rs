@@ -0,0 +1,323 @@
+use crate::types::feature_match::Match;
+use crate::types::keypoint::Keypoint;
+use nalgebra::{Matrix2, Matrix3, Matrix3x4, Matrix4, Vector2, Vector3, Vector4, SVD, U1, U2, U3};
+use serde::{Deserialize, Serialize};
+
+/// How a 3D point is computed from two observations.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum TriangulationMethod {
+    /// Homogeneous linear triangulation (DLT).
+    Linear,
+    /// The midpoint of the shortest segment between the two viewing rays.
+    Midpoint,
+    /// Move both observations onto a common epipolar line with minimal
+    /// image displacement, then triangulate them linearly. This is the
+    /// optimal method for Gaussian image noise (Lindstrom's niter2).
+    Optimal,
+}
+
+/// A triangulated match.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TriangulatedPoint {
+    /// The point in world coordinates.
+    pub point: (f64, f64, f64),
+    /// Distance in pixels between the reprojected point and keypoint 0.
+    pub reprojection_error_0: f64,
+    /// Distance in pixels between the reprojected point and keypoint 1.
+    pub reprojection_error_1: f64,
+    /// The angle in radians between the two viewing rays.
+    pub parallax_angle: f64,
+    /// Whether the point lies in front of both cameras.
+    pub in_front: bool,
+}
+
+/// Build the camera matrix K [R | t].
+///
+/// # Arguments
+/// * `k` - The intrinsic calibration matrix.
+/// * `r` - The rotation from world to camera coordinates.
+/// * `t` - The translation from world to camera coordinates.
+/// # Return value
+/// The 3x4 camera matrix.
+pub fn camera_matrix(k: &Matrix3<f64>, r: &Matrix3<f64>, t: &Vector3<f64>) -> Matrix3x4<f64> {
+    let mut rt = Matrix3x4::zeros();
+    rt.fixed_slice_mut::<U3, U3>(0, 0).copy_from(r);
+    rt.fixed_slice_mut::<U3, U1>(0, 3).copy_from(t);
+    k * rt
+}
+
+/// The center of a finite camera, C = -M^-1 p4 for P = [M | p4].
+fn camera_center(camera: &Matrix3x4<f64>) -> Option<Vector3<f64>> {
+    let m: Matrix3<f64> = camera.fixed_slice::<U3, U3>(0, 0).into_owned();
+    let p_4: Vector3<f64> = camera.column(3).into_owned();
+    m.try_inverse().map(|m_inverse| -(m_inverse * p_4))
+}
+
+/// The fundamental matrix of two finite cameras, F = [e1]x P1 P0^+.
+///
+/// # Arguments
+/// * `camera_0` - The first camera matrix.
+/// * `camera_1` - The second camera matrix.
+/// # Return value
+/// Optionally, F such that p1^T F p0 = 0. None if either camera is not finite.
+pub fn fundamental_from_cameras(
+    camera_0: &Matrix3x4<f64>,
+    camera_1: &Matrix3x4<f64>,
+) -> Option<Matrix3<f64>> {
+    let center_0 = camera_center(camera_0)?;
+    let epipole_1 = camera_1 * Vector4::new(center_0[0], center_0[1], center_0[2], 1f64);
+    let pseudo_inverse = camera_0.transpose() * (camera_0 * camera_0.transpose()).try_inverse()?;
+    Some(epipole_1.cross_matrix() * camera_1 * pseudo_inverse)
+}
+
+/// Project a point and return the distance to an observation in pixels.
+fn reprojection_error(camera: &Matrix3x4<f64>, point: &Vector4<f64>, observed: (f64, f64)) -> f64 {
+    let projected = camera * point;
+    if projected[2].abs() < f64::EPSILON {
+        return f64::MAX;
+    }
+    let delta_x = projected[0] / projected[2] - observed.0;
+    let delta_y = projected[1] / projected[2] - observed.1;
+    f64::sqrt(delta_x * delta_x + delta_y * delta_y)
+}
+
+/// Linear triangulation of a single point.
+fn triangulate_linear(
+    camera_0: &Matrix3x4<f64>,
+    camera_1: &Matrix3x4<f64>,
+    p_0: (f64, f64),
+    p_1: (f64, f64),
+) -> Option<Vector4<f64>> {
+    let mut a = Matrix4::zeros();
+    for (row, (camera, (x, y))) in [(camera_0, p_0), (camera_1, p_1)].iter().enumerate() {
+        a.set_row(2 * row, &(camera.row(2) * *x - camera.row(0)));
+        a.set_row(2 * row + 1, &(camera.row(2) * *y - camera.row(1)));
+    }
+    let svd = SVD::new(a, false, true);
+    let v_t = svd.v_t?;
+    let mut min_i = 0;
+    for i in 1..4 {
+        if svd.singular_values[i] < svd.singular_values[min_i] {
+            min_i = i;
+        }
+    }
+    let point: Vector4<f64> = v_t.row(min_i).transpose();
+    if point[3].abs() < f64::EPSILON {
+        return None;
+    }
+    Some(point / point[3])
+}
+
+/// Midpoint triangulation of a single point.
+fn triangulate_midpoint(
+    camera_0: &Matrix3x4<f64>,
+    camera_1: &Matrix3x4<f64>,
+    p_0: (f64, f64),
+    p_1: (f64, f64),
+) -> Option<Vector4<f64>> {
+    let ray = |camera: &Matrix3x4<f64>, (x, y): (f64, f64)| -> Option<Vector3<f64>> {
+        let m: Matrix3<f64> = camera.fixed_slice::<U3, U3>(0, 0).into_owned();
+        m.try_inverse()
+            .map(|m_inverse| (m_inverse * Vector3::new(x, y, 1f64)).normalize())
+    };
+    let (center_0, center_1) = (camera_center(camera_0)?, camera_center(camera_1)?);
+    let (ray_0, ray_1) = (ray(camera_0, p_0)?, ray(camera_1, p_1)?);
+    // Solve for the ray parameters of the closest points
+    let baseline = center_1 - center_0;
+    let cos = ray_0.dot(&ray_1);
+    let denominator = 1f64 - cos * cos;
+    if denominator < f64::EPSILON {
+        return None;
+    }
+    let s_0 = (baseline.dot(&ray_0) - cos * baseline.dot(&ray_1)) / denominator;
+    let s_1 = (cos * baseline.dot(&ray_0) - baseline.dot(&ray_1)) / denominator;
+    let midpoint = ((center_0 + ray_0 * s_0) + (center_1 + ray_1 * s_1)) / 2f64;
+    Some(Vector4::new(midpoint[0], midpoint[1], midpoint[2], 1f64))
+}
+
+/// Correct a pair of observations so that they satisfy p1^T F p0 = 0,
+/// using two iterations of Lindstrom's method.
+fn correct_observations(
+    fund_mat: &Matrix3<f64>,
+    p_0: (f64, f64),
+    p_1: (f64, f64),
+) -> Option<((f64, f64), (f64, f64))> {
+    let x = Vector3::new(p_1.0, p_1.1, 1f64);
+    let x_prime = Vector3::new(p_0.0, p_0.1, 1f64);
+    let e_tilde: Matrix2<f64> = fund_mat.fixed_slice::<U2, U2>(0, 0).into_owned();
+    let f_x_prime = fund_mat * x_prime;
+    let f_t_x = fund_mat.transpose() * x;
+    let mut n = Vector2::new(f_x_prime[0], f_x_prime[1]);
+    let mut n_prime = Vector2::new(f_t_x[0], f_t_x[1]);
+    let a = n.dot(&(e_tilde * n_prime));
+    let b = 0.5f64 * (n.dot(&n) + n_prime.dot(&n_prime));
+    let c = x.dot(&f_x_prime);
+    let d = f64::sqrt(b * b - a * c);
+    if !d.is_finite() || b + d == 0f64 {
+        return None;
+    }
+    let mut lambda = c / (b + d);
+    let delta_x = n * lambda;
+    let delta_x_prime = n_prime * lambda;
+    n -= e_tilde * delta_x_prime;
+    n_prime -= e_tilde.transpose() * delta_x;
+    let norm = n.dot(&n) + n_prime.dot(&n_prime);
+    if norm == 0f64 {
+        return Some((p_0, p_1));
+    }
+    lambda *= 2f64 * d / norm;
+    let delta_x = n * lambda;
+    let delta_x_prime = n_prime * lambda;
+    Some((
+        (p_0.0 - delta_x_prime[0], p_0.1 - delta_x_prime[1]),
+        (p_1.0 - delta_x[0], p_1.1 - delta_x[1]),
+    ))
+}
+
+/// Triangulate matched keypoints seen by two cameras.
+///
+/// # Arguments
+/// * `camera_0` - The 3x4 camera matrix of the first image.
+/// * `camera_1` - The 3x4 camera matrix of the second image.
+/// * `keypoints_0` - Keypoints in set 0
+/// * `keypoints_1` - Keypoints in set 1
+/// * `matches` - Set of matches referring to keypoints_0 and keypoints_1.
+/// * `method` - The triangulation method.
+/// # Return value
+/// One entry per match. None if the point could not be triangulated,
+/// e.g. because it lies at infinity or the viewing rays are parallel.
+pub fn triangulate(
+    camera_0: &Matrix3x4<f64>,
+    camera_1: &Matrix3x4<f64>,
+    keypoints_0: &[Keypoint],
+    keypoints_1: &[Keypoint],
+    matches: &[Match],
+    method: TriangulationMethod,
+) -> Vec<Option<TriangulatedPoint>> {
+    let fund_mat = match method {
+        TriangulationMethod::Optimal => fundamental_from_cameras(camera_0, camera_1),
+        _ => None,
+    };
+    let centers = (camera_center(camera_0), camera_center(camera_1));
+    matches
+        .iter()
+        .map(|match_i| {
+            let k_0 = keypoints_0[match_i.index_0].point;
+            let k_1 = keypoints_1[match_i.index_1].point;
+            let p_0 = (f64::from(k_0.0), f64::from(k_0.1));
+            let p_1 = (f64::from(k_1.0), f64::from(k_1.1));
+            let point = match method {
+                TriangulationMethod::Linear => triangulate_linear(camera_0, camera_1, p_0, p_1),
+                TriangulationMethod::Midpoint => triangulate_midpoint(camera_0, camera_1, p_0, p_1),
+                TriangulationMethod::Optimal => {
+                    let (p_0, p_1) = correct_observations(fund_mat.as_ref()?, p_0, p_1)?;
+                    triangulate_linear(camera_0, camera_1, p_0, p_1)
+                }
+            }?;
+            let (center_0, center_1) = (centers.0?, centers.1?);
+            let point_3 = Vector3::new(point[0], point[1], point[2]);
+            let (ray_0, ray_1) = (point_3 - center_0, point_3 - center_1);
+            let cos = ray_0.dot(&ray_1) / (ray_0.norm() * ray_1.norm());
+            let depth = |camera: &Matrix3x4<f64>| {
+                (camera * point)[2] * camera.fixed_slice::<U3, U3>(0, 0).determinant()
+            };
+            Some(TriangulatedPoint {
+                point: (point[0], point[1], point[2]),
+                reprojection_error_0: reprojection_error(camera_0, &point, p_0),
+                reprojection_error_1: reprojection_error(camera_1, &point, p_1),
+                parallax_angle: f64::acos(cos.clamp(-1f64, 1f64)),
+                in_front: depth(camera_0) > 0f64 && depth(camera_1) > 0f64,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        camera_matrix, correct_observations, fundamental_from_cameras, triangulate,
+        TriangulationMethod,
+    };
+    use crate::types::feature_match::Match;
+    use crate::types::keypoint::Keypoint;
+    use nalgebra::{Matrix3, Rotation3, Vector3, Vector4};
+
+    #[test]
+    fn triangulation_recovers_points() {
+        let k = Matrix3::new(500f64, 0f64, 320f64, 0f64, 500f64, 240f64, 0f64, 0f64, 1f64);
+        let camera_0 = camera_matrix(&k, &Matrix3::identity(), &Vector3::zeros());
+        let rotation = *Rotation3::from_axis_angle(&Vector3::y_axis(), 0.1f64).matrix();
+        let camera_1 = camera_matrix(&k, &rotation, &Vector3::new(-1f64, 0.1f64, 0.05f64));
+        let fund_mat = fundamental_from_cameras(&camera_0, &camera_1).unwrap();
+        let mut keypoints_0 = vec![];
+        let mut keypoints_1 = vec![];
+        let mut points = vec![];
+        for i in 0..20 {
+            let point = Vector4::new(
+                (i % 5) as f64 - 2f64,
+                (i / 5) as f64 - 1.5f64,
+                4f64 + (i % 3) as f64,
+                1f64,
+            );
+            for (camera, keypoints) in
+                [(&camera_0, &mut keypoints_0), (&camera_1, &mut keypoints_1)].iter_mut()
+            {
+                let p = *camera * point;
+                // Half a pixel of alternating noise
+                let noise = if i % 2 == 0 { 0.5f64 } else { -0.5f64 };
+                keypoints.push(Keypoint {
+                    point: ((p[0] / p[2] + noise) as f32, (p[1] / p[2]) as f32),
+                    response: 1f32,
+                    size: 1f32,
+                    octave: 0,
+                    class_id: 0,
+                    angle: 0f32,
+                });
+            }
+            points.push(point);
+        }
+        let matches: Vec<Match> = (0..20)
+            .map(|i| Match {
+                index_0: i,
+                index_1: i,
+                distance: 0f64,
+            })
+            .collect();
+        for &method in &[
+            TriangulationMethod::Linear,
+            TriangulationMethod::Midpoint,
+            TriangulationMethod::Optimal,
+        ] {
+            let triangulated = triangulate(
+                &camera_0,
+                &camera_1,
+                &keypoints_0,
+                &keypoints_1,
+                &matches,
+                method,
+            );
+            for (point, expected) in triangulated.iter().zip(points.iter()) {
+                let point = point.unwrap();
+                assert!(point.in_front);
+                assert!((point.point.2 - expected[2]).abs() < 0.1f64);
+                assert!(point.reprojection_error_0 < 1f64);
+                assert!(point.reprojection_error_1 < 1f64);
+                assert!(point.parallax_angle > 0.05f64);
+            }
+        }
+        // The optimal correction satisfies the epipolar constraint
+        let k_0 = keypoints_0[3].point;
+        let k_1 = keypoints_1[3].point;
+        let (p_0, p_1) = (
+            (f64::from(k_0.0), f64::from(k_0.1)),
+            (f64::from(k_1.0), f64::from(k_1.1)),
+        );
+        let epipolar_error = |p_0: (f64, f64), p_1: (f64, f64)| {
+            Vector3::new(p_1.0, p_1.1, 1f64)
+                .dot(&(fund_mat * Vector3::new(p_0.0, p_0.1, 1f64)))
+                .abs()
+        };
+        let (c_0, c_1) = correct_observations(&fund_mat, p_0, p_1).unwrap();
+        assert!(epipolar_error(c_0, c_1) < 1e-6 * epipolar_error(p_0, p_1));
+    }
+}
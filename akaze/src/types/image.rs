@@ -1,6 +1,7 @@
 use image::{DynamicImage, GenericImageView, GrayImage, Pixel, RgbImage};
 use random;
 use random::Source;
+use serde::{Deserialize, Serialize};
 use std::f32;
 use std::path::PathBuf;
 
@@ -28,7 +29,7 @@ use std::path::PathBuf;
 /// because the image crate versions are missing some key optimizations
 /// like using a separable filter, and using the filters implemented
 /// here ended up speeding up everything a lot.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GrayFloatImage {
     pub buffer: Vec<f32>,
     width: usize,
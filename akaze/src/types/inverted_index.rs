@@ -0,0 +1,161 @@
+use crate::types::vocabulary::BowVector;
+use serde::{Deserialize, Serialize};
+
+/// An image database for bag-of-words retrieval.
+///
+/// For each word, the index lists the images containing it and their
+/// weights, so a query only visits images that share a word with it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct InvertedIndex {
+    /// Pairs of image id and weight for each word
+    entries: Vec<Vec<(usize, f64)>>,
+    /// The number of images added
+    image_count: usize,
+}
+
+impl InvertedIndex {
+    /// Create an empty database for a vocabulary.
+    ///
+    /// # Arguments
+    /// * `word_count` - The number of words of the vocabulary.
+    pub fn new(word_count: usize) -> InvertedIndex {
+        InvertedIndex {
+            entries: vec![vec![]; word_count],
+            image_count: 0,
+        }
+    }
+
+    /// The number of images in the database.
+    pub fn len(&self) -> usize {
+        self.image_count
+    }
+
+    /// Whether the database contains no images.
+    pub fn is_empty(&self) -> bool {
+        self.image_count == 0
+    }
+
+    /// Add an image.
+    ///
+    /// # Arguments
+    /// * `bow` - The bag-of-words vector of the image.
+    /// # Return value
+    /// The image id, which counts up from 0.
+    pub fn add(&mut self, bow: &BowVector) -> usize {
+        let image_id = self.image_count;
+        for &(word, weight) in &bow.words {
+            self.entries[word].push((image_id, weight));
+        }
+        self.image_count += 1;
+        image_id
+    }
+
+    /// Find the images most similar to a query.
+    ///
+    /// # Arguments
+    /// * `bow` - The bag-of-words vector of the query.
+    /// * `max_results` - The maximum number of results.
+    /// # Return value
+    /// Pairs of image id and L1 score (see `BowVector::score`), best first.
+    /// Images without any word in common with the query are omitted.
+    pub fn query(&self, bow: &BowVector, max_results: usize) -> Vec<(usize, f64)> {
+        let mut scores = vec![0f64; self.image_count];
+        let mut seen = vec![false; self.image_count];
+        for &(word, query_weight) in &bow.words {
+            for &(image_id, weight) in &self.entries[word] {
+                scores[image_id] +=
+                    query_weight.abs() + weight.abs() - (query_weight - weight).abs();
+                seen[image_id] = true;
+            }
+        }
+        let mut results: Vec<(usize, f64)> = scores
+            .into_iter()
+            .enumerate()
+            .filter(|&(image_id, _)| seen[image_id])
+            .map(|(image_id, score)| (image_id, score / 2f64))
+            .collect();
+        results.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        results.truncate(max_results);
+        results
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::InvertedIndex;
+    use crate::types::keypoint::Descriptor;
+    use crate::types::vocabulary::{Vocabulary, VocabularyConfig};
+    use random::{Source, Xorshift128Plus};
+
+    /// Descriptors of an image, noisy copies of a few prototypes.
+    fn image(prototypes: &[Vec<u8>], source: &mut Xorshift128Plus) -> Vec<Descriptor> {
+        (0..40)
+            .map(|i| {
+                let mut vector = prototypes[i % prototypes.len()].clone();
+                let bit = source.read::<usize>() % (vector.len() * 8);
+                vector[bit / 8] ^= 1 << (bit % 8);
+                Descriptor { vector }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vocabulary_retrieves_similar_images() {
+        let mut source = Xorshift128Plus::new([1, 2]);
+        let prototypes: Vec<Vec<u8>> = (0..26)
+            .map(|_| (0..61).map(|_| source.read::<u64>() as u8).collect())
+            .collect();
+        // Each image shows a different subset of the prototypes
+        let images: Vec<Vec<Descriptor>> = (0..6)
+            .map(|i| image(&prototypes[i * 4..i * 4 + 6], &mut source))
+            .collect();
+        let config = VocabularyConfig {
+            branching: 4,
+            depth: 3,
+            ..VocabularyConfig::default()
+        };
+        let vocabulary =
+            Vocabulary::train(&images, &config, &mut Xorshift128Plus::new(config.seed));
+        assert!(vocabulary.word_count() > 8);
+        let mut index = InvertedIndex::new(vocabulary.word_count());
+        for image in &images[..5] {
+            index.add(&vocabulary.transform(image));
+        }
+        for (i, image) in images[..5].iter().enumerate() {
+            let bow = vocabulary.transform(image);
+            assert!((bow.score(&bow) - 1f64).abs() < 1e-9);
+            let results = index.query(&bow, 2);
+            assert_eq!(results[0].0, i);
+            assert!((results[0].1 - 1f64).abs() < 1e-9);
+        }
+        // A new view of image 2's scene is closest to image 2
+        let query = vocabulary.transform(&image(&prototypes[8..14], &mut source));
+        assert_eq!(index.query(&query, 1)[0].0, 2);
+    }
+
+    #[test]
+    fn vocabulary_without_refinement() {
+        let mut source = Xorshift128Plus::new([3, 4]);
+        let prototypes: Vec<Vec<u8>> = (0..8)
+            .map(|_| (0..61).map(|_| source.read::<u64>() as u8).collect())
+            .collect();
+        let images: Vec<Vec<Descriptor>> =
+            (0..2).map(|_| image(&prototypes, &mut source)).collect();
+        let config = VocabularyConfig {
+            branching: 4,
+            depth: 2,
+            max_iterations: 0,
+            ..VocabularyConfig::default()
+        };
+        // The members are assigned to the seeded centers
+        let vocabulary =
+            Vocabulary::train(&images, &config, &mut Xorshift128Plus::new(config.seed));
+        assert!(vocabulary.word_count() > 1);
+        let bow = vocabulary.transform(&images[0]);
+        assert!((bow.score(&bow) - 1f64).abs() < 1e-9);
+    }
+}
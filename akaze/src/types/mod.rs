@@ -1,4 +1,8 @@
 pub mod evolution;
 pub mod feature_match;
 pub mod image;
+pub mod inverted_index;
 pub mod keypoint;
+pub mod spatial_index;
+pub mod tracks;
+pub mod vocabulary;
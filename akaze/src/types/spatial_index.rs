@@ -0,0 +1,126 @@
+use std::collections::HashMap;
+
+/// A uniform grid over image points for radius queries.
+#[derive(Debug, Clone)]
+pub struct GridIndex {
+    cell_size: f32,
+    /// Point indices of each non-empty cell
+    cells: HashMap<(i32, i32), Vec<usize>>,
+    points: Vec<(f32, f32)>,
+    /// The smallest and largest occupied cell coordinates
+    bounds: ((i32, i32), (i32, i32)),
+}
+
+impl GridIndex {
+    /// Index a set of points.
+    ///
+    /// # Arguments
+    /// * `points` - The points, e.g. keypoint positions.
+    /// * `cell_size` - The grid spacing in pixels, which must be positive.
+    ///   Queries are fastest when it is close to the typical query radius.
+    pub fn new(points: &[(f32, f32)], cell_size: f32) -> GridIndex {
+        assert!(cell_size > 0f32, "the cell size must be positive");
+        let mut index = GridIndex {
+            cell_size,
+            cells: HashMap::new(),
+            points: points.to_vec(),
+            bounds: ((i32::MAX, i32::MAX), (i32::MIN, i32::MIN)),
+        };
+        for (i, &point) in points.iter().enumerate() {
+            let cell = index.cell(point);
+            index.cells.entry(cell).or_default().push(i);
+            let (min, max) = &mut index.bounds;
+            *min = (min.0.min(cell.0), min.1.min(cell.1));
+            *max = (max.0.max(cell.0), max.1.max(cell.1));
+        }
+        index
+    }
+
+    /// The grid cell containing a point.
+    pub fn cell(&self, point: (f32, f32)) -> (i32, i32) {
+        (
+            f32::floor(point.0 / self.cell_size) as i32,
+            f32::floor(point.1 / self.cell_size) as i32,
+        )
+    }
+
+    /// The indices of the points in a cell.
+    pub fn points_in_cell(&self, cell: (i32, i32)) -> &[usize] {
+        self.cells.get(&cell).map(|c| c.as_slice()).unwrap_or(&[])
+    }
+
+    /// Find the points within a radius.
+    ///
+    /// # Arguments
+    /// * `center` - The query point.
+    /// * `radius` - The search radius in pixels.
+    /// # Return value
+    /// The indices of the points at a distance of at most `radius`.
+    pub fn within(&self, center: (f32, f32), radius: f32) -> Vec<usize> {
+        let min = self.cell((center.0 - radius, center.1 - radius));
+        let max = self.cell((center.0 + radius, center.1 + radius));
+        let mut found = vec![];
+        for x in min.0..=max.0 {
+            for y in min.1..=max.1 {
+                for &i in self.points_in_cell((x, y)) {
+                    let (delta_x, delta_y) =
+                        (self.points[i].0 - center.0, self.points[i].1 - center.1);
+                    if delta_x * delta_x + delta_y * delta_y <= radius * radius {
+                        found.push(i);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    /// Find the points near a line.
+    ///
+    /// Only the cells the band around the line passes through are visited,
+    /// so this is much faster than testing every point.
+    ///
+    /// # Arguments
+    /// * `line` - The line (a, b, c) with a x + b y + c = 0.
+    /// * `distance` - The maximum distance in pixels from the line.
+    /// # Return value
+    /// The indices of the points at a distance of at most `distance`.
+    pub fn near_line(&self, line: (f32, f32, f32), distance: f32) -> Vec<usize> {
+        let (a, b, c) = line;
+        let norm = f32::sqrt(a * a + b * b);
+        let mut found = vec![];
+        if norm <= 0f32 || self.points.is_empty() {
+            return found;
+        }
+        let (min, max) = self.bounds;
+        // Walk along the axis the line is closer to, and visit the cells
+        // across it covered by the band
+        let horizontal = b.abs() >= a.abs();
+        let (along, across) = if horizontal {
+            ((min.0, max.0), (min.1, max.1))
+        } else {
+            ((min.1, max.1), (min.0, max.0))
+        };
+        let (p, q) = if horizontal { (a, b) } else { (b, a) };
+        let half_width = distance * norm / q.abs();
+        for i in along.0..=along.1 {
+            let start = i as f32 * self.cell_size;
+            let end = start + self.cell_size;
+            let across_start = -(p * start + c) / q;
+            let across_end = -(p * end + c) / q;
+            let low = across_start.min(across_end) - half_width;
+            let high = across_start.max(across_end) + half_width;
+            let first = (f32::floor(low / self.cell_size) as i32).max(across.0);
+            let last = (f32::floor(high / self.cell_size) as i32).min(across.1);
+            for j in first..=last {
+                let cell = if horizontal { (i, j) } else { (j, i) };
+                for &k in self.points_in_cell(cell) {
+                    let (x, y) = self.points[k];
+                    if (a * x + b * y + c).abs() <= distance * norm {
+                        found.push(k);
+                    }
+                }
+            }
+        }
+        found
+    }
+}
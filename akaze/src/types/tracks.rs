@@ -0,0 +1,235 @@
+use crate::types::feature_match::Match;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+
+/// A keypoint in one image of a collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Observation {
+    /// The image in which the keypoint was found.
+    pub image_id: usize,
+    /// The index of the keypoint in that image.
+    pub keypoint_index: usize,
+}
+
+/// A set of observations of the same scene point, at most one per image.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    /// The observations, sorted by image id.
+    pub observations: Vec<Observation>,
+}
+
+impl Track {
+    /// The keypoint index observed in an image, if any.
+    pub fn keypoint_in(&self, image_id: usize) -> Option<usize> {
+        self.observations
+            .binary_search_by_key(&image_id, |observation| observation.image_id)
+            .ok()
+            .map(|i| self.observations[i].keypoint_index)
+    }
+}
+
+/// Links pairwise matches into multi-view tracks.
+///
+/// Matches are merged with a union-find forest over observations, so the
+/// order in which image pairs are added does not change the result.
+#[derive(Debug, Clone, Default)]
+pub struct Tracks {
+    /// The forest node of each observation
+    nodes: HashMap<Observation, usize>,
+    /// The observation of each node
+    observations: Vec<Observation>,
+    /// Parent of each node, a root is its own parent
+    parents: Vec<usize>,
+    /// Upper bound of the height of each root
+    ranks: Vec<u8>,
+}
+
+impl Tracks {
+    /// Create an empty track builder.
+    pub fn new() -> Tracks {
+        Tracks::default()
+    }
+
+    fn node(&mut self, observation: Observation) -> usize {
+        let observations = &mut self.observations;
+        let parents = &mut self.parents;
+        let ranks = &mut self.ranks;
+        *self.nodes.entry(observation).or_insert_with(|| {
+            observations.push(observation);
+            parents.push(parents.len());
+            ranks.push(0);
+            parents.len() - 1
+        })
+    }
+
+    fn find(&mut self, node: usize) -> usize {
+        let mut root = node;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        // Path compression
+        let mut node = node;
+        while self.parents[node] != root {
+            let parent = self.parents[node];
+            self.parents[node] = root;
+            node = parent;
+        }
+        root
+    }
+
+    fn union(&mut self, node_0: usize, node_1: usize) {
+        let (root_0, root_1) = (self.find(node_0), self.find(node_1));
+        if root_0 == root_1 {
+            return;
+        }
+        if self.ranks[root_0] < self.ranks[root_1] {
+            self.parents[root_0] = root_1;
+        } else {
+            self.parents[root_1] = root_0;
+            if self.ranks[root_0] == self.ranks[root_1] {
+                self.ranks[root_0] += 1;
+            }
+        }
+    }
+
+    /// Add the matches between two images.
+    ///
+    /// # Arguments
+    /// * `image_id_0` - The image of `index_0` in the matches.
+    /// * `image_id_1` - The image of `index_1` in the matches.
+    /// * `matches` - The (ideally geometrically verified) matches.
+    pub fn add_matches(&mut self, image_id_0: usize, image_id_1: usize, matches: &[Match]) {
+        if image_id_0 == image_id_1 {
+            warn!("Ignoring matches of image {} with itself.", image_id_0);
+            return;
+        }
+        for match_i in matches {
+            let node_0 = self.node(Observation {
+                image_id: image_id_0,
+                keypoint_index: match_i.index_0,
+            });
+            let node_1 = self.node(Observation {
+                image_id: image_id_1,
+                keypoint_index: match_i.index_1,
+            });
+            self.union(node_0, node_1);
+        }
+    }
+
+    /// Build the tracks from all matches added so far.
+    ///
+    /// Tracks with two different keypoints in the same image are
+    /// inconsistent, since a scene point projects to one place per image,
+    /// and are rejected entirely.
+    ///
+    /// # Arguments
+    /// * `min_length` - The minimum number of observations of a track.
+    /// # Return value
+    /// The consistent tracks, ordered by their first observation.
+    pub fn build(&mut self, min_length: usize) -> Vec<Track> {
+        let mut components: BTreeMap<usize, Vec<Observation>> = BTreeMap::new();
+        for node in 0..self.observations.len() {
+            let root = self.find(node);
+            components
+                .entry(root)
+                .or_default()
+                .push(self.observations[node]);
+        }
+        let mut inconsistent = 0;
+        let mut tracks: Vec<Track> = components
+            .into_values()
+            .filter_map(|mut observations| {
+                observations.sort();
+                let consistent = observations
+                    .windows(2)
+                    .all(|pair| pair[0].image_id != pair[1].image_id);
+                if !consistent {
+                    inconsistent += 1;
+                    None
+                } else if observations.len() < usize::max(min_length, 2) {
+                    None
+                } else {
+                    Some(Track { observations })
+                }
+            })
+            .collect();
+        tracks.sort_by(|a, b| a.observations[0].cmp(&b.observations[0]));
+        debug!(
+            "Built {} tracks, rejected {} inconsistent tracks.",
+            tracks.len(),
+            inconsistent
+        );
+        tracks
+    }
+}
+
+/// Group the observations of tracks by image.
+///
+/// # Arguments
+/// * `tracks` - The tracks.
+/// # Return value
+/// For each image id, the pairs of track index and keypoint index.
+pub fn observations_by_image(tracks: &[Track]) -> BTreeMap<usize, Vec<(usize, usize)>> {
+    let mut by_image: BTreeMap<usize, Vec<(usize, usize)>> = BTreeMap::new();
+    for (track_index, track) in tracks.iter().enumerate() {
+        for observation in &track.observations {
+            by_image
+                .entry(observation.image_id)
+                .or_default()
+                .push((track_index, observation.keypoint_index));
+        }
+    }
+    by_image
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{observations_by_image, Observation, Tracks};
+    use crate::types::feature_match::Match;
+
+    fn matches(pairs: &[(usize, usize)]) -> Vec<Match> {
+        pairs
+            .iter()
+            .map(|&(index_0, index_1)| Match {
+                index_0,
+                index_1,
+                distance: 0f64,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn tracks_link_pairs_and_reject_inconsistent() {
+        let mut tracks = Tracks::new();
+        tracks.add_matches(0, 1, &matches(&[(0, 5), (1, 6), (2, 7)]));
+        tracks.add_matches(1, 2, &matches(&[(5, 9), (6, 3)]));
+        // Closes a loop consistently for the first track...
+        tracks.add_matches(0, 2, &matches(&[(0, 9)]));
+        // ...and inconsistently for the second: keypoints 1 and 2 of image 0
+        tracks.add_matches(2, 0, &matches(&[(3, 2)]));
+        let built = tracks.build(2);
+        assert_eq!(built.len(), 1);
+        assert_eq!(
+            built[0].observations,
+            vec![
+                Observation {
+                    image_id: 0,
+                    keypoint_index: 0
+                },
+                Observation {
+                    image_id: 1,
+                    keypoint_index: 5
+                },
+                Observation {
+                    image_id: 2,
+                    keypoint_index: 9
+                },
+            ]
+        );
+        assert_eq!(built[0].keypoint_in(2), Some(9));
+        assert_eq!(built[0].keypoint_in(3), None);
+        assert!(tracks.build(4).is_empty());
+        let by_image = observations_by_image(&built);
+        assert_eq!(by_image[&1], vec![(0, 5)]);
+    }
+}
@@ -0,0 +1,337 @@
+use crate::ops::feature_matching::hamming_distance;
+use crate::types::keypoint::Descriptor;
+use random::Source;
+use serde::{Deserialize, Serialize};
+
+/// Options for training a vocabulary tree.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct VocabularyConfig {
+    /// Number of children of each node
+    pub branching: usize,
+    /// Number of levels below the root. The vocabulary has at most
+    /// `branching ^ depth` words.
+    pub depth: usize,
+    /// Maximum number of k-majority iterations per node
+    pub max_iterations: usize,
+    /// Seed for the random number generator used to pick initial centers
+    pub seed: [u64; 2],
+}
+
+impl Default for VocabularyConfig {
+    fn default() -> VocabularyConfig {
+        VocabularyConfig {
+            branching: 10,
+            depth: 5,
+            max_iterations: 10,
+            seed: [42, 69],
+        }
+    }
+}
+
+/// A node of the vocabulary tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Node {
+    /// The cluster center. Empty for the root.
+    descriptor: Descriptor,
+    /// Indices of the child nodes
+    children: Vec<usize>,
+    /// The word id, for leaves
+    word: Option<usize>,
+}
+
+/// A sparse, L1 normalized bag-of-words vector.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BowVector {
+    /// Pairs of word id and weight, sorted by word id.
+    pub words: Vec<(usize, f64)>,
+}
+
+impl BowVector {
+    /// The L1 similarity score of two vectors, between 0 (nothing in
+    /// common) and 1 (identical).
+    pub fn score(&self, other: &BowVector) -> f64 {
+        let mut score = 0f64;
+        let (mut i, mut j) = (0, 0);
+        while i < self.words.len() && j < other.words.len() {
+            let ((word_0, weight_0), (word_1, weight_1)) = (self.words[i], other.words[j]);
+            if word_0 < word_1 {
+                i += 1;
+            } else if word_1 < word_0 {
+                j += 1;
+            } else {
+                score += weight_0.abs() + weight_1.abs() - (weight_0 - weight_1).abs();
+                i += 1;
+                j += 1;
+            }
+        }
+        score / 2f64
+    }
+}
+
+/// A vocabulary tree of binary words with TF-IDF weights, in the style
+/// of DBoW2.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Vocabulary {
+    /// The options the vocabulary was trained with
+    pub config: VocabularyConfig,
+    /// The tree, the root is node 0
+    nodes: Vec<Node>,
+    /// The inverse document frequency of each word
+    idf: Vec<f64>,
+}
+
+/// Compute the bitwise majority of a set of descriptors.
+fn majority(members: &[&Descriptor]) -> Descriptor {
+    let length = members[0].vector.len();
+    let mut counts = vec![0usize; length * 8];
+    for member in members {
+        for (i, byte) in member.vector.iter().enumerate() {
+            for bit in 0..8 {
+                if byte & (1 << bit) != 0 {
+                    counts[i * 8 + bit] += 1;
+                }
+            }
+        }
+    }
+    let mut vector = vec![0u8; length];
+    for (i, count) in counts.iter().enumerate() {
+        if 2 * count > members.len() {
+            vector[i / 8] |= 1 << (i % 8);
+        }
+    }
+    Descriptor { vector }
+}
+
+/// The index of the nearest center and its distance.
+fn nearest(descriptor: &Descriptor, centers: &[&Descriptor]) -> (usize, usize) {
+    let mut best = (0, usize::MAX);
+    for (i, center) in centers.iter().enumerate() {
+        let distance = hamming_distance(descriptor, center, best.1);
+        if distance < best.1 {
+            best = (i, distance);
+        }
+    }
+    best
+}
+
+/// Cluster binary descriptors with k-majority, seeded by k-means++.
+///
+/// # Return value
+/// The non-empty clusters as pairs of center and members.
+fn k_majority<'a, S: Source>(
+    members: &[&'a Descriptor],
+    k: usize,
+    max_iterations: usize,
+    source: &mut S,
+) -> Vec<(Descriptor, Vec<&'a Descriptor>)> {
+    // k-means++ initialization
+    let mut centers: Vec<Descriptor> =
+        vec![members[source.read::<usize>() % members.len()].clone()];
+    let mut distances: Vec<f64> = members
+        .iter()
+        .map(|member| hamming_distance(member, &centers[0], usize::MAX) as f64)
+        .collect();
+    while centers.len() < k {
+        let total: f64 = distances.iter().map(|d| d * d).sum();
+        if total <= 0f64 {
+            // All remaining members coincide with a center
+            break;
+        }
+        let mut target = source.read_f64() * total;
+        let mut chosen = members.len() - 1;
+        for (i, d) in distances.iter().enumerate() {
+            target -= d * d;
+            if target < 0f64 {
+                chosen = i;
+                break;
+            }
+        }
+        centers.push(members[chosen].clone());
+        let center = &centers[centers.len() - 1];
+        for (member, distance) in members.iter().zip(distances.iter_mut()) {
+            *distance = distance.min(hamming_distance(member, center, usize::MAX) as f64);
+        }
+    }
+    let mut assignments = vec![usize::MAX; members.len()];
+    // Every member must be assigned once, even without refinement
+    for _ in 0..max_iterations.max(1) {
+        let center_refs: Vec<&Descriptor> = centers.iter().collect();
+        let mut changed = false;
+        for (member, assignment) in members.iter().zip(assignments.iter_mut()) {
+            let (cluster, _) = nearest(member, &center_refs);
+            if *assignment != cluster {
+                *assignment = cluster;
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+        centers = (0..centers.len())
+            .map(|cluster| {
+                let cluster_members: Vec<&Descriptor> = members
+                    .iter()
+                    .zip(assignments.iter())
+                    .filter(|(_, &assignment)| assignment == cluster)
+                    .map(|(member, _)| *member)
+                    .collect();
+                if cluster_members.is_empty() {
+                    centers[cluster].clone()
+                } else {
+                    majority(&cluster_members)
+                }
+            })
+            .collect();
+    }
+    let mut clusters: Vec<(Descriptor, Vec<&Descriptor>)> =
+        centers.into_iter().map(|center| (center, vec![])).collect();
+    for (member, &assignment) in members.iter().zip(assignments.iter()) {
+        clusters[assignment].1.push(member);
+    }
+    clusters.retain(|(_, cluster_members)| !cluster_members.is_empty());
+    clusters
+}
+
+impl Vocabulary {
+    /// Train a vocabulary by hierarchical k-majority clustering.
+    ///
+    /// # Arguments
+    /// * `images` - The descriptors of each training image. The IDF weight
+    ///   of a word is ln(N / n), where N is the number of images and n the
+    ///   number of images containing the word.
+    /// * `config` - The training options.
+    /// * `source` - Random number source used to pick initial centers.
+    /// # Return value
+    /// The vocabulary.
+    pub fn train<S: Source>(
+        images: &[Vec<Descriptor>],
+        config: &VocabularyConfig,
+        source: &mut S,
+    ) -> Vocabulary {
+        let all: Vec<&Descriptor> = images.iter().flatten().collect();
+        debug!(
+            "Training vocabulary from {} descriptors of {} images.",
+            all.len(),
+            images.len()
+        );
+        let mut vocabulary = Vocabulary {
+            config: *config,
+            nodes: vec![Node {
+                descriptor: Descriptor { vector: vec![] },
+                children: vec![],
+                word: None,
+            }],
+            idf: vec![],
+        };
+        // Grow the tree, splitting each node into up to `branching` clusters
+        let mut queue = vec![(0usize, all, 0usize)];
+        while let Some((parent, members, level)) = queue.pop() {
+            if level >= config.depth || members.len() <= 1 {
+                continue;
+            }
+            let clusters: Vec<(Descriptor, Vec<&Descriptor>)> = if members.len() <= config.branching
+            {
+                members
+                    .iter()
+                    .map(|&member| (member.clone(), vec![member]))
+                    .collect()
+            } else {
+                k_majority(&members, config.branching, config.max_iterations, source)
+            };
+            if clusters.len() <= 1 {
+                continue;
+            }
+            for (center, cluster_members) in clusters {
+                vocabulary.nodes.push(Node {
+                    descriptor: center,
+                    children: vec![],
+                    word: None,
+                });
+                let child = vocabulary.nodes.len() - 1;
+                vocabulary.nodes[parent].children.push(child);
+                queue.push((child, cluster_members, level + 1));
+            }
+        }
+        let mut word_count = 0;
+        for node in vocabulary.nodes.iter_mut() {
+            if node.children.is_empty() {
+                node.word = Some(word_count);
+                word_count += 1;
+            }
+        }
+        // Inverse document frequencies
+        let mut document_counts = vec![0usize; word_count];
+        for image in images {
+            let mut words: Vec<usize> = image.iter().map(|d| vocabulary.word(d)).collect();
+            words.sort();
+            words.dedup();
+            for word in words {
+                document_counts[word] += 1;
+            }
+        }
+        vocabulary.idf = document_counts
+            .iter()
+            .map(|&count| {
+                if count > 0 {
+                    f64::ln(images.len() as f64 / count as f64)
+                } else {
+                    0f64
+                }
+            })
+            .collect();
+        debug!("Trained vocabulary with {} words.", word_count);
+        vocabulary
+    }
+
+    /// The number of words.
+    pub fn word_count(&self) -> usize {
+        self.idf.len()
+    }
+
+    /// The inverse document frequency weight of a word.
+    pub fn idf(&self, word: usize) -> f64 {
+        self.idf[word]
+    }
+
+    /// Find the word of a descriptor by descending the tree.
+    pub fn word(&self, descriptor: &Descriptor) -> usize {
+        let mut node = 0;
+        while !self.nodes[node].children.is_empty() {
+            let children: Vec<&Descriptor> = self.nodes[node]
+                .children
+                .iter()
+                .map(|&child| &self.nodes[child].descriptor)
+                .collect();
+            node = self.nodes[node].children[nearest(descriptor, &children).0];
+        }
+        self.nodes[node].word.unwrap_or(0)
+    }
+
+    /// Convert the descriptors of an image to a TF-IDF weighted,
+    /// L1 normalized bag-of-words vector.
+    ///
+    /// # Arguments
+    /// * `descriptors` - The descriptors of the image.
+    /// # Return value
+    /// The bag-of-words vector. Words with zero weight are omitted.
+    pub fn transform(&self, descriptors: &[Descriptor]) -> BowVector {
+        let mut words: Vec<usize> = descriptors.iter().map(|d| self.word(d)).collect();
+        words.sort();
+        let mut bow = BowVector::default();
+        for word in words {
+            let weight = self.idf[word] / descriptors.len() as f64;
+            match bow.words.last_mut() {
+                Some((last, last_weight)) if *last == word => *last_weight += weight,
+                _ => bow.words.push((word, weight)),
+            }
+        }
+        bow.words.retain(|&(_, weight)| weight > 0f64);
+        let norm: f64 = bow.words.iter().map(|(_, weight)| weight.abs()).sum();
+        if norm > 0f64 {
+            for (_, weight) in bow.words.iter_mut() {
+                *weight /= norm;
+            }
+        }
+        bow
+    }
+}